@@ -0,0 +1,301 @@
+//! GC content and kmer uniqueness summaries for cytogenetic bands and chromosome arms
+//!
+//! Bands are read from a cytoband (ideogram) file as distributed by UCSC, with the columns contig,
+//! start, end, band name and Giemsa stain.  Arms are formed from the bands whose names start with
+//! p or q.  Kmer uniqueness requires two passes over the input: the first counts all canonical
+//! kmers in the genome and the second counts, for each band, the kmers (assigned to the band
+//! containing their first base) that occur only once.
+use std::{
+    io::{BufRead, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+use rustc_hash::FxHashMap;
+
+use crate::{
+    cli::BandsConfig,
+    fasta::{tar::open_fasta, Base, FastaReader, GapPolicy},
+    kmers::{KType, KmerBuilder, KmerCounts, KMER_LENGTH},
+};
+
+pub struct Band {
+    name: String,
+    stain: String,
+    start: u64,
+    end: u64,
+    counts: [u64; 4],
+    kmers: u64,
+    unique_kmers: u64,
+}
+
+impl Band {
+    fn new(name: &str, stain: &str, start: u64, end: u64) -> Self {
+        Self {
+            name: name.to_owned(),
+            stain: stain.to_owned(),
+            start,
+            end,
+            counts: [0; 4],
+            kmers: 0,
+            unique_kmers: 0,
+        }
+    }
+
+    /// Arm (p or q) from the band name, if present
+    pub fn arm(&self) -> Option<char> {
+        self.name.chars().next().filter(|c| matches!(c, 'p' | 'q'))
+    }
+
+    fn add(&mut self, other: &Band) {
+        self.start = self.start.min(other.start);
+        self.end = self.end.max(other.end);
+        for (a, b) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *a += *b
+        }
+        self.kmers += other.kmers;
+        self.unique_kmers += other.unique_kmers
+    }
+
+    fn write<W: Write>(&self, w: &mut W, ctg: &str, stain: bool) -> std::io::Result<()> {
+        let acgt: u64 = self.counts.iter().sum();
+        let ratio = |a: u64, b: u64| if b > 0 { a as f64 / b as f64 } else { f64::NAN };
+        let gc = self.counts[Base::C as usize] + self.counts[Base::G as usize];
+        write!(w, "{ctg}\t{}\t{}\t{}", self.start, self.end, self.name)?;
+        if stain {
+            write!(w, "\t{}", self.stain)?
+        }
+        writeln!(
+            w,
+            "\t{}\t{acgt}\t{}\t{}\t{}\t{}",
+            self.end - self.start,
+            ratio(gc, acgt),
+            self.kmers,
+            self.unique_kmers,
+            ratio(self.unique_kmers, self.kmers)
+        )
+    }
+}
+
+/// Bands for each contig, in file order
+pub struct Bands {
+    contigs: Vec<(String, Vec<Band>)>,
+    index: FxHashMap<String, usize>,
+}
+
+impl Bands {
+    /// Index of contig.  If not found, the name with a chr prefix added or removed is tried
+    fn find(&self, ctg: &str) -> Option<usize> {
+        self.index.get(ctg).copied().or_else(|| {
+            let alt = match ctg.strip_prefix("chr") {
+                Some(s) => s.to_owned(),
+                None => format!("chr{ctg}"),
+            };
+            self.index.get(&alt).copied()
+        })
+    }
+}
+
+pub fn read_cytobands<P: AsRef<Path>>(path: P) -> anyhow::Result<Bands> {
+    let path = path.as_ref();
+    let rdr = CompressIo::new()
+        .path(path)
+        .bufreader()
+        .with_context(|| format!("Could not open cytoband file {}", path.display()))?;
+
+    let mut bands = Bands {
+        contigs: Vec::new(),
+        index: FxHashMap::default(),
+    };
+    for (i, l) in rdr.lines().enumerate() {
+        let l = l.with_context(|| format!("Error reading from {}", path.display()))?;
+        if l.is_empty() || l.starts_with('#') {
+            continue;
+        }
+        let v: Vec<_> = l.trim_end().split('\t').collect();
+        let err = || format!("Error at line {} of {}", i + 1, path.display());
+        if v.len() < 4 {
+            return Err(anyhow!("Too few columns")).with_context(err);
+        }
+        let start = v[1].parse::<u64>().with_context(err)?;
+        let end = v[2].parse::<u64>().with_context(err)?;
+        if end <= start {
+            return Err(anyhow!("Band end before start")).with_context(err);
+        }
+        let ix = *bands.index.entry(v[0].to_owned()).or_insert_with(|| {
+            bands.contigs.push((v[0].to_owned(), Vec::new()));
+            bands.contigs.len() - 1
+        });
+        let b = Band::new(v[3], v.get(4).copied().unwrap_or("."), start, end);
+        bands.contigs[ix].1.push(b)
+    }
+    for (_, v) in bands.contigs.iter_mut() {
+        v.sort_unstable_by_key(|b| b.start)
+    }
+    let n: usize = bands.contigs.iter().map(|(_, v)| v.len()).sum();
+    info!("Read {n} bands for {} contigs", bands.contigs.len());
+    Ok(bands)
+}
+
+/// Band containing pos.  Positions must be non decreasing within a contig, with the cursor being
+/// reset at the start of each contig
+fn find_band(v: &[Band], cursor: &mut usize, pos: u64) -> Option<usize> {
+    while *cursor < v.len() && v[*cursor].end <= pos {
+        *cursor += 1
+    }
+    v.get(*cursor).filter(|b| b.start <= pos).map(|_| *cursor)
+}
+
+/// Read the input, calling f for each base with the bands of the current contig, the index of
+/// the band containing the base, the base, and (if valid) the kmer ending at the base with the
+/// index of the band containing the start of the kmer
+#[allow(clippy::type_complexity)]
+fn scan<F>(cfg: &BandsConfig, bands: &mut Bands, mut f: F) -> anyhow::Result<()>
+where
+    F: FnMut(&mut [Band], Option<usize>, Base, Option<([KType; 2], Option<usize>)>),
+{
+    let rdr = open_fasta(cfg.input())?;
+    let mut rdr = FastaReader::new(rdr, GapPolicy::MinGap(1000));
+    let mut builder = KmerBuilder::new();
+    let mut ctg = (String::new(), None);
+    let mut cursors = (0, 0);
+    let k = KMER_LENGTH as u64;
+    while let Some(s) = rdr
+        .next_seq()
+        .with_context(|| "Error reading input sequence")?
+    {
+        if s.contig() != ctg.0 {
+            ctg = (s.contig().to_owned(), bands.find(s.contig()));
+            cursors = (0, 0)
+        }
+        let v = match ctg.1 {
+            Some(i) => bands.contigs[i].1.as_mut_slice(),
+            None => &mut [],
+        };
+        builder.clear();
+        for (pos, b) in (s.start()..).zip(s.iter()) {
+            builder.add_base(*b, None);
+            let bi = find_band(v, &mut cursors.0, pos);
+            let km = builder
+                .kmers()
+                .map(|x| (x, find_band(v, &mut cursors.1, pos + 1 - k)));
+            f(v, bi, *b, km)
+        }
+    }
+    Ok(())
+}
+
+fn output_bands<P: AsRef<Path>>(path: P, bands: &Bands) -> anyhow::Result<()> {
+    let mut wrt = CompressIo::new()
+        .path(path)
+        .bufwriter()
+        .with_context(|| "Could not open band summary file for output")?;
+    writeln!(
+        wrt,
+        "contig\tstart\tend\tband\tstain\tlength\tacgt\tgc\tkmers\tunique_kmers\tunique_fraction"
+    )?;
+    for (ctg, v) in bands.contigs.iter() {
+        for b in v {
+            b.write(&mut wrt, ctg, true)?
+        }
+    }
+    wrt.flush()
+        .with_context(|| "Error flushing data to band summary file")
+}
+
+fn output_arms<P: AsRef<Path>>(path: P, bands: &Bands) -> anyhow::Result<()> {
+    let mut wrt = CompressIo::new()
+        .path(path)
+        .bufwriter()
+        .with_context(|| "Could not open arm summary file for output")?;
+    writeln!(
+        wrt,
+        "contig\tstart\tend\tarm\tlength\tacgt\tgc\tkmers\tunique_kmers\tunique_fraction"
+    )?;
+    for (ctg, v) in bands.contigs.iter() {
+        for arm in ['p', 'q'] {
+            let mut it = v.iter().filter(|b| b.arm() == Some(arm));
+            if let Some(b) = it.next() {
+                let mut a = Band::new(&arm.to_string(), ".", b.start, b.end);
+                a.add(b);
+                it.for_each(|b| a.add(b));
+                a.write(&mut wrt, ctg, false)?
+            }
+        }
+    }
+    wrt.flush()
+        .with_context(|| "Error flushing data to arm summary file")
+}
+
+pub fn bands(cfg: &BandsConfig) -> anyhow::Result<()> {
+    let mut bands = read_cytobands(cfg.cytobands())?;
+
+    let mut counts = cfg.kmers().then(KmerCounts::new);
+    info!("Starting to read input");
+    scan(cfg, &mut bands, |v, bi, b, km| {
+        if let (Some(c), Some((k, _))) = (counts.as_mut(), km) {
+            c.add(k)
+        }
+        if let Some(i) = bi.filter(|_| !b.is_gap()) {
+            v[i].counts[b as usize] += 1
+        }
+    })?;
+    info!("Finished reading input");
+
+    if let Some(c) = counts {
+        info!("Re-reading input for kmer uniqueness");
+        scan(cfg, &mut bands, |v, _, _, km| {
+            if let Some((k, Some(i))) = km {
+                v[i].kmers += 1;
+                if c.is_unique(k) {
+                    v[i].unique_kmers += 1
+                }
+            }
+        })?;
+        info!("Finished reading input");
+    }
+
+    let mut stains: Vec<(&str, [u64; 4])> = Vec::new();
+    for b in bands.contigs.iter().flat_map(|(_, v)| v.iter()) {
+        let ix = match stains.iter().position(|(s, _)| *s == b.stain) {
+            Some(i) => i,
+            None => {
+                stains.push((&b.stain, [0; 4]));
+                stains.len() - 1
+            }
+        };
+        for (a, c) in stains[ix].1.iter_mut().zip(b.counts.iter()) {
+            *a += *c
+        }
+    }
+    for (s, c) in stains.iter() {
+        let acgt: u64 = c.iter().sum();
+        if acgt > 0 {
+            let gc = (c[Base::C as usize] + c[Base::G as usize]) as f64 / acgt as f64;
+            info!("Stain {s}: {acgt} bases, GC {gc:.4}")
+        }
+    }
+
+    output_bands(format!("{}_bands.txt", cfg.prefix()), &bands)?;
+    output_arms(format!("{}_arms.txt", cfg.prefix()), &bands)
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_find_band() {
+        let v = vec![
+            Band::new("p11", "gneg", 0, 10),
+            Band::new("q11", "gpos50", 20, 30),
+        ];
+        let mut c = 0;
+        assert_eq!(find_band(&v, &mut c, 5), Some(0));
+        assert_eq!(find_band(&v, &mut c, 15), None);
+        assert_eq!(find_band(&v, &mut c, 25), Some(1));
+        assert_eq!(v[1].arm(), Some('q'));
+        assert_eq!(find_band(&v, &mut c, 30), None);
+    }
+}
@@ -0,0 +1,135 @@
+//! Timing of the main stages of the analysis (reading and parsing the input, GC window counting
+//! and kmer generation) to help choose thread counts and chunk sizes and to catch performance
+//! regressions
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+
+use crate::{
+    cli::{BenchConfig, Config},
+    fasta::{tar::open_fasta, FastaReader, Seq},
+    kmers::{KmerBuilder, KmerWork},
+    process,
+    reader::gap_policy,
+    synthetic::{write_genome, GenomeParams},
+};
+
+/// Synthetic input file, removed when dropped
+struct TempInput(PathBuf);
+
+impl Drop for TempInput {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.0) {
+            warn!("Could not remove {}: {e}", self.0.display())
+        }
+    }
+}
+
+fn make_synthetic(size: u64, seed: u64) -> anyhow::Result<TempInput> {
+    let path = std::env::temp_dir().join(format!("analyze_ref_gc_bench_{}.fa", std::process::id()));
+    info!(
+        "Generating synthetic genome of {size} bases in {}",
+        path.display()
+    );
+    let tmp = TempInput(path);
+    let mut wrt = BufWriter::new(
+        File::create(&tmp.0).with_context(|| format!("Could not create {}", tmp.0.display()))?,
+    );
+    let par = GenomeParams {
+        size,
+        seed,
+        ..Default::default()
+    };
+    write_genome(&mut wrt, None, &par)
+        .and_then(|_| wrt.flush())
+        .with_context(|| "Error writing synthetic genome")?;
+    Ok(tmp)
+}
+
+/// Call f for each sequence in the input, parsed in the current thread
+fn for_each_seq<F: FnMut(&Seq)>(cfg: &Config, mut f: F) -> anyhow::Result<()> {
+    let rdr = open_fasta(cfg.input())?;
+    for s in FastaReader::new(rdr, gap_policy(cfg)) {
+        f(&s.with_context(|| "Error reading input sequence")?)
+    }
+    Ok(())
+}
+
+fn time<F: FnOnce() -> anyhow::Result<()>>(f: F) -> anyhow::Result<Duration> {
+    let t = Instant::now();
+    f()?;
+    Ok(t.elapsed())
+}
+
+pub fn bench(bcfg: &mut BenchConfig) -> anyhow::Result<()> {
+    let _tmp = if bcfg.analysis().input().is_none() {
+        let tmp = make_synthetic(bcfg.synthetic_size(), bcfg.analysis().seed())?;
+        bcfg.set_input(tmp.0.clone());
+        Some(tmp)
+    } else {
+        None
+    };
+    let cfg = bcfg.analysis();
+    info!(
+        "Threads: {}, queue size: {}, chunk size: {}",
+        cfg.threads(),
+        cfg.queue_size(),
+        cfg.chunk_size()
+            .map(|c| c.to_string())
+            .unwrap_or_else(|| "none".to_owned())
+    );
+
+    // Reader only: read and parse the input in a single thread
+    info!("Running reader stage");
+    let mut bases = 0;
+    let t_reader = time(|| for_each_seq(cfg, |s| bases += s.len() as u64))?;
+
+    // GC only: the full analysis pipeline without target regions
+    info!("Running GC stage");
+    let t_gc = time(|| process::process(cfg).map(|_| ()))?;
+
+    // Kmer only: generation of kmers from the input (done by the reader thread when there are
+    // target regions), optionally adding them to the kmer table
+    info!("Running kmer stage");
+    let mut kmer_work = if bcfg.kmer_table() {
+        info!("Allocating kmer table");
+        Some(KmerWork::new())
+    } else {
+        None
+    };
+    let mut n_kmers = 0u64;
+    let t_kmer = time(|| {
+        let mut kb = KmerBuilder::new();
+        for_each_seq(cfg, |s| {
+            kb.clear();
+            for b in s.iter() {
+                kb.add_base(*b, None);
+                if let Some(k) = kb.kmers() {
+                    n_kmers += 1;
+                    if let Some(w) = kmer_work.as_mut() {
+                        w.add_kmer(k[0], None);
+                        w.add_kmer(k[1], None)
+                    }
+                }
+            }
+        })
+    })?;
+    debug!("Kmers generated: {n_kmers}");
+
+    let mut out = std::io::stdout().lock();
+    writeln!(out, "stage\tseconds\tbases\tMb_per_sec")?;
+    for (stage, t) in [("reader", t_reader), ("gc", t_gc), ("kmer", t_kmer)] {
+        let secs = t.as_secs_f64();
+        writeln!(
+            out,
+            "{stage}\t{secs:.3}\t{bases}\t{:.2}",
+            (bases as f64) * 1.0e-6 / secs
+        )?
+    }
+    Ok(())
+}
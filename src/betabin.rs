@@ -1,30 +1,234 @@
 use std::io::Write;
 
+use clap::{builder::PossibleValue, ValueEnum};
 use libm::lgamma;
 
-use crate::process::GcRes;
+use crate::{
+    dist_table::{DistFormat, DistTable},
+    process::GcRes,
+};
+
 pub fn lbeta(a: f64, b: f64) -> f64 {
     lgamma(a) + lgamma(b) - lgamma(a + b)
 }
 
 const BINS: usize = 1000;
 
-pub fn write_hist<W: Write>(
-    wrt: &mut W,
+/// Precision of the sums used when smoothing the GC distributions
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Precision {
+    /// Plain floating point sums
+    Standard,
+    /// Compensated (Neumaier) sums, for very long reads or very many windows
+    Extended,
+}
+
+impl ValueEnum for Precision {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Standard, Self::Extended]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(self.name()))
+    }
+}
+
+impl Precision {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Standard => "standard",
+            Self::Extended => "extended",
+        }
+    }
+}
+
+/// Method used to smooth the GC distributions
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Smoothing {
+    /// Spread each window over the bins with the beta distribution
+    Betabin,
+    /// Gaussian kernel on the GC fraction of each window
+    Kde,
+    /// Histogram of the GC fraction of each window
+    None,
+}
+
+impl ValueEnum for Smoothing {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Betabin, Self::Kde, Self::None]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(self.name()))
+    }
+}
+
+impl Smoothing {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Betabin => "betabin",
+            Self::Kde => "kde",
+            Self::None => "none",
+        }
+    }
+}
+
+/// Smoothing method with its parameters
+#[derive(Debug, Copy, Clone)]
+pub struct Smoother {
+    smoothing: Smoothing,
+    bandwidth: f64,
+    precision: Precision,
+}
+
+impl Default for Smoother {
+    /// Beta-binomial smoothing with standard precision
+    fn default() -> Self {
+        Self::new(Smoothing::Betabin, 0.01, Precision::Standard)
+    }
+}
+
+impl Smoother {
+    /// bandwidth (on the GC fraction scale) is only used for KDE smoothing
+    pub fn new(smoothing: Smoothing, bandwidth: f64, precision: Precision) -> Self {
+        Self {
+            smoothing,
+            bandwidth,
+            precision,
+        }
+    }
+
+    pub fn precision(&self) -> Precision {
+        self.precision
+    }
+
+    /// Unnormalized bin probabilities for a window with a GC and b AT bases, returning their sum
+    fn probs(&self, lnp: &[(f64, f64, f64)], a: f64, b: f64, out: &mut Vec<f64>) -> f64 {
+        match self.smoothing {
+            Smoothing::Betabin => bin_probs(lnp, a, b, self.precision, out),
+            Smoothing::Kde => {
+                let z = kde_probs(lnp, a / (a + b), self.bandwidth, self.precision, out);
+                if z > 0.0 {
+                    z
+                } else {
+                    point_probs(lnp.len(), a / (a + b), out)
+                }
+            }
+            Smoothing::None => point_probs(lnp.len(), a / (a + b), out),
+        }
+    }
+}
+
+/// Running sum, with a compensation term for extended precision
+#[derive(Debug, Default, Copy, Clone)]
+struct Sum {
+    s: f64,
+    c: f64,
+}
+
+impl Sum {
+    #[inline]
+    fn add(&mut self, x: f64, precision: Precision) {
+        if precision == Precision::Extended {
+            let t = self.s + x;
+            if self.s.abs() >= x.abs() {
+                self.c += (self.s - t) + x
+            } else {
+                self.c += (x - t) + self.s
+            }
+            self.s = t
+        } else {
+            self.s += x
+        }
+    }
+
+    fn value(&self) -> f64 {
+        self.s + self.c
+    }
+}
+
+/// Unnormalized beta(a + 1, b + 1) probabilities for each bin, returning their sum.  The log
+/// probabilities are scaled by their maximum before exponentiating (log-sum-exp), so that large
+/// a and b (long reads) do not underflow
+fn bin_probs(
+    lnp: &[(f64, f64, f64)],
+    a: f64,
+    b: f64,
+    precision: Precision,
+    out: &mut Vec<f64>,
+) -> f64 {
+    out.clear();
+    let mut m = f64::NEG_INFINITY;
+    for (_, lnp, lnp1) in lnp.iter() {
+        let lp = lnp * a + lnp1 * b;
+        m = m.max(lp);
+        out.push(lp)
+    }
+    let mut z = Sum::default();
+    for p in out.iter_mut() {
+        *p = (*p - m).exp();
+        z.add(*p, precision)
+    }
+    z.value()
+}
+
+/// Unnormalized Gaussian kernel (standard deviation h) centred on the GC fraction p for each bin,
+/// returning their sum.  Renormalizing over the bins corrects for the truncation at 0 and 1
+fn kde_probs(
+    lnp: &[(f64, f64, f64)],
+    p: f64,
+    h: f64,
+    precision: Precision,
+    out: &mut Vec<f64>,
+) -> f64 {
+    out.clear();
+    let mut z = Sum::default();
+    for (x, _, _) in lnp.iter() {
+        let d = (x - p) / h;
+        // Kernel is negligible beyond 8 standard deviations
+        let q = if d.abs() < 8.0 {
+            (-0.5 * d * d).exp()
+        } else {
+            0.0
+        };
+        z.add(q, precision);
+        out.push(q)
+    }
+    z.value()
+}
+
+/// All the weight in the bin containing the GC fraction p (no smoothing).  Windows with no
+/// informative bases (p is NaN) are given no weight
+fn point_probs(n: usize, p: f64, out: &mut Vec<f64>) -> f64 {
+    out.clear();
+    out.resize(n, 0.0);
+    if p.is_nan() {
+        return 0.0;
+    }
+    out[((p * n as f64) as usize).min(n - 1)] = 1.0;
+    1.0
+}
+
+/// Smoothed GC distributions for each read length (and optionally bisulfite converted reads and
+/// the read length mixture)
+pub fn gc_hist_table(
     read_len: &[u32],
+    weights: Option<&[f64]>,
     res: &GcRes,
     bisulfite: bool,
-) -> anyhow::Result<()> {
+    smoother: Smoother,
+) -> DistTable {
+    let precision = smoother.precision();
     let l = read_len.len();
 
     let l2 = if bisulfite { l * 2 } else { l };
 
     let mut hist: Vec<_> = (0..l2)
-        .map(|_| vec![0.0; BINS].into_boxed_slice())
+        .map(|_| vec![Sum::default(); BINS].into_boxed_slice())
         .collect();
     let mut lnp = Vec::with_capacity(BINS);
     let mut tmp = Vec::with_capacity(BINS);
-    let mut t = vec![0.0; l2];
+    let mut t = vec![Sum::default(); l2];
     let inc = 1.0 / (BINS as f64);
     for i in 0..BINS {
         let x = inc * (0.5 + (i as f64));
@@ -32,50 +236,124 @@ pub fn write_hist<W: Write>(
     }
     for (ix, h) in hist.iter_mut().enumerate() {
         let gc_hist = res.get_gc_hist(read_len[ix % l]).unwrap();
-        let hash = if ix < l {
-            gc_hist.hash()
+        let counts = if ix < l {
+            gc_hist.counts()
         } else {
-            gc_hist.bisulfite_hash().unwrap()
+            gc_hist.bisulfite_counts().unwrap()
         };
-        for (b, a, x) in hash.iter().map(|(ct, x)| {
+        for (b, a, x) in counts.iter().map(|(ct, x)| {
             let (r, s) = ct.counts();
-            (r as f64, s as f64, *x as f64)
+            (r as f64, s as f64, x)
         }) {
-            t[ix] += x;
-
-            let konst = lbeta(a + 1.0, b + 1.0);
-            tmp.clear();
-            let mut z = 0.0;
-            for (_, lnp, lnp1) in lnp.iter() {
-                let p = (lnp * a + lnp1 * b - konst).exp();
-                z += p;
-                tmp.push(p);
+            let z = smoother.probs(&lnp, a, b, &mut tmp);
+            if z <= 0.0 {
+                continue;
             }
+            t[ix].add(x, precision);
             for (p, q) in tmp.iter().zip(h.iter_mut()) {
-                *q += x * p / z
+                q.add(x * p / z, precision)
             }
         }
     }
     let scale = BINS as f64;
-    write!(wrt, "gc")?;
-    for l in read_len {
-        write!(wrt, "\tread_len:{}bp", l)?;
+    let gc: Vec<f64> = lnp.iter().map(|(x, _, _)| *x).collect();
+    let mut columns = Vec::with_capacity(l2 + 2);
+    let mut mix = [vec![0.0; BINS], vec![0.0; BINS]];
+    for (j, rl) in read_len.iter().enumerate() {
+        let w = weights.map(|w| w[j]).unwrap_or(0.0);
+        let mut add_col = |name: String, ix: usize, m: &mut [f64]| {
+            let t = t[ix].value();
+            let v: Vec<f64> = hist[ix].iter().map(|h| h.value() * scale / t).collect();
+            for (x, d) in m.iter_mut().zip(v.iter()) {
+                *x += w * d
+            }
+            columns.push((name, v))
+        };
+        add_col(format!("read_len:{rl}bp"), j, &mut mix[0]);
         if bisulfite {
-            write!(wrt, "\tbisulfite_read_len:{}bp", l)?
+            add_col(format!("bisulfite_read_len:{rl}bp"), j + l, &mut mix[1]);
         }
     }
-    writeln!(wrt)?;
-    for i in 0..BINS {
-        write!(wrt, "{}", lnp[i].0)?;
-        for j in 0..l {
-            let h = &hist[j];
-            write!(wrt, "\t{}", h[i] * scale / t[j])?;
-            if bisulfite {
-                let h = &hist[j + l];
-                write!(wrt, "\t{}", h[i] * scale / t[j + l])?;
-            }
+    if weights.is_some() {
+        let [m, bs_m] = mix;
+        columns.push(("mixture".to_owned(), m));
+        if bisulfite {
+            columns.push(("bisulfite_mixture".to_owned(), bs_m))
         }
-        writeln!(wrt)?
     }
-    Ok(())
+    DistTable::new(gc, columns)
+}
+
+pub fn write_hist<W: Write>(
+    wrt: &mut W,
+    read_len: &[u32],
+    weights: Option<&[f64]>,
+    res: &GcRes,
+    bisulfite: bool,
+    smoother: Smoother,
+    format: DistFormat,
+) -> anyhow::Result<()> {
+    gc_hist_table(read_len, weights, res, bisulfite, smoother).write(wrt, format)
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_bin_probs() {
+        let lnp: Vec<_> = (0..BINS)
+            .map(|i| {
+                let x = (0.5 + i as f64) / BINS as f64;
+                (x, x.ln(), (1.0 - x).ln())
+            })
+            .collect();
+        let mut v = Vec::new();
+        // Windows of 1 Mb would underflow without rescaling
+        for p in [Precision::Standard, Precision::Extended] {
+            let z = bin_probs(&lnp, 4.0e5, 6.0e5, p, &mut v);
+            assert!(z.is_finite() && z > 0.0);
+            let (i, _) = v
+                .iter()
+                .enumerate()
+                .max_by(|a, b| a.1.total_cmp(b.1))
+                .unwrap();
+            assert!(i == 399 || i == 400);
+        }
+        let mut s = Sum::default();
+        for x in [1.0, 1.0e100, 1.0, -1.0e100] {
+            s.add(x, Precision::Extended)
+        }
+        assert_eq!(s.value(), 2.0);
+    }
+
+    #[test]
+    fn test_smoothing() {
+        let lnp: Vec<_> = (0..BINS)
+            .map(|i| {
+                let x = (0.5 + i as f64) / BINS as f64;
+                (x, x.ln(), (1.0 - x).ln())
+            })
+            .collect();
+        let mut v = Vec::new();
+        let s = Smoother::new(Smoothing::None, 0.0, Precision::Standard);
+        assert_eq!(s.probs(&lnp, 30.0, 70.0, &mut v), 1.0);
+        assert_eq!(v[300], 1.0);
+        assert_eq!(s.probs(&lnp, 1.0, 0.0, &mut v), 1.0);
+        assert_eq!(v[BINS - 1], 1.0);
+        assert_eq!(s.probs(&lnp, 0.0, 0.0, &mut v), 0.0);
+        let s = Smoother::new(Smoothing::Kde, 0.01, Precision::Standard);
+        let z = s.probs(&lnp, 30.0, 70.0, &mut v);
+        let mean: f64 = v
+            .iter()
+            .zip(lnp.iter())
+            .map(|(p, (x, _, _))| p * x)
+            .sum::<f64>()
+            / z;
+        assert!((mean - 0.3).abs() < 1.0e-6);
+        assert_eq!(v[100], 0.0);
+        // Bandwidth much smaller than a bin
+        let s = Smoother::new(Smoothing::Kde, 1.0e-6, Precision::Standard);
+        assert_eq!(s.probs(&lnp, 30.1, 69.9, &mut v), 1.0);
+    }
 }
@@ -0,0 +1,107 @@
+//! Smoothed observed/expected GC bias curves
+use std::io::Write;
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+
+use crate::{cli::BiasConfig, dist_table::read_dist_table, loess::loess};
+
+/// Bins with an expected density below this are given zero weight
+const MIN_EXPECTED: f64 = 1.0e-8;
+
+/// Normal quantile for 95% confidence bands
+const Z95: f64 = 1.959964;
+
+struct BiasCurve {
+    name: String,
+    fit: Vec<f64>,
+    se: Option<Vec<f64>>,
+}
+
+pub fn bias(cfg: &BiasConfig) -> anyhow::Result<()> {
+    let expected = read_dist_table(cfg.expected())?;
+    let observed = read_dist_table(cfg.observed())?;
+    if expected.gc().len() != observed.gc().len() {
+        return Err(anyhow!(
+            "Expected and observed distribution files have different numbers of bins"
+        ));
+    }
+    let x = expected.gc();
+    let nb = x.len() as f64;
+
+    let mut curves = Vec::new();
+    for (name, o) in observed.columns() {
+        let Some(e) = expected.column(name) else {
+            debug!("Column {name} not found in expected distribution");
+            continue;
+        };
+        info!("Calculating bias curve for {name}");
+        let w: Vec<f64> = e
+            .iter()
+            .map(|e| if *e > MIN_EXPECTED { *e } else { 0.0 })
+            .collect();
+        let y: Vec<f64> = o
+            .iter()
+            .zip(w.iter())
+            .map(|(o, e)| if *e > 0.0 { o / e } else { 0.0 })
+            .collect();
+        // Binomial sampling variance of the ratio in each bin
+        let var: Vec<f64> = match cfg.n_reads() {
+            Some(n) => o
+                .iter()
+                .zip(w.iter())
+                .map(|(o, e)| {
+                    if *e > 0.0 {
+                        let p = (o / nb).clamp(0.0, 1.0);
+                        p * (1.0 - p) / (n as f64) * (nb / e).powi(2)
+                    } else {
+                        0.0
+                    }
+                })
+                .collect(),
+            None => vec![0.0; y.len()],
+        };
+        let (fit, fit_var) = loess(x, &y, &w, &var, cfg.span());
+        let se = cfg
+            .n_reads()
+            .map(|_| fit_var.iter().map(|v| v.sqrt()).collect());
+        curves.push(BiasCurve {
+            name: name.to_owned(),
+            fit,
+            se,
+        })
+    }
+    if curves.is_empty() {
+        return Err(anyhow!(
+            "No matching columns found between expected and observed distributions"
+        ));
+    }
+
+    let name = format!("{}_bias.txt", cfg.prefix());
+    let mut wrt = CompressIo::new()
+        .path(&name)
+        .bufwriter()
+        .with_context(|| format!("Could not open output bias file {name}"))?;
+
+    write!(wrt, "gc")?;
+    for c in curves.iter() {
+        write!(wrt, "\tbias_{}", c.name)?;
+        if c.se.is_some() {
+            write!(wrt, "\tlower_{}\tupper_{}", c.name, c.name)?
+        }
+    }
+    writeln!(wrt)?;
+    for (i, gc) in x.iter().enumerate() {
+        write!(wrt, "{gc}")?;
+        for c in curves.iter() {
+            let f = c.fit[i];
+            write!(wrt, "\t{f}")?;
+            if let Some(se) = c.se.as_ref() {
+                let d = Z95 * se[i];
+                write!(wrt, "\t{}\t{}", (f - d).max(0.0), f + d)?
+            }
+        }
+        writeln!(wrt)?
+    }
+    Ok(())
+}
@@ -0,0 +1,182 @@
+//! Bootstrap confidence bands for the GC distributions (--bootstrap)
+//!
+//! The genome is divided into blocks of a fixed size (or into contigs), and each block is assigned
+//! pseudo randomly (from a hash of the contig, block and seed) to one of [`BOOTSTRAP_GROUPS`]
+//! groups, with separate distributions collected for each group.  For each replicate the groups
+//! with windows are resampled with replacement, and the distribution is recomputed from the
+//! resampled groups weighted by their number of windows.  The bands are the 2.5% and 97.5%
+//! quantiles of the replicates for each bin.  Resampling groups rather than single blocks keeps
+//! the memory used bounded whatever the genome size, and as the assignment only depends on the
+//! block the result does not depend on the number of threads.
+use std::hash::{Hash, Hasher};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rustc_hash::FxHasher;
+
+use crate::{
+    betabin::{gc_hist_table, Smoother},
+    process::GcRes,
+};
+
+/// Number of groups that blocks are assigned to
+pub const BOOTSTRAP_GROUPS: usize = 32;
+
+/// Quantiles of the replicates giving the lower and upper bands
+const BAND_QUANTILES: [f64; 2] = [0.025, 0.975];
+
+/// Bootstrap settings
+#[derive(Debug, Copy, Clone)]
+pub struct BootstrapOpts {
+    replicates: usize,
+    block_size: Option<u64>,
+}
+
+impl BootstrapOpts {
+    /// If block_size is None, whole contigs are resampled
+    pub fn new(replicates: usize, block_size: Option<u64>) -> Self {
+        Self {
+            replicates,
+            block_size,
+        }
+    }
+
+    pub fn replicates(&self) -> usize {
+        self.replicates
+    }
+
+    pub fn block_size(&self) -> Option<u64> {
+        self.block_size
+    }
+}
+
+/// Group for a window with midpoint mid.  If block_size is None, whole contigs are assigned
+pub fn boot_group(seed: u64, block_size: Option<u64>, contig: &str, mid: u64) -> usize {
+    let mut h = FxHasher::default();
+    (seed, contig, block_size.map(|b| mid / b)).hash(&mut h);
+    // The high bits of FxHasher are the best mixed
+    (h.finish() >> 32) as usize % BOOTSTRAP_GROUPS
+}
+
+/// Total weight of the windows for each column of a table from gc_hist_table (without weights)
+fn column_totals(read_len: &[u32], res: &GcRes, bisulfite: bool) -> Vec<f64> {
+    let mut v = Vec::new();
+    for rl in read_len {
+        let h = res.get_gc_hist(*rl).unwrap();
+        v.push(h.counts().iter().map(|(_, x)| x).sum());
+        if bisulfite {
+            v.push(
+                h.bisulfite_counts()
+                    .map(|c| c.iter().map(|(_, x)| x).sum())
+                    .unwrap_or(0.0),
+            )
+        }
+    }
+    v
+}
+
+/// Value at quantile q of sorted values
+fn quantile(v: &[f64], q: f64) -> f64 {
+    v[((v.len() - 1) as f64 * q).round() as usize]
+}
+
+/// Lower and upper band columns for each column of the distribution table, named
+/// ``lower_<column>`` and ``upper_<column>``
+pub fn band_columns(
+    read_len: &[u32],
+    parts: &[GcRes],
+    bisulfite: bool,
+    smoother: Smoother,
+    replicates: usize,
+    seed: u64,
+) -> Vec<(String, Vec<f64>)> {
+    // (densities, totals) for each column of the groups with windows
+    let groups: Vec<_> = parts
+        .iter()
+        .map(|r| {
+            (
+                gc_hist_table(read_len, None, r, bisulfite, smoother),
+                column_totals(read_len, r, bisulfite),
+            )
+        })
+        .filter(|(_, t)| t.iter().any(|x| *x > 0.0))
+        .collect();
+    let Some((tab, _)) = groups.first() else {
+        return Vec::new();
+    };
+    let names: Vec<_> = tab.columns().map(|(s, _)| s.to_owned()).collect();
+    let bins = tab.gc().len();
+    let n = groups.len();
+
+    // Replicate densities for each column and bin
+    let mut reps = vec![vec![Vec::with_capacity(replicates); bins]; names.len()];
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut mult = vec![0.0; n];
+    let mut dens = vec![0.0; bins];
+    for _ in 0..replicates {
+        mult.iter_mut().for_each(|m| *m = 0.0);
+        for _ in 0..n {
+            mult[rng.gen_range(0..n)] += 1.0
+        }
+        for (ix, r) in reps.iter_mut().enumerate() {
+            dens.iter_mut().for_each(|d| *d = 0.0);
+            let mut z = 0.0;
+            for ((t, tot), m) in groups.iter().zip(&mult) {
+                let w = tot[ix] * m;
+                if w > 0.0 {
+                    let (_, v) = t.columns().nth(ix).unwrap();
+                    for (d, x) in dens.iter_mut().zip(v) {
+                        *d += w * x
+                    }
+                    z += w
+                }
+            }
+            if z > 0.0 {
+                for (r, d) in r.iter_mut().zip(&dens) {
+                    r.push(d / z)
+                }
+            }
+        }
+    }
+    let mut cols = Vec::with_capacity(names.len() * 2);
+    for (name, mut r) in names.iter().zip(reps) {
+        let mut bands = BAND_QUANTILES.map(|_| Vec::with_capacity(bins));
+        for v in r.iter_mut() {
+            v.sort_unstable_by(|a, b| a.total_cmp(b));
+            for (b, q) in bands.iter_mut().zip(BAND_QUANTILES) {
+                b.push(if v.is_empty() {
+                    f64::NAN
+                } else {
+                    quantile(v, q)
+                })
+            }
+        }
+        let [lower, upper] = bands;
+        cols.push((format!("lower_{name}"), lower));
+        cols.push((format!("upper_{name}"), upper))
+    }
+    cols
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_bootstrap() {
+        let a = boot_group(1, Some(1000), "chr1", 10);
+        assert_eq!(a, boot_group(1, Some(1000), "chr1", 999));
+        assert_eq!(
+            boot_group(1, None, "chr1", 10),
+            boot_group(1, None, "chr1", 1_000_000)
+        );
+        let mut n = [0; BOOTSTRAP_GROUPS];
+        for i in 0..3200 {
+            n[boot_group(1, Some(1000), "chr1", i * 1000)] += 1
+        }
+        assert!(n.iter().all(|x| *x > 50 && *x < 150));
+        let v = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(quantile(&v, 0.025), 1.0);
+        assert_eq!(quantile(&v, 0.975), 5.0);
+        assert_eq!(quantile(&v, 0.5), 3.0);
+    }
+}
@@ -0,0 +1,269 @@
+//! Per contig lengths and MD5 checksums computed from the input as it is read, with the layout
+//! information needed to write a samtools style index (.fai)
+//!
+//! Checksums follow the SAM specification for the M5 tag: the MD5 of the sequence converted to
+//! upper case with whitespace (including line endings) removed.
+
+use serde::Serialize;
+
+use crate::tap::StreamTap;
+
+/// Size of buffer of bases passed to the MD5 context
+const MD5_BUF: usize = 1 << 16;
+
+/// Length, checksum and (if the line lengths are consistent) index information for a contig
+#[derive(Debug, Clone, Serialize)]
+pub struct ContigSum {
+    name: Box<str>,
+    length: u64,
+    md5: String,
+    #[serde(skip)]
+    fai: Option<(u64, u64, u64)>,
+}
+
+impl ContigSum {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    pub fn md5(&self) -> &str {
+        &self.md5
+    }
+
+    /// (offset, line bases, line width) for .fai output, or None if the line lengths are not
+    /// consistent
+    pub fn fai(&self) -> Option<(u64, u64, u64)> {
+        self.fai
+    }
+}
+
+struct CurrentContig {
+    name: String,
+    md5: md5::Context,
+    buf: Vec<u8>,
+    length: u64,
+    offset: Option<u64>,
+    line_bases: u64,
+    line_width: u64,
+    short_line: bool,
+    fai_ok: bool,
+    cur_bases: u64,
+    cur_width: u64,
+}
+
+impl CurrentContig {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            md5: md5::Context::new(),
+            buf: Vec::with_capacity(MD5_BUF),
+            length: 0,
+            offset: None,
+            line_bases: 0,
+            line_width: 0,
+            short_line: false,
+            fai_ok: true,
+            cur_bases: 0,
+            cur_width: 0,
+        }
+    }
+
+    /// All lines apart from the last must have the same length
+    fn end_line(&mut self) {
+        let (b, w) = (self.cur_bases, self.cur_width);
+        if self.line_width == 0 {
+            (self.line_bases, self.line_width) = (b, w)
+        } else if self.short_line || b > self.line_bases {
+            self.fai_ok = false
+        } else if b < self.line_bases {
+            self.short_line = true
+        } else if w != self.line_width {
+            self.fai_ok = false
+        }
+        (self.cur_bases, self.cur_width) = (0, 0)
+    }
+
+    fn add_base(&mut self, c: u8) {
+        self.buf.push(c.to_ascii_uppercase());
+        if self.buf.len() >= MD5_BUF {
+            self.md5.consume(&self.buf);
+            self.buf.clear()
+        }
+    }
+
+    fn finish(mut self) -> ContigSum {
+        if self.cur_width > 0 {
+            self.end_line()
+        }
+        self.md5.consume(&self.buf);
+        let fai = match self.offset {
+            Some(off) if self.fai_ok && self.line_bases > 0 => {
+                Some((off, self.line_bases, self.line_width))
+            }
+            _ => None,
+        };
+        ContigSum {
+            name: self.name.into_boxed_str(),
+            length: self.length,
+            md5: format!("{:x}", self.md5.compute()),
+            fai,
+        }
+    }
+}
+
+/// Tracks the contigs in the input
+#[derive(Default)]
+pub struct ChecksumState {
+    pos: u64,
+    at_line_start: bool,
+    in_header: bool,
+    name_done: bool,
+    name: String,
+    current: Option<CurrentContig>,
+    contigs: Vec<ContigSum>,
+}
+
+impl ChecksumState {
+    pub fn new() -> Self {
+        Self {
+            at_line_start: true,
+            ..Default::default()
+        }
+    }
+
+    /// Finish the last contig and return the results for all contigs
+    pub fn finish(mut self) -> Vec<ContigSum> {
+        if let Some(ctg) = self.current.take() {
+            self.contigs.push(ctg.finish())
+        }
+        self.contigs
+    }
+}
+
+impl StreamTap for ChecksumState {
+    fn update(&mut self, buf: &[u8]) {
+        for c in buf.iter().copied() {
+            if self.in_header {
+                if c == b'\n' {
+                    self.in_header = false;
+                    self.at_line_start = true;
+                    self.current = Some(CurrentContig::new(std::mem::take(&mut self.name)))
+                } else if !self.name_done {
+                    if c.is_ascii_whitespace() {
+                        self.name_done = true
+                    } else {
+                        self.name.push(c as char)
+                    }
+                }
+            } else if self.at_line_start && c == b'>' {
+                if let Some(ctg) = self.current.take() {
+                    self.contigs.push(ctg.finish())
+                }
+                self.in_header = true;
+                self.name_done = false;
+            } else if let Some(ctg) = self.current.as_mut() {
+                if self.at_line_start && ctg.offset.is_none() {
+                    ctg.offset = Some(self.pos)
+                }
+                ctg.cur_width += 1;
+                if c == b'\n' {
+                    ctg.end_line();
+                    self.at_line_start = true
+                } else {
+                    self.at_line_start = false;
+                    if !c.is_ascii_whitespace() {
+                        ctg.cur_bases += 1;
+                        ctg.length += 1;
+                        ctg.add_base(c)
+                    }
+                }
+            }
+            self.pos += 1
+        }
+    }
+}
+
+/// MD5 digest and size of the whole (decompressed) input stream
+#[derive(Debug, Clone, Serialize)]
+pub struct InputDigest {
+    md5: String,
+    bytes: u64,
+}
+
+impl InputDigest {
+    pub fn md5(&self) -> &str {
+        &self.md5
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+}
+
+/// Digest of the raw input stream, taken before any renaming of duplicate records
+pub struct DigestState {
+    ctx: md5::Context,
+    bytes: u64,
+}
+
+impl Default for DigestState {
+    fn default() -> Self {
+        Self {
+            ctx: md5::Context::new(),
+            bytes: 0,
+        }
+    }
+}
+
+impl DigestState {
+    pub fn finish(self) -> InputDigest {
+        InputDigest {
+            md5: format!("{:x}", self.ctx.compute()),
+            bytes: self.bytes,
+        }
+    }
+}
+
+impl StreamTap for DigestState {
+    fn update(&mut self, buf: &[u8]) {
+        self.ctx.consume(buf);
+        self.bytes += buf.len() as u64
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use crate::tap::TapReader;
+    #[allow(unused_imports)]
+    use std::io::Read;
+
+    #[test]
+    fn test_checksums() {
+        let input = b">chr1 test\nACGTacgtNN\nACGTA\n>chr2\nAC\nACG\nA\n";
+        let mut state = ChecksumState::new();
+        let mut digest = DigestState::default();
+        let mut rdr = TapReader::new(&input[..], vec![&mut state, &mut digest]);
+        let mut s = String::new();
+        rdr.read_to_string(&mut s).unwrap();
+        let d = digest.finish();
+        assert_eq!(d.bytes(), input.len() as u64);
+        assert_eq!(d.md5(), format!("{:x}", md5::compute(input)));
+        let v = state.finish();
+        assert_eq!(v.len(), 2);
+        assert_eq!(v[0].name(), "chr1");
+        assert_eq!(v[0].length(), 15);
+        assert_eq!(
+            v[0].md5(),
+            format!("{:x}", md5::compute(b"ACGTACGTNNACGTA"))
+        );
+        assert_eq!(v[0].fai(), Some((11, 10, 11)));
+        assert_eq!(v[1].length(), 6);
+        assert_eq!(v[1].fai(), None);
+    }
+}
@@ -2,20 +2,144 @@ use std::path::{Path, PathBuf};
 
 use anyhow::Context;
 use chrono::{DateTime, Local};
+use clap::ArgMatches;
+use rustc_hash::FxHashSet;
 
+mod bands;
+mod bench;
+mod bias;
 mod cli_model;
+mod cohort;
+mod coverage;
+mod dup_rate;
+mod gc_pyramid;
+mod gc_track;
+mod generate;
+mod isochores;
+mod lint;
+mod observe;
+mod read_length_dist;
+mod saturation;
+mod simulate;
+mod unique_bed;
+mod update_kmers;
 
-use crate::regions::{read_bed::read_bed, Regions};
+pub use bands::BandsConfig;
+pub use bench::BenchConfig;
+pub use bias::BiasConfig;
+pub use cohort::CohortConfig;
+pub use coverage::CoverageConfig;
+pub use dup_rate::DupRateConfig;
+pub use gc_pyramid::GcPyramidConfig;
+pub use gc_track::GcTrackConfig;
+pub use generate::GenerateConfig;
+pub use isochores::IsochoreConfig;
+pub use lint::LintConfig;
+pub use observe::ObserveConfig;
+pub use saturation::SaturationConfig;
+pub use simulate::SimulateConfig;
+pub use unique_bed::UniqueBedConfig;
+pub use update_kmers::UpdateKmersConfig;
+
+use read_length_dist::{apply_trim, read_length_dist, trim_model};
+
+use crate::{
+    betabin::{Precision, Smoother, Smoothing},
+    bootstrap::BootstrapOpts,
+    contig_filter::{read_contig_list, ContigFilter},
+    contig_groups::{read_contig_groups, ContigGroups},
+    conversion::{Chemistry, Conversion},
+    dist_table::DistFormat,
+    dropout_risk::RiskWeights,
+    duplicates::DuplicatePolicy,
+    gtf::{read_gtf, Annotation},
+    kmcv::{KmerCompression, KmerWriterOpts},
+    kmer_text::KmerTextFilter,
+    memory::parse_mem_size,
+    output::OutputFormat,
+    plot_script::PlotScript,
+    process::{EndPolicy, Engine},
+    reads::infer_read_lengths,
+    regions::{
+        read_bed::read_bed,
+        span::{ContigSpan, Spans},
+        Regions,
+    },
+    repeat_scan::{MAX_MOTIF_LEN, TELOMERE_MOTIFS},
+    rmsk::{read_rmsk, RepeatMask},
+    shuffle::ShuffleMode,
+    warnings::add_warning,
+};
 
 pub struct Config {
     input: Option<PathBuf>,
     prefix: String,
     identifier: Option<String>,
     threads: usize,
+    pin_threads: bool,
+    max_mem: Option<u64>,
+    queue_size: Option<usize>,
+    chunk_size: Option<usize>,
+    flush_blocks: Option<usize>,
+    engine: Engine,
+    performance: bool,
     threshold: f64,
+    threshold_sweep: Option<Vec<f64>>,
     bisulfite: bool,
+    conversion: Conversion,
+    gembs: bool,
+    registry: Option<PathBuf>,
     read_lengths: Vec<u32>,
+    long_reads: bool,
+    read_length_weights: Option<Vec<f64>>,
     target: Option<Regions>,
+    spans: Option<Spans>,
+    sample_fraction: Option<f64>,
+    stride: u32,
+    end_policy: EndPolicy,
+    seed: u64,
+    null_model: Option<ShuffleMode>,
+    skew: bool,
+    per_base: bool,
+    n_content: bool,
+    dump_windows: Option<usize>,
+    cross_validate: Option<u64>,
+    bootstrap: Option<BootstrapOpts>,
+    checksums: bool,
+    input_digest: bool,
+    composition: bool,
+    dict: bool,
+    repeat_motifs: Option<Vec<String>>,
+    min_repeat_length: u64,
+    repeat_mask: Option<RepeatMask>,
+    annotation: Option<Annotation>,
+    contig_groups: Option<ContigGroups>,
+    contig_filter: Option<ContigFilter>,
+    kmer_contigs: Option<FxHashSet<String>>,
+    repeat_kmers: bool,
+    off_target_too: bool,
+    padding: Option<u64>,
+    kmer_gc: bool,
+    mappability: bool,
+    gatk_intervals: bool,
+    target_gc_length: bool,
+    shared_kmers: Option<f64>,
+    kmer_text: Option<KmerTextFilter>,
+    matched_controls: Option<usize>,
+    risk_weights: RiskWeights,
+    risk_threshold: f64,
+    on_duplicate: DuplicatePolicy,
+    strict: bool,
+    other_limit: Option<f64>,
+    log_contigs: bool,
+    kmer_writer: KmerWriterOpts,
+    output_format: OutputFormat,
+    dist_format: DistFormat,
+    precision: Precision,
+    smoothing: Smoothing,
+    kde_bandwidth: f64,
+    plot_script: Option<PlotScript>,
+    summary: bool,
     date: DateTime<Local>,
 }
 
@@ -28,14 +152,63 @@ impl Config {
         self.threads
     }
 
+    /// Limit on estimated memory use (bytes)
+    pub fn max_mem(&self) -> Option<u64> {
+        self.max_mem
+    }
+
+    /// Capacity of the queue of sequence blocks waiting for the process threads
+    pub fn queue_size(&self) -> usize {
+        self.queue_size.unwrap_or(self.threads * 4)
+    }
+
+    /// Maximum number of bases per block sent to the process threads
+    pub fn chunk_size(&self) -> Option<usize> {
+        self.chunk_size
+    }
+
+    /// Number of blocks after which process threads fold their results into the shared total
+    pub fn flush_blocks(&self) -> Option<usize> {
+        self.flush_blocks
+    }
+
+    pub fn engine(&self) -> Engine {
+        self.engine
+    }
+
+    /// Collect timings of the processing stages and threads
+    pub fn performance(&self) -> bool {
+        self.performance
+    }
+
+    /// Pin process threads to cores
+    pub fn pin_threads(&self) -> bool {
+        self.pin_threads
+    }
+
     pub fn read_lengths(&self) -> &[u32] {
         &self.read_lengths
     }
 
+    /// Collapse counts for read lengths over 1000 into bins of GC fraction
+    pub fn long_reads(&self) -> bool {
+        self.long_reads
+    }
+
+    /// Weights for read lengths (if present, same order as read_lengths() and normalized to sum to 1)
+    pub fn read_length_weights(&self) -> Option<&[f64]> {
+        self.read_length_weights.as_deref()
+    }
+
     pub fn threshold(&self) -> f64 {
         self.threshold
     }
 
+    /// Additional thresholds for which distributions are collected
+    pub fn threshold_sweep(&self) -> Option<&[f64]> {
+        self.threshold_sweep.as_deref()
+    }
+
     pub fn prefix(&self) -> &str {
         self.prefix.as_str()
     }
@@ -52,44 +225,473 @@ impl Config {
         self.bisulfite
     }
 
+    /// Model for incomplete conversion of bisulfite converted reads
+    pub fn conversion(&self) -> &Conversion {
+        &self.conversion
+    }
+
+    /// Also write GC model JSON for gemBS
+    pub fn gembs(&self) -> bool {
+        self.gembs
+    }
+
+    /// Directory of model registry
+    pub fn registry(&self) -> Option<&Path> {
+        self.registry.as_deref()
+    }
+
     pub fn target_regions(&self) -> Option<&Regions> {
         self.target.as_ref()
     }
+
+    /// Proportion of the input to analyze (blocks are selected at random)
+    pub fn sample_fraction(&self) -> Option<f64> {
+        self.sample_fraction
+    }
+
+    /// Only windows ending at multiples of stride are evaluated
+    pub fn stride(&self) -> u32 {
+        self.stride
+    }
+
+    /// Treatment of partial windows at the ends of sequences
+    pub fn end_policy(&self) -> EndPolicy {
+        self.end_policy
+    }
+
+    /// Seed for random number generation
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Also generate distributions for shuffled sequences
+    pub fn null_model(&self) -> Option<ShuffleMode> {
+        self.null_model
+    }
+
+    /// Collect base composition by position within windows
+    pub fn per_base(&self) -> bool {
+        self.per_base
+    }
+
+    /// Collect GC and AT skew distributions
+    pub fn skew(&self) -> bool {
+        self.skew
+    }
+
+    /// Collect statistics on the gap content of windows
+    pub fn n_content(&self) -> bool {
+        self.n_content
+    }
+
+    /// Number of evaluated windows to sample for output
+    pub fn dump_windows(&self) -> Option<usize> {
+        self.dump_windows
+    }
+
+    /// Block size for cross-validation split of the genome if requested
+    pub fn cross_validate(&self) -> Option<u64> {
+        self.cross_validate
+    }
+
+    /// Bootstrap settings for confidence bands on the distributions
+    pub fn bootstrap(&self) -> Option<BootstrapOpts> {
+        self.bootstrap
+    }
+
+    /// Compute contig lengths and MD5 checksums
+    pub fn checksums(&self) -> bool {
+        self.checksums
+    }
+
+    /// Compute MD5 digest of the raw (decompressed) input stream
+    pub fn input_digest(&self) -> bool {
+        self.input_digest
+    }
+
+    /// Summarize the composition of the input (and targets)
+    pub fn composition(&self) -> bool {
+        self.composition
+    }
+
+    /// Write sequence dictionary and index files
+    pub fn dict(&self) -> bool {
+        self.dict
+    }
+
+    /// Motifs for repeat scan (if requested)
+    pub fn repeat_motifs(&self) -> Option<&[String]> {
+        self.repeat_motifs.as_deref()
+    }
+
+    /// Minimum length of repeat runs to report
+    pub fn min_repeat_length(&self) -> u64 {
+        self.min_repeat_length
+    }
+
+    /// Also collect distributions for off target windows
+    pub fn off_target_too(&self) -> bool {
+        self.off_target_too
+    }
+
+    /// Also collect distributions for targets padded by this many bases on each side
+    pub fn padding(&self) -> Option<u64> {
+        self.padding
+    }
+
+    /// Off target bases are passed to the process threads, which split the sequence into on and
+    /// off target (or padded target) parts, rather than being masked by the reader
+    pub fn split_targets(&self) -> bool {
+        self.off_target_too || self.padding.is_some()
+    }
+
+    /// Tabulate kmer uniqueness by window GC (requires two more passes over the input)
+    pub fn kmer_gc(&self) -> bool {
+        self.kmer_gc
+    }
+
+    /// Write track of unique kmers overlapping each target position
+    pub fn mappability(&self) -> bool {
+        self.mappability
+    }
+
+    /// Write target GC content in the layout of GATK AnnotateIntervals
+    pub fn gatk_intervals(&self) -> bool {
+        self.gatk_intervals
+    }
+
+    /// Output 2D histogram of target length and GC content
+    pub fn target_gc_length(&self) -> bool {
+        self.target_gc_length
+    }
+
+    /// Report pairs of targets sharing at least this fraction of kmers
+    pub fn shared_kmers(&self) -> Option<f64> {
+        self.shared_kmers
+    }
+
+    /// Weights for the target dropout risk components
+    pub fn risk_weights(&self) -> &RiskWeights {
+        &self.risk_weights
+    }
+
+    /// Targets with at least this dropout risk are counted as high risk
+    pub fn risk_threshold(&self) -> f64 {
+        self.risk_threshold
+    }
+
+    /// Number of matched off target control regions to write
+    pub fn matched_controls(&self) -> Option<usize> {
+        self.matched_controls
+    }
+
+    /// Selection of kmers written as text
+    pub fn kmer_text(&self) -> Option<KmerTextFilter> {
+        self.kmer_text
+    }
+
+    /// How to handle input records with duplicate names
+    pub fn on_duplicate(&self) -> DuplicatePolicy {
+        self.on_duplicate
+    }
+
+    /// Records with no sequence are an error rather than a warning
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Maximum proportion of characters other than ACGTN in a record (None with --permissive)
+    pub fn other_limit(&self) -> Option<f64> {
+        self.other_limit
+    }
+
+    /// Log each contig as it is read
+    pub fn log_contigs(&self) -> bool {
+        self.log_contigs
+    }
+
+    /// Compression options for output kmer files
+    pub fn kmer_writer(&self) -> &KmerWriterOpts {
+        &self.kmer_writer
+    }
+
+    /// Precision used when smoothing the GC distributions
+    pub fn precision(&self) -> Precision {
+        self.precision
+    }
+
+    pub fn smoothing(&self) -> Smoothing {
+        self.smoothing
+    }
+
+    /// Bandwidth for KDE smoothing
+    pub fn kde_bandwidth(&self) -> f64 {
+        self.kde_bandwidth
+    }
+
+    /// Smoothing method, parameters and precision used for the GC distributions
+    pub fn smoother(&self) -> Smoother {
+        Smoother::new(self.smoothing, self.kde_bandwidth, self.precision)
+    }
+
+    /// Format of main results file
+    pub fn output_format(&self) -> OutputFormat {
+        self.output_format
+    }
+
+    /// Layout of distribution files
+    pub fn dist_format(&self) -> DistFormat {
+        self.dist_format
+    }
+
+    /// Also write a script to plot the distributions
+    pub fn plot_script(&self) -> Option<PlotScript> {
+        self.plot_script
+    }
+
+    /// Print a summary of the run to stderr at the end
+    pub fn summary(&self) -> bool {
+        self.summary
+    }
+
+    /// Repeat intervals from RepeatMasker file (if supplied)
+    pub fn repeat_mask(&self) -> Option<&RepeatMask> {
+        self.repeat_mask.as_ref()
+    }
+
+    /// Contigs excluded by length (if length filters were set)
+    pub fn contig_filter(&self) -> Option<&ContigFilter> {
+        self.contig_filter.as_ref()
+    }
+
+    /// Contigs from which kmers are recorded (all if not set)
+    pub fn kmer_contigs(&self) -> Option<&FxHashSet<String>> {
+        self.kmer_contigs.as_ref()
+    }
+
+    /// Assignment of contigs to groups for per group results
+    pub fn contig_groups(&self) -> Option<&ContigGroups> {
+        self.contig_groups.as_ref()
+    }
+
+    /// Gene annotation for stratifying windows by annotation class
+    pub fn annotation(&self) -> Option<&Annotation> {
+        self.annotation.as_ref()
+    }
+
+    /// Calculate kmer uniqueness for repeat partitions (requires two more passes over the input)
+    pub fn repeat_kmers(&self) -> bool {
+        self.repeat_kmers
+    }
+
+    /// Contig spans selected with --region (analysis is restricted to these if present)
+    pub fn spans(&self) -> Option<&Spans> {
+        self.spans.as_ref()
+    }
+}
+
+/// Task selected on the command line
+pub enum Task {
+    Analyze(Box<Config>),
+    Observe(ObserveConfig),
+    Bias(BiasConfig),
+    Simulate(SimulateConfig),
+    Coverage(CoverageConfig),
+    Bench(Box<BenchConfig>),
+    Generate(GenerateConfig),
+    Isochores(IsochoreConfig),
+    Bands(BandsConfig),
+    GcTrack(GcTrackConfig),
+    Lint(LintConfig),
+    DupRate(DupRateConfig),
+    UniqueBed(UniqueBedConfig),
+    UpdateKmers(UpdateKmersConfig),
+    Cohort(CohortConfig),
+    Saturation(SaturationConfig),
+    GcPyramid(GcPyramidConfig),
 }
 
-pub fn handle_cli() -> anyhow::Result<Config> {
+pub fn handle_cli() -> anyhow::Result<Task> {
     let c = cli_model::cli_model();
     let m = c.get_matches();
     super::utils::init_log(&m);
 
+    match m.subcommand() {
+        Some(("observe", m)) => Ok(Task::Observe(observe::observe_config(m)?)),
+        Some(("bias", m)) => Ok(Task::Bias(bias::bias_config(m)?)),
+        Some(("simulate", m)) => Ok(Task::Simulate(simulate::simulate_config(m)?)),
+        Some(("estimate-coverage", m)) => Ok(Task::Coverage(coverage::coverage_config(m)?)),
+        Some(("bench", m)) => Ok(Task::Bench(Box::new(bench::bench_config(m)?))),
+        Some(("generate", m)) => Ok(Task::Generate(generate::generate_config(m)?)),
+        Some(("isochores", m)) => Ok(Task::Isochores(isochores::isochore_config(m)?)),
+        Some(("bands", m)) => Ok(Task::Bands(bands::bands_config(m)?)),
+        Some(("gc-track", m)) => Ok(Task::GcTrack(gc_track::gc_track_config(m)?)),
+        Some(("lint", m)) => Ok(Task::Lint(lint::lint_config(m)?)),
+        Some(("dup-rate", m)) => Ok(Task::DupRate(dup_rate::dup_rate_config(m)?)),
+        Some(("unique-bed", m)) => Ok(Task::UniqueBed(unique_bed::unique_bed_config(m)?)),
+        Some(("update-kmers", m)) => Ok(Task::UpdateKmers(update_kmers::update_kmers_config(m)?)),
+        Some(("cohort-summary", m)) => Ok(Task::Cohort(cohort::cohort_config(m)?)),
+        Some(("saturation", m)) => Ok(Task::Saturation(saturation::saturation_config(m)?)),
+        Some(("gc-pyramid", m)) => Ok(Task::GcPyramid(gc_pyramid::gc_pyramid_config(m)?)),
+        _ => Ok(Task::Analyze(Box::new(analyze_config(&m)?))),
+    }
+}
+
+fn kmer_writer_opts(m: &ArgMatches) -> KmerWriterOpts {
+    KmerWriterOpts::new(
+        *m.get_one::<KmerCompression>("kmer_compression")
+            .expect("Missing default argument"),
+        m.get_one::<u64>("kmer_threads").map(|x| *x as usize),
+    )
+}
+
+/// Chunks must hold at least one window of each read length, so that the chunk loop advances
+fn check_chunk_size(chunk_size: Option<usize>, read_lengths: &[u32]) -> anyhow::Result<()> {
+    let max = read_lengths.iter().max().copied().unwrap_or(1).max(1);
+    match chunk_size {
+        Some(c) if c < max as usize => Err(anyhow!(
+            "Bad chunk size {c}: must be at least the largest read length ({max})"
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn get_threshold(m: &ArgMatches) -> anyhow::Result<f64> {
+    match m
+        .get_one::<f64>("threshold")
+        .expect("Missing default argument")
+    {
+        x if x > &0.0 && x <= &1.0 => Ok(*x),
+        _ => Err(anyhow!("Illegal threshold: must be > 0 and <= 1.0")),
+    }
+}
+
+fn get_threshold_sweep(m: &ArgMatches) -> anyhow::Result<Option<Vec<f64>>> {
+    m.get_many::<f64>("threshold_sweep")
+        .map(|v| {
+            v.map(|x| match x {
+                x if x > &0.0 && x <= &1.0 => Ok(*x),
+                _ => Err(anyhow!(
+                    "Illegal threshold {x} in sweep: must be > 0 and <= 1.0"
+                )),
+            })
+            .collect()
+        })
+        .transpose()
+}
+
+/// Motifs for repeat scan: the telomere motifs if --repeat-scan is given plus any from
+/// --repeat-motif
+fn get_repeat_motifs(m: &ArgMatches) -> anyhow::Result<Option<Vec<String>>> {
+    let mut v: Vec<String> = Vec::new();
+    if m.get_flag("repeat_scan") {
+        v.extend(TELOMERE_MOTIFS.iter().map(|s| s.to_string()))
+    }
+    if let Some(it) = m.get_many::<String>("repeat_motif") {
+        for s in it {
+            let s = s.to_ascii_uppercase();
+            if s.is_empty() || s.len() > MAX_MOTIF_LEN {
+                return Err(anyhow!(
+                    "Illegal repeat motif {s}: length must be between 1 and {MAX_MOTIF_LEN}"
+                ));
+            }
+            if !s.bytes().all(|c| matches!(c, b'A' | b'C' | b'G' | b'T')) {
+                return Err(anyhow!("Illegal repeat motif {s}: only ACGT allowed"));
+            }
+            if !v.contains(&s) {
+                v.push(s)
+            }
+        }
+    }
+    Ok((!v.is_empty()).then_some(v))
+}
+
+/// Configuration for the main analysis from command line arguments (the first being the program
+/// name), without initializing logging
+pub fn config_from_args<I, T>(args: I) -> anyhow::Result<Config>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<std::ffi::OsString> + Clone,
+{
+    let m = cli_model::cli_model().try_get_matches_from(args)?;
+    analyze_config(&m)
+}
+
+fn analyze_config(m: &ArgMatches) -> anyhow::Result<Config> {
     let input = m.get_one::<PathBuf>("input").map(|p| p.to_owned());
-    
+
     let target = match m.get_one::<PathBuf>("targets") {
-        Some(p) => Some(
-            read_bed(p)
-                .with_context(|| format!("Error reading target regions from {}", p.display()))?,
-        ),
+        Some(p) => Some(read_bed(
+            p,
+            m.get_one::<u64>("weight_column").map(|c| *c as usize),
+            m.get_flag("bed12_blocks"),
+        )?),
         None => None,
     };
 
+    let spans = m
+        .get_many::<String>("region")
+        .map(|v| {
+            v.map(|s| s.parse::<ContigSpan>())
+                .collect::<anyhow::Result<Spans>>()
+        })
+        .transpose()?;
+
+    let sample_fraction = match m.get_one::<f64>("sample_fraction") {
+        Some(x) if *x > 0.0 && *x <= 1.0 => Some(*x),
+        Some(_) => return Err(anyhow!("Illegal sample fraction: must be > 0 and <= 1.0")),
+        None => None,
+    };
+
+    let max_mem = m
+        .get_one::<String>("max_mem")
+        .map(|s| parse_mem_size(s))
+        .transpose()?;
+
+    let chunk_size = m
+        .get_one::<String>("chunk_size")
+        .map(|s| {
+            parse_mem_size(s)
+                .map(|x| x as usize)
+                .with_context(|| "Bad chunk size")
+        })
+        .transpose()?;
+
     let threads = m
         .get_one::<u64>("threads")
         .map(|x| *x as usize)
         .unwrap_or_else(num_cpus::get);
 
-    let read_lengths: Vec<u32> = m
-        .get_many("read_lengths")
-        .expect("Missing default argument")
-        .copied()
-        .collect();
+    let mut read_length_weights = None;
+    let read_lengths: Vec<u32> = if let Some(p) = m.get_one::<PathBuf>("infer_read_lengths") {
+        infer_read_lengths(p)
+            .with_context(|| format!("Error inferring read lengths from {}", p.display()))?
+    } else if let Some(p) = m.get_one::<PathBuf>("read_length_dist") {
+        let (rl, w) = read_length_dist(p).with_context(|| {
+            format!(
+                "Error reading read length distribution from {}",
+                p.display()
+            )
+        })?;
+        read_length_weights = Some(w);
+        rl
+    } else {
+        m.get_many("read_lengths")
+            .expect("Missing default argument")
+            .copied()
+            .collect()
+    };
+    let (read_lengths, read_length_weights) = if let Some(p) = m.get_one::<PathBuf>("trim") {
+        let (t, tw) = trim_model(p)
+            .with_context(|| format!("Error reading trimming model from {}", p.display()))?;
+        let (rl, w) = apply_trim(&read_lengths, read_length_weights.as_deref(), &t, &tw)?;
+        (rl, Some(w))
+    } else {
+        (read_lengths, read_length_weights)
+    };
+    check_chunk_size(chunk_size, &read_lengths)?;
 
-    let threshold = match m
-        .get_one::<f64>("threshold")
-        .expect("Missing default argument")
-    {
-        x if x > &0.0 && x <= &1.0 => Ok(*x),
-        _ => Err(anyhow!("Illegal threshold: must be > 0 and <= 1.0")),
-    }?;
+    let threshold = get_threshold(m)?;
 
     let prefix = m
         .get_one::<String>("prefix")
@@ -98,17 +700,282 @@ pub fn handle_cli() -> anyhow::Result<Config> {
 
     let identifier = m.get_one::<String>("identifier").map(|s| s.to_owned());
 
+    let smoothing = *m
+        .get_one::<Smoothing>("smoothing")
+        .expect("Missing default argument");
+    let kde_bandwidth = match m.get_one::<f64>("kde_bandwidth") {
+        None => 0.01,
+        Some(x) if *x > 0.0 && *x <= 1.0 => *x,
+        Some(_) => return Err(anyhow!("Illegal KDE bandwidth: must be > 0 and <= 1.0")),
+    };
+    if smoothing != Smoothing::Kde && m.contains_id("kde_bandwidth") {
+        warn!("--kde-bandwidth has no effect without --smoothing kde")
+    }
+
     let bisulfite = !m.get_flag("no_bisulfite");
+    let conversion_rate = match m.get_one::<f64>("conversion_rate") {
+        None => 1.0,
+        Some(x) if *x > 0.0 && *x <= 1.0 => *x,
+        Some(_) => return Err(anyhow!("Illegal conversion rate: must be > 0 and <= 1.0")),
+    };
+    let methylation = match m.get_one::<f64>("methylation_level") {
+        None => 0.0,
+        Some(x) if (0.0..=1.0).contains(x) => *x,
+        Some(_) => {
+            return Err(anyhow!(
+                "Illegal methylation level: must be >= 0 and <= 1.0"
+            ))
+        }
+    };
+    let conversion = Conversion::new(
+        *m.get_one::<Chemistry>("chemistry")
+            .expect("Missing default argument"),
+        conversion_rate,
+        methylation,
+        read_lengths.iter().copied().max().unwrap_or(0),
+    );
+
+    let repeat_motifs = get_repeat_motifs(m)?;
+
+    let repeat_mask = m
+        .get_one::<PathBuf>("repeatmasker")
+        .map(|p| {
+            read_rmsk(p).with_context(|| format!("Error reading repeats from {}", p.display()))
+        })
+        .transpose()?;
+    let annotation = m
+        .get_one::<PathBuf>("gtf")
+        .map(|p| {
+            read_gtf(p).with_context(|| format!("Error reading annotation from {}", p.display()))
+        })
+        .transpose()?;
+    let contig_groups = m
+        .get_one::<PathBuf>("contig_groups")
+        .map(read_contig_groups)
+        .transpose()?;
+    if contig_groups.is_some() && input.is_none() {
+        return Err(anyhow!(
+            "Kmer uniqueness by contig group needs an input file as the input is read again"
+        ));
+    }
+    let (min_len, max_len) = (
+        m.get_one::<u64>("min_contig_len").copied(),
+        m.get_one::<u64>("max_contig_len").copied(),
+    );
+    let contig_filter = if min_len.is_some() || max_len.is_some() {
+        let p = input.as_deref().ok_or_else(|| {
+            anyhow!("Contig length filters need an input file to find the contig lengths")
+        })?;
+        Some(ContigFilter::new(p, min_len, max_len)?)
+    } else {
+        None
+    };
+    let kmer_contigs = m
+        .get_one::<PathBuf>("kmer_contigs")
+        .map(read_contig_list)
+        .transpose()?;
+    if let (Some(set), Some(reg)) = (kmer_contigs.as_ref(), target.as_ref()) {
+        let missing: Vec<_> = reg
+            .iter()
+            .map(|(c, _)| c)
+            .filter(|c| !set.contains(*c))
+            .collect();
+        if !missing.is_empty() {
+            add_warning(
+                "target_contig_not_in_kmer_contigs",
+                format!(
+                    "Kmers not recorded for targets on contigs not in kmer contig list: {}",
+                    missing.join(", ")
+                ),
+            )
+        }
+    }
+    let repeat_kmers = repeat_mask.is_some() && !m.get_flag("no_repeat_kmers");
+    if repeat_kmers && input.is_none() {
+        return Err(anyhow!(
+            "Kmer uniqueness for repeats needs an input file as the input is read again (or use --no-repeat-kmers)"
+        ));
+    }
+    let kmer_gc = m.get_flag("kmer_gc");
+    if kmer_gc && input.is_none() {
+        return Err(anyhow!(
+            "Kmer uniqueness by GC needs an input file as the input is read again"
+        ));
+    }
+
+    let mappability = m.get_flag("mappability");
+    if mappability && input.is_none() {
+        return Err(anyhow!(
+            "Mappability track needs an input file as the input is read again"
+        ));
+    }
+
+    let matched_controls = m.get_one::<u64>("matched_controls").map(|x| *x as usize);
+    if matched_controls.is_some() && input.is_none() {
+        return Err(anyhow!(
+            "Matched control regions need an input file as the input is read again"
+        ));
+    }
+
+    let risk_weights = match m
+        .get_many::<f64>("risk_weights")
+        .expect("Missing default argument")
+        .collect::<Vec<_>>()[..]
+    {
+        [gc, red, n] => RiskWeights::new(*gc, *red, *n)?,
+        _ => return Err(anyhow!("Three risk weights must be given")),
+    };
+    let risk_threshold = *m
+        .get_one::<f64>("risk_threshold")
+        .expect("Missing default argument");
+    if !(0.0..=1.0).contains(&risk_threshold) {
+        return Err(anyhow!("Illegal risk threshold: must be between 0 and 1"));
+    }
+
+    let other_limit = match m.get_one::<f64>("max_other") {
+        _ if m.get_flag("permissive") => None,
+        None => Some(0.01),
+        Some(x) if (0.0..=1.0).contains(x) => Some(*x),
+        Some(_) => return Err(anyhow!("Illegal --max-other: must be between 0 and 1")),
+    };
+
+    let shared_kmers = m
+        .get_one::<f64>("shared_kmers")
+        .map(|x| {
+            if *x > 0.0 && *x <= 1.0 {
+                Ok(*x)
+            } else {
+                Err(anyhow!(
+                    "Illegal shared kmer fraction: must be > 0 and <= 1.0"
+                ))
+            }
+        })
+        .transpose()?;
+
+    let dist_format = *m
+        .get_one::<DistFormat>("dist_format")
+        .expect("Missing default argument");
+    let plot_script = m.get_one::<PlotScript>("plot_script").copied();
+    if dist_format == DistFormat::Long && plot_script == Some(PlotScript::Gnuplot) {
+        return Err(anyhow!(
+            "Gnuplot script can not be used with long format distribution files (use r)"
+        ));
+    }
+
+    let flush_blocks = m.get_one::<u64>("flush_blocks").map(|x| *x as usize);
+    let engine = *m
+        .get_one::<Engine>("engine")
+        .expect("Missing default argument");
+    if flush_blocks.is_some() && engine == Engine::Rayon {
+        warn!("--flush-blocks has no effect with the rayon engine")
+    }
 
     Ok(Config {
         input,
         prefix,
         identifier,
         threads,
+        pin_threads: m.get_flag("pin_threads"),
+        max_mem,
+        queue_size: m.get_one::<u64>("queue_size").map(|x| *x as usize),
+        chunk_size,
+        flush_blocks,
+        engine,
+        performance: m.get_flag("performance"),
         bisulfite,
+        conversion,
+        gembs: m.get_flag("gembs"),
+        registry: m.get_one::<PathBuf>("registry").map(|p| p.to_owned()),
         threshold,
+        threshold_sweep: get_threshold_sweep(m)?,
         read_lengths,
+        long_reads: m.get_flag("long_reads"),
+        read_length_weights,
         target,
+        spans,
+        sample_fraction,
+        stride: *m
+            .get_one::<u32>("stride")
+            .expect("Missing default argument"),
+        end_policy: *m
+            .get_one::<EndPolicy>("end_policy")
+            .expect("Missing default argument"),
+        seed: *m.get_one::<u64>("seed").expect("Missing default argument"),
+        null_model: m.get_one::<ShuffleMode>("null_model").copied(),
+        skew: m.get_flag("skew"),
+        per_base: m.get_flag("per_base"),
+        n_content: m.get_flag("n_content"),
+        dump_windows: m.get_one::<u64>("dump_windows").map(|x| *x as usize),
+        cross_validate: m.get_flag("cross_validate").then(|| {
+            *m.get_one::<u64>("cv_block_size")
+                .expect("Missing default argument")
+        }),
+        bootstrap: m.get_one::<u64>("bootstrap").map(|n| {
+            let block_size = if m.get_flag("bootstrap_by_contig") {
+                None
+            } else {
+                m.get_one::<u64>("bootstrap_block_size").copied()
+            };
+            BootstrapOpts::new(*n as usize, block_size)
+        }),
+        checksums: m.get_flag("checksums") || m.get_flag("dict"),
+        input_digest: m.get_flag("input_digest"),
+        composition: m.get_flag("composition"),
+        dict: m.get_flag("dict"),
+        repeat_motifs,
+        min_repeat_length: *m
+            .get_one::<u64>("min_repeat_length")
+            .expect("Missing default argument"),
+        repeat_mask,
+        annotation,
+        contig_groups,
+        contig_filter,
+        kmer_contigs,
+        repeat_kmers,
+        off_target_too: m.get_flag("off_target_too"),
+        padding: m.get_one::<u64>("padding").copied(),
+        kmer_gc,
+        mappability,
+        gatk_intervals: m.get_flag("gatk_intervals"),
+        target_gc_length: m.get_flag("target_gc_length"),
+        shared_kmers,
+        matched_controls,
+        risk_weights,
+        risk_threshold,
+        kmer_text: m.get_one::<KmerTextFilter>("kmer_text").copied(),
+        on_duplicate: *m
+            .get_one::<DuplicatePolicy>("on_duplicate")
+            .expect("Missing default argument"),
+        strict: m.get_flag("strict"),
+        other_limit,
+        log_contigs: m.get_flag("log_contigs"),
+        kmer_writer: kmer_writer_opts(m),
+        output_format: *m
+            .get_one::<OutputFormat>("output_format")
+            .expect("Missing default argument"),
+        dist_format,
+        precision: *m
+            .get_one::<Precision>("precision")
+            .expect("Missing default argument"),
+        smoothing,
+        kde_bandwidth,
+        plot_script,
+        summary: !(m.get_flag("no_summary") || m.get_flag("quiet")),
         date: Local::now(),
     })
 }
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_chunk_size() {
+        let cfg =
+            |c: &str| config_from_args(["analyze_ref_gc", "-r", "20", "50", "--chunk-size", c]);
+        assert!(cfg("0.5").is_err());
+        assert!(cfg("49").is_err());
+        assert_eq!(cfg("50").unwrap().chunk_size(), Some(50));
+        assert_eq!(cfg("1K").unwrap().chunk_size(), Some(1024));
+    }
+}
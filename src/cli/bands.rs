@@ -0,0 +1,52 @@
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+
+pub struct BandsConfig {
+    input: Option<PathBuf>,
+    cytobands: PathBuf,
+    prefix: String,
+    kmers: bool,
+}
+
+impl BandsConfig {
+    pub fn input(&self) -> Option<&Path> {
+        self.input.as_deref()
+    }
+
+    pub fn cytobands(&self) -> &Path {
+        &self.cytobands
+    }
+
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_str()
+    }
+
+    /// Calculate kmer uniqueness (requires a second pass over the input)
+    pub fn kmers(&self) -> bool {
+        self.kmers
+    }
+}
+
+pub(super) fn bands_config(m: &ArgMatches) -> anyhow::Result<BandsConfig> {
+    let input = m.get_one::<PathBuf>("input").map(|p| p.to_owned());
+    let kmers = !m.get_flag("no_kmers");
+    if kmers && input.is_none() {
+        return Err(anyhow!(
+            "Kmer uniqueness needs an input file as the input is read twice (or use --no-kmers)"
+        ));
+    }
+
+    Ok(BandsConfig {
+        input,
+        cytobands: m
+            .get_one::<PathBuf>("cytobands")
+            .map(|p| p.to_owned())
+            .expect("Missing required argument"),
+        prefix: m
+            .get_one::<String>("prefix")
+            .map(|s| s.to_owned())
+            .expect("Missing default argument"),
+        kmers,
+    })
+}
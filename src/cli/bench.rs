@@ -0,0 +1,139 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use chrono::Local;
+use clap::ArgMatches;
+
+use super::Config;
+use crate::{
+    betabin::{Precision, Smoothing},
+    conversion::{Chemistry, Conversion},
+    dist_table::DistFormat,
+    dropout_risk::RiskWeights,
+    duplicates::DuplicatePolicy,
+    kmcv::KmerWriterOpts,
+    memory::parse_mem_size,
+    output::OutputFormat,
+    process::{EndPolicy, Engine},
+};
+
+pub struct BenchConfig {
+    cfg: Config,
+    synthetic_size: u64,
+    kmer_table: bool,
+}
+
+impl BenchConfig {
+    /// Configuration used for the GC stage
+    pub fn analysis(&self) -> &Config {
+        &self.cfg
+    }
+
+    /// Size of the synthetic genome generated when no input is given
+    pub fn synthetic_size(&self) -> u64 {
+        self.synthetic_size
+    }
+
+    /// Also populate the kmer table in the kmer stage
+    pub fn kmer_table(&self) -> bool {
+        self.kmer_table
+    }
+
+    pub(crate) fn set_input(&mut self, input: PathBuf) {
+        self.cfg.input = Some(input)
+    }
+}
+
+pub(super) fn bench_config(m: &ArgMatches) -> anyhow::Result<BenchConfig> {
+    let get_size = |s: &str| {
+        m.get_one::<String>(s)
+            .map(|x| parse_mem_size(x).with_context(|| format!("Bad value for {s}")))
+            .transpose()
+    };
+
+    let cfg = Config {
+        input: m.get_one::<PathBuf>("input").map(|p| p.to_owned()),
+        prefix: String::new(),
+        identifier: None,
+        threads: m
+            .get_one::<u64>("threads")
+            .map(|x| *x as usize)
+            .unwrap_or_else(num_cpus::get),
+        pin_threads: false,
+        max_mem: None,
+        queue_size: m.get_one::<u64>("queue_size").map(|x| *x as usize),
+        chunk_size: get_size("chunk_size")?.map(|x| x as usize),
+        flush_blocks: None,
+        engine: Engine::Channel,
+        performance: false,
+        threshold: super::get_threshold(m)?,
+        threshold_sweep: None,
+        bisulfite: !m.get_flag("no_bisulfite"),
+        conversion: Conversion::new(Chemistry::Bisulfite, 1.0, 0.0, 0),
+        gembs: false,
+        registry: None,
+        read_lengths: m
+            .get_many("read_lengths")
+            .expect("Missing default argument")
+            .copied()
+            .collect(),
+        long_reads: false,
+        read_length_weights: None,
+        target: None,
+        spans: None,
+        sample_fraction: None,
+        stride: 1,
+        end_policy: EndPolicy::Threshold,
+        seed: *m.get_one::<u64>("seed").expect("Missing default argument"),
+        null_model: None,
+        skew: false,
+        per_base: false,
+        n_content: false,
+        dump_windows: None,
+        cross_validate: None,
+        bootstrap: None,
+        checksums: false,
+        input_digest: false,
+        composition: false,
+        dict: false,
+        repeat_motifs: None,
+        min_repeat_length: 36,
+        repeat_mask: None,
+        annotation: None,
+        contig_groups: None,
+        contig_filter: None,
+        kmer_contigs: None,
+        repeat_kmers: false,
+        off_target_too: false,
+        padding: None,
+        kmer_gc: false,
+        mappability: false,
+        gatk_intervals: false,
+        target_gc_length: false,
+        shared_kmers: None,
+        kmer_text: None,
+        matched_controls: None,
+        risk_weights: RiskWeights::new(1.0, 1.0, 1.0)?,
+        risk_threshold: 0.5,
+        on_duplicate: DuplicatePolicy::Error,
+        strict: false,
+        other_limit: None,
+        log_contigs: false,
+        kmer_writer: KmerWriterOpts::default(),
+        output_format: OutputFormat::Json,
+        dist_format: DistFormat::Wide,
+        precision: Precision::Standard,
+        smoothing: Smoothing::Betabin,
+        kde_bandwidth: 0.01,
+        plot_script: None,
+        summary: false,
+        date: Local::now(),
+    };
+    super::check_chunk_size(cfg.chunk_size, &cfg.read_lengths)?;
+
+    Ok(BenchConfig {
+        cfg,
+        synthetic_size: get_size("synthetic_size")?.expect("Missing default argument"),
+        kmer_table: m.get_flag("kmer_table"),
+    })
+}
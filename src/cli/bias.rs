@@ -0,0 +1,65 @@
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+
+pub struct BiasConfig {
+    expected: PathBuf,
+    observed: PathBuf,
+    prefix: String,
+    span: f64,
+    n_reads: Option<u64>,
+}
+
+impl BiasConfig {
+    pub fn expected(&self) -> &Path {
+        &self.expected
+    }
+
+    pub fn observed(&self) -> &Path {
+        &self.observed
+    }
+
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_str()
+    }
+
+    /// Proportion of points used for each local regression
+    pub fn span(&self) -> f64 {
+        self.span
+    }
+
+    /// Number of reads in observed distribution (used for confidence bands)
+    pub fn n_reads(&self) -> Option<u64> {
+        self.n_reads
+    }
+}
+
+pub(super) fn bias_config(m: &ArgMatches) -> anyhow::Result<BiasConfig> {
+    let expected = m
+        .get_one::<PathBuf>("expected")
+        .map(|p| p.to_owned())
+        .expect("Missing required argument");
+
+    let observed = m
+        .get_one::<PathBuf>("observed")
+        .map(|p| p.to_owned())
+        .expect("Missing required argument");
+
+    let prefix = m
+        .get_one::<String>("prefix")
+        .map(|s| s.to_owned())
+        .expect("Missing default argument");
+
+    let span = match m.get_one::<f64>("span").expect("Missing default argument") {
+        x if x > &0.0 && x <= &1.0 => Ok(*x),
+        _ => Err(anyhow!("Illegal span: must be > 0 and <= 1.0")),
+    }?;
+
+    Ok(BiasConfig {
+        expected,
+        observed,
+        prefix,
+        span,
+        n_reads: m.get_one::<u64>("n_reads").copied(),
+    })
+}
@@ -2,12 +2,42 @@ use std::path::PathBuf;
 
 use clap::{command, value_parser, Arg, ArgAction, Command};
 
-use crate::utils::LogLevel;
+use crate::{
+    betabin::{Precision, Smoothing},
+    conversion::Chemistry,
+    dist_table::DistFormat,
+    duplicates::DuplicatePolicy,
+    kmcv::KmerCompression,
+    kmer_text::KmerTextFilter,
+    output::OutputFormat,
+    plot_script::PlotScript,
+    process::{EndPolicy, Engine},
+    shuffle::ShuffleMode,
+    utils::LogLevel,
+};
 
 pub(super) fn cli_model() -> Command {
     command!()
+        .args_conflicts_with_subcommands(true)
+        .subcommand(observe_model())
+        .subcommand(bias_model())
+        .subcommand(simulate_model())
+        .subcommand(coverage_model())
+        .subcommand(bench_model())
+        .subcommand(generate_model())
+        .subcommand(isochores_model())
+        .subcommand(bands_model())
+        .subcommand(gc_track_model())
+        .subcommand(lint_model())
+        .subcommand(dup_rate_model())
+        .subcommand(unique_bed_model())
+        .subcommand(update_kmers_model())
+        .subcommand(cohort_model())
+        .subcommand(saturation_model())
+        .subcommand(gc_pyramid_model())
         .arg(
             Arg::new("timestamp")
+                .global(true)
                 .short('X')
                 .long("timestamp")
                 .value_parser(value_parser!(stderrlog::Timestamp))
@@ -17,6 +47,7 @@ pub(super) fn cli_model() -> Command {
         )
         .arg(
             Arg::new("loglevel")
+                .global(true)
                 .short('l')
                 .long("loglevel")
                 .value_name("LOGLEVEL")
@@ -27,6 +58,7 @@ pub(super) fn cli_model() -> Command {
         )
         .arg(
             Arg::new("quiet")
+                .global(true)
                 .action(ArgAction::SetTrue)
                 .long("quiet")
                 .conflicts_with("loglevel")
@@ -40,6 +72,54 @@ pub(super) fn cli_model() -> Command {
                 .value_name("INT")
                 .help("Set number of process threads [default: number of available cores]"),
         )
+        .arg(
+            Arg::new("pin_threads")
+                .action(ArgAction::SetTrue)
+                .long("pin-threads")
+                .help("Pin process threads to cores"),
+        )
+        .arg(
+            Arg::new("max_mem")
+                .long("max-mem")
+                .value_parser(value_parser!(String))
+                .value_name("SIZE")
+                .help("Abort if estimated memory use exceeds SIZE (e.g. 16G) [default: available memory]"),
+        )
+        .arg(
+            Arg::new("queue_size")
+                .long("queue-size")
+                .value_parser(value_parser!(u64).range(1..))
+                .value_name("INT")
+                .help("Number of sequence blocks queued for the process threads [default: 4 x threads]"),
+        )
+        .arg(
+            Arg::new("chunk_size")
+                .long("chunk-size")
+                .value_parser(value_parser!(String))
+                .value_name("SIZE")
+                .help("Split sequences into chunks of SIZE bases (e.g. 10M) for processing [default: no splitting]"),
+        )
+        .arg(
+            Arg::new("flush_blocks")
+                .long("flush-blocks")
+                .value_parser(value_parser!(u64).range(1..))
+                .value_name("INT")
+                .help("Fold per thread results into the shared total every INT sequence blocks"),
+        )
+        .arg(
+            Arg::new("performance")
+                .action(ArgAction::SetTrue)
+                .long("performance")
+                .help("Include stage and thread timings in the JSON output"),
+        )
+        .arg(
+            Arg::new("engine")
+                .long("engine")
+                .value_parser(value_parser!(Engine))
+                .value_name("ENGINE")
+                .default_value("channel")
+                .help("Execution engine for the process threads"),
+        )
         .arg(
             Arg::new("threshold")
                 .short('T')
@@ -49,12 +129,168 @@ pub(super) fn cli_model() -> Command {
                 .default_value("0.8")
                 .help("Set threshold (0 > x <= 1) for proportion of bases required"),
         )
+        .arg(
+            Arg::new("threshold_sweep")
+                .long("threshold-sweep")
+                .value_parser(value_parser!(f64))
+                .value_delimiter(',')
+                .value_name("PROPORTIONS")
+                .help("Also collect distributions for each threshold in comma separated list"),
+        )
         .arg(
             Arg::new("no_bisulfite")
                 .action(ArgAction::SetTrue)
                 .long("no-bisulfite")
                 .help("Do not generate distributions for bisulfite converted sequences"),
         )
+        .arg(
+            Arg::new("chemistry")
+                .long("chemistry")
+                .value_parser(value_parser!(Chemistry))
+                .value_name("CHEMISTRY")
+                .default_value("bisulfite")
+                .conflicts_with("no_bisulfite")
+                .help("Conversion chemistry for converted distributions"),
+        )
+        .arg(
+            Arg::new("conversion_rate")
+                .long("conversion-rate")
+                .value_parser(value_parser!(f64))
+                .value_name("RATE")
+                .conflicts_with("no_bisulfite")
+                .help("Bisulfite conversion rate (0 < x <= 1) [default: 1]"),
+        )
+        .arg(
+            Arg::new("methylation_level")
+                .long("methylation-level")
+                .value_parser(value_parser!(f64))
+                .value_name("LEVEL")
+                .conflicts_with("no_bisulfite")
+                .help("Proportion (0 <= x <= 1) of CpG cytosines protected from conversion [default: 0]"),
+        )
+        .arg(
+            Arg::new("gembs")
+                .action(ArgAction::SetTrue)
+                .long("gembs")
+                .help("Also write GC model JSON file for gemBS"),
+        )
+        .arg(
+            Arg::new("registry")
+                .long("registry")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("DIR")
+                .help("Also write GC models to registry in DIR (as <DIR>/<reference>/<read length>.json)"),
+        )
+        .arg(
+            Arg::new("output_format")
+                .long("output-format")
+                .value_parser(value_parser!(OutputFormat))
+                .value_name("FORMAT")
+                .default_value("json")
+                .help("Format of main results file (json or msgpack)"),
+        )
+        .arg(
+            Arg::new("dist_format")
+                .long("dist-format")
+                .value_parser(value_parser!(DistFormat))
+                .value_name("FORMAT")
+                .default_value("wide")
+                .help("Layout of distribution files (wide or long)"),
+        )
+        .arg(
+            Arg::new("precision")
+                .long("precision")
+                .value_parser(value_parser!(Precision))
+                .value_name("PRECISION")
+                .default_value("standard")
+                .help("Precision of sums used when smoothing the GC distributions"),
+        )
+        .arg(
+            Arg::new("smoothing")
+                .long("smoothing")
+                .value_parser(value_parser!(Smoothing))
+                .value_name("METHOD")
+                .default_value("betabin")
+                .help("Method used to smooth the GC distributions"),
+        )
+        .arg(
+            Arg::new("kde_bandwidth")
+                .long("kde-bandwidth")
+                .value_parser(value_parser!(f64))
+                .value_name("BANDWIDTH")
+                .help("Bandwidth (on GC fraction scale) for KDE smoothing (0 < x <= 1) [default: 0.01]"),
+        )
+        .arg(
+            Arg::new("plot_script")
+                .long("plot-script")
+                .value_parser(value_parser!(PlotScript))
+                .value_name("TYPE")
+                .help("Also write a script to plot the distributions (gnuplot or r)"),
+        )
+        .arg(
+            Arg::new("no_summary")
+                .action(ArgAction::SetTrue)
+                .long("no-summary")
+                .help("Do not print a summary of the run to stderr"),
+        )
+        .arg(
+            Arg::new("checksums")
+                .action(ArgAction::SetTrue)
+                .long("checksums")
+                .help("Add contig lengths and MD5 checksums to JSON output"),
+        )
+        .arg(
+            Arg::new("input_digest")
+                .action(ArgAction::SetTrue)
+                .long("input-digest")
+                .help("Add MD5 digest of the input stream to JSON and kmer file provenance"),
+        )
+        .arg(
+            Arg::new("composition")
+                .action(ArgAction::SetTrue)
+                .long("composition")
+                .help("Add base, dinucleotide and trinucleotide composition summary to JSON output"),
+        )
+        .arg(
+            Arg::new("dict")
+                .action(ArgAction::SetTrue)
+                .long("dict")
+                .help("Also write sequence dictionary (.dict) and index (.fai) files"),
+        )
+        .arg(
+            Arg::new("log_contigs")
+                .action(ArgAction::SetTrue)
+                .long("log-contigs")
+                .help("Log length, N content, elapsed time and throughput as each contig is read"),
+        )
+        .arg(
+            Arg::new("strict")
+                .action(ArgAction::SetTrue)
+                .long("strict")
+                .help("Stop with an error if the input has records with no sequence"),
+        )
+        .arg(
+            Arg::new("max_other")
+                .long("max-other")
+                .value_parser(value_parser!(f64))
+                .value_name("PROPORTION")
+                .conflicts_with("permissive")
+                .help("Stop with an error if the proportion of characters other than ACGTN in a record is above this [default: 0.01]"),
+        )
+        .arg(
+            Arg::new("permissive")
+                .action(ArgAction::SetTrue)
+                .long("permissive")
+                .help("Treat characters other than ACGTN as gaps whatever their proportion"),
+        )
+        .arg(
+            Arg::new("on_duplicate")
+                .long("on-duplicate")
+                .value_parser(value_parser!(DuplicatePolicy))
+                .value_name("POLICY")
+                .default_value("error")
+                .help("Handling of input records with duplicate names"),
+        )
         .arg(
             Arg::new("prefix")
                 .short('p')
@@ -80,6 +316,303 @@ pub(super) fn cli_model() -> Command {
                 .value_name("TARGET BED")
                 .help("BED file with target regions (for targetted sequencing)"),
         )
+        .arg(
+            Arg::new("weight_column")
+                .long("weight-column")
+                .value_parser(value_parser!(u64).range(4..))
+                .value_name("COLUMN")
+                .requires("targets")
+                .help("Column of target BED file with weights for region contributions"),
+        )
+        .arg(
+            Arg::new("bed12_blocks")
+                .action(ArgAction::SetTrue)
+                .long("bed12-blocks")
+                .requires("targets")
+                .help("Use blocks (exons) from BED12 target file rather than whole regions"),
+        )
+        .arg(
+            Arg::new("off_target_too")
+                .action(ArgAction::SetTrue)
+                .long("off-target-too")
+                .requires("targets")
+                .help("Also generate distributions for off target windows"),
+        )
+        .arg(
+            Arg::new("padding")
+                .long("padding")
+                .value_parser(value_parser!(u64).range(1..))
+                .value_name("INT")
+                .requires("targets")
+                .help("Also generate distributions for targets padded by INT bases on each side"),
+        )
+        .arg(
+            Arg::new("kmer_compression")
+                .long("kmer-compression")
+                .value_parser(value_parser!(KmerCompression))
+                .value_name("CODEC")
+                .default_value("zstd")
+                .help("Compression for output kmer files"),
+        )
+        .arg(
+            Arg::new("kmer_threads")
+                .long("kmer-threads")
+                .value_parser(value_parser!(u64).range(1..))
+                .value_name("INT")
+                .help("Threads used to compress kmer files [default: number of physical cores]"),
+        )
+        .arg(
+            Arg::new("mappability")
+                .action(ArgAction::SetTrue)
+                .long("mappability")
+                .requires("targets")
+                .help("Write bedGraph of unique kmers overlapping each target position"),
+        )
+        .arg(
+            Arg::new("gatk_intervals")
+                .action(ArgAction::SetTrue)
+                .long("gatk-intervals")
+                .requires("targets")
+                .help("Write target GC content as GATK annotated intervals file"),
+        )
+        .arg(
+            Arg::new("target_gc_length")
+                .action(ArgAction::SetTrue)
+                .long("target-gc-length")
+                .requires("targets")
+                .help("Write joint distribution of target length and GC content"),
+        )
+        .arg(
+            Arg::new("matched_controls")
+                .long("matched-controls")
+                .value_parser(value_parser!(u64).range(1..))
+                .value_name("INT")
+                .requires("targets")
+                .help("Write INT off target regions matched in length and GC to the targets"),
+        )
+        .arg(
+            Arg::new("risk_weights")
+                .long("risk-weights")
+                .value_parser(value_parser!(f64))
+                .value_delimiter(',')
+                .num_args(1)
+                .value_name("GC,REDUNDANCY,N")
+                .default_value("1,1,1")
+                .requires("targets")
+                .help("Weights for GC extremeness, kmer redundancy and N content in target dropout risk"),
+        )
+        .arg(
+            Arg::new("risk_threshold")
+                .long("risk-threshold")
+                .value_parser(value_parser!(f64))
+                .value_name("SCORE")
+                .default_value("0.5")
+                .requires("targets")
+                .help("Targets with dropout risk of at least SCORE are counted as high risk"),
+        )
+        .arg(
+            Arg::new("kmer_contigs")
+                .long("kmer-contigs")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("FILE")
+                .requires("targets")
+                .help("Only record kmers from the contigs listed in FILE (first column)"),
+        )
+        .arg(
+            Arg::new("shared_kmers")
+                .long("shared-kmers")
+                .value_parser(value_parser!(f64))
+                .value_name("FRACTION")
+                .requires("targets")
+                .help("Report pairs of targets sharing at least FRACTION of the kmers of the smaller target"),
+        )
+        .arg(
+            Arg::new("kmer_text")
+                .long("kmer-text")
+                .value_parser(value_parser!(KmerTextFilter))
+                .value_name("FILTER")
+                .requires("targets")
+                .help("Write kmers selected by FILTER with their hits as text"),
+        )
+        .arg(
+            Arg::new("region")
+                .long("region")
+                .action(ArgAction::Append)
+                .value_parser(value_parser!(String))
+                .value_name("CHR[:START[-END]]")
+                .help("Restrict analysis to region (can be repeated)"),
+        )
+        .arg(
+            Arg::new("min_contig_len")
+                .long("min-contig-len")
+                .value_parser(value_parser!(u64))
+                .value_name("INT")
+                .help("Exclude contigs shorter than INT bases"),
+        )
+        .arg(
+            Arg::new("max_contig_len")
+                .long("max-contig-len")
+                .value_parser(value_parser!(u64))
+                .value_name("INT")
+                .help("Exclude contigs longer than INT bases"),
+        )
+        .arg(
+            Arg::new("sample_fraction")
+                .long("sample-fraction")
+                .value_parser(value_parser!(f64))
+                .value_name("PROPORTION")
+                .help("Analyze a random subset (0 > x <= 1) of the input for a quick estimate"),
+        )
+        .arg(
+            Arg::new("stride")
+                .long("stride")
+                .value_parser(value_parser!(u32).range(1..))
+                .value_name("INT")
+                .default_value("1")
+                .help("Only evaluate windows at every INT positions"),
+        )
+        .arg(
+            Arg::new("end_policy")
+                .long("end-policy")
+                .value_parser(value_parser!(EndPolicy))
+                .value_name("POLICY")
+                .default_value("threshold")
+                .help("Treatment of partial windows at the ends of sequences"),
+        )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .value_parser(value_parser!(u64))
+                .value_name("INT")
+                .default_value("0")
+                .help("Seed for random number generator"),
+        )
+        .arg(
+            Arg::new("null_model")
+                .long("null-model")
+                .value_parser(value_parser!(ShuffleMode))
+                .value_name("MODE")
+                .help("Also generate distributions for shuffled (mono or di nucleotide preserving) sequence"),
+        )
+        .arg(
+            Arg::new("skew")
+                .action(ArgAction::SetTrue)
+                .long("skew")
+                .help("Also generate GC and AT skew distributions"),
+        )
+        .arg(
+            Arg::new("per_base")
+                .action(ArgAction::SetTrue)
+                .long("per-base")
+                .help("Also report expected base composition by position in reads"),
+        )
+        .arg(
+            Arg::new("dump_windows")
+                .long("dump-windows")
+                .value_parser(value_parser!(u64).range(1..))
+                .value_name("INT")
+                .help("Write a random sample of INT evaluated windows to <prefix>_windows.txt"),
+        )
+        .arg(
+            Arg::new("cross_validate")
+                .action(ArgAction::SetTrue)
+                .long("cross-validate")
+                .help("Also compare distributions from two random halves of the genome"),
+        )
+        .arg(
+            Arg::new("cv_block_size")
+                .long("cv-block-size")
+                .value_parser(value_parser!(u64).range(1..))
+                .value_name("INT")
+                .default_value("1000000")
+                .requires("cross_validate")
+                .help("Size of blocks assigned to each half for --cross-validate"),
+        )
+        .arg(
+            Arg::new("bootstrap")
+                .long("bootstrap")
+                .value_parser(value_parser!(u64).range(2..))
+                .value_name("INT")
+                .help("Add bootstrap 95% bands from INT replicates to the distribution output"),
+        )
+        .arg(
+            Arg::new("bootstrap_block_size")
+                .long("bootstrap-block-size")
+                .value_parser(value_parser!(u64).range(1..))
+                .value_name("INT")
+                .default_value("1000000")
+                .help("Size of blocks resampled for --bootstrap"),
+        )
+        .arg(
+            Arg::new("bootstrap_by_contig")
+                .action(ArgAction::SetTrue)
+                .long("bootstrap-by-contig")
+                .requires("bootstrap")
+                .conflicts_with("bootstrap_block_size")
+                .help("Resample whole contigs for --bootstrap"),
+        )
+        .arg(
+            Arg::new("n_content")
+                .action(ArgAction::SetTrue)
+                .long("n-content")
+                .help("Report the gap content of windows"),
+        )
+        .arg(
+            Arg::new("repeat_scan")
+                .action(ArgAction::SetTrue)
+                .long("repeat-scan")
+                .help("Scan input for telomere repeats (TTAGGG and variants)"),
+        )
+        .arg(
+            Arg::new("repeat_motif")
+                .long("repeat-motif")
+                .action(ArgAction::Append)
+                .value_parser(value_parser!(String))
+                .value_name("SEQ")
+                .help("Scan input for tandem repeats of motif (can be repeated)"),
+        )
+        .arg(
+            Arg::new("min_repeat_length")
+                .long("min-repeat-length")
+                .value_parser(value_parser!(u64).range(1..))
+                .value_name("INT")
+                .default_value("36")
+                .help("Minimum length of repeat runs to report"),
+        )
+        .arg(
+            Arg::new("repeatmasker")
+                .long("repeatmasker")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("RMSK FILE")
+                .help("Stratify distributions into repeat and non-repeat windows using RepeatMasker .out file"),
+        )
+        .arg(
+            Arg::new("gtf")
+                .long("gtf")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("GTF FILE")
+                .help("Stratify distributions into CDS, UTR, intron and intergenic windows using GTF file"),
+        )
+        .arg(
+            Arg::new("contig_groups")
+                .long("contig-groups")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("GROUP FILE")
+                .help("File with contig and group columns for per group GC distributions and kmer uniqueness"),
+        )
+        .arg(
+            Arg::new("no_repeat_kmers")
+                .action(ArgAction::SetTrue)
+                .long("no-repeat-kmers")
+                .requires("repeatmasker")
+                .help("Do not calculate kmer uniqueness for repeat and non-repeat sequence"),
+        )
+        .arg(
+            Arg::new("kmer_gc")
+                .action(ArgAction::SetTrue)
+                .long("kmer-gc")
+                .help("Tabulate kmer uniqueness against GC of the surrounding window"),
+        )
         .arg(
             Arg::new("read_lengths")
                 .short('r')
@@ -90,6 +623,873 @@ pub(super) fn cli_model() -> Command {
                 .default_values(["50", "75", "100", "150", "200", "250", "300"])
                 .help("Set read lengths to analyze"),
         )
+        .arg(
+            Arg::new("infer_read_lengths")
+                .long("infer-read-lengths")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("READ FILE")
+                .conflicts_with("read_lengths")
+                .help("Infer read lengths from a sample of reads (FASTQ, SAM or BAM)"),
+        )
+        .arg(
+            Arg::new("read_length_dist")
+                .long("read-length-dist")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("TSV FILE")
+                .conflicts_with_all(["read_lengths", "infer_read_lengths"])
+                .help("File with read lengths and weights to generate a weighted mixture distribution"),
+        )
+        .arg(
+            Arg::new("trim")
+                .long("trim")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("TSV FILE")
+                .help("File with bases trimmed and weights to mix effective read lengths after trimming"),
+        )
+        .arg(
+            Arg::new("long_reads")
+                .long("long-reads")
+                .action(ArgAction::SetTrue)
+                .help("Bin counts for read lengths over 1000 by GC fraction (ONT/PacBio)"),
+        )
+        .arg(
+            Arg::new("input")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("INPUT")
+                .help("Input FASTA file"),
+        )
+}
+
+fn observe_model() -> Command {
+    Command::new("observe")
+        .about("Generate observed GC distribution from reads in a BAM, SAM or FASTQ file")
+        .arg(
+            Arg::new("threshold")
+                .short('T')
+                .long("threshold")
+                .value_parser(value_parser!(f64))
+                .value_name("PROPORTION")
+                .default_value("0.8")
+                .help("Set threshold (0 > x <= 1) for proportion of bases required"),
+        )
+        .arg(
+            Arg::new("bisulfite")
+                .action(ArgAction::SetTrue)
+                .long("bisulfite")
+                .help("Also generate distributions for bisulfite converted reads"),
+        )
+        .arg(
+            Arg::new("prefix")
+                .short('p')
+                .long("prefix")
+                .value_parser(value_parser!(String))
+                .value_name("PREFIX")
+                .default_value("analyze_gc")
+                .help("Set prefix for output file names"),
+        )
+        .arg(
+            Arg::new("read_lengths")
+                .short('r')
+                .long("read_lengths")
+                .value_parser(value_parser!(u32).range(1..))
+                .value_name("INT")
+                .num_args(1..)
+                .help("Set read lengths to report [default: dominant read lengths in input]"),
+        )
+        .arg(
+            Arg::new("input")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("INPUT")
+                .required(true)
+                .help("Input BAM, SAM or FASTQ file (CRAM is not supported: convert to BAM first)"),
+        )
+}
+
+fn bias_model() -> Command {
+    Command::new("bias")
+        .about("Generate smoothed observed/expected GC bias curves")
+        .arg(
+            Arg::new("expected")
+                .short('e')
+                .long("expected")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("DIST FILE")
+                .required(true)
+                .help("Expected distribution file"),
+        )
+        .arg(
+            Arg::new("observed")
+                .short('o')
+                .long("observed")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("DIST FILE")
+                .required(true)
+                .help("Observed distribution file"),
+        )
+        .arg(
+            Arg::new("span")
+                .short('s')
+                .long("span")
+                .value_parser(value_parser!(f64))
+                .value_name("PROPORTION")
+                .default_value("0.3")
+                .help("Proportion of points used in each local regression"),
+        )
+        .arg(
+            Arg::new("n_reads")
+                .short('n')
+                .long("n-reads")
+                .value_parser(value_parser!(u64).range(1..))
+                .value_name("INT")
+                .help("Number of reads in observed distribution (enables confidence bands)"),
+        )
+        .arg(
+            Arg::new("prefix")
+                .short('p')
+                .long("prefix")
+                .value_parser(value_parser!(String))
+                .value_name("PREFIX")
+                .default_value("analyze_gc")
+                .help("Set prefix for output file names"),
+        )
+}
+
+fn simulate_model() -> Command {
+    Command::new("simulate")
+        .about("Predict per target coverage and dropout under a GC bias model")
+        .arg(
+            Arg::new("kmcv")
+                .short('k')
+                .long("kmcv")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("KMCV FILE")
+                .required(true)
+                .help("Kmer file generated with target regions"),
+        )
+        .arg(
+            Arg::new("targets")
+                .short('t')
+                .long("targets")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("TARGET FILE")
+                .required(true)
+                .help("Target statistics file generated with target regions"),
+        )
+        .arg(
+            Arg::new("bias")
+                .short('b')
+                .long("bias")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("BIAS FILE")
+                .required(true)
+                .help("Bias curve file"),
+        )
+        .arg(
+            Arg::new("column")
+                .short('c')
+                .long("column")
+                .value_parser(value_parser!(String))
+                .value_name("NAME")
+                .help("Bias curve to use [default: first curve in file]"),
+        )
+        .arg(
+            Arg::new("depth")
+                .short('d')
+                .long("depth")
+                .value_parser(value_parser!(f64))
+                .value_name("DEPTH")
+                .default_value("100")
+                .help("Mean depth over targets"),
+        )
+        .arg(
+            Arg::new("min_depth")
+                .short('m')
+                .long("min-depth")
+                .value_parser(value_parser!(u32))
+                .value_name("DEPTH")
+                .default_value("10")
+                .help("Targets with coverage below this are counted as dropouts"),
+        )
+        .arg(
+            Arg::new("prefix")
+                .short('p')
+                .long("prefix")
+                .value_parser(value_parser!(String))
+                .value_name("PREFIX")
+                .default_value("analyze_gc")
+                .help("Set prefix for output file names"),
+        )
+}
+
+fn dup_rate_model() -> Command {
+    Command::new("dup-rate")
+        .about("Estimate duplicate rate inflation per GC bin under a GC bias model")
+        .arg(
+            Arg::new("expected")
+                .short('e')
+                .long("expected")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("DIST FILE")
+                .required(true)
+                .help("Expected distribution file"),
+        )
+        .arg(
+            Arg::new("bias")
+                .short('b')
+                .long("bias")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("BIAS FILE")
+                .required(true)
+                .help("Bias curve file"),
+        )
+        .arg(
+            Arg::new("n_reads")
+                .short('n')
+                .long("n-reads")
+                .value_parser(value_parser!(u64).range(1..))
+                .value_name("INT")
+                .required(true)
+                .help("Number of reads (or read pairs) sequenced"),
+        )
+        .arg(
+            Arg::new("target_size")
+                .short('s')
+                .long("target-size")
+                .value_parser(value_parser!(u64).range(1..))
+                .value_name("BASES")
+                .required(true)
+                .help("Total size of targeted regions"),
+        )
+        .arg(
+            Arg::new("prefix")
+                .short('p')
+                .long("prefix")
+                .value_parser(value_parser!(String))
+                .value_name("PREFIX")
+                .default_value("analyze_gc")
+                .help("Set prefix for output file names"),
+        )
+}
+
+fn unique_bed_model() -> Command {
+    Command::new("unique-bed")
+        .about("Write targets with a high proportion of uniquely mapping kmers in BED format")
+        .arg(
+            Arg::new("kmcv")
+                .short('k')
+                .long("kmcv")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("KMCV FILE")
+                .required(true)
+                .help("Kmer file generated with target regions"),
+        )
+        .arg(
+            Arg::new("min_unique")
+                .short('m')
+                .long("min-unique")
+                .value_parser(value_parser!(f64))
+                .value_name("PROPORTION")
+                .default_value("0.8")
+                .help("Minimum proportion of uniquely mapping kmers for a target"),
+        )
+        .arg(
+            Arg::new("prefix")
+                .short('p')
+                .long("prefix")
+                .value_parser(value_parser!(String))
+                .value_name("PREFIX")
+                .default_value("analyze_gc")
+                .help("Set prefix for output file names"),
+        )
+}
+
+fn update_kmers_model() -> Command {
+    Command::new("update-kmers")
+        .about("Add targets to an existing kmer file without regenerating it")
+        .arg(
+            Arg::new("kmcv")
+                .short('k')
+                .long("kmcv")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("KMCV FILE")
+                .required(true)
+                .help("Kmer file to update"),
+        )
+        .arg(
+            Arg::new("targets")
+                .short('R')
+                .long("targets")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("TARGET BED")
+                .required(true)
+                .help("BED file with the new target regions"),
+        )
+        .arg(
+            Arg::new("kmer_compression")
+                .long("kmer-compression")
+                .value_parser(value_parser!(KmerCompression))
+                .value_name("CODEC")
+                .default_value("zstd")
+                .help("Compression for output kmer files"),
+        )
+        .arg(
+            Arg::new("kmer_threads")
+                .long("kmer-threads")
+                .value_parser(value_parser!(u64).range(1..))
+                .value_name("INT")
+                .help("Threads used to compress kmer files [default: number of physical cores]"),
+        )
+        .arg(
+            Arg::new("prefix")
+                .short('p')
+                .long("prefix")
+                .value_parser(value_parser!(String))
+                .value_name("PREFIX")
+                .default_value("analyze_gc")
+                .help("Set prefix for output file names"),
+        )
+        .arg(
+            Arg::new("input")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("INPUT")
+                .required(true)
+                .help(
+                    "Reference used to generate the kmer file (uncompressed FASTA with .fai index)",
+                ),
+        )
+}
+
+fn coverage_model() -> Command {
+    Command::new("estimate-coverage")
+        .about("Estimate on target rate and per target coverage from reads using a kmer file")
+        .arg(
+            Arg::new("kmcv")
+                .short('k')
+                .long("kmcv")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("KMCV FILE")
+                .required(true)
+                .help("Kmer file generated with target regions"),
+        )
+        .arg(
+            Arg::new("min_kmers")
+                .short('m')
+                .long("min-kmers")
+                .value_parser(value_parser!(u32).range(1..))
+                .value_name("INT")
+                .default_value("1")
+                .help("Minimum number of uniquely mapping kmers to assign a read to a target"),
+        )
+        .arg(
+            Arg::new("prefix")
+                .short('p')
+                .long("prefix")
+                .value_parser(value_parser!(String))
+                .value_name("PREFIX")
+                .default_value("analyze_gc")
+                .help("Set prefix for output file names"),
+        )
+        .arg(
+            Arg::new("input")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("INPUT")
+                .required(true)
+                .help("Input FASTQ, SAM or BAM file"),
+        )
+}
+
+fn bench_model() -> Command {
+    Command::new("bench")
+        .about("Time the reader, GC and kmer stages on the input or a synthetic genome")
+        .arg(
+            Arg::new("threads")
+                .short('t')
+                .long("threads")
+                .value_parser(value_parser!(u64).range(1..))
+                .value_name("INT")
+                .help("Set number of process threads [default: number of available cores]"),
+        )
+        .arg(
+            Arg::new("queue_size")
+                .long("queue-size")
+                .value_parser(value_parser!(u64).range(1..))
+                .value_name("INT")
+                .help("Number of sequence blocks queued for the process threads [default: 4 x threads]"),
+        )
+        .arg(
+            Arg::new("chunk_size")
+                .long("chunk-size")
+                .value_parser(value_parser!(String))
+                .value_name("SIZE")
+                .help("Split sequences into chunks of SIZE bases (e.g. 10M) for processing [default: no splitting]"),
+        )
+        .arg(
+            Arg::new("threshold")
+                .short('T')
+                .long("threshold")
+                .value_parser(value_parser!(f64))
+                .value_name("PROPORTION")
+                .default_value("0.8")
+                .help("Set threshold (0 > x <= 1) for proportion of bases required"),
+        )
+        .arg(
+            Arg::new("no_bisulfite")
+                .action(ArgAction::SetTrue)
+                .long("no-bisulfite")
+                .help("Do not generate distributions for bisulfite converted sequences"),
+        )
+        .arg(
+            Arg::new("read_lengths")
+                .short('r')
+                .long("read_lengths")
+                .value_parser(value_parser!(u32).range(1..))
+                .value_name("INT")
+                .num_args(1..)
+                .default_values(["50", "75", "100", "150", "200", "250", "300"])
+                .help("Set read lengths to analyze"),
+        )
+        .arg(
+            Arg::new("synthetic_size")
+                .short('s')
+                .long("synthetic-size")
+                .value_parser(value_parser!(String))
+                .value_name("SIZE")
+                .default_value("100M")
+                .help("Size of synthetic genome used if no input is given"),
+        )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .value_parser(value_parser!(u64))
+                .value_name("INT")
+                .default_value("0")
+                .help("Seed for random number generator"),
+        )
+        .arg(
+            Arg::new("kmer_table")
+                .action(ArgAction::SetTrue)
+                .long("kmer-table")
+                .help("Also populate the kmer table in the kmer stage (needs 32 GB of memory)"),
+        )
+        .arg(
+            Arg::new("input")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("INPUT")
+                .help("Input FASTA file"),
+        )
+}
+
+fn generate_model() -> Command {
+    Command::new("generate")
+        .about("Generate a synthetic genome with isochores, gaps and repeats")
+        .arg(
+            Arg::new("size")
+                .short('s')
+                .long("size")
+                .value_parser(value_parser!(String))
+                .value_name("SIZE")
+                .default_value("100M")
+                .help("Total size of genome (e.g. 100M)"),
+        )
+        .arg(
+            Arg::new("contig_length")
+                .long("contig-length")
+                .value_parser(value_parser!(String))
+                .value_name("SIZE")
+                .default_value("50M")
+                .help("Maximum contig length"),
+        )
+        .arg(
+            Arg::new("gc")
+                .long("gc")
+                .value_parser(value_parser!(f64))
+                .value_name("PROPORTION")
+                .default_value("0.41")
+                .help("Mean GC content"),
+        )
+        .arg(
+            Arg::new("gc_sd")
+                .long("gc-sd")
+                .value_parser(value_parser!(f64))
+                .value_name("PROPORTION")
+                .default_value("0.05")
+                .help("Standard deviation of GC content between isochores"),
+        )
+        .arg(
+            Arg::new("isochore_length")
+                .long("isochore-length")
+                .value_parser(value_parser!(f64))
+                .value_name("LENGTH")
+                .default_value("300000")
+                .help("Mean isochore length"),
+        )
+        .arg(
+            Arg::new("gap_rate")
+                .long("gap-rate")
+                .value_parser(value_parser!(f64))
+                .value_name("RATE")
+                .default_value("0.5")
+                .help("Number of N gaps per Mb"),
+        )
+        .arg(
+            Arg::new("gap_length")
+                .long("gap-length")
+                .value_parser(value_parser!(f64))
+                .value_name("LENGTH")
+                .default_value("5000")
+                .help("Mean length of N gaps"),
+        )
+        .arg(
+            Arg::new("repeat_families")
+                .long("repeat-families")
+                .value_parser(value_parser!(u64))
+                .value_name("INT")
+                .default_value("10")
+                .help("Number of distinct repeat elements"),
+        )
+        .arg(
+            Arg::new("repeat_length")
+                .long("repeat-length")
+                .value_parser(value_parser!(u64))
+                .value_name("LENGTH")
+                .default_value("300")
+                .help("Length of repeat elements"),
+        )
+        .arg(
+            Arg::new("repeat_rate")
+                .long("repeat-rate")
+                .value_parser(value_parser!(f64))
+                .value_name("RATE")
+                .default_value("50")
+                .help("Number of repeat copies per Mb"),
+        )
+        .arg(
+            Arg::new("repeat_divergence")
+                .long("repeat-divergence")
+                .value_parser(value_parser!(f64))
+                .value_name("PROPORTION")
+                .default_value("0.1")
+                .help("Proportion of bases changed in each repeat copy"),
+        )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .value_parser(value_parser!(u64))
+                .value_name("INT")
+                .default_value("0")
+                .help("Seed for random number generator"),
+        )
+        .arg(
+            Arg::new("prefix")
+                .short('p')
+                .long("prefix")
+                .value_parser(value_parser!(String))
+                .value_name("PREFIX")
+                .default_value("synthetic")
+                .help("Set prefix for output file names"),
+        )
+        .arg(
+            Arg::new("bed")
+                .action(ArgAction::SetTrue)
+                .long("bed")
+                .help("Also write BED file with isochores, gaps and repeat copies"),
+        )
+}
+
+fn isochores_model() -> Command {
+    Command::new("isochores")
+        .about("Segment contigs into regions of homogeneous GC content (isochores)")
+        .arg(
+            Arg::new("window")
+                .short('w')
+                .long("window")
+                .value_parser(value_parser!(u64).range(1..))
+                .value_name("LENGTH")
+                .default_value("100000")
+                .help("Window size for GC calculation"),
+        )
+        .arg(
+            Arg::new("threshold")
+                .short('T')
+                .long("threshold")
+                .value_parser(value_parser!(f64))
+                .value_name("PROPORTION")
+                .default_value("0.8")
+                .help("Set threshold (0 > x <= 1) for proportion of bases required in a window"),
+        )
+        .arg(
+            Arg::new("min_length")
+                .long("min-length")
+                .value_parser(value_parser!(u64))
+                .value_name("LENGTH")
+                .default_value("300000")
+                .help("Minimum isochore length"),
+        )
+        .arg(
+            Arg::new("min_diff")
+                .long("min-diff")
+                .value_parser(value_parser!(f64))
+                .value_name("PROPORTION")
+                .default_value("0.01")
+                .help("Minimum difference in GC between adjacent isochores"),
+        )
+        .arg(
+            Arg::new("penalty")
+                .long("penalty")
+                .value_parser(value_parser!(f64))
+                .value_name("FLOAT")
+                .default_value("3")
+                .help("Penalty for splitting (higher values give fewer isochores)"),
+        )
+        .arg(
+            Arg::new("prefix")
+                .short('p')
+                .long("prefix")
+                .value_parser(value_parser!(String))
+                .value_name("PREFIX")
+                .default_value("analyze_gc")
+                .help("Set prefix for output file names"),
+        )
+        .arg(
+            Arg::new("input")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("INPUT")
+                .help("Input FASTA file"),
+        )
+}
+
+fn bands_model() -> Command {
+    Command::new("bands")
+        .about("Summarize GC content and kmer uniqueness for cytogenetic bands and arms")
+        .arg(
+            Arg::new("cytobands")
+                .short('c')
+                .long("cytobands")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("FILE")
+                .required(true)
+                .help("Cytoband (ideogram) file"),
+        )
+        .arg(
+            Arg::new("no_kmers")
+                .action(ArgAction::SetTrue)
+                .long("no-kmers")
+                .help("Do not calculate kmer uniqueness"),
+        )
+        .arg(
+            Arg::new("prefix")
+                .short('p')
+                .long("prefix")
+                .value_parser(value_parser!(String))
+                .value_name("PREFIX")
+                .default_value("analyze_gc")
+                .help("Set prefix for output file names"),
+        )
+        .arg(
+            Arg::new("input")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("INPUT")
+                .help("Input FASTA file"),
+        )
+}
+
+fn gc_track_model() -> Command {
+    Command::new("gc-track")
+        .about("Write bedGraph of windowed GC content as genome wide percentiles")
+        .arg(
+            Arg::new("window")
+                .short('w')
+                .long("window")
+                .value_parser(value_parser!(u64).range(1..))
+                .value_name("LENGTH")
+                .default_value("1000")
+                .help("Window size"),
+        )
+        .arg(
+            Arg::new("threshold")
+                .short('T')
+                .long("threshold")
+                .value_parser(value_parser!(f64))
+                .value_name("PROPORTION")
+                .default_value("0.8")
+                .help("Set threshold (0 > x <= 1) for proportion of bases required in a window"),
+        )
+        .arg(
+            Arg::new("skew")
+                .action(ArgAction::SetTrue)
+                .long("skew")
+                .help("Also write GC and AT skew tracks"),
+        )
+        .arg(
+            Arg::new("zarr")
+                .action(ArgAction::SetTrue)
+                .long("zarr")
+                .help("Write tracks to a Zarr store instead of text files"),
+        )
+        .arg(
+            Arg::new("prefix")
+                .short('p')
+                .long("prefix")
+                .value_parser(value_parser!(String))
+                .value_name("PREFIX")
+                .default_value("analyze_gc")
+                .help("Set prefix for output file names"),
+        )
+        .arg(
+            Arg::new("input")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("INPUT")
+                .help("Input FASTA file"),
+        )
+}
+
+fn lint_model() -> Command {
+    Command::new("lint")
+        .about("Check FASTA file for malformed records, illegal characters and line lengths")
+        .arg(
+            Arg::new("max_issues")
+                .long("max-issues")
+                .value_parser(value_parser!(usize))
+                .value_name("INT")
+                .default_value("100")
+                .help("Maximum number of issues of each kind to report individually"),
+        )
+        .arg(
+            Arg::new("prefix")
+                .short('p')
+                .long("prefix")
+                .value_parser(value_parser!(String))
+                .value_name("PREFIX")
+                .default_value("analyze_gc")
+                .help("Set prefix for output file names"),
+        )
+        .arg(
+            Arg::new("input")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("INPUT")
+                .help("Input FASTA file"),
+        )
+}
+
+fn cohort_model() -> Command {
+    Command::new("cohort-summary")
+        .about("Compare the GC distributions from several result JSON files")
+        .arg(
+            Arg::new("read_lengths")
+                .short('r')
+                .long("read-lengths")
+                .value_parser(value_parser!(u32).range(1..))
+                .value_name("INT")
+                .num_args(1..)
+                .help("Read lengths to compare [default: those present in all inputs]"),
+        )
+        .arg(
+            Arg::new("bins")
+                .short('b')
+                .long("bins")
+                .value_parser(value_parser!(u32).range(2..=10000))
+                .value_name("INT")
+                .default_value("100")
+                .help("Number of GC bins the distributions are aligned to"),
+        )
+        .arg(
+            Arg::new("prefix")
+                .short('p')
+                .long("prefix")
+                .value_parser(value_parser!(String))
+                .value_name("PREFIX")
+                .default_value("analyze_gc")
+                .help("Set prefix for output file names"),
+        )
+        .arg(
+            Arg::new("inputs")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("JSON")
+                .num_args(2..)
+                .required(true)
+                .help("Result JSON files from analyze_ref_gc"),
+        )
+}
+
+fn saturation_model() -> Command {
+    Command::new("saturation")
+        .about("Expected number of unique target kmers observed as a function of depth")
+        .arg(
+            Arg::new("kmcv")
+                .short('k')
+                .long("kmcv")
+                .value_parser(value_parser!(PathBuf))
+                .value_name("KMCV FILE")
+                .required(true)
+                .help("Kmer file generated with target regions"),
+        )
+        .arg(
+            Arg::new("read_length")
+                .short('r')
+                .long("read-length")
+                .value_parser(value_parser!(u32).range(1..))
+                .value_name("INT")
+                .default_value("150")
+                .help("Read length"),
+        )
+        .arg(
+            Arg::new("depths")
+                .short('d')
+                .long("depths")
+                .value_parser(value_parser!(f64))
+                .value_name("DEPTH")
+                .num_args(1..)
+                .default_values(["1", "2", "5", "10", "20", "50", "100"])
+                .help("Mean depths at which to evaluate the curve"),
+        )
+        .arg(
+            Arg::new("prefix")
+                .short('p')
+                .long("prefix")
+                .value_parser(value_parser!(String))
+                .value_name("PREFIX")
+                .default_value("analyze_gc")
+                .help("Set prefix for output file names"),
+        )
+}
+
+fn gc_pyramid_model() -> Command {
+    Command::new("gc-pyramid")
+        .about("Write GC content at several window sizes from a single pass")
+        .arg(
+            Arg::new("windows")
+                .short('w')
+                .long("windows")
+                .value_parser(value_parser!(u64).range(1..))
+                .value_name("LENGTH")
+                .num_args(1..)
+                .default_values(["100", "1000", "10000", "100000"])
+                .help("Window sizes (each a multiple of the next smaller size)"),
+        )
+        .arg(
+            Arg::new("threshold")
+                .short('T')
+                .long("threshold")
+                .value_parser(value_parser!(f64))
+                .value_name("PROPORTION")
+                .default_value("0.8")
+                .help("Set threshold (0 > x <= 1) for proportion of bases required in a window"),
+        )
+        .arg(
+            Arg::new("zarr")
+                .action(ArgAction::SetTrue)
+                .long("zarr")
+                .help("Write tracks to a Zarr store instead of text files"),
+        )
+        .arg(
+            Arg::new("prefix")
+                .short('p')
+                .long("prefix")
+                .value_parser(value_parser!(String))
+                .value_name("PREFIX")
+                .default_value("analyze_gc")
+                .help("Set prefix for output file names"),
+        )
         .arg(
             Arg::new("input")
                 .value_parser(value_parser!(PathBuf))
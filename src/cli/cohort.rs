@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+
+use clap::ArgMatches;
+
+pub struct CohortConfig {
+    inputs: Vec<PathBuf>,
+    prefix: String,
+    read_lengths: Option<Vec<u32>>,
+    bins: u32,
+}
+
+impl CohortConfig {
+    pub fn inputs(&self) -> &[PathBuf] {
+        &self.inputs
+    }
+
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_str()
+    }
+
+    /// Read lengths to compare (if None, those present in all inputs)
+    pub fn read_lengths(&self) -> Option<&[u32]> {
+        self.read_lengths.as_deref()
+    }
+
+    /// Number of GC bins the distributions are aligned to
+    pub fn bins(&self) -> u32 {
+        self.bins
+    }
+}
+
+pub(super) fn cohort_config(m: &ArgMatches) -> anyhow::Result<CohortConfig> {
+    let inputs: Vec<PathBuf> = m
+        .get_many::<PathBuf>("inputs")
+        .expect("Missing required argument")
+        .cloned()
+        .collect();
+
+    let read_lengths = m.get_many::<u32>("read_lengths").map(|v| {
+        let mut v: Vec<u32> = v.copied().collect();
+        v.sort_unstable();
+        v.dedup();
+        v
+    });
+
+    Ok(CohortConfig {
+        inputs,
+        prefix: m
+            .get_one::<String>("prefix")
+            .map(|s| s.to_owned())
+            .expect("Missing default argument"),
+        read_lengths,
+        bins: *m.get_one::<u32>("bins").expect("Missing default argument"),
+    })
+}
@@ -0,0 +1,55 @@
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+
+pub struct CoverageConfig {
+    kmcv: PathBuf,
+    input: PathBuf,
+    prefix: String,
+    min_kmers: u32,
+}
+
+impl CoverageConfig {
+    pub fn kmcv(&self) -> &Path {
+        &self.kmcv
+    }
+
+    pub fn input(&self) -> &Path {
+        &self.input
+    }
+
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_str()
+    }
+
+    /// Minimum number of kmers matching a target uniquely for a read to be assigned to the target
+    pub fn min_kmers(&self) -> u32 {
+        self.min_kmers
+    }
+}
+
+pub(super) fn coverage_config(m: &ArgMatches) -> anyhow::Result<CoverageConfig> {
+    let kmcv = m
+        .get_one::<PathBuf>("kmcv")
+        .map(|p| p.to_owned())
+        .expect("Missing required argument");
+
+    let input = m
+        .get_one::<PathBuf>("input")
+        .map(|p| p.to_owned())
+        .expect("Missing required argument");
+
+    let prefix = m
+        .get_one::<String>("prefix")
+        .map(|s| s.to_owned())
+        .expect("Missing default argument");
+
+    Ok(CoverageConfig {
+        kmcv,
+        input,
+        prefix,
+        min_kmers: *m
+            .get_one::<u32>("min_kmers")
+            .expect("Missing default argument"),
+    })
+}
@@ -0,0 +1,60 @@
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+
+pub struct DupRateConfig {
+    expected: PathBuf,
+    bias: PathBuf,
+    prefix: String,
+    n_reads: u64,
+    target_size: u64,
+}
+
+impl DupRateConfig {
+    pub fn expected(&self) -> &Path {
+        &self.expected
+    }
+
+    pub fn bias(&self) -> &Path {
+        &self.bias
+    }
+
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_str()
+    }
+
+    /// Number of reads (or read pairs) sequenced
+    pub fn n_reads(&self) -> u64 {
+        self.n_reads
+    }
+
+    /// Total size of the targeted regions in bases
+    pub fn target_size(&self) -> u64 {
+        self.target_size
+    }
+}
+
+pub(super) fn dup_rate_config(m: &ArgMatches) -> anyhow::Result<DupRateConfig> {
+    let get_path = |s| {
+        m.get_one::<PathBuf>(s)
+            .map(|p| p.to_owned())
+            .expect("Missing required argument")
+    };
+
+    let prefix = m
+        .get_one::<String>("prefix")
+        .map(|s| s.to_owned())
+        .expect("Missing default argument");
+
+    Ok(DupRateConfig {
+        expected: get_path("expected"),
+        bias: get_path("bias"),
+        prefix,
+        n_reads: *m
+            .get_one::<u64>("n_reads")
+            .expect("Missing required argument"),
+        target_size: *m
+            .get_one::<u64>("target_size")
+            .expect("Missing required argument"),
+    })
+}
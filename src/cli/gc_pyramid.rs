@@ -0,0 +1,61 @@
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+
+pub struct GcPyramidConfig {
+    input: Option<PathBuf>,
+    prefix: String,
+    windows: Vec<u64>,
+    threshold: f64,
+    zarr: bool,
+}
+
+impl GcPyramidConfig {
+    pub fn input(&self) -> Option<&Path> {
+        self.input.as_deref()
+    }
+
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_str()
+    }
+
+    /// Window sizes in increasing order, each a multiple of the previous
+    pub fn windows(&self) -> &[u64] {
+        &self.windows
+    }
+
+    /// Minimum proportion of non gap bases for the GC of a window to be reported
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    /// Write to a Zarr store rather than text files
+    pub fn zarr(&self) -> bool {
+        self.zarr
+    }
+}
+
+pub(super) fn gc_pyramid_config(m: &ArgMatches) -> anyhow::Result<GcPyramidConfig> {
+    let mut windows: Vec<u64> = m
+        .get_many::<u64>("windows")
+        .expect("Missing default argument")
+        .copied()
+        .collect();
+    windows.sort_unstable();
+    windows.dedup();
+    if windows.windows(2).any(|w| w[1] % w[0] != 0) {
+        return Err(anyhow!(
+            "Illegal window sizes: each size must be a multiple of the next smaller size"
+        ));
+    }
+    Ok(GcPyramidConfig {
+        input: m.get_one::<PathBuf>("input").map(|p| p.to_owned()),
+        prefix: m
+            .get_one::<String>("prefix")
+            .map(|s| s.to_owned())
+            .expect("Missing default argument"),
+        windows,
+        threshold: super::get_threshold(m)?,
+        zarr: m.get_flag("zarr"),
+    })
+}
@@ -0,0 +1,58 @@
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+
+pub struct GcTrackConfig {
+    input: Option<PathBuf>,
+    prefix: String,
+    window: u64,
+    threshold: f64,
+    skew: bool,
+    zarr: bool,
+}
+
+impl GcTrackConfig {
+    pub fn input(&self) -> Option<&Path> {
+        self.input.as_deref()
+    }
+
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_str()
+    }
+
+    /// Window size for GC calculation
+    pub fn window(&self) -> u64 {
+        self.window
+    }
+
+    /// Minimum proportion of non gap bases for a window to be reported
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    /// Also write GC and AT skew tracks
+    pub fn skew(&self) -> bool {
+        self.skew
+    }
+
+    /// Write to a Zarr store rather than text files
+    pub fn zarr(&self) -> bool {
+        self.zarr
+    }
+}
+
+pub(super) fn gc_track_config(m: &ArgMatches) -> anyhow::Result<GcTrackConfig> {
+    Ok(GcTrackConfig {
+        input: m.get_one::<PathBuf>("input").map(|p| p.to_owned()),
+        prefix: m
+            .get_one::<String>("prefix")
+            .map(|s| s.to_owned())
+            .expect("Missing default argument"),
+        window: *m
+            .get_one::<u64>("window")
+            .expect("Missing default argument"),
+        threshold: super::get_threshold(m)?,
+        skew: m.get_flag("skew"),
+        zarr: m.get_flag("zarr"),
+    })
+}
@@ -0,0 +1,75 @@
+use anyhow::Context;
+use clap::ArgMatches;
+
+use crate::{memory::parse_mem_size, synthetic::GenomeParams};
+
+pub struct GenerateConfig {
+    params: GenomeParams,
+    prefix: String,
+    bed: bool,
+}
+
+impl GenerateConfig {
+    pub fn params(&self) -> &GenomeParams {
+        &self.params
+    }
+
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_str()
+    }
+
+    /// Also write BED file with isochores, gaps and repeats
+    pub fn bed(&self) -> bool {
+        self.bed
+    }
+}
+
+pub(super) fn generate_config(m: &ArgMatches) -> anyhow::Result<GenerateConfig> {
+    let get_size = |s: &str| {
+        let x = m.get_one::<String>(s).expect("Missing default argument");
+        parse_mem_size(x).with_context(|| format!("Bad value for {s}"))
+    };
+    let get_f64 = |s: &str| *m.get_one::<f64>(s).expect("Missing default argument");
+    let get_usize = |s: &str| *m.get_one::<u64>(s).expect("Missing default argument") as usize;
+
+    let params = GenomeParams {
+        size: get_size("size")?,
+        contig_length: get_size("contig_length")?,
+        gc: get_f64("gc"),
+        gc_sd: get_f64("gc_sd"),
+        isochore_length: get_f64("isochore_length"),
+        gap_rate: get_f64("gap_rate"),
+        gap_length: get_f64("gap_length"),
+        repeat_families: get_usize("repeat_families"),
+        repeat_length: get_usize("repeat_length"),
+        repeat_rate: get_f64("repeat_rate"),
+        repeat_divergence: get_f64("repeat_divergence"),
+        seed: *m.get_one::<u64>("seed").expect("Missing default argument"),
+    };
+    if !(params.gc > 0.0 && params.gc < 1.0) {
+        return Err(anyhow!("Illegal GC content: must be > 0 and < 1"));
+    }
+    if !(0.0..=1.0).contains(&params.repeat_divergence) {
+        return Err(anyhow!("Illegal repeat divergence: must be >= 0 and <= 1"));
+    }
+    for (s, x) in [
+        ("gc-sd", params.gc_sd),
+        ("isochore-length", params.isochore_length),
+        ("gap-rate", params.gap_rate),
+        ("gap-length", params.gap_length),
+        ("repeat-rate", params.repeat_rate),
+    ] {
+        if !(x >= 0.0 && x.is_finite()) {
+            return Err(anyhow!("Illegal value for {s}: must be >= 0"));
+        }
+    }
+
+    Ok(GenerateConfig {
+        params,
+        prefix: m
+            .get_one::<String>("prefix")
+            .map(|s| s.to_owned())
+            .expect("Missing default argument"),
+        bed: m.get_flag("bed"),
+    })
+}
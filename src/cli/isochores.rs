@@ -0,0 +1,78 @@
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+
+pub struct IsochoreConfig {
+    input: Option<PathBuf>,
+    prefix: String,
+    window: u64,
+    threshold: f64,
+    min_windows: usize,
+    min_diff: f64,
+    penalty: f64,
+}
+
+impl IsochoreConfig {
+    pub fn input(&self) -> Option<&Path> {
+        self.input.as_deref()
+    }
+
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_str()
+    }
+
+    /// Window size for GC calculation
+    pub fn window(&self) -> u64 {
+        self.window
+    }
+
+    /// Minimum proportion of non gap bases for a window to be used
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    /// Minimum number of windows in a segment
+    pub fn min_windows(&self) -> usize {
+        self.min_windows
+    }
+
+    /// Minimum difference in GC between adjacent segments
+    pub fn min_diff(&self) -> f64 {
+        self.min_diff
+    }
+
+    /// Multiplier for the penalty on splitting segments
+    pub fn penalty(&self) -> f64 {
+        self.penalty
+    }
+}
+
+pub(super) fn isochore_config(m: &ArgMatches) -> anyhow::Result<IsochoreConfig> {
+    let get_u64 = |s: &str| *m.get_one::<u64>(s).expect("Missing default argument");
+    let get_f64 = |s: &str| *m.get_one::<f64>(s).expect("Missing default argument");
+
+    let window = get_u64("window");
+    let min_diff = get_f64("min_diff");
+    if !(0.0..1.0).contains(&min_diff) {
+        return Err(anyhow!(
+            "Illegal minimum GC difference: must be >= 0 and < 1"
+        ));
+    }
+    let penalty = get_f64("penalty");
+    if !(penalty >= 0.0 && penalty.is_finite()) {
+        return Err(anyhow!("Illegal penalty: must be >= 0"));
+    }
+
+    Ok(IsochoreConfig {
+        input: m.get_one::<PathBuf>("input").map(|p| p.to_owned()),
+        prefix: m
+            .get_one::<String>("prefix")
+            .map(|s| s.to_owned())
+            .expect("Missing default argument"),
+        window,
+        threshold: super::get_threshold(m)?,
+        min_windows: get_u64("min_length").div_ceil(window) as usize,
+        min_diff,
+        penalty,
+    })
+}
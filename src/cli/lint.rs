@@ -0,0 +1,37 @@
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+
+pub struct LintConfig {
+    input: Option<PathBuf>,
+    prefix: String,
+    max_issues: usize,
+}
+
+impl LintConfig {
+    pub fn input(&self) -> Option<&Path> {
+        self.input.as_deref()
+    }
+
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_str()
+    }
+
+    /// Maximum number of issues of each kind to report individually
+    pub fn max_issues(&self) -> usize {
+        self.max_issues
+    }
+}
+
+pub(super) fn lint_config(m: &ArgMatches) -> anyhow::Result<LintConfig> {
+    Ok(LintConfig {
+        input: m.get_one::<PathBuf>("input").map(|p| p.to_owned()),
+        prefix: m
+            .get_one::<String>("prefix")
+            .map(|s| s.to_owned())
+            .expect("Missing default argument"),
+        max_issues: *m
+            .get_one::<usize>("max_issues")
+            .expect("Missing default argument"),
+    })
+}
@@ -0,0 +1,58 @@
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+
+pub struct ObserveConfig {
+    input: PathBuf,
+    prefix: String,
+    read_lengths: Option<Vec<u32>>,
+    threshold: f64,
+    bisulfite: bool,
+}
+
+impl ObserveConfig {
+    pub fn input(&self) -> &Path {
+        &self.input
+    }
+
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_str()
+    }
+
+    /// Read lengths to report.  If not set, the dominant read lengths in the input are used
+    pub fn read_lengths(&self) -> Option<&[u32]> {
+        self.read_lengths.as_deref()
+    }
+
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    pub fn bisulfite(&self) -> bool {
+        self.bisulfite
+    }
+}
+
+pub(super) fn observe_config(m: &ArgMatches) -> anyhow::Result<ObserveConfig> {
+    let input = m
+        .get_one::<PathBuf>("input")
+        .map(|p| p.to_owned())
+        .expect("Missing required argument");
+
+    let prefix = m
+        .get_one::<String>("prefix")
+        .map(|s| s.to_owned())
+        .expect("Missing default argument");
+
+    let read_lengths = m
+        .get_many::<u32>("read_lengths")
+        .map(|v| v.copied().collect());
+
+    Ok(ObserveConfig {
+        input,
+        prefix,
+        read_lengths,
+        threshold: super::get_threshold(m)?,
+        bisulfite: m.get_flag("bisulfite"),
+    })
+}
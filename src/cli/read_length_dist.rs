@@ -0,0 +1,134 @@
+use std::{io::BufRead, path::Path};
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+
+/// Read a two column (length, weight) file.  Empty lines and lines starting with '#' are skipped.
+/// Weights are normalized to sum to 1.  Returns vectors of lengths and weights
+pub(super) fn read_length_dist<P: AsRef<Path>>(path: P) -> anyhow::Result<(Vec<u32>, Vec<f64>)> {
+    let rdr = CompressIo::new()
+        .path(path)
+        .bufreader()
+        .with_context(|| "Error opening read length distribution file")?;
+    read_weighted_lengths(rdr, "read length", 1)
+}
+
+/// Read a two column (bases trimmed, weight) trimming model, in the same format as the read
+/// length distribution
+pub(super) fn trim_model<P: AsRef<Path>>(path: P) -> anyhow::Result<(Vec<u32>, Vec<f64>)> {
+    let rdr = CompressIo::new()
+        .path(path)
+        .bufreader()
+        .with_context(|| "Error opening trimming model file")?;
+    read_weighted_lengths(rdr, "trimmed length", 0)
+}
+
+/// Effective read lengths and weights after applying the trimming model to the nominal read
+/// lengths (weighted equally if no weights are given).  Trimmed lengths of 0 or less are dropped
+pub(super) fn apply_trim(
+    lengths: &[u32],
+    weights: Option<&[f64]>,
+    trim: &[u32],
+    trim_weights: &[f64],
+) -> anyhow::Result<(Vec<u32>, Vec<f64>)> {
+    let mut v: Vec<(u32, f64)> = Vec::new();
+    for (i, l) in lengths.iter().enumerate() {
+        let w = weights.map(|w| w[i]).unwrap_or(1.0 / lengths.len() as f64);
+        for (t, tw) in trim.iter().zip(trim_weights.iter()) {
+            if t < l && w * tw > 0.0 {
+                v.push((l - t, w * tw))
+            }
+        }
+    }
+    v.sort_unstable_by_key(|(l, _)| *l);
+    let mut out: (Vec<u32>, Vec<f64>) = (Vec::new(), Vec::new());
+    for (l, w) in v {
+        match out.0.last() {
+            Some(l1) if *l1 == l => *out.1.last_mut().unwrap() += w,
+            _ => {
+                out.0.push(l);
+                out.1.push(w)
+            }
+        }
+    }
+    let z: f64 = out.1.iter().sum();
+    if z <= 0.0 {
+        return Err(anyhow!("No read lengths remain after trimming"));
+    }
+    for w in out.1.iter_mut() {
+        *w /= z
+    }
+    debug!("{} effective read lengths after trimming", out.0.len());
+    Ok(out)
+}
+
+fn read_weighted_lengths<R: BufRead>(
+    mut rdr: R,
+    desc: &str,
+    min: u32,
+) -> anyhow::Result<(Vec<u32>, Vec<f64>)> {
+    let mut buf = String::new();
+    let mut lengths: Vec<u32> = Vec::new();
+    let mut weights = Vec::new();
+
+    let mut line = 0;
+    while rdr
+        .read_line(&mut buf)
+        .with_context(|| format!("Error reading line {} from {desc} file", line + 1))?
+        > 0
+    {
+        line += 1;
+        let s = buf.trim();
+        if !(s.is_empty() || s.starts_with('#')) {
+            let mut itr = s.split_ascii_whitespace();
+            let l = itr
+                .next()
+                .unwrap()
+                .parse::<u32>()
+                .with_context(|| format!("Bad {desc} at line {line}"))?;
+            let w = itr
+                .next()
+                .ok_or_else(|| anyhow!("Missing weight at line {line}"))?
+                .parse::<f64>()
+                .with_context(|| format!("Bad weight at line {line}"))?;
+            if l < min {
+                return Err(anyhow!(
+                    "Bad {desc} {l} at line {line} (must be at least {min})"
+                ));
+            }
+            if !(w >= 0.0 && w.is_finite()) {
+                return Err(anyhow!("Illegal weight at line {line}"));
+            }
+            if lengths.contains(&l) {
+                return Err(anyhow!("Duplicate {desc} {l} at line {line}"));
+            }
+            lengths.push(l);
+            weights.push(w);
+        }
+        buf.clear();
+    }
+
+    let z: f64 = weights.iter().sum();
+    if z <= 0.0 {
+        return Err(anyhow!("No positive weights found in {desc} file"));
+    }
+    for w in weights.iter_mut() {
+        *w /= z
+    }
+    debug!("Read in {} {desc}s", lengths.len());
+    Ok((lengths, weights))
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_apply_trim() {
+        let (l, w) = apply_trim(&[100, 150], None, &[0, 50, 100], &[0.5, 0.25, 0.25]).unwrap();
+        assert_eq!(l, vec![50, 100, 150]);
+        // Length 100 from 100 untrimmed and 150 trimmed by 50; 100 trimmed by 100 is dropped
+        assert_eq!(w, vec![0.25 / 0.875, 0.375 / 0.875, 0.25 / 0.875]);
+        assert!(apply_trim(&[50], None, &[50], &[1.0]).is_err());
+    }
+}
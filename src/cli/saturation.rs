@@ -0,0 +1,61 @@
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+
+pub struct SaturationConfig {
+    kmcv: PathBuf,
+    prefix: String,
+    read_length: u32,
+    depths: Vec<f64>,
+}
+
+impl SaturationConfig {
+    pub fn kmcv(&self) -> &Path {
+        &self.kmcv
+    }
+
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_str()
+    }
+
+    pub fn read_length(&self) -> u32 {
+        self.read_length
+    }
+
+    /// Mean depths at which the curve is evaluated (sorted)
+    pub fn depths(&self) -> &[f64] {
+        &self.depths
+    }
+}
+
+pub(super) fn saturation_config(m: &ArgMatches) -> anyhow::Result<SaturationConfig> {
+    let kmcv = m
+        .get_one::<PathBuf>("kmcv")
+        .map(|p| p.to_owned())
+        .expect("Missing required argument");
+
+    let prefix = m
+        .get_one::<String>("prefix")
+        .map(|s| s.to_owned())
+        .expect("Missing default argument");
+
+    let mut depths: Vec<f64> = m
+        .get_many::<f64>("depths")
+        .expect("Missing default argument")
+        .copied()
+        .collect();
+    if depths.iter().any(|x| !(x.is_finite() && *x > 0.0)) {
+        return Err(anyhow!("Illegal depth: must be > 0"));
+    }
+    depths.sort_unstable_by(|a, b| a.total_cmp(b));
+    depths.dedup();
+
+    Ok(SaturationConfig {
+        kmcv,
+        prefix,
+        read_length: *m
+            .get_one::<u32>("read_length")
+            .expect("Missing default argument"),
+        depths,
+    })
+}
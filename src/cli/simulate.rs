@@ -0,0 +1,76 @@
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+
+pub struct SimulateConfig {
+    kmcv: PathBuf,
+    targets: PathBuf,
+    bias: PathBuf,
+    column: Option<String>,
+    prefix: String,
+    depth: f64,
+    min_depth: u32,
+}
+
+impl SimulateConfig {
+    pub fn kmcv(&self) -> &Path {
+        &self.kmcv
+    }
+
+    pub fn targets(&self) -> &Path {
+        &self.targets
+    }
+
+    pub fn bias(&self) -> &Path {
+        &self.bias
+    }
+
+    /// Column of bias file to use (if not set, the first bias column is used)
+    pub fn column(&self) -> Option<&str> {
+        self.column.as_deref()
+    }
+
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_str()
+    }
+
+    /// Mean depth over all targets
+    pub fn depth(&self) -> f64 {
+        self.depth
+    }
+
+    /// Targets with coverage below this are considered as dropouts
+    pub fn min_depth(&self) -> u32 {
+        self.min_depth
+    }
+}
+
+pub(super) fn simulate_config(m: &ArgMatches) -> anyhow::Result<SimulateConfig> {
+    let get_path = |s| {
+        m.get_one::<PathBuf>(s)
+            .map(|p| p.to_owned())
+            .expect("Missing required argument")
+    };
+
+    let prefix = m
+        .get_one::<String>("prefix")
+        .map(|s| s.to_owned())
+        .expect("Missing default argument");
+
+    let depth = match m.get_one::<f64>("depth").expect("Missing default argument") {
+        x if x > &0.0 && x.is_finite() => Ok(*x),
+        _ => Err(anyhow!("Illegal depth: must be > 0")),
+    }?;
+
+    Ok(SimulateConfig {
+        kmcv: get_path("kmcv"),
+        targets: get_path("targets"),
+        bias: get_path("bias"),
+        column: m.get_one::<String>("column").map(|s| s.to_owned()),
+        prefix,
+        depth,
+        min_depth: *m
+            .get_one::<u32>("min_depth")
+            .expect("Missing default argument"),
+    })
+}
@@ -0,0 +1,52 @@
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+
+pub struct UniqueBedConfig {
+    kmcv: PathBuf,
+    prefix: String,
+    min_unique: f64,
+}
+
+impl UniqueBedConfig {
+    pub fn kmcv(&self) -> &Path {
+        &self.kmcv
+    }
+
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_str()
+    }
+
+    /// Minimum proportion of uniquely mapping kmers for a target to be output
+    pub fn min_unique(&self) -> f64 {
+        self.min_unique
+    }
+}
+
+pub(super) fn unique_bed_config(m: &ArgMatches) -> anyhow::Result<UniqueBedConfig> {
+    let kmcv = m
+        .get_one::<PathBuf>("kmcv")
+        .map(|p| p.to_owned())
+        .expect("Missing required argument");
+
+    let prefix = m
+        .get_one::<String>("prefix")
+        .map(|s| s.to_owned())
+        .expect("Missing default argument");
+
+    let min_unique = match m
+        .get_one::<f64>("min_unique")
+        .expect("Missing default argument")
+    {
+        x if (0.0..=1.0).contains(x) => Ok(*x),
+        _ => Err(anyhow!(
+            "Illegal minimum unique proportion: must be >= 0 and <= 1.0"
+        )),
+    }?;
+
+    Ok(UniqueBedConfig {
+        kmcv,
+        prefix,
+        min_unique,
+    })
+}
@@ -0,0 +1,58 @@
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+
+use crate::kmcv::KmerWriterOpts;
+
+pub struct UpdateKmersConfig {
+    kmcv: PathBuf,
+    targets: PathBuf,
+    input: PathBuf,
+    prefix: String,
+    kmer_writer: KmerWriterOpts,
+}
+
+impl UpdateKmersConfig {
+    pub fn kmcv(&self) -> &Path {
+        &self.kmcv
+    }
+
+    /// BED file with the targets to add
+    pub fn targets(&self) -> &Path {
+        &self.targets
+    }
+
+    /// Reference used to generate the kmer file
+    pub fn input(&self) -> &Path {
+        &self.input
+    }
+
+    pub fn prefix(&self) -> &str {
+        self.prefix.as_str()
+    }
+
+    pub fn kmer_writer(&self) -> &KmerWriterOpts {
+        &self.kmer_writer
+    }
+}
+
+pub(super) fn update_kmers_config(m: &ArgMatches) -> anyhow::Result<UpdateKmersConfig> {
+    let get_path = |s: &str| {
+        m.get_one::<PathBuf>(s)
+            .map(|p| p.to_owned())
+            .expect("Missing required argument")
+    };
+
+    let prefix = m
+        .get_one::<String>("prefix")
+        .map(|s| s.to_owned())
+        .expect("Missing default argument");
+
+    Ok(UpdateKmersConfig {
+        kmcv: get_path("kmcv"),
+        targets: get_path("targets"),
+        input: get_path("input"),
+        prefix,
+        kmer_writer: super::kmer_writer_opts(m),
+    })
+}
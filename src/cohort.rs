@@ -0,0 +1,254 @@
+//! Comparison of the GC distributions from several result files (cohort-summary)
+//!
+//! The window counts for each read length are read from the ``read_length_specific_counts`` of
+//! each result JSON file and aligned to a common set of GC bins, so that results for different
+//! references (or read lengths calculated with different options) can be compared directly.  For
+//! each read length the Jensen-Shannon divergence (bits) and the total variation distance are
+//! calculated between every pair of inputs.  The pairwise values are written in long form to
+//! ``<prefix>_cohort_pairs.tsv``, and the Jensen-Shannon divergences as a square matrix to
+//! ``<prefix>_cohort_<read length>bp.tsv`` for use with heatmap tools.  A summary of each input is
+//! written to ``<prefix>_cohort_samples.tsv``.
+use std::{io::Write, path::Path};
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+use rustc_hash::FxHashSet;
+use serde_json::Value;
+
+use crate::{cli::CohortConfig, cross_validation::divergence};
+
+/// One result file
+struct CohortInput {
+    name: String,
+    identifier: Option<String>,
+    counts: Value,
+}
+
+impl CohortInput {
+    fn read(path: &Path) -> anyhow::Result<Self> {
+        let rdr = CompressIo::new()
+            .path(path)
+            .bufreader()
+            .with_context(|| format!("Could not open result file {}", path.display()))?;
+        let mut v: Value = serde_json::from_reader(rdr)
+            .with_context(|| format!("Error reading result file {}", path.display()))?;
+        let counts = v
+            .get_mut("read_length_specific_counts")
+            .map(|c| c.take())
+            .filter(|c| c.is_object())
+            .ok_or_else(|| anyhow!("No GC counts found in {}", path.display()))?;
+        let name = path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .map(|s| s.trim_end_matches(".gz").trim_end_matches(".json"))
+            .unwrap_or_default()
+            .to_owned();
+        Ok(Self {
+            name,
+            identifier: v
+                .get("identifier")
+                .and_then(|s| s.as_str())
+                .map(|s| s.to_owned()),
+            counts,
+        })
+    }
+
+    /// Read lengths with counts
+    fn read_lengths(&self) -> FxHashSet<u32> {
+        self.counts
+            .as_object()
+            .unwrap()
+            .keys()
+            .filter_map(|k| k.parse().ok())
+            .collect()
+    }
+
+    /// Window counts for read length rl binned into bins GC bins, and the mean GC
+    fn hist(&self, rl: u32, bins: u32) -> anyhow::Result<(Vec<f64>, f64)> {
+        let counts = self
+            .counts
+            .get(rl.to_string())
+            .and_then(|c| c.get("counts"))
+            .and_then(|c| c.as_object())
+            .ok_or_else(|| anyhow!("No counts for read length {rl} in {}", self.name))?;
+        binned_counts(counts.iter().map(|(k, v)| (k.as_str(), v)), bins)
+            .with_context(|| format!("Error in counts for read length {rl} in {}", self.name))
+    }
+}
+
+/// Sum the counts with keys ``at:gc`` into bins GC bins of equal width, returning the bins and
+/// the mean GC (NaN if there are no windows)
+fn binned_counts<'a, I>(counts: I, bins: u32) -> anyhow::Result<(Vec<f64>, f64)>
+where
+    I: Iterator<Item = (&'a str, &'a Value)>,
+{
+    let mut h = vec![0.0; bins as usize];
+    let (mut s, mut z) = (0.0, 0.0);
+    for (k, v) in counts {
+        let (at, gc) = k
+            .split_once(':')
+            .and_then(|(a, g)| Some((a.parse::<u64>().ok()?, g.parse::<u64>().ok()?)))
+            .ok_or_else(|| anyhow!("Illegal count key {k}"))?;
+        let x = v.as_f64().ok_or_else(|| anyhow!("Illegal count for {k}"))?;
+        if let Some(ix) = (gc * bins as u64).checked_div(at + gc) {
+            let ix = ix.min(bins as u64 - 1);
+            h[ix as usize] += x;
+            s += x * gc as f64 / (at + gc) as f64;
+            z += x
+        }
+    }
+    Ok((h, if z > 0.0 { s / z } else { f64::NAN }))
+}
+
+/// Unique names for the inputs (file names without the extension, or the full paths if these
+/// clash)
+fn input_names(cfg: &CohortConfig, inputs: &[CohortInput]) -> Vec<String> {
+    let names: FxHashSet<_> = inputs.iter().map(|i| i.name.as_str()).collect();
+    if names.len() == inputs.len() {
+        inputs.iter().map(|i| i.name.clone()).collect()
+    } else {
+        cfg.inputs()
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect()
+    }
+}
+
+pub fn cohort_summary(cfg: &CohortConfig) -> anyhow::Result<()> {
+    let inputs = cfg
+        .inputs()
+        .iter()
+        .map(|p| CohortInput::read(p))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let names = input_names(cfg, &inputs);
+
+    let read_lengths = match cfg.read_lengths() {
+        Some(v) => v.to_vec(),
+        None => {
+            let mut common = inputs[0].read_lengths();
+            for i in &inputs[1..] {
+                let rl = i.read_lengths();
+                common.retain(|x| rl.contains(x))
+            }
+            let mut v: Vec<_> = common.into_iter().collect();
+            v.sort_unstable();
+            v
+        }
+    };
+    if read_lengths.is_empty() {
+        return Err(anyhow!("No read lengths common to all inputs"));
+    }
+    let bins = cfg.bins();
+    let n = inputs.len();
+    let prefix = cfg.prefix();
+
+    let mut samples = CompressIo::new()
+        .path(format!("{prefix}_cohort_samples.tsv"))
+        .bufwriter()
+        .with_context(|| "Could not open cohort samples file for output")?;
+    writeln!(samples, "read_length\tsample\tidentifier\twindows\tmean_gc")?;
+    let mut pairs = CompressIo::new()
+        .path(format!("{prefix}_cohort_pairs.tsv"))
+        .bufwriter()
+        .with_context(|| "Could not open cohort pairs file for output")?;
+    writeln!(
+        pairs,
+        "read_length\tsample_1\tsample_2\tjensen_shannon\ttotal_variation\tmean_gc_difference"
+    )?;
+
+    for rl in read_lengths {
+        info!("Comparing distributions for read length {rl}");
+        let (hists, gc): (Vec<_>, Vec<_>) = inputs
+            .iter()
+            .map(|i| i.hist(rl, bins))
+            .collect::<anyhow::Result<Vec<_>>>()?
+            .into_iter()
+            .unzip();
+        let totals: Vec<f64> = hists.iter().map(|h| h.iter().sum()).collect();
+        for ((i, name), (t, g)) in inputs.iter().zip(&names).zip(totals.iter().zip(&gc)) {
+            writeln!(
+                samples,
+                "{rl}\t{name}\t{}\t{t}\t{g:.6}",
+                i.identifier.as_deref().unwrap_or("")
+            )?;
+            if *t <= 0.0 {
+                warn!("No windows for read length {rl} in {name}")
+            }
+        }
+        let dens: Vec<Vec<f64>> = hists
+            .iter()
+            .zip(&totals)
+            .map(|(h, t)| {
+                h.iter()
+                    .map(|x| if *t > 0.0 { x / t } else { 0.0 })
+                    .collect()
+            })
+            .collect();
+
+        // Jensen-Shannon matrix (NaN where either input has no windows)
+        let mut js = vec![vec![0.0; n]; n];
+        for i in 0..n {
+            for j in i + 1..n {
+                let (d, tv) = if totals[i] > 0.0 && totals[j] > 0.0 {
+                    divergence(&dens[i], &dens[j])
+                } else {
+                    (f64::NAN, f64::NAN)
+                };
+                js[i][j] = d;
+                js[j][i] = d;
+                writeln!(
+                    pairs,
+                    "{rl}\t{}\t{}\t{d:.6e}\t{tv:.6e}\t{:.6}",
+                    names[i],
+                    names[j],
+                    gc[j] - gc[i]
+                )?
+            }
+        }
+        let path = format!("{prefix}_cohort_{rl}bp.tsv");
+        let mut wrt = CompressIo::new()
+            .path(&path)
+            .bufwriter()
+            .with_context(|| format!("Could not open cohort matrix file {path} for output"))?;
+        write!(wrt, "sample")?;
+        for name in &names {
+            write!(wrt, "\t{name}")?
+        }
+        writeln!(wrt)?;
+        for (name, row) in names.iter().zip(&js) {
+            write!(wrt, "{name}")?;
+            for d in row {
+                write!(wrt, "\t{d:.6e}")?
+            }
+            writeln!(wrt)?
+        }
+    }
+    Ok(())
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_cohort() {
+        let v: Value =
+            serde_json::from_str(r#"{"0:4": 1, "2:2": 2.5, "4:0": 3, "0:0": 7}"#).unwrap();
+        let (h, gc) = binned_counts(
+            v.as_object().unwrap().iter().map(|(k, v)| (k.as_str(), v)),
+            4,
+        )
+        .unwrap();
+        assert_eq!(h, [3.0, 0.0, 2.5, 1.0]);
+        assert!((gc - 2.25 / 6.5).abs() < 1.0e-12);
+        let bad: Value = serde_json::from_str(r#"{"a:4": 1}"#).unwrap();
+        assert!(binned_counts(
+            bad.as_object()
+                .unwrap()
+                .iter()
+                .map(|(k, v)| (k.as_str(), v)),
+            4
+        )
+        .is_err());
+    }
+}
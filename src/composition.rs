@@ -0,0 +1,267 @@
+//! Base composition summary for the whole input and the target territory (--composition)
+//!
+//! Counts of single bases, dinucleotides and trinucleotides are collected from the raw input as
+//! it is read (as for the repeat scan).  Di and trinucleotides are only counted where all bases
+//! are ACGT, and for the target territory where all bases are on target (using the same rule as
+//! the target statistics).  Entropies are in bits, from the ACGT mono, di and trinucleotide
+//! frequencies.
+use serde::Serialize;
+
+use crate::{
+    fasta::Base,
+    regions::{Region, Regions},
+    tap::StreamTap,
+};
+
+/// Mono, di and trinucleotide counts
+#[derive(Debug, Clone)]
+struct KmerCounts {
+    /// A, C, T, G, N (and other characters)
+    mono: [u64; 5],
+    di: [u64; 16],
+    tri: [u64; 64],
+    /// Codes of the last two bases, and the number of preceding consecutive ACGT bases (max 2)
+    prev: u8,
+    run: u8,
+}
+
+impl Default for KmerCounts {
+    fn default() -> Self {
+        Self {
+            mono: [0; 5],
+            di: [0; 16],
+            tri: [0; 64],
+            prev: 0,
+            run: 0,
+        }
+    }
+}
+
+impl KmerCounts {
+    #[inline]
+    fn add(&mut self, b: Base) {
+        if b.is_gap() {
+            self.mono[4] += 1;
+            self.run = 0
+        } else {
+            let x = b as u8;
+            self.mono[x as usize] += 1;
+            if self.run > 0 {
+                self.di[(((self.prev & 3) << 2) | x) as usize] += 1
+            }
+            if self.run > 1 {
+                self.tri[(((self.prev & 15) << 2) | x) as usize] += 1
+            }
+            self.prev = (self.prev << 2) | x;
+            self.run = (self.run + 1).min(2)
+        }
+    }
+
+    /// Break the context (at the start of a contig or leaving a target)
+    fn reset(&mut self) {
+        self.run = 0
+    }
+
+    fn summary(&self) -> BaseSummary {
+        let [a, c, t, g, n] = self.mono;
+        let acgt = a + c + g + t;
+        BaseSummary {
+            a,
+            c,
+            g,
+            t,
+            n,
+            gc_percent: if acgt > 0 {
+                100.0 * (c + g) as f64 / acgt as f64
+            } else {
+                f64::NAN
+            },
+            cpg: self.di[((Base::C as usize) << 2) | Base::G as usize],
+            entropy_mono: entropy(&self.mono[..4]),
+            entropy_di: entropy(&self.di),
+            entropy_tri: entropy(&self.tri),
+        }
+    }
+}
+
+/// Shannon entropy (bits) of the frequencies from counts
+fn entropy(counts: &[u64]) -> f64 {
+    let n: u64 = counts.iter().sum();
+    if n == 0 {
+        return f64::NAN;
+    }
+    counts
+        .iter()
+        .filter(|x| **x > 0)
+        .map(|x| {
+            let p = *x as f64 / n as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BaseSummary {
+    #[serde(rename = "A")]
+    a: u64,
+    #[serde(rename = "C")]
+    c: u64,
+    #[serde(rename = "G")]
+    g: u64,
+    #[serde(rename = "T")]
+    t: u64,
+    /// N and other non ACGT characters
+    #[serde(rename = "N")]
+    n: u64,
+    gc_percent: f64,
+    cpg: u64,
+    entropy_mono: f64,
+    entropy_di: f64,
+    entropy_tri: f64,
+}
+
+impl BaseSummary {
+    pub fn gc_percent(&self) -> f64 {
+        self.gc_percent
+    }
+
+    pub fn cpg(&self) -> u64 {
+        self.cpg
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompositionSummary {
+    genome: BaseSummary,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    targets: Option<BaseSummary>,
+}
+
+impl CompositionSummary {
+    pub fn genome(&self) -> &BaseSummary {
+        &self.genome
+    }
+
+    pub fn targets(&self) -> Option<&BaseSummary> {
+        self.targets.as_ref()
+    }
+}
+
+/// Tap collecting the composition of the input, and of the targets if given
+pub struct CompositionScan<'a> {
+    genome: KmerCounts,
+    targets: Option<(&'a Regions, KmerCounts)>,
+    /// Target regions for the current contig from the first that could contain pos
+    current: &'a [Region],
+    pos: u64,
+    in_header: bool,
+    name_done: bool,
+    at_line_start: bool,
+    name: Vec<u8>,
+}
+
+impl<'a> CompositionScan<'a> {
+    pub fn new(targets: Option<&'a Regions>) -> Self {
+        Self {
+            genome: KmerCounts::default(),
+            targets: targets.map(|r| (r, KmerCounts::default())),
+            current: &[],
+            pos: 0,
+            in_header: false,
+            name_done: false,
+            at_line_start: true,
+            name: Vec::new(),
+        }
+    }
+
+    fn new_contig(&mut self) {
+        self.genome.reset();
+        if let Some((reg, k)) = self.targets.as_mut() {
+            k.reset();
+            let name = String::from_utf8_lossy(&self.name);
+            self.current = reg.get(&name).map(|r| r.regions()).unwrap_or(&[])
+        }
+        self.name.clear();
+        self.pos = 0
+    }
+
+    #[inline]
+    fn add_base(&mut self, c: u8) {
+        let b = Base::from_u8(c);
+        self.genome.add(b);
+        if let Some((_, k)) = self.targets.as_mut() {
+            let pos = self.pos;
+            while self.current.first().is_some_and(|r| pos > r.end()) {
+                self.current = &self.current[1..]
+            }
+            if self.current.first().is_some_and(|r| pos >= r.start()) {
+                k.add(b)
+            } else {
+                k.reset()
+            }
+        }
+        self.pos += 1
+    }
+
+    pub fn finish(self) -> CompositionSummary {
+        CompositionSummary {
+            genome: self.genome.summary(),
+            targets: self.targets.map(|(_, k)| k.summary()),
+        }
+    }
+}
+
+impl StreamTap for CompositionScan<'_> {
+    fn update(&mut self, buf: &[u8]) {
+        for c in buf.iter().copied() {
+            if self.in_header {
+                if c == b'\n' {
+                    self.in_header = false;
+                    self.at_line_start = true;
+                    self.new_contig()
+                } else if !self.name_done {
+                    if c.is_ascii_whitespace() {
+                        self.name_done = true
+                    } else {
+                        self.name.push(c)
+                    }
+                }
+            } else if self.at_line_start && c == b'>' {
+                self.in_header = true;
+                self.name_done = false
+            } else if c == b'\n' {
+                self.at_line_start = true
+            } else {
+                self.at_line_start = false;
+                if !c.is_ascii_whitespace() {
+                    self.add_base(c)
+                }
+            }
+        }
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_composition() {
+        let mut scan = CompositionScan::new(None);
+        scan.update(b">chr1 test\nACGTN\nCG\n>chr2\nGG");
+        let s = scan.finish().genome;
+        assert_eq!((s.a, s.c, s.g, s.t, s.n), (1, 2, 4, 1, 1));
+        assert!((s.gc_percent - 75.0).abs() < 1.0e-12);
+        // CpG in ACGT and CG, but not across the N or the contig boundary
+        assert_eq!(s.cpg, 2);
+        assert!((s.entropy_mono - 1.75).abs() < 1.0e-12);
+
+        let mut k = KmerCounts::default();
+        for b in [Base::A, Base::C, Base::G, Base::T] {
+            k.add(b)
+        }
+        assert_eq!(k.di.iter().sum::<u64>(), 3);
+        assert_eq!(k.tri.iter().sum::<u64>(), 2);
+        assert_eq!(entropy(&k.tri), 1.0);
+    }
+}
@@ -0,0 +1,173 @@
+//! Exclusion of contigs by length, for example to drop organelle genomes, plasmids and small
+//! unplaced contigs that can distort whole genome GC summaries.  The contig lengths are taken
+//! from the .fai index if present, otherwise the input is scanned before the analysis
+//!
+//! Also reading of contig lists, such as the allowlist of contigs used for kmer recording
+use std::{io::BufRead, path::Path};
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+use rustc_hash::FxHashSet;
+use serde::Serialize;
+
+use crate::fasta::{
+    fai::{find_fai, read_fai},
+    tar::open_fasta,
+};
+
+/// Contig excluded by the length filters
+#[derive(Debug, Clone, Serialize)]
+pub struct FilteredContig {
+    name: String,
+    length: u64,
+}
+
+impl FilteredContig {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+}
+
+pub struct ContigFilter {
+    excluded: FxHashSet<String>,
+    filtered: Vec<FilteredContig>,
+}
+
+impl ContigFilter {
+    /// Find the contigs of input with length outside of [min, max]
+    pub fn new(input: &Path, min: Option<u64>, max: Option<u64>) -> anyhow::Result<Self> {
+        let lengths = match find_fai(input) {
+            Some(fai) => read_fai(fai)?
+                .iter()
+                .map(|e| (e.name().to_owned(), e.length()))
+                .collect(),
+            None => {
+                info!("Scanning input for contig lengths");
+                contig_lengths(open_fasta(Some(input))?)?
+            }
+        };
+        let filtered: Vec<_> = lengths
+            .into_iter()
+            .filter(|(_, l)| min.is_some_and(|m| *l < m) || max.is_some_and(|m| *l > m))
+            .map(|(name, length)| FilteredContig { name, length })
+            .collect();
+        if !filtered.is_empty() {
+            info!(
+                "Excluding {} contigs by length: {}",
+                filtered.len(),
+                filtered
+                    .iter()
+                    .map(|c| format!("{} ({})", c.name, c.length))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        }
+        Ok(Self {
+            excluded: filtered.iter().map(|c| c.name.clone()).collect(),
+            filtered,
+        })
+    }
+
+    pub fn is_excluded(&self, ctg: &str) -> bool {
+        self.excluded.contains(ctg)
+    }
+
+    /// Contigs excluded by the filters in input order
+    pub fn filtered(&self) -> &[FilteredContig] {
+        &self.filtered
+    }
+}
+
+/// Contig names from the first column of each line.  Empty lines and lines starting with '#' are
+/// skipped, so a .fai index can also be used
+fn parse_contig_list<R: BufRead>(rdr: R, path: &Path) -> anyhow::Result<FxHashSet<String>> {
+    let mut set = FxHashSet::default();
+    for l in rdr.lines() {
+        let l = l.with_context(|| format!("Error reading from {}", path.display()))?;
+        let s = l.trim();
+        if s.is_empty() || s.starts_with('#') {
+            continue;
+        }
+        set.insert(s.split_ascii_whitespace().next().unwrap().to_owned());
+    }
+    if set.is_empty() {
+        return Err(anyhow!("No contigs found in {}", path.display()));
+    }
+    Ok(set)
+}
+
+/// Read a list of contig names (one per line, only the first column is used)
+pub fn read_contig_list<P: AsRef<Path>>(path: P) -> anyhow::Result<FxHashSet<String>> {
+    let path = path.as_ref();
+    let rdr = CompressIo::new()
+        .path(path)
+        .bufreader()
+        .with_context(|| format!("Could not open contig list {}", path.display()))?;
+    let set = parse_contig_list(rdr, path)?;
+    info!("Read {} contigs from {}", set.len(), path.display());
+    Ok(set)
+}
+
+/// Name and number of sequence characters for each record
+fn contig_lengths<R: BufRead>(mut rdr: R) -> anyhow::Result<Vec<(String, u64)>> {
+    let mut v: Vec<(String, u64)> = Vec::new();
+    let mut buf = Vec::new();
+    while rdr
+        .read_until(b'\n', &mut buf)
+        .with_context(|| "Error reading input")?
+        > 0
+    {
+        if buf[0] == b'>' {
+            let name = buf[1..]
+                .split(|c| c.is_ascii_whitespace())
+                .next()
+                .unwrap_or(&[]);
+            v.push((String::from_utf8_lossy(name).into_owned(), 0))
+        } else if let Some((_, l)) = v.last_mut() {
+            *l += buf.iter().filter(|c| !c.is_ascii_whitespace()).count() as u64
+        }
+        buf.clear()
+    }
+    Ok(v)
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_contig_lengths() {
+        let input = b">chr1 x\nACGT\nAC\n>chrM\nNNA\r\n>empty\n";
+        let v = contig_lengths(&input[..]).unwrap();
+        assert_eq!(
+            v,
+            vec![
+                ("chr1".to_owned(), 6),
+                ("chrM".to_owned(), 3),
+                ("empty".to_owned(), 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_contig_list() {
+        let input = b"# primary
+chr1	248956422	6
+
+chr2 x
+";
+        let s = parse_contig_list(&input[..], Path::new("test")).unwrap();
+        assert_eq!(s.len(), 2);
+        assert!(s.contains("chr1") && s.contains("chr2"));
+        assert!(parse_contig_list(
+            &b"# none
+"[..],
+            Path::new("test")
+        )
+        .is_err());
+    }
+}
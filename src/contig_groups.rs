@@ -0,0 +1,255 @@
+//! Grouping of contigs (e.g., autosome/sex/organelle, or the haplotypes of a phased assembly) for
+//! reporting GC distributions and kmer uniqueness per group
+//!
+//! Windows are assigned to the group of their contig.  Kmer uniqueness requires two passes over
+//! the input (as for the repeat partitions): the first counts all canonical kmers in the genome
+//! and the second counts, for each group, the kmers that occur only once (the passes are shared
+//! with the other kmer uniqueness options; see [`crate::rescan`]).  Kmers are unique with respect
+//! to the whole genome, so sequence shared between haplotypes is not unique.
+use std::{io::BufRead, path::Path};
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+
+use crate::{
+    fasta::{Base, Seq},
+    kmers::{KmerBuilder, KmerCounts},
+    warnings::add_warning,
+};
+
+/// Mapping of contig names to groups
+pub struct ContigGroups {
+    groups: Vec<String>,
+    contigs: FxHashMap<String, usize>,
+}
+
+impl ContigGroups {
+    /// Group names in order of first appearance in the group file
+    pub fn groups(&self) -> &[String] {
+        &self.groups
+    }
+
+    /// Index of group for contig
+    pub fn group(&self, ctg: &str) -> Option<usize> {
+        self.contigs.get(ctg).copied()
+    }
+}
+
+fn parse_groups<R: BufRead>(rdr: R, path: &Path) -> anyhow::Result<ContigGroups> {
+    let mut groups: Vec<String> = Vec::new();
+    let mut contigs = FxHashMap::default();
+    for (i, l) in rdr.lines().enumerate() {
+        let l = l.with_context(|| format!("Error reading from {}", path.display()))?;
+        let s = l.trim();
+        if s.is_empty() || s.starts_with('#') {
+            continue;
+        }
+        let err = || format!("Error at line {} of {}", i + 1, path.display());
+        let mut itr = s.split_ascii_whitespace();
+        let ctg = itr.next().unwrap();
+        let grp = itr
+            .next()
+            .ok_or_else(|| anyhow!("Missing group"))
+            .with_context(err)?;
+        let ix = match groups.iter().position(|g| g == grp) {
+            Some(ix) => ix,
+            None => {
+                groups.push(grp.to_owned());
+                groups.len() - 1
+            }
+        };
+        if contigs.insert(ctg.to_owned(), ix).is_some() {
+            return Err(anyhow!("Duplicate contig {ctg}")).with_context(err);
+        }
+    }
+    if groups.is_empty() {
+        return Err(anyhow!("No groups found in {}", path.display()));
+    }
+    info!(
+        "Read {} contigs in {} groups from {}",
+        contigs.len(),
+        groups.len(),
+        path.display()
+    );
+    Ok(ContigGroups { groups, contigs })
+}
+
+/// Read a two column (contig, group) file.  Empty lines and lines starting with '#' are skipped
+pub fn read_contig_groups<P: AsRef<Path>>(path: P) -> anyhow::Result<ContigGroups> {
+    let path = path.as_ref();
+    let rdr = CompressIo::new()
+        .path(path)
+        .bufreader()
+        .with_context(|| format!("Could not open contig group file {}", path.display()))?;
+    parse_groups(rdr, path)
+}
+
+/// Contig, base and kmer counts for a group
+#[derive(Default, Debug, Clone, Serialize)]
+pub struct GroupSummary {
+    name: String,
+    contigs: u64,
+    bases: u64,
+    kmers: u64,
+    unique_kmers: u64,
+}
+
+impl GroupSummary {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn contigs(&self) -> u64 {
+        self.contigs
+    }
+
+    /// Number of non gap bases
+    pub fn bases(&self) -> u64 {
+        self.bases
+    }
+
+    pub fn kmers(&self) -> u64 {
+        self.kmers
+    }
+
+    pub fn unique_kmers(&self) -> u64 {
+        self.unique_kmers
+    }
+}
+
+/// Accumulates contig, base, kmer and unique kmer counts for each group from the sequences of
+/// the input
+pub struct GroupCounts<'a> {
+    groups: &'a ContigGroups,
+    summary: Vec<GroupSummary>,
+    ungrouped: Vec<String>,
+    last: Option<String>,
+}
+
+impl<'a> GroupCounts<'a> {
+    pub fn new(groups: &'a ContigGroups) -> Self {
+        let summary = groups
+            .groups()
+            .iter()
+            .map(|g| GroupSummary {
+                name: g.clone(),
+                ..Default::default()
+            })
+            .collect();
+        Self {
+            groups,
+            summary,
+            ungrouped: Vec::new(),
+            last: None,
+        }
+    }
+
+    /// Add sequence s, classifying its kmers using the genome wide counts
+    pub fn add_seq(&mut self, s: &Seq, counts: &KmerCounts) {
+        // A contig can be split into several sequences at long gaps
+        let new = self.last.as_deref() != Some(s.contig());
+        if new {
+            self.last = Some(s.contig().to_owned())
+        }
+        let Some(p) = self.groups.group(s.contig()).map(|i| &mut self.summary[i]) else {
+            if new {
+                self.ungrouped.push(s.contig().to_owned())
+            }
+            return;
+        };
+        p.contigs += new as u64;
+        p.bases += s.iter().filter(|b| **b < Base::N).count() as u64;
+        let mut builder = KmerBuilder::new();
+        for b in s.iter() {
+            builder.add_base(*b, None);
+            if let Some(km) = builder.kmers() {
+                p.kmers += 1;
+                if counts.is_unique(km) {
+                    p.unique_kmers += 1
+                }
+            }
+        }
+    }
+
+    /// Summaries for each group, warning about contigs not assigned to a group
+    pub fn finish(self) -> Vec<GroupSummary> {
+        if !self.ungrouped.is_empty() {
+            add_warning(
+                "ungrouped_contigs",
+                format!(
+                    "{} contigs not assigned to a group: {}",
+                    self.ungrouped.len(),
+                    self.ungrouped.join(", ")
+                ),
+            )
+        }
+        self.summary
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use crate::{
+        fasta::{FastaReader, GapPolicy},
+        kmers::KMER_LENGTH,
+    };
+
+    #[test]
+    fn test_parse_groups() {
+        let input = "# contig group\nchr1\tautosome\nchrX\tsex\nchr2 autosome\n\nchrM\torganelle\n";
+        let g = parse_groups(input.as_bytes(), Path::new("groups.txt")).unwrap();
+        assert_eq!(g.groups(), ["autosome", "sex", "organelle"]);
+        assert_eq!(g.group("chr2"), Some(0));
+        assert_eq!(g.group("chrM"), Some(2));
+        assert_eq!(g.group("chrY"), None);
+        assert!(parse_groups("chr1 a\nchr1 b\n".as_bytes(), Path::new("x")).is_err());
+    }
+
+    #[test]
+    fn test_group_counts() {
+        let g = parse_groups("chr1 a\nchr2 b\n".as_bytes(), Path::new("x")).unwrap();
+        let x = "ACGTTGCAAGGCTTACGATC";
+        let y = "TTGACCAGTAGGCATCCAAG";
+        // chr1 is split at the gap, so is read as two sequences holding the same kmers
+        let input = format!(
+            ">chr1\n{x}{}{x}\n>chr3\nACGT\n>chr2\n{y}\n",
+            "N".repeat(1000)
+        );
+        let mut seqs = Vec::new();
+        let mut rdr = FastaReader::new(input.as_bytes(), GapPolicy::MinGap(1000));
+        while let Some(s) = rdr.next_seq().unwrap() {
+            seqs.push(s)
+        }
+        let mut counts = KmerCounts::new();
+        for s in seqs.iter() {
+            let mut builder = KmerBuilder::new();
+            for b in s.iter() {
+                builder.add_base(*b, None);
+                if let Some(km) = builder.kmers() {
+                    counts.add(km)
+                }
+            }
+        }
+        let mut gc = GroupCounts::new(&g);
+        for s in seqs.iter() {
+            gc.add_seq(s, &counts)
+        }
+        assert_eq!(gc.ungrouped, ["chr3"]);
+        let v = gc.finish();
+        let n = (21 - KMER_LENGTH) as u64;
+        let a = &v[0];
+        assert_eq!(
+            (a.contigs(), a.bases(), a.kmers(), a.unique_kmers()),
+            (1, 40, 2 * n, 0)
+        );
+        let b = &v[1];
+        assert_eq!(
+            (b.contigs(), b.bases(), b.kmers(), b.unique_kmers()),
+            (1, 20, n, n)
+        );
+    }
+}
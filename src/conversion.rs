@@ -0,0 +1,267 @@
+//! Model for incomplete conversion of bisulfite (or enzymatically) converted reads
+//!
+//! With bisulfite (or EM-seq) chemistry and a conversion rate r, each unmethylated C on a C
+//! depleted read (G on a G depleted read) remains unconverted with probability 1 - r.  Cs in a
+//! CpG context are methylated with probability m (the methylation level) and are then protected
+//! from conversion, so they remain with probability m + (1 - m)(1 - r); Cs outside CpGs are taken
+//! to be unmethylated.  The unconverted bases are counted as GC bases in the converted comparison
+//! (G+C:A for C depleted reads, C+G:T for G depleted reads), so a window contributes to the
+//! converted distribution with binomial weights over the number of unconverted bases.  With
+//! complete conversion and no methylation (the default) this reduces to the G:A and C:T
+//! comparisons.
+//!
+//! TAPS instead converts methylated Cs, so only CpG Cs are converted (with probability m r).  As
+//! most Cs remain, all bases are compared, with converted bases counted as AT bases.
+use std::borrow::Cow;
+
+use clap::{builder::PossibleValue, ValueEnum};
+use libm::lgamma;
+
+/// Conversion chemistry for converted libraries
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Chemistry {
+    /// Unmethylated Cs converted by bisulfite treatment
+    Bisulfite,
+    /// Unmethylated Cs converted enzymatically (same rules as bisulfite)
+    EmSeq,
+    /// Methylated Cs converted (TET-assisted pyridine borane sequencing)
+    Taps,
+}
+
+impl ValueEnum for Chemistry {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Bisulfite, Self::EmSeq, Self::Taps]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(self.name()))
+    }
+}
+
+impl Chemistry {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Bisulfite => "bisulfite",
+            Self::EmSeq => "em-seq",
+            Self::Taps => "taps",
+        }
+    }
+
+    /// True if methylated rather than unmethylated Cs are converted
+    pub fn converts_methylated(&self) -> bool {
+        matches!(self, Self::Taps)
+    }
+}
+
+/// Binomial probabilities below this (relative to the total) are dropped
+const MIN_PROB: f64 = 1.0e-9;
+
+/// Above this number of convertible bases (only reached with long reads) the expected number
+/// of unconverted bases is used instead of the full distribution
+const MAX_TABLE: u32 = 4096;
+
+/// Windows of up to this length have the combined CpG and non CpG distributions precomputed
+const MAX_CPG_TABLE: u32 = 320;
+
+/// Binomial(n, p) distributions for n up to a maximum
+struct Binomials {
+    p: f64,
+    table: Vec<Vec<(u32, f64)>>,
+}
+
+impl Binomials {
+    fn new(p: f64, max_n: u32) -> Self {
+        let table = if p > 0.0 && p < 1.0 {
+            (0..=max_n.min(MAX_TABLE)).map(|n| binomial(n, p)).collect()
+        } else {
+            Vec::new()
+        };
+        Self { p, table }
+    }
+
+    /// (number of successes, probability) given n trials
+    fn get(&self, n: u32) -> Cow<'_, [(u32, f64)]> {
+        if self.p <= 0.0 {
+            Cow::Borrowed(&[(0, 1.0)])
+        } else if let Some(v) = self.table.get(n as usize) {
+            Cow::Borrowed(v)
+        } else {
+            Cow::Owned(vec![((n as f64 * self.p).round() as u32, 1.0)])
+        }
+    }
+}
+
+pub struct Conversion {
+    chemistry: Chemistry,
+    rate: f64,
+    methylation: f64,
+    non_cpg: Binomials,
+    cpg: Binomials,
+    // Combined distributions indexed by n and the number of CpGs (if the model uses CpGs)
+    combined: Vec<Vec<Vec<(u32, f64)>>>,
+}
+
+impl Conversion {
+    /// Conversion model for windows of up to max_len bases
+    pub fn new(chemistry: Chemistry, rate: f64, methylation: f64, max_len: u32) -> Self {
+        assert!(rate > 0.0 && rate <= 1.0, "Conversion rate out of range");
+        assert!(
+            (0.0..=1.0).contains(&methylation),
+            "Methylation level out of range"
+        );
+        // Probabilities of non CpG and CpG Cs remaining unconverted
+        let (p, p_cpg) = if chemistry.converts_methylated() {
+            (1.0, 1.0 - methylation * rate)
+        } else {
+            let q = 1.0 - rate;
+            (q, methylation + (1.0 - methylation) * q)
+        };
+        let non_cpg = Binomials::new(p, max_len);
+        let cpg = if methylation > 0.0 {
+            Binomials::new(p_cpg, max_len >> 1)
+        } else {
+            Binomials::new(0.0, 0)
+        };
+        let mut conv = Self {
+            chemistry,
+            rate,
+            methylation,
+            non_cpg,
+            cpg,
+            combined: Vec::new(),
+        };
+        if conv.uses_cpg() {
+            conv.combined = (0..=max_len.min(MAX_CPG_TABLE))
+                .map(|n| (0..=n >> 1).map(|k| conv.convolve(n, k)).collect())
+                .collect()
+        }
+        conv
+    }
+
+    pub fn chemistry(&self) -> Chemistry {
+        self.chemistry
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn methylation(&self) -> f64 {
+        self.methylation
+    }
+
+    /// True if conversion is complete with no methylation, in which case the rate and
+    /// methylation level have no effect
+    pub fn is_complete(&self) -> bool {
+        self.rate >= 1.0 && self.methylation <= 0.0
+    }
+
+    /// True if the model needs the number of CpGs in each window
+    pub fn uses_cpg(&self) -> bool {
+        self.methylation > 0.0
+    }
+
+    /// (number of unconverted bases, probability) given n convertible bases, n_cpg of which are
+    /// in a CpG context
+    pub fn unconverted(&self, n: u32, n_cpg: u32) -> Cow<'_, [(u32, f64)]> {
+        if !self.uses_cpg() || n_cpg == 0 {
+            self.non_cpg.get(n)
+        } else if let Some(v) = self
+            .combined
+            .get(n as usize)
+            .and_then(|v| v.get(n_cpg as usize))
+        {
+            Cow::Borrowed(v)
+        } else {
+            Cow::Owned(self.convolve(n, n_cpg))
+        }
+    }
+
+    /// Distribution of the sum of unconverted non CpG and CpG bases
+    fn convolve(&self, n: u32, n_cpg: u32) -> Vec<(u32, f64)> {
+        let n_cpg = n_cpg.min(n);
+        let a = self.non_cpg.get(n - n_cpg);
+        let b = self.cpg.get(n_cpg);
+        let mut v = vec![0.0; (a[a.len() - 1].0 + b[b.len() - 1].0) as usize + 1];
+        for (i, p) in a.iter() {
+            for (j, q) in b.iter() {
+                v[(i + j) as usize] += p * q
+            }
+        }
+        v.into_iter()
+            .enumerate()
+            .filter(|(_, x)| *x >= MIN_PROB)
+            .map(|(k, x)| (k as u32, x))
+            .collect()
+    }
+}
+
+/// Binomial(n, p) probabilities, omitting negligible terms and renormalized to sum to 1
+fn binomial(n: u32, p: f64) -> Vec<(u32, f64)> {
+    let (lp, lq) = (p.ln(), (1.0 - p).ln());
+    let lc = lgamma(n as f64 + 1.0);
+    let mut v: Vec<_> = (0..=n)
+        .map(|k| {
+            let (k1, j1) = (k as f64, (n - k) as f64);
+            let l = lc - lgamma(k1 + 1.0) - lgamma(j1 + 1.0) + k1 * lp + j1 * lq;
+            (k, l.exp())
+        })
+        .filter(|(_, x)| *x >= MIN_PROB)
+        .collect();
+    let z: f64 = v.iter().map(|(_, x)| x).sum();
+    for (_, x) in v.iter_mut() {
+        *x /= z
+    }
+    v
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[allow(dead_code)]
+    fn moments(v: &[(u32, f64)]) -> (f64, f64) {
+        let s: f64 = v.iter().map(|(_, x)| x).sum();
+        let m: f64 = v.iter().map(|(k, x)| *k as f64 * x).sum();
+        (s, m)
+    }
+
+    #[test]
+    fn test_conversion() {
+        let bs = Chemistry::Bisulfite;
+        let c = Conversion::new(bs, 1.0, 0.0, 100);
+        assert!(c.is_complete());
+        assert_eq!(*c.unconverted(50, 10), [(0, 1.0)]);
+        let c = Conversion::new(bs, 0.75, 0.0, 100);
+        assert_eq!(*c.unconverted(0, 0), [(0, 1.0)]);
+        assert_eq!(*c.unconverted(10000, 0), [(2500, 1.0)]);
+        let v = c.unconverted(2, 1);
+        assert_eq!(v.len(), 3);
+        assert!((v[1].1 - 0.375).abs() < 1.0e-12);
+        let (s, m) = moments(&c.unconverted(100, 0));
+        assert!((s - 1.0).abs() < 1.0e-12);
+        assert!((m - 25.0).abs() < 1.0e-6);
+        assert!(c.unconverted(100, 0).len() < 101);
+
+        // CpG Cs fully methylated, others fully converted
+        let c = Conversion::new(bs, 1.0, 1.0, 100);
+        assert!(!c.is_complete());
+        assert_eq!(*c.unconverted(20, 5), [(5, 1.0)]);
+        // 10 CpG Cs remaining with p = 0.5 + 0.5 * 0.2 and 40 others with p = 0.2
+        let c = Conversion::new(bs, 0.8, 0.5, 100);
+        let (s, m) = moments(&c.unconverted(50, 10));
+        assert!((s - 1.0).abs() < 1.0e-6);
+        assert!((m - 14.0).abs() < 1.0e-6);
+        assert_eq!(*c.unconverted(50, 10), c.convolve(50, 10));
+        assert_eq!(*c.unconverted(500, 100), c.convolve(500, 100));
+
+        // With TAPS only methylated CpG Cs are converted
+        let c = Conversion::new(Chemistry::Taps, 1.0, 0.0, 100);
+        assert_eq!(*c.unconverted(20, 5), [(20, 1.0)]);
+        let c = Conversion::new(Chemistry::Taps, 1.0, 1.0, 100);
+        assert_eq!(*c.unconverted(20, 5), [(15, 1.0)]);
+        let c = Conversion::new(Chemistry::Taps, 0.9, 0.5, 100);
+        let (_, m) = moments(&c.unconverted(20, 10));
+        assert!((m - 15.5).abs() < 1.0e-6);
+    }
+}
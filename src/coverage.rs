@@ -0,0 +1,175 @@
+//! Estimation of on target rate and per target coverage from sequenced reads using the kmer
+//! information in a kmcv file
+use std::io::Write;
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+use serde::Serialize;
+
+use crate::{
+    cli::CoverageConfig,
+    fasta::Base,
+    kmcv::{open_kmcv, KmcvTarget, KmerType},
+    kmers::{KType, KmerBuilder, KMER_LENGTH},
+    reads::{open_reads, ReadRec},
+};
+
+/// Marks a kmer that maps to multiple locations, at least one of which is on target
+const MULTI_TARGET: u32 = u32::MAX;
+
+/// Kmers hitting at least one target, sorted by kmer.  The associated value is the target id
+/// for kmers mapping uniquely to a target, or MULTI_TARGET
+struct TargetKmers {
+    kmers: Vec<KType>,
+    hits: Vec<u32>,
+}
+
+impl TargetKmers {
+    fn get(&self, k: KType) -> Option<u32> {
+        self.kmers.binary_search(&k).ok().map(|i| self.hits[i])
+    }
+}
+
+#[derive(Serialize, Default)]
+struct CoverageSummary {
+    total_reads: u64,
+    on_target_reads: u64,
+    assigned_reads: u64,
+    on_target_rate: f64,
+}
+
+fn read_target_kmers(
+    cfg: &CoverageConfig,
+) -> anyhow::Result<(TargetKmers, Vec<Box<str>>, Vec<KmcvTarget>)> {
+    info!("Reading kmer file {}", cfg.kmcv().display());
+    let mut rdr = open_kmcv(cfg.kmcv())?;
+    if rdr.kmer_length() as usize != KMER_LENGTH {
+        return Err(anyhow!(
+            "Kmer length in kmer file ({}) does not match expected ({KMER_LENGTH})",
+            rdr.kmer_length()
+        ));
+    }
+    let mut kmers = Vec::new();
+    let mut hits = Vec::new();
+    while let Some((k, ktype, h)) = rdr.next_kmer()? {
+        let x = match ktype {
+            KmerType::UniqueOnTarget => h[0],
+            KmerType::LowMultiMap(_) if h.iter().any(|x| *x != 0) => MULTI_TARGET,
+            _ => continue,
+        };
+        kmers.push(k);
+        hits.push(x);
+    }
+    info!("{} kmers hitting targets", kmers.len());
+    Ok((
+        TargetKmers { kmers, hits },
+        rdr.contigs().to_vec(),
+        rdr.targets().to_vec(),
+    ))
+}
+
+/// Returns target id with the most uniquely mapping kmers in the read (if at least min_kmers)
+/// and whether any kmer from the read hits a target
+fn assign_read(
+    seq: &[u8],
+    tk: &TargetKmers,
+    kb: &mut KmerBuilder,
+    votes: &mut Vec<(u32, u32)>,
+    min_kmers: u32,
+) -> (Option<u32>, bool) {
+    kb.clear();
+    votes.clear();
+    let mut on_target = false;
+    for c in seq {
+        kb.add_base(Base::from_u8(*c), None);
+        // The kmer file has kmers from both strands, so we only need to check one
+        if let Some(x) = kb.kmers().and_then(|k| tk.get(k[0])) {
+            on_target = true;
+            if x != MULTI_TARGET {
+                match votes.iter_mut().find(|(t, _)| *t == x) {
+                    Some((_, n)) => *n += 1,
+                    None => votes.push((x, 1)),
+                }
+            }
+        }
+    }
+    let best = votes
+        .iter()
+        .max_by_key(|(_, n)| *n)
+        .filter(|(_, n)| *n >= min_kmers)
+        .map(|(t, _)| *t);
+    (best, on_target)
+}
+
+pub fn estimate_coverage(cfg: &CoverageConfig) -> anyhow::Result<()> {
+    let (tk, contigs, targets) = read_target_kmers(cfg)?;
+
+    let mut rdr = open_reads(cfg.input())?;
+    let mut rec = ReadRec::default();
+    let mut kb = KmerBuilder::new();
+    let mut votes = Vec::new();
+    let mut target_reads = vec![0u64; targets.len()];
+    let mut target_bases = vec![0u64; targets.len()];
+    let mut summ = CoverageSummary::default();
+
+    info!("Reading input from {}", cfg.input().display());
+    while rdr.next_read(&mut rec)? {
+        if !rec.is_primary() || rec.seq().is_empty() {
+            continue;
+        }
+        summ.total_reads += 1;
+        let (best, on_target) = assign_read(rec.seq(), &tk, &mut kb, &mut votes, cfg.min_kmers());
+        if on_target {
+            summ.on_target_reads += 1
+        }
+        if let Some(t) = best {
+            summ.assigned_reads += 1;
+            target_reads[t as usize - 1] += 1;
+            target_bases[t as usize - 1] += rec.seq().len() as u64;
+        }
+    }
+    if summ.total_reads == 0 {
+        return Err(anyhow!("No reads found in {}", cfg.input().display()));
+    }
+    summ.on_target_rate = (summ.on_target_reads as f64) / (summ.total_reads as f64);
+    info!(
+        "Total reads: {}, on target: {} ({:.2}%), assigned to targets: {}",
+        summ.total_reads,
+        summ.on_target_reads,
+        100.0 * summ.on_target_rate,
+        summ.assigned_reads
+    );
+
+    let name = format!("{}_coverage.txt", cfg.prefix());
+    let mut wrt = CompressIo::new()
+        .path(&name)
+        .bufwriter()
+        .with_context(|| format!("Could not open output file {name}"))?;
+    writeln!(wrt, "target_id\tcontig\tstart\tend\treads\tcoverage")?;
+    for (i, t) in targets.iter().enumerate() {
+        let cov = if t.is_empty() {
+            0.0
+        } else {
+            (target_bases[i] as f64) / (t.len() as f64)
+        };
+        writeln!(
+            wrt,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            i + 1,
+            contigs[t.contig_id() as usize],
+            t.start(),
+            t.end(),
+            target_reads[i],
+            cov
+        )?
+    }
+    wrt.flush()
+        .with_context(|| "Error flushing data to coverage output file")?;
+
+    let name = format!("{}_coverage.json", cfg.prefix());
+    let wrt = CompressIo::new()
+        .path(&name)
+        .bufwriter()
+        .with_context(|| format!("Could not open output file {name}"))?;
+    serde_json::to_writer_pretty(wrt, &summ).with_context(|| "Error writing coverage summary JSON")
+}
@@ -0,0 +1,118 @@
+//! Cross-validation split of the genome
+//!
+//! The genome is divided into blocks of a fixed size, and each block is assigned pseudo randomly
+//! (from a hash of the contig, block and seed) to one of two halves.  Separate distributions are
+//! collected for the two halves, and the divergence between them gives an estimate of the
+//! sampling noise floor, against which differences between the reference and sample
+//! distributions can be judged.  As the assignment only depends on the block, the split does not
+//! depend on the number of threads or on how the input was divided between them.
+use std::hash::{Hash, Hasher};
+
+use rustc_hash::FxHasher;
+use serde::Serialize;
+
+use crate::{
+    betabin::{gc_hist_table, Smoother},
+    output::mean_gc,
+    process::GcRes,
+};
+
+/// Half (0 or 1) for a window with midpoint mid
+pub fn cv_half(seed: u64, block_size: u64, contig: &str, mid: u64) -> usize {
+    let mut h = FxHasher::default();
+    (seed, contig, mid / block_size).hash(&mut h);
+    // The high bits of FxHasher are the best mixed
+    (h.finish() >> 63) as usize
+}
+
+/// Comparison of the distributions from the two halves for one read length
+#[derive(Debug, Serialize)]
+pub struct CvDivergence {
+    read_length: u32,
+    windows: [f64; 2],
+    mean_gc: [f64; 2],
+    /// Jensen-Shannon divergence (base 2, so between 0 and 1)
+    jensen_shannon: f64,
+    /// Total variation distance
+    total_variation: f64,
+}
+
+impl CvDivergence {
+    pub fn read_length(&self) -> u32 {
+        self.read_length
+    }
+
+    pub fn jensen_shannon(&self) -> f64 {
+        self.jensen_shannon
+    }
+
+    pub fn total_variation(&self) -> f64 {
+        self.total_variation
+    }
+}
+
+/// (Jensen-Shannon divergence, total variation distance) between two discrete distributions
+pub(crate) fn divergence(p: &[f64], q: &[f64]) -> (f64, f64) {
+    let kl = |a: f64, m: f64| if a > 0.0 { a * (a / m).log2() } else { 0.0 };
+    p.iter().zip(q).fold((0.0, 0.0), |(js, tv), (a, b)| {
+        let m = 0.5 * (a + b);
+        (js + 0.5 * (kl(*a, m) + kl(*b, m)), tv + 0.5 * (a - b).abs())
+    })
+}
+
+/// Divergence between the (non-converted) distributions for each read length from the two
+/// halves.  Read lengths where either half has no windows are skipped
+pub fn cv_divergence(
+    read_len: &[u32],
+    parts: &[GcRes; 2],
+    smoother: Smoother,
+) -> Vec<CvDivergence> {
+    let tabs = parts
+        .each_ref()
+        .map(|r| gc_hist_table(read_len, None, r, false, smoother));
+    read_len
+        .iter()
+        .enumerate()
+        .filter_map(|(ix, rl)| {
+            let [(n0, gc0), (n1, gc1)] = parts.each_ref().map(|r| mean_gc(r, *rl));
+            if n0 <= 0.0 || n1 <= 0.0 {
+                return None;
+            }
+            // Densities are scaled to average 1 over the bins
+            let [p, q] = tabs.each_ref().map(|t| {
+                let (_, v) = t.columns().nth(ix).unwrap();
+                let s: f64 = v.iter().sum();
+                v.iter().map(|x| x / s).collect::<Vec<_>>()
+            });
+            let (jensen_shannon, total_variation) = divergence(&p, &q);
+            Some(CvDivergence {
+                read_length: *rl,
+                windows: [n0, n1],
+                mean_gc: [gc0, gc1],
+                jensen_shannon,
+                total_variation,
+            })
+        })
+        .collect()
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_divergence() {
+        let p = [0.5, 0.5, 0.0];
+        assert_eq!(divergence(&p, &p), (0.0, 0.0));
+        let (js, tv) = divergence(&p, &[0.0, 0.0, 1.0]);
+        assert!((js - 1.0).abs() < 1.0e-12);
+        assert!((tv - 1.0).abs() < 1.0e-12);
+        let (js, tv) = divergence(&p, &[0.5, 0.0, 0.5]);
+        assert!((js - 0.5).abs() < 1.0e-12);
+        assert!((tv - 0.5).abs() < 1.0e-12);
+        let a = cv_half(1, 1000, "chr1", 10);
+        assert_eq!(a, cv_half(1, 1000, "chr1", 999));
+        let n: usize = (0..1000).map(|i| cv_half(1, 1000, "chr1", i * 1000)).sum();
+        assert!(n > 400 && n < 600);
+    }
+}
@@ -0,0 +1,262 @@
+//! Reading and writing of GC distribution files as written by [`crate::betabin::write_hist`]
+//!
+//! In the default wide format there is a gc column followed by one density column per read
+//! length (and per mode for bisulfite analyses).  The long format has one row per (gc,
+//! read_length, mode) with the density in a single column.
+use std::{
+    io::{BufRead, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use clap::{builder::PossibleValue, ValueEnum};
+use compress_io::compress::CompressIo;
+
+/// Layout of distribution files
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DistFormat {
+    /// One column per read length
+    Wide,
+    /// One row per gc bin, read length and mode
+    Long,
+}
+
+impl ValueEnum for DistFormat {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Wide, Self::Long]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(self.name()))
+    }
+}
+
+impl DistFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Wide => "wide",
+            Self::Long => "long",
+        }
+    }
+}
+
+const LONG_HEADER: &str = "gc\tread_length\tmode\tdensity";
+
+/// (read length, mode) for a wide format column name
+fn long_key(name: &str) -> (&str, &'static str) {
+    let (s, mode) = match name.strip_prefix("bisulfite_") {
+        Some(s) => (s, "bisulfite"),
+        None => (name, "standard"),
+    };
+    let rl = s
+        .strip_prefix("read_len:")
+        .and_then(|s| s.strip_suffix("bp"))
+        .unwrap_or(s);
+    (rl, mode)
+}
+
+/// Wide format column name for (read length, mode)
+fn wide_name(rl: &str, mode: &str) -> String {
+    let s = if rl.parse::<u32>().is_ok() {
+        format!("read_len:{rl}bp")
+    } else {
+        rl.to_owned()
+    };
+    if mode == "bisulfite" {
+        format!("bisulfite_{s}")
+    } else {
+        s
+    }
+}
+
+/// A GC distribution table with a gc column and one or more named density columns
+pub struct DistTable {
+    gc: Vec<f64>,
+    columns: Vec<(String, Vec<f64>)>,
+}
+
+impl DistTable {
+    pub fn new(gc: Vec<f64>, columns: Vec<(String, Vec<f64>)>) -> Self {
+        Self { gc, columns }
+    }
+
+    pub fn gc(&self) -> &[f64] {
+        &self.gc
+    }
+
+    pub fn columns(&self) -> impl Iterator<Item = (&str, &[f64])> {
+        self.columns.iter().map(|(s, v)| (s.as_str(), v.as_slice()))
+    }
+
+    /// Add columns after the existing columns
+    pub fn add_columns(&mut self, columns: Vec<(String, Vec<f64>)>) {
+        self.columns.extend(columns)
+    }
+
+    pub fn column(&self, name: &str) -> Option<&[f64]> {
+        self.columns
+            .iter()
+            .find(|(s, _)| s == name)
+            .map(|(_, v)| v.as_slice())
+    }
+
+    pub fn write<W: Write>(&self, wrt: &mut W, format: DistFormat) -> anyhow::Result<()> {
+        match format {
+            DistFormat::Wide => {
+                write!(wrt, "gc")?;
+                for (name, _) in self.columns() {
+                    write!(wrt, "\t{name}")?;
+                }
+                writeln!(wrt)?;
+                for (i, gc) in self.gc.iter().enumerate() {
+                    write!(wrt, "{gc}")?;
+                    for (_, v) in self.columns() {
+                        write!(wrt, "\t{}", v[i])?;
+                    }
+                    writeln!(wrt)?
+                }
+            }
+            DistFormat::Long => {
+                writeln!(wrt, "{LONG_HEADER}")?;
+                for (name, v) in self.columns() {
+                    let (rl, mode) = long_key(name);
+                    for (gc, d) in self.gc.iter().zip(v) {
+                        writeln!(wrt, "{gc}\t{rl}\t{mode}\t{d}")?
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse a long format table, with the rows for each column in gc order
+fn parse_long<R: BufRead>(rdr: R) -> anyhow::Result<DistTable> {
+    let mut gc = Vec::new();
+    let mut columns: Vec<(String, Vec<f64>)> = Vec::new();
+    for (i, l) in rdr.lines().enumerate() {
+        // The header line has already been read
+        let line = i + 2;
+        let l = l.with_context(|| format!("Error reading line {line} from distribution file"))?;
+        let v: Vec<_> = l.trim_end().split('\t').collect();
+        if v.len() != 4 {
+            return Err(anyhow!("Wrong number of columns at line {line}"));
+        }
+        let x = v[0]
+            .parse::<f64>()
+            .with_context(|| format!("Bad gc value at line {line}"))?;
+        let d = v[3]
+            .parse::<f64>()
+            .with_context(|| format!("Bad density value at line {line}"))?;
+        let name = wide_name(v[1], v[2]);
+        if columns.last().map(|(s, _)| s) != Some(&name) {
+            if columns.iter().any(|(s, _)| *s == name) {
+                return Err(anyhow!("Rows for {} {} not together", v[1], v[2]));
+            }
+            columns.push((name, Vec::new()))
+        }
+        let first = columns.len() == 1;
+        let c = &mut columns.last_mut().unwrap().1;
+        if first {
+            gc.push(x)
+        } else if gc.get(c.len()) != Some(&x) {
+            return Err(anyhow!("Inconsistent gc value at line {line}"));
+        }
+        c.push(d)
+    }
+    if columns.iter().any(|(_, v)| v.len() != gc.len()) {
+        return Err(anyhow!("Columns have different numbers of rows"));
+    }
+    Ok(DistTable { gc, columns })
+}
+
+fn parse_dist_table<R: BufRead>(mut rdr: R) -> anyhow::Result<DistTable> {
+    let mut buf = String::new();
+    rdr.read_line(&mut buf)
+        .with_context(|| "Error reading header line from distribution file")?;
+    if buf.trim_end() == LONG_HEADER {
+        return parse_long(rdr);
+    }
+    let mut hdr = buf.trim_end().split('\t');
+    if hdr.next() != Some("gc") {
+        return Err(anyhow!("Bad header: expected gc as first column"));
+    }
+    let mut columns: Vec<_> = hdr.map(|s| (s.to_owned(), Vec::new())).collect();
+    let mut gc = Vec::new();
+
+    let mut line = 1;
+    loop {
+        buf.clear();
+        if rdr
+            .read_line(&mut buf)
+            .with_context(|| format!("Error reading line {} from distribution file", line + 1))?
+            == 0
+        {
+            break;
+        }
+        line += 1;
+        let mut itr = buf.trim_end().split('\t');
+        gc.push(
+            itr.next()
+                .unwrap()
+                .parse::<f64>()
+                .with_context(|| format!("Bad gc value at line {line}"))?,
+        );
+        for (_, v) in columns.iter_mut() {
+            v.push(
+                itr.next()
+                    .ok_or_else(|| anyhow!("Missing column at line {line}"))?
+                    .parse::<f64>()
+                    .with_context(|| format!("Bad density value at line {line}"))?,
+            )
+        }
+    }
+    Ok(DistTable { gc, columns })
+}
+
+/// Read a distribution file in either the wide or the long format
+pub fn read_dist_table<P: AsRef<Path>>(path: P) -> anyhow::Result<DistTable> {
+    let path = path.as_ref();
+    let rdr = CompressIo::new()
+        .path(path)
+        .bufreader()
+        .with_context(|| format!("Could not open distribution file {}", path.display()))?;
+    let tab = parse_dist_table(rdr)
+        .with_context(|| format!("Error reading distribution file {}", path.display()))?;
+    debug!(
+        "Read {} rows and {} columns from {}",
+        tab.gc.len(),
+        tab.columns.len(),
+        path.display()
+    );
+    Ok(tab)
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_dist_formats() {
+        let tab = DistTable::new(
+            vec![0.25, 0.75],
+            vec![
+                ("read_len:100bp".to_owned(), vec![0.5, 1.5]),
+                ("bisulfite_read_len:100bp".to_owned(), vec![1.0, 1.0]),
+                ("mixture".to_owned(), vec![0.5, 1.5]),
+            ],
+        );
+        let mut long = Vec::new();
+        tab.write(&mut long, DistFormat::Long).unwrap();
+        let s = String::from_utf8(long.clone()).unwrap();
+        assert_eq!(s.lines().nth(3), Some("0.25\t100\tbisulfite\t1"));
+        assert_eq!(s.lines().nth(5), Some("0.25\tmixture\tstandard\t0.5"));
+        let mut wide = Vec::new();
+        tab.write(&mut wide, DistFormat::Wide).unwrap();
+        for v in [long, wide] {
+            let t = parse_dist_table(&v[..]).unwrap();
+            assert_eq!(t.gc(), tab.gc());
+            assert!(t.columns().eq(tab.columns()));
+        }
+    }
+}
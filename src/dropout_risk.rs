@@ -0,0 +1,192 @@
+//! Per target dropout risk score
+//!
+//! Three components, each between 0 and 1, are combined as a weighted mean:
+//!
+//! - GC extremeness: |gc - 0.5| * 2, from the GC content of the ACGT bases of the target
+//! - Kmer redundancy: the proportion of the distinct kmers hitting the target that do not map
+//!   uniquely (1 if no kmers were recorded for the target)
+//! - N content: the proportion of the target that is not ACGT
+//!
+//! Targets scoring at or above the risk threshold are counted as high risk in the summary.
+use serde::Serialize;
+
+use crate::{
+    fasta::Base,
+    kmcv::KmerType,
+    kmers::{KmerVec, KmerWork},
+    regions::Regions,
+    target_stats::TargetStats,
+};
+
+/// Weights for the (GC extremeness, kmer redundancy, N content) components
+#[derive(Debug, Copy, Clone, Serialize)]
+pub struct RiskWeights {
+    gc: f64,
+    redundancy: f64,
+    n_content: f64,
+}
+
+impl RiskWeights {
+    /// Weights scaled to sum to 1.  Weights must be non negative and not all zero
+    pub fn new(gc: f64, redundancy: f64, n_content: f64) -> anyhow::Result<Self> {
+        let w = [gc, redundancy, n_content];
+        let s: f64 = w.iter().sum();
+        if w.iter().any(|x| x.is_nan() || *x < 0.0) || s <= 0.0 {
+            return Err(anyhow!(
+                "Illegal risk weights: must be non negative and not all zero"
+            ));
+        }
+        Ok(Self {
+            gc: gc / s,
+            redundancy: redundancy / s,
+            n_content: n_content / s,
+        })
+    }
+}
+
+/// Risk components and score for one target
+#[derive(Debug, Default, Copy, Clone)]
+pub struct TargetRisk {
+    gc_extremeness: f64,
+    redundancy: f64,
+    n_content: f64,
+    score: f64,
+}
+
+impl TargetRisk {
+    fn new(w: &RiskWeights, gc_extremeness: f64, redundancy: f64, n_content: f64) -> Self {
+        Self {
+            gc_extremeness,
+            redundancy,
+            n_content,
+            score: w.gc * gc_extremeness + w.redundancy * redundancy + w.n_content * n_content,
+        }
+    }
+
+    pub fn redundancy(&self) -> f64 {
+        self.redundancy
+    }
+
+    pub fn n_content(&self) -> f64 {
+        self.n_content
+    }
+
+    pub fn score(&self) -> f64 {
+        self.score
+    }
+}
+
+/// Add a kmer to the (distinct kmers, unique kmers) counts for each target hit.  Hits are stored
+/// as target idx + 1, with 1 for off target
+fn add_kmer(v: &KmerVec, counts: &mut [(u64, u64)]) {
+    if v[0] & 0x80000000 != 0 {
+        return;
+    }
+    for x in v.iter().take_while(|x| **x != 0).filter(|x| **x > 1) {
+        counts[(*x - 2) as usize].0 += 1
+    }
+    if KmerType::from_kmer_vec(v) == KmerType::UniqueOnTarget {
+        counts[(v[0] - 2) as usize].1 += 1
+    }
+}
+
+/// Risk for each target, indexed by target idx - 1
+pub fn target_risk(
+    reg: &Regions,
+    k_work: &KmerWork,
+    stats: &TargetStats,
+    w: &RiskWeights,
+) -> Vec<TargetRisk> {
+    let mut counts = vec![(0, 0); reg.n_regions()];
+    for (_, v) in k_work.kmers() {
+        add_kmer(v, &mut counts)
+    }
+    let mut risk = vec![TargetRisk::default(); reg.n_regions()];
+    for (_, cr) in reg.iter() {
+        for r in cr.regions() {
+            let i = u32::from(r.idx()) as usize - 1;
+            let c = stats.counts(r.idx());
+            let acgt: u64 = c.iter().sum();
+            let gc = if acgt > 0 {
+                (c[Base::C as usize] + c[Base::G as usize]) as f64 / acgt as f64
+            } else {
+                0.5
+            };
+            let (k, u) = counts[i];
+            let redundancy = if k > 0 {
+                1.0 - u as f64 / k as f64
+            } else {
+                1.0
+            };
+            let len = r.end() - r.start();
+            let n_content = 1.0 - (acgt.min(len) as f64 / len.max(1) as f64);
+            risk[i] = TargetRisk::new(w, (gc - 0.5).abs() * 2.0, redundancy, n_content)
+        }
+    }
+    risk
+}
+
+/// Summary of the target risk scores for the JSON output
+#[derive(Debug, Clone, Serialize)]
+pub struct RiskSummary {
+    weights: RiskWeights,
+    threshold: f64,
+    mean_score: f64,
+    max_score: f64,
+    high_risk_targets: usize,
+    /// Mean of each component over the targets
+    mean_gc_extremeness: f64,
+    mean_redundancy: f64,
+    mean_n_content: f64,
+}
+
+impl RiskSummary {
+    pub fn new(risk: &[TargetRisk], weights: RiskWeights, threshold: f64) -> Self {
+        let n = risk.len().max(1) as f64;
+        let mean = |f: fn(&TargetRisk) -> f64| risk.iter().map(f).sum::<f64>() / n;
+        Self {
+            weights,
+            threshold,
+            mean_score: mean(|r| r.score),
+            max_score: risk.iter().map(|r| r.score).fold(0.0, f64::max),
+            high_risk_targets: risk.iter().filter(|r| r.score >= threshold).count(),
+            mean_gc_extremeness: mean(|r| r.gc_extremeness),
+            mean_redundancy: mean(|r| r.redundancy),
+            mean_n_content: mean(|r| r.n_content),
+        }
+    }
+
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    pub fn high_risk_targets(&self) -> usize {
+        self.high_risk_targets
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_risk() {
+        assert!(RiskWeights::new(0.0, 0.0, 0.0).is_err());
+        assert!(RiskWeights::new(-1.0, 1.0, 1.0).is_err());
+        let w = RiskWeights::new(2.0, 1.0, 1.0).unwrap();
+        let r = TargetRisk::new(&w, 1.0, 0.5, 0.0);
+        assert!((r.score() - 0.625).abs() < 1.0e-12);
+
+        // Targets 1 and 2 (stored as 2 and 3)
+        let mut counts = vec![(0, 0); 2];
+        add_kmer(&[2, 0, 0, 0, 0, 0, 0, 0], &mut counts);
+        add_kmer(&[2, 3, 0, 0, 0, 0, 0, 0], &mut counts);
+        add_kmer(&[3, 1, 0, 0, 0, 0, 0, 0], &mut counts);
+        add_kmer(&[0x80000000, 0, 0, 0, 0, 0, 0, 0], &mut counts);
+        assert_eq!(counts, [(2, 1), (2, 0)]);
+
+        let s = RiskSummary::new(&[r, TargetRisk::new(&w, 0.0, 0.0, 0.0)], w, 0.5);
+        assert_eq!(s.high_risk_targets(), 1);
+        assert!((s.mean_score - 0.3125).abs() < 1.0e-12);
+    }
+}
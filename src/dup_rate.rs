@@ -0,0 +1,122 @@
+//! Estimation of the duplicate rate inflation caused by GC bias
+//!
+//! Reads are modelled as falling on the available fragment start positions (both strands of the
+//! targets) at random.  The positions are split between GC bins according to the expected
+//! distribution, and the reads according to the expected distribution weighted by the bias
+//! curve.  The expected proportion of duplicates in a bin with R reads and N positions is then
+//! 1 - N(1 - exp(-R/N)) / R.  Bins that receive more reads than their share of positions have an
+//! inflated duplicate rate compared to an unbiased library of the same size
+use std::io::Write;
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+
+use crate::{cli::DupRateConfig, dist_table::read_dist_table, simulate::lookup_bias};
+
+/// Expected proportion of duplicate reads when r reads fall at random on n positions
+fn dup_fraction(r: f64, n: f64) -> f64 {
+    if r > 0.0 && n > 0.0 {
+        1.0 + n * (-r / n).exp_m1() / r
+    } else {
+        0.0
+    }
+}
+
+struct DupCurve {
+    name: String,
+    dup: Vec<f64>,
+    inflation: Vec<f64>,
+}
+
+pub fn dup_rate(cfg: &DupRateConfig) -> anyhow::Result<()> {
+    let expected = read_dist_table(cfg.expected())?;
+    let bias_tab = read_dist_table(cfg.bias())?;
+    let x = expected.gc();
+    let n_reads = cfg.n_reads() as f64;
+    let positions = 2.0 * cfg.target_size() as f64;
+    let dup0 = dup_fraction(n_reads, positions);
+
+    let mut curves = Vec::new();
+    for (name, e) in expected.columns() {
+        let Some(b) = bias_tab.column(&format!("bias_{name}")) else {
+            debug!("Bias curve for column {name} not found");
+            continue;
+        };
+        let tot: f64 = e.iter().sum();
+        let w: Vec<f64> = e
+            .iter()
+            .zip(x.iter())
+            .map(|(p, gc)| p * lookup_bias(bias_tab.gc(), b, *gc))
+            .collect();
+        let tot_w: f64 = w.iter().sum();
+        if tot <= 0.0 || tot_w <= 0.0 {
+            warn!("No reads expected for column {name}");
+            continue;
+        }
+        let mut dups = 0.0;
+        let (dup, inflation): (Vec<_>, Vec<_>) = e
+            .iter()
+            .zip(w.iter())
+            .map(|(p, w)| {
+                let r = n_reads * w / tot_w;
+                let d = dup_fraction(r, positions * p / tot);
+                dups += r * d;
+                let i = if *p > 0.0 && dup0 > 0.0 {
+                    d / dup0
+                } else {
+                    f64::NAN
+                };
+                (d, i)
+            })
+            .unzip();
+        info!(
+            "Column {name}: expected duplicate rate {:.4} (unbiased {dup0:.4})",
+            dups / n_reads
+        );
+        curves.push(DupCurve {
+            name: name.to_owned(),
+            dup,
+            inflation,
+        })
+    }
+    if curves.is_empty() {
+        return Err(anyhow!(
+            "No matching columns found between expected distribution and bias curves"
+        ));
+    }
+
+    let name = format!("{}_dup_rate.txt", cfg.prefix());
+    let mut wrt = CompressIo::new()
+        .path(&name)
+        .bufwriter()
+        .with_context(|| format!("Could not open output duplicate rate file {name}"))?;
+
+    write!(wrt, "gc")?;
+    for c in curves.iter() {
+        write!(wrt, "\tdup_{}\tinflation_{}", c.name, c.name)?
+    }
+    writeln!(wrt)?;
+    for (i, gc) in x.iter().enumerate() {
+        write!(wrt, "{gc}")?;
+        for c in curves.iter() {
+            write!(wrt, "\t{}\t{}", c.dup[i], c.inflation[i])?
+        }
+        writeln!(wrt)?
+    }
+    wrt.flush()
+        .with_context(|| "Error writing out duplicate rate file")
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_dup_fraction() {
+        assert_eq!(dup_fraction(0.0, 100.0), 0.0);
+        // One read per position: a proportion exp(-1) of positions is not hit
+        let d = dup_fraction(100.0, 100.0);
+        assert!((d - (-1.0f64).exp()).abs() < 1.0e-12);
+        assert!(dup_fraction(10.0, 1.0e9) < 1.0e-7);
+    }
+}
@@ -0,0 +1,122 @@
+//! Detection of FASTA records with duplicate names.  Otherwise the records would be silently
+//! treated as the same contig, so that target lookups and per contig statistics would be
+//! conflated.  Header lines are checked (and rewritten if duplicates are to be renamed) in the
+//! raw input stream by [`CheckReader`](crate::input_check::CheckReader), so that all later stages
+//! see the same names
+use std::io;
+
+use clap::{builder::PossibleValue, ValueEnum};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::warnings::add_warning;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DuplicatePolicy {
+    /// Stop with an error
+    Error,
+    /// Rename later records to <name>_<n>
+    Rename,
+    /// Treat records as parts of the same contig
+    Merge,
+}
+
+impl ValueEnum for DuplicatePolicy {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Error, Self::Rename, Self::Merge]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(self.name()))
+    }
+}
+
+impl DuplicatePolicy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Rename => "rename",
+            Self::Merge => "merge",
+        }
+    }
+}
+
+/// Check of record names against those seen previously in the input
+pub struct DupCheck {
+    policy: DuplicatePolicy,
+    /// Number of records seen for each name in the input
+    seen: FxHashMap<Vec<u8>, u32>,
+    /// Names as output (including renamed records)
+    used: FxHashSet<Vec<u8>>,
+}
+
+impl DupCheck {
+    pub fn new(policy: DuplicatePolicy) -> Self {
+        Self {
+            policy,
+            seen: FxHashMap::default(),
+            used: FxHashSet::default(),
+        }
+    }
+
+    /// Returns the new name if the record should be renamed
+    pub fn check(&mut self, name: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        let n = self.seen.entry(name.to_vec()).or_insert(0);
+        *n += 1;
+        if *n == 1 && self.used.insert(name.to_vec()) {
+            return Ok(None);
+        }
+        let s = String::from_utf8_lossy(name);
+        match self.policy {
+            DuplicatePolicy::Error => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Duplicate contig name {s} in input (see --on-duplicate)"),
+            )),
+            DuplicatePolicy::Merge => {
+                add_warning(
+                    "duplicate_contig",
+                    format!("Duplicate contig name {s}: records merged"),
+                );
+                Ok(None)
+            }
+            DuplicatePolicy::Rename => {
+                let mut k = *n;
+                let new = loop {
+                    let t = format!("{s}_{k}").into_bytes();
+                    if !self.used.contains(&t) {
+                        break t;
+                    }
+                    k += 1
+                };
+                self.used.insert(new.clone());
+                add_warning(
+                    "duplicate_contig",
+                    format!(
+                        "Duplicate contig name {s}: record renamed to {}",
+                        String::from_utf8_lossy(&new)
+                    ),
+                );
+                Ok(Some(new))
+            }
+        }
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_dup_check() {
+        let mut d = DupCheck::new(DuplicatePolicy::Rename);
+        let v: Vec<_> = ["chr1", "chr2", "chr1", "chr1_3", "chr1"]
+            .iter()
+            .map(|s| d.check(s.as_bytes()).unwrap())
+            .collect();
+        assert_eq!(v[2].as_deref(), Some(&b"chr1_2"[..]));
+        assert_eq!(v[3], None);
+        assert_eq!(v[4].as_deref(), Some(&b"chr1_4"[..]));
+        let mut d = DupCheck::new(DuplicatePolicy::Error);
+        assert!(d.check(b"chr1").is_ok());
+        assert!(d.check(b"chr1").is_err());
+    }
+}
@@ -0,0 +1,206 @@
+//! Typed errors for the main classes of failure
+//!
+//! Library functions return [`anyhow::Result`], with these errors either as the error itself or
+//! attached as context, so they can be found by walking the error chain (see [`exit_code`]).
+//! The binary uses this to exit with a distinct code for each class, so that pipelines can tell
+//! a malformed input from a full disk without parsing the message.
+use std::{fmt, io};
+
+/// Exit code for errors not covered by the other codes
+pub const EXIT_ERROR: i32 = 1;
+/// Exit code for malformed sequence input (2 is used by clap for command line errors)
+pub const EXIT_PARSE: i32 = 3;
+/// Exit code for bad target or region specifications
+pub const EXIT_REGION: i32 = 4;
+/// Exit code for failures writing output (including a full disk)
+pub const EXIT_OUTPUT: i32 = 5;
+
+/// Number of bytes either side of the offending byte in a parse error hexdump
+const HEXDUMP_CONTEXT: usize = 8;
+
+/// Malformed FASTA input, with the location where known
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    msg: &'static str,
+    contig: Option<String>,
+    line: Option<u64>,
+    offset: Option<u64>,
+    hexdump: Option<String>,
+}
+
+impl ParseError {
+    pub fn new(msg: &'static str) -> Self {
+        Self {
+            msg,
+            contig: None,
+            line: None,
+            offset: None,
+            hexdump: None,
+        }
+    }
+
+    pub fn with_contig(mut self, contig: &str) -> Self {
+        if !contig.is_empty() {
+            self.contig = Some(contig.to_owned())
+        }
+        self
+    }
+
+    /// Set the line number (from 1, if known) and byte offset (from 0) of the error
+    pub fn with_position(mut self, line: Option<u64>, offset: u64) -> Self {
+        self.line = line;
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Add a hexdump of the bytes around buf[ix], with the offending byte in brackets
+    pub fn with_bytes(mut self, buf: &[u8], ix: usize) -> Self {
+        let a = ix.saturating_sub(HEXDUMP_CONTEXT);
+        let b = (ix + HEXDUMP_CONTEXT + 1).min(buf.len());
+        let v: Vec<_> = (a..b)
+            .map(|i| {
+                if i == ix {
+                    format!("[{:02x}]", buf[i])
+                } else {
+                    format!("{:02x}", buf[i])
+                }
+            })
+            .collect();
+        if !v.is_empty() {
+            self.hexdump = Some(v.join(" "))
+        }
+        self
+    }
+
+    pub fn msg(&self) -> &str {
+        self.msg
+    }
+
+    pub fn contig(&self) -> Option<&str> {
+        self.contig.as_deref()
+    }
+
+    /// Line number in the input (from 1)
+    pub fn line(&self) -> Option<u64> {
+        self.line
+    }
+
+    /// Byte offset in the (uncompressed) input (from 0)
+    pub fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+
+    pub fn hexdump(&self) -> Option<&str> {
+        self.hexdump.as_deref()
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.msg)?;
+        let mut loc = Vec::new();
+        if let Some(c) = self.contig.as_deref() {
+            loc.push(format!("contig {c}"))
+        }
+        if let Some(l) = self.line {
+            loc.push(format!("line {l}"))
+        }
+        if let Some(o) = self.offset {
+            loc.push(format!("byte offset {o}"))
+        }
+        if !loc.is_empty() {
+            write!(f, " ({})", loc.join(", "))?
+        }
+        if let Some(h) = self.hexdump.as_deref() {
+            write!(f, ": {h}")?
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Bad target file or region specification
+#[derive(Debug, Clone)]
+pub struct RegionError {
+    msg: String,
+}
+
+impl RegionError {
+    pub fn new<S: Into<String>>(msg: S) -> Self {
+        Self { msg: msg.into() }
+    }
+}
+
+impl fmt::Display for RegionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl std::error::Error for RegionError {}
+
+/// Failure writing an output file
+#[derive(Debug, Clone)]
+pub struct OutputError {
+    desc: String,
+}
+
+impl OutputError {
+    /// Error writing desc (e.g., "target statistics file foo.txt")
+    pub fn new<S: Into<String>>(desc: S) -> Self {
+        Self { desc: desc.into() }
+    }
+}
+
+impl fmt::Display for OutputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Could not write {}", self.desc)
+    }
+}
+
+impl std::error::Error for OutputError {}
+
+/// Exit code for an error from the typed errors (as the error or as context) in the chain.  A
+/// full disk anywhere in the chain counts as an output error
+pub fn exit_code(e: &anyhow::Error) -> i32 {
+    if e.downcast_ref::<ParseError>().is_some() {
+        EXIT_PARSE
+    } else if e.downcast_ref::<RegionError>().is_some() {
+        EXIT_REGION
+    } else if e.downcast_ref::<OutputError>().is_some()
+        || e.chain().any(|c| {
+            c.downcast_ref::<io::Error>()
+                .is_some_and(|e| e.kind() == io::ErrorKind::StorageFull)
+        })
+    {
+        EXIT_OUTPUT
+    } else {
+        EXIT_ERROR
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_exit_code() {
+        let e = anyhow::Error::from(ParseError::new("Bad character").with_contig("chr1"));
+        assert_eq!(e.to_string(), "Bad character (contig chr1)");
+        assert_eq!(exit_code(&e), EXIT_PARSE);
+        assert_eq!(exit_code(&e.context("Error reading input")), EXIT_PARSE);
+        let e = anyhow!("Bad start value").context(RegionError::new("Error reading regions"));
+        assert_eq!(exit_code(&e), EXIT_REGION);
+        let e = anyhow::Error::from(io::Error::from(io::ErrorKind::StorageFull));
+        assert_eq!(exit_code(&e.context("Error flushing")), EXIT_OUTPUT);
+        assert_eq!(exit_code(&anyhow!("Other")), EXIT_ERROR);
+        let e = ParseError::new("Illegal character in sequence")
+            .with_position(Some(2), 7)
+            .with_bytes(b"ACGT\x01\n", 4);
+        assert_eq!(
+            e.to_string(),
+            "Illegal character in sequence (line 2, byte offset 7): 41 43 47 54 [01] 0a"
+        );
+    }
+}
@@ -0,0 +1,649 @@
+//! Gap aware streaming FASTA reader
+//!
+//! Records are returned as [`Seq`] blocks, with records being split at long runs of gap (non
+//! ACGT) characters according to the [`GapPolicy`].  Leading and trailing gaps are always removed.
+use std::{
+    io::BufRead,
+    ops::{Deref, Range},
+    sync::Arc,
+};
+
+use crate::error::ParseError;
+
+pub mod fai;
+pub mod tar;
+
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+#[repr(u8)]
+pub enum Base {
+    A = 0,
+    C,
+    T,
+    G,
+    N,
+    #[default]
+    Other,
+}
+
+impl Base {
+    pub fn from_u8(c: u8) -> Self {
+        match c {
+            b'A' | b'a' => Self::A,
+            b'C' | b'c' => Self::C,
+            b'G' | b'g' => Self::G,
+            b'T' | b't' => Self::T,
+            b'N' | b'n' => Self::N,
+            _ => Self::Other,
+        }
+    }
+
+    pub fn is_gap(&self) -> bool {
+        ((*self as usize) & 4) == 4
+    }
+}
+
+/// A contiguous stretch of sequence from a contig, split at long gaps
+#[derive(Debug, Clone)]
+pub struct Seq {
+    contig: Arc<str>,
+    start: u64, // zero offset from start of contig
+    v: Vec<Base>,
+}
+
+impl Deref for Seq {
+    type Target = [Base];
+
+    fn deref(&self) -> &Self::Target {
+        &self.v
+    }
+}
+
+impl Seq {
+    pub fn contig(&self) -> &str {
+        &self.contig
+    }
+
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// End (exclusive) of sequence as zero offset from start of contig
+    pub fn end(&self) -> u64 {
+        self.start + self.v.len() as u64
+    }
+
+    /// Contig position of base i of the sequence
+    pub fn pos(&self, i: usize) -> u64 {
+        self.start + i as u64
+    }
+
+    /// Gap before this sequence, as [start, end) in contig coordinates, given the previous
+    /// sequence from the reader.  This is the long gap at which the contig was split, or the
+    /// leading gap if this is the first sequence of the contig.  Returns None if there is no gap
+    pub fn gap_before(&self, prev: Option<&Seq>) -> Option<Range<u64>> {
+        let start = prev
+            .filter(|p| p.contig == self.contig && p.end() <= self.start)
+            .map(|p| p.end())
+            .unwrap_or(0);
+        Some(start..self.start).filter(|r| !r.is_empty())
+    }
+
+    /// Runs of gap characters within the sequence (shorter than the split length of the gap
+    /// policy) in contig coordinates
+    pub fn gaps(&self) -> impl Iterator<Item = Range<u64>> + '_ {
+        let mut i = 0;
+        std::iter::from_fn(move || {
+            let j = i + self.v[i..].iter().position(|b| b.is_gap())?;
+            let k = j + self.v[j..]
+                .iter()
+                .position(|b| !b.is_gap())
+                .unwrap_or(self.v.len() - j);
+            i = k;
+            Some(self.pos(j)..self.pos(k))
+        })
+    }
+
+    pub fn bases_mut(&mut self) -> &mut [Base] {
+        &mut self.v
+    }
+
+    /// Part of sequence lying within [start, end) of the contig, with leading and trailing gaps
+    /// removed.  Returns None if no non-gap bases remain
+    pub fn subseq(&self, start: u64, end: u64) -> Option<Seq> {
+        let s = start.max(self.start).saturating_sub(self.start) as usize;
+        let e = end.saturating_sub(self.start).min(self.v.len() as u64) as usize;
+        let v = self.v.get(s..e)?;
+        let i = v.iter().position(|b| !b.is_gap())?;
+        let j = v.iter().rposition(|b| !b.is_gap()).unwrap();
+        Some(Seq {
+            contig: self.contig.clone(),
+            start: self.start + (s + i) as u64,
+            v: v[i..=j].to_vec(),
+        })
+    }
+
+    /// Part of sequence lying within [start, end) of the contig, keeping any leading or trailing
+    /// gaps.  Returns None if this is empty
+    pub fn slice(&self, start: u64, end: u64) -> Option<Seq> {
+        let s = start.max(self.start).saturating_sub(self.start) as usize;
+        let e = end.saturating_sub(self.start).min(self.v.len() as u64) as usize;
+        let v = self.v.get(s..e).filter(|v| !v.is_empty())?;
+        Some(Seq {
+            contig: self.contig.clone(),
+            start: self.start + s as u64,
+            v: v.to_vec(),
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum RdrState {
+    Start,
+    StartSeqId,
+    InSeqId,
+    StartSeq,
+    StartSeqAfterNewLine,
+    StartGap,
+    InGap,
+    InGapAfterNewLine,
+    InSeq,
+    InSeqAfterNewLine,
+    InLongGap,
+    InLongGapAfterNewLine,
+    EndSeq,
+    EndSeqAfterLongGap,
+    StartSeqAfterInitialGap,
+    NewContig,
+}
+
+/// Policy for splitting records at runs of gap characters
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum GapPolicy {
+    /// Never split records
+    Never,
+    /// Split records at gaps of at least this length
+    MinGap(u32),
+}
+
+impl GapPolicy {
+    fn max_gap(&self) -> u32 {
+        match self {
+            Self::Never => u32::MAX,
+            Self::MinGap(x) => (*x).max(1),
+        }
+    }
+}
+
+/// Hook allowing bases to be inspected and modified as they are read
+pub trait BaseFilter {
+    /// Called at the start of each contig
+    fn new_contig(&mut self, _name: &str) {}
+
+    /// Called for each base that will be stored in a [`Seq`], with pos being the zero offset
+    /// position from the start of the contig.  The returned base is stored
+    fn filter(&mut self, _pos: u64, b: Base) -> Base {
+        b
+    }
+}
+
+/// Filter that stores all bases unchanged
+#[derive(Debug, Default, Copy, Clone)]
+pub struct NoFilter;
+
+impl BaseFilter for NoFilter {}
+
+pub struct FastaReader<R: BufRead, F: BaseFilter = NoFilter> {
+    r: R,
+    state: RdrState,
+    seq_id: String,
+    contig: Arc<str>,
+    max_gap: u32,
+    pos: u64,
+    contig_start: u64,
+    filter: F,
+    // Lines (if known) and bytes consumed, for error reporting
+    line: Option<u64>,
+    offset: u64,
+}
+
+struct SeqWork<'a, F: BaseFilter> {
+    v: Vec<Base>,
+    pos: u64,
+    filter: &'a mut F,
+}
+
+type ProcFn<F> = fn(c: u8, v: Option<&mut SeqWork<F>>) -> Result<(RdrState, bool), ParseError>;
+
+impl<R: BufRead> FastaReader<R> {
+    pub fn new(r: R, policy: GapPolicy) -> Self {
+        Self::with_filter(r, policy, NoFilter)
+    }
+}
+
+impl<R: BufRead, F: BaseFilter> FastaReader<R, F> {
+    pub fn with_filter(r: R, policy: GapPolicy, filter: F) -> Self {
+        Self {
+            r,
+            state: RdrState::Start,
+            seq_id: String::new(),
+            contig: Arc::from(""),
+            max_gap: policy.max_gap(),
+            pos: 0,
+            contig_start: 0,
+            filter,
+            line: Some(0),
+            offset: 0,
+        }
+    }
+
+    /// Set the position of the first base of each contig (for input starting part way through a
+    /// contig)
+    pub fn with_contig_start(mut self, pos: u64) -> Self {
+        self.contig_start = pos;
+        self
+    }
+
+    /// Set the number of lines (None if unknown) and bytes preceding the input, for input
+    /// starting part way through a file, so that parse errors give the position in the file
+    pub fn with_input_position(mut self, line: Option<u64>, offset: u64) -> Self {
+        self.line = line;
+        self.offset = offset;
+        self
+    }
+
+    pub fn base_filter(&self) -> &F {
+        &self.filter
+    }
+
+    pub fn into_base_filter(self) -> F {
+        self.filter
+    }
+
+    /// Read the next sequence block, returning None at the end of the input
+    pub fn next_seq(&mut self) -> anyhow::Result<Option<Seq>> {
+        let v = Vec::new();
+        let mut gap = 0;
+        let mut start = self.pos;
+        let mut seq_work = SeqWork {
+            v,
+            pos: 0,
+            filter: &mut self.filter,
+        };
+
+        loop {
+            let buf = self.r.fill_buf()?;
+            if buf.is_empty() {
+                break;
+            }
+            let mut used = 0;
+            let mut seq_ready = false;
+            for (ix, c) in buf.iter().enumerate() {
+                // Start position is fixed once the first base is stored
+                if seq_work.v.is_empty() {
+                    start = self.pos
+                }
+                seq_work.pos = self.pos;
+                trace!("pos = {}, state = {:?}", self.pos, self.state);
+                let res = match self.state {
+                    RdrState::Start => proc_start(*c).map(|s| (s, false)),
+                    RdrState::StartSeqId => {
+                        proc_start_seq_id(*c, &mut self.seq_id).map(|s| (s, false))
+                    }
+                    RdrState::StartSeqAfterNewLine => proc_start_seq_after_new_line(*c),
+                    RdrState::InSeqId => proc_in_seq_id(*c, &mut self.seq_id).map(|s| (s, false)),
+                    RdrState::NewContig => {
+                        debug!("Starting reading contig {}", self.seq_id);
+                        self.contig = Arc::from(self.seq_id.as_str());
+                        seq_work.filter.new_contig(&self.seq_id);
+                        self.pos = self.contig_start;
+                        // A header line straight after another is an empty record
+                        proc_start_seq_after_new_line(*c)
+                    }
+                    RdrState::StartSeq => proc_start_seq(*c),
+                    RdrState::InSeq => {
+                        gap = 0;
+                        proc_in_seq(*c, Some(&mut seq_work))
+                    }
+                    RdrState::InSeqAfterNewLine => {
+                        proc_after_new_line(*c, Some(&mut seq_work), proc_in_seq)
+                    }
+                    RdrState::InGapAfterNewLine => {
+                        proc_after_new_line(*c, Some(&mut seq_work), proc_in_gap)
+                    }
+                    RdrState::InLongGapAfterNewLine => {
+                        proc_after_new_line::<F>(*c, None, proc_in_long_gap)
+                    }
+                    RdrState::StartGap => {
+                        gap = 1;
+                        proc_in_gap(*c, Some(&mut seq_work))
+                    }
+                    RdrState::InGap => {
+                        gap += 1;
+                        if gap >= self.max_gap {
+                            assert!(seq_work.v.len() > gap as usize);
+                            seq_work.v.truncate(seq_work.v.len() - gap as usize);
+                            gap = 0;
+                            proc_in_long_gap::<F>(*c, None)
+                        } else {
+                            proc_in_gap(*c, Some(&mut seq_work))
+                        }
+                    }
+                    RdrState::InLongGap => proc_in_long_gap::<F>(*c, None),
+                    RdrState::EndSeq => {
+                        used = ix;
+                        seq_ready = true;
+                        Ok((RdrState::StartSeqId, false))
+                    }
+                    RdrState::EndSeqAfterLongGap => {
+                        used = if ix > 0 { ix - 1 } else { ix };
+                        if self.pos > 0 {
+                            self.pos -= 1;
+                        }
+                        seq_ready = true;
+                        Ok((RdrState::StartSeq, false))
+                    }
+                    RdrState::StartSeqAfterInitialGap => {
+                        used = if ix > 0 { ix - 1 } else { ix };
+                        if self.pos > 0 {
+                            self.pos -= 1;
+                        }
+                        seq_ready = true;
+                        Ok((RdrState::InSeq, false))
+                    }
+                };
+                let (new_state, inc_pos) = res.map_err(|e| {
+                    let line = self.line.map(|l| l + newlines(&buf[..ix]) + 1);
+                    e.with_contig(&self.contig)
+                        .with_position(line, self.offset + ix as u64)
+                        .with_bytes(buf, ix)
+                })?;
+                self.state = new_state;
+                if inc_pos {
+                    self.pos += 1
+                }
+                if seq_ready {
+                    break;
+                }
+            }
+            let used = if seq_ready {
+                used
+            } else if matches!(
+                self.state,
+                RdrState::EndSeqAfterLongGap | RdrState::StartSeqAfterInitialGap
+            ) {
+                buf.len() - 1
+            } else {
+                buf.len()
+            };
+            if let Some(l) = self.line.as_mut() {
+                *l += newlines(&buf[..used])
+            }
+            self.offset += used as u64;
+            self.r.consume(used);
+            if seq_ready && !seq_work.v.is_empty() {
+                break;
+            }
+        }
+
+        let SeqWork { mut v, .. } = seq_work;
+
+        if gap > 0 {
+            assert!(v.len() >= gap as usize);
+            v.truncate(v.len() - gap as usize);
+        }
+
+        Ok(if v.is_empty() {
+            None
+        } else {
+            Some(Seq {
+                contig: self.contig.clone(),
+                start,
+                v,
+            })
+        })
+    }
+}
+
+impl<R: BufRead, F: BaseFilter> Iterator for FastaReader<R, F> {
+    type Item = anyhow::Result<Seq>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_seq().transpose()
+    }
+}
+
+fn newlines(buf: &[u8]) -> u64 {
+    buf.iter().filter(|c| **c == b'\n').count() as u64
+}
+
+fn proc_in_gen<F: BaseFilter>(
+    c: u8,
+    sw: Option<&mut SeqWork<F>>,
+    s1: RdrState,
+    s2: RdrState,
+    s3: RdrState,
+) -> Result<(RdrState, bool), ParseError> {
+    if c == b'\n' {
+        Ok((s1, false))
+    } else if c.is_ascii_graphic() {
+        let gc = Base::from_u8(c);
+        if let Some(s) = sw {
+            let b = s.filter.filter(s.pos, gc);
+            s.v.push(b)
+        } else {
+            trace!("No SeqWork. Base: {:?}", gc);
+        }
+        Ok(if gc.is_gap() { (s2, true) } else { (s3, true) })
+    } else {
+        Err(ParseError::new("Illegal character in sequence"))
+    }
+}
+
+fn proc_in_gap<F: BaseFilter>(
+    c: u8,
+    sw: Option<&mut SeqWork<F>>,
+) -> Result<(RdrState, bool), ParseError> {
+    proc_in_gen(
+        c,
+        sw,
+        RdrState::InGapAfterNewLine,
+        RdrState::InGap,
+        RdrState::InSeq,
+    )
+}
+
+fn proc_in_long_gap<F: BaseFilter>(
+    c: u8,
+    sw: Option<&mut SeqWork<F>>,
+) -> Result<(RdrState, bool), ParseError> {
+    proc_in_gen(
+        c,
+        sw,
+        RdrState::InLongGapAfterNewLine,
+        RdrState::InLongGap,
+        RdrState::EndSeqAfterLongGap,
+    )
+}
+
+fn proc_after_new_line<F: BaseFilter>(
+    c: u8,
+    sw: Option<&mut SeqWork<F>>,
+    f: ProcFn<F>,
+) -> Result<(RdrState, bool), ParseError> {
+    if c == b'>' {
+        Ok((RdrState::EndSeq, false))
+    } else {
+        f(c, sw)
+    }
+}
+
+fn proc_in_seq<F: BaseFilter>(
+    c: u8,
+    sw: Option<&mut SeqWork<F>>,
+) -> Result<(RdrState, bool), ParseError> {
+    proc_in_gen(
+        c,
+        sw,
+        RdrState::InSeqAfterNewLine,
+        RdrState::StartGap,
+        RdrState::InSeq,
+    )
+}
+
+fn proc_start_seq_after_new_line(c: u8) -> Result<(RdrState, bool), ParseError> {
+    if c == b'>' {
+        Ok((RdrState::StartSeqId, false))
+    } else {
+        proc_start_seq(c)
+    }
+}
+
+fn proc_start_seq(c: u8) -> Result<(RdrState, bool), ParseError> {
+    if c == b'\n' {
+        Ok((RdrState::StartSeqAfterNewLine, false))
+    } else if c.is_ascii_graphic() {
+        let gc = Base::from_u8(c);
+        Ok((
+            if gc.is_gap() {
+                RdrState::StartSeq
+            } else {
+                RdrState::StartSeqAfterInitialGap
+            },
+            true,
+        ))
+    } else {
+        Err(ParseError::new("Illegal character in sequence"))
+    }
+}
+
+fn proc_in_seq_id(c: u8, s: &mut String) -> Result<RdrState, ParseError> {
+    if c == b'\n' {
+        if let Some(i) = s.find(char::is_whitespace) {
+            s.truncate(i)
+        }
+        Ok(RdrState::NewContig)
+    } else if c.is_ascii() && (c == b'\t' || !c.is_ascii_control()) {
+        s.push(c as char);
+        Ok(RdrState::InSeqId)
+    } else {
+        Err(ParseError::new("Illegal character in sequence name"))
+    }
+}
+fn proc_start_seq_id(c: u8, s: &mut String) -> Result<RdrState, ParseError> {
+    s.clear();
+    proc_in_seq_id(c, s)
+}
+fn proc_start(c: u8) -> Result<RdrState, ParseError> {
+    if c == b'>' {
+        Ok(RdrState::StartSeqId)
+    } else {
+        Err(ParseError::new("Bad FASTA format: expecting '>'"))
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use std::io::BufReader;
+
+    #[test]
+    fn test1() {
+        let s = ">seq1\nACTNNCCGT\nNACCAGTNNNNC\n>seq2\nNNN\n>seq3\nNNNNNNNNN\nNNNACTCNNN\n";
+        let b = BufReader::new(s.as_bytes());
+        let mut rdr = FastaReader::new(b, GapPolicy::MinGap(4));
+        let exp_len = [16, 1, 4];
+        for l in exp_len {
+            let a = rdr.next_seq().unwrap().unwrap();
+            println!("{:?}", a);
+            assert_eq!(a.len(), l);
+        }
+        let a = rdr.next_seq().unwrap();
+        assert!(a.is_none());
+    }
+
+    #[test]
+    fn test2() {
+        let s = ">seq1\nACTNNCCGT\nNACCAGTNNNNC\n>seq2\nNNN\n>seq3\nNNNNNNNNN\nNNNACTCNNN\n";
+        let b = BufReader::with_capacity(16, s.as_bytes());
+        let mut rdr = FastaReader::new(b, GapPolicy::MinGap(4));
+        let exp_len = [16, 1, 4];
+        for l in exp_len {
+            let a = rdr.next_seq().unwrap().unwrap();
+            println!("{:?}", a);
+            assert_eq!(a.len(), l);
+        }
+        let a = rdr.next_seq().unwrap();
+        assert!(a.is_none());
+    }
+
+    #[test]
+    fn test3() {
+        let s = ">seq1\nACTNNCCGT\nNACCAGTNNNNC\n>seq2\nNNN\n>seq3\nNNNNNNNNN\nNNNACTCNNN\n";
+        let b = BufReader::with_capacity(30, s.as_bytes());
+        let mut rdr = FastaReader::new(b, GapPolicy::MinGap(4));
+        let exp_len = [16, 1, 4];
+        for l in exp_len {
+            let a = rdr.next_seq().unwrap().unwrap();
+            println!("{:?}", a);
+            assert_eq!(a.len(), l);
+        }
+        let a = rdr.next_seq().unwrap();
+        assert!(a.is_none());
+    }
+
+    #[test]
+    fn test_empty_records() {
+        let s = ">chrEmpty\n>seq1\nACGT\n>seq2\n\n>seq3\nNNNN\n>seq4\n>seq5 x\nNAC\n>seq6";
+        for cap in [1, 4, 64] {
+            let b = BufReader::with_capacity(cap, s.as_bytes());
+            let v: Vec<_> = FastaReader::new(b, GapPolicy::MinGap(4))
+                .map(|x| x.unwrap())
+                .map(|x| (x.contig().to_owned(), x.start(), x.len()))
+                .collect();
+            assert_eq!(v, [("seq1".to_owned(), 0, 4), ("seq5".to_owned(), 1, 2)]);
+        }
+    }
+
+    #[test]
+    fn test_coordinates() {
+        let s = ">seq1\nNNACNTNNCCGTNNNNNACGN\n>seq2\nAC\n";
+        let b = BufReader::new(s.as_bytes());
+        let v: Vec<_> = FastaReader::new(b, GapPolicy::MinGap(4))
+            .map(|x| x.unwrap())
+            .collect();
+        let c: Vec<_> = v.iter().map(|x| (x.contig(), x.start(), x.end())).collect();
+        assert_eq!(c, [("seq1", 2, 12), ("seq1", 17, 20), ("seq2", 0, 2)]);
+        assert_eq!(v[0].gaps().collect::<Vec<_>>(), [4..5, 6..8]);
+        assert_eq!(v[0].gap_before(None), Some(0..2));
+        assert_eq!(v[1].gap_before(Some(&v[0])), Some(12..17));
+        assert_eq!(v[2].gap_before(Some(&v[1])), None);
+        assert_eq!(v[1].pos(2), 19);
+    }
+
+    #[test]
+    fn test_contig_start() {
+        let s = ">seq1\nNNACTNNCCGT\n";
+        let b = BufReader::new(s.as_bytes());
+        let mut rdr = FastaReader::new(b, GapPolicy::MinGap(4)).with_contig_start(100);
+        let a = rdr.next_seq().unwrap().unwrap();
+        assert_eq!(a.start(), 102);
+        let x = a.subseq(105, 108).unwrap();
+        assert_eq!((x.start(), x.len()), (107, 1));
+        assert!(a.subseq(105, 107).is_none());
+    }
+
+    #[test]
+    fn test_error_position() {
+        let s = ">seq1\nACGT\nAC\x01GT\n";
+        for cap in [1, 4, 64] {
+            let b = BufReader::with_capacity(cap, s.as_bytes());
+            let e = FastaReader::new(b, GapPolicy::MinGap(4))
+                .next_seq()
+                .unwrap_err();
+            let e = e.downcast_ref::<ParseError>().unwrap();
+            assert_eq!(e.contig(), Some("seq1"));
+            assert_eq!((e.line(), e.offset()), (Some(3), Some(13)));
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! Reading of samtools style FASTA index (.fai) files, used to seek to regions of uncompressed
+//! FASTA files
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+#[derive(Debug, Clone)]
+pub struct FaiEntry {
+    name: Box<str>,
+    length: u64,
+    offset: u64,
+    line_bases: u64,
+    line_width: u64,
+}
+
+impl FaiEntry {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// Byte offset in the FASTA file of the base at pos (zero offset)
+    pub fn byte_offset(&self, pos: u64) -> u64 {
+        self.offset + (pos / self.line_bases) * self.line_width + pos % self.line_bases
+    }
+}
+
+fn parse_fai_line(l: &str) -> anyhow::Result<FaiEntry> {
+    let v: Vec<_> = l.split('\t').collect();
+    if v.len() < 5 {
+        return Err(anyhow!("Too few columns"));
+    }
+    let get = |i: usize| {
+        v[i].parse::<u64>()
+            .with_context(|| format!("Bad value in column {}", i + 1))
+    };
+    let (length, offset, line_bases, line_width) = (get(1)?, get(2)?, get(3)?, get(4)?);
+    if line_bases == 0 || line_width < line_bases {
+        return Err(anyhow!("Bad line length"));
+    }
+    Ok(FaiEntry {
+        name: v[0].to_owned().into_boxed_str(),
+        length,
+        offset,
+        line_bases,
+        line_width,
+    })
+}
+
+pub fn read_fai<P: AsRef<Path>>(path: P) -> anyhow::Result<Vec<FaiEntry>> {
+    let path = path.as_ref();
+    let rdr = BufReader::new(
+        File::open(path).with_context(|| format!("Could not open index {}", path.display()))?,
+    );
+    let mut v = Vec::new();
+    for (i, l) in rdr.lines().enumerate() {
+        let l = l.with_context(|| format!("Error reading from {}", path.display()))?;
+        if !l.is_empty() {
+            v.push(
+                parse_fai_line(&l)
+                    .with_context(|| format!("Error at line {} of {}", i + 1, path.display()))?,
+            )
+        }
+    }
+    Ok(v)
+}
+
+/// Check whether a file is compressed from the magic number (gzip/bgzip, bzip2, xz, zstd).
+/// Returns None if the file can not be read
+pub fn is_compressed<P: AsRef<Path>>(path: P) -> Option<bool> {
+    let mut buf = [0u8; 4];
+    let n = File::open(path).and_then(|mut f| f.read(&mut buf)).ok()?;
+    Some(matches!(
+        &buf[..n],
+        [0x1f, 0x8b, ..]
+            | [b'B', b'Z', b'h', ..]
+            | [0xfd, b'7', b'z', b'X']
+            | [0x28, 0xb5, 0x2f, 0xfd]
+    ))
+}
+
+/// Returns the path of the index for input if it exists and the input is an uncompressed file
+/// (compression is detected from the magic number so that gzip etc. are not seeked into)
+pub fn find_fai<P: AsRef<Path>>(input: P) -> Option<PathBuf> {
+    let input = input.as_ref();
+    let mut fai = input.as_os_str().to_owned();
+    fai.push(".fai");
+    let fai = PathBuf::from(fai);
+    if !fai.is_file() {
+        return None;
+    }
+    if is_compressed(input)? {
+        warn!(
+            "Index {} ignored as input is compressed: input will be read sequentially",
+            fai.display()
+        );
+        None
+    } else {
+        Some(fai)
+    }
+}
+
+/// Reader for the bases from [start, end) of a contig presented as a single FASTA record
+pub fn open_span<P: AsRef<Path>>(
+    path: P,
+    entry: &FaiEntry,
+    start: u64,
+    end: u64,
+) -> anyhow::Result<impl BufRead> {
+    let path = path.as_ref();
+    let end = end.min(entry.length);
+    let (off, end_off) = (entry.byte_offset(start), entry.byte_offset(end));
+    let mut f = File::open(path).with_context(|| format!("Could not open {}", path.display()))?;
+    f.seek(SeekFrom::Start(off))
+        .with_context(|| format!("Could not seek in {}", path.display()))?;
+    let hdr = format!(">{}\n", entry.name).into_bytes();
+    Ok(BufReader::new(
+        Cursor::new(hdr).chain(f.take(end_off.saturating_sub(off))),
+    ))
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_byte_offset() {
+        let e = parse_fai_line("chr1\t250\t6\t60\t61").unwrap();
+        assert_eq!(e.byte_offset(0), 6);
+        assert_eq!(e.byte_offset(59), 65);
+        assert_eq!(e.byte_offset(60), 67);
+        assert_eq!(e.byte_offset(250), 6 + 4 * 61 + 10);
+        assert!(parse_fai_line("chr1\t250\t6\t0\t61").is_err());
+    }
+}
@@ -0,0 +1,355 @@
+//! Reading of FASTA input from tar archives (possibly compressed) of per chromosome FASTA files,
+//! as supplied by several reference distributions
+//!
+//! The FASTA members of the archive are presented as a single concatenated stream, so the rest of
+//! the input handling is unchanged.  Archives are detected from the ustar magic in the first
+//! header, so this also works for archives read from stdin.  Members are used if their names end
+//! in a FASTA extension; other members are skipped with a warning.  Members are not decompressed,
+//! so a tar of gzipped FASTA files should be unpacked first.
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Cursor, Read},
+    path::Path,
+};
+
+use anyhow::Context;
+use compress_io::compress::{CompressIo, Reader};
+
+use crate::warnings::add_warning;
+
+const BLOCK: usize = 512;
+
+const FASTA_EXT: [&str; 6] = [".fa", ".fasta", ".fna", ".fas", ".mfa", ".seq"];
+
+fn is_fasta_name(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    FASTA_EXT.iter().any(|e| name.ends_with(e))
+}
+
+/// Check for a ustar header block with a valid checksum
+fn is_tar_header(b: &[u8]) -> bool {
+    if b.len() < BLOCK || &b[257..262] != b"ustar" {
+        return false;
+    }
+    let sum: u64 = b[..BLOCK]
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            if (148..156).contains(&i) {
+                32
+            } else {
+                *c as u64
+            }
+        })
+        .sum();
+    parse_octal(&b[148..156]) == Some(sum)
+}
+
+/// Check whether a file is an (uncompressed) tar archive.  Returns None if the file can not be read
+pub fn is_tar<P: AsRef<Path>>(path: P) -> Option<bool> {
+    let mut buf = Vec::with_capacity(BLOCK);
+    File::open(path)
+        .and_then(|f| f.take(BLOCK as u64).read_to_end(&mut buf))
+        .ok()?;
+    Some(is_tar_header(&buf))
+}
+
+/// Parse a NUL or space terminated octal field
+fn parse_octal(b: &[u8]) -> Option<u64> {
+    let s = std::str::from_utf8(b).ok()?;
+    let s = s.trim_matches(|c: char| c == '\0' || c == ' ');
+    if s.is_empty() {
+        Some(0)
+    } else {
+        u64::from_str_radix(s, 8).ok()
+    }
+}
+
+fn cstr(b: &[u8]) -> String {
+    let n = b.iter().position(|c| *c == 0).unwrap_or(b.len());
+    String::from_utf8_lossy(&b[..n]).into_owned()
+}
+
+/// Path from the records of a pax extended header
+fn pax_path(b: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(b)
+        .lines()
+        .filter_map(|l| l.split_once(' ').map(|(_, kv)| kv))
+        .find_map(|kv| kv.strip_prefix("path=").map(|s| s.to_owned()))
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Concatenation of the FASTA members of a tar archive.  A newline is added after a member if
+/// the last line is not terminated
+pub struct TarReader<R: BufRead> {
+    inner: R,
+    // Bytes remaining in the current member
+    remaining: u64,
+    // Padding after the current member
+    pad: u64,
+    // Name from a preceding GNU long name or pax header
+    long_name: Option<String>,
+    add_newline: bool,
+    done: bool,
+    skipped: Vec<String>,
+}
+
+impl<R: BufRead> TarReader<R> {
+    /// Create reader given the first header block
+    fn new(inner: R, hdr: &[u8]) -> io::Result<Self> {
+        let mut rdr = Self {
+            inner,
+            remaining: 0,
+            pad: 0,
+            long_name: None,
+            add_newline: false,
+            done: false,
+            skipped: Vec::new(),
+        };
+        rdr.header(hdr)?;
+        Ok(rdr)
+    }
+
+    fn skip(&mut self, mut n: u64) -> io::Result<()> {
+        while n > 0 {
+            let b = self.inner.fill_buf()?;
+            if b.is_empty() {
+                return Err(invalid("Unexpected end of tar archive"));
+            }
+            let k = (b.len() as u64).min(n);
+            self.inner.consume(k as usize);
+            n -= k
+        }
+        Ok(())
+    }
+
+    fn read_member(&mut self, size: u64) -> io::Result<Vec<u8>> {
+        let mut v = Vec::with_capacity(size as usize);
+        (&mut self.inner).take(size).read_to_end(&mut v)?;
+        if (v.len() as u64) < size {
+            return Err(invalid("Unexpected end of tar archive"));
+        }
+        Ok(v)
+    }
+
+    /// Process a header block, either setting up the member for reading or skipping it
+    fn header(&mut self, hdr: &[u8]) -> io::Result<()> {
+        if hdr.iter().all(|c| *c == 0) {
+            self.done = true;
+            return Ok(());
+        }
+        if !is_tar_header(hdr) {
+            return Err(invalid("Invalid tar header"));
+        }
+        let size = parse_octal(&hdr[124..136]).ok_or_else(|| invalid("Invalid tar member size"))?;
+        let pad = (BLOCK as u64 - size % BLOCK as u64) % BLOCK as u64;
+        match hdr[156] {
+            b'0' | 0 => {
+                let name = self.long_name.take().unwrap_or_else(|| {
+                    let (prefix, name) = (cstr(&hdr[345..500]), cstr(&hdr[..100]));
+                    if prefix.is_empty() {
+                        name
+                    } else {
+                        format!("{prefix}/{name}")
+                    }
+                });
+                if is_fasta_name(&name) {
+                    debug!("Reading tar member {name}");
+                    (self.remaining, self.pad) = (size, pad);
+                    return Ok(());
+                }
+                self.skipped.push(name)
+            }
+            b'L' => {
+                let v = self.read_member(size)?;
+                self.long_name = Some(cstr(&v));
+                return self.skip(pad);
+            }
+            b'x' => {
+                let v = self.read_member(size)?;
+                self.long_name = pax_path(&v);
+                return self.skip(pad);
+            }
+            _ => (),
+        }
+        self.skip(size + pad)
+    }
+
+    /// Move to the next FASTA member, returning false at the end of the archive
+    fn next_member(&mut self) -> io::Result<bool> {
+        while self.remaining == 0 && !self.done {
+            self.skip(self.pad)?;
+            self.pad = 0;
+            let hdr = self.read_member(BLOCK as u64)?;
+            self.header(&hdr)?
+        }
+        Ok(!self.done)
+    }
+}
+
+impl<R: BufRead> Drop for TarReader<R> {
+    fn drop(&mut self) {
+        if !self.skipped.is_empty() {
+            add_warning(
+                "skipped_tar_members",
+                format!(
+                    "{} non FASTA members of tar archive skipped: {}",
+                    self.skipped.len(),
+                    self.skipped.join(", ")
+                ),
+            )
+        }
+    }
+}
+
+impl<R: BufRead> Read for TarReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let b = self.fill_buf()?;
+            let n = b.len().min(buf.len());
+            buf[..n].copy_from_slice(&b[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for TarReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.add_newline {
+            return Ok(b"\n");
+        }
+        if !self.next_member()? {
+            return Ok(&[]);
+        }
+        let n = self.remaining;
+        let b = self.inner.fill_buf()?;
+        if b.is_empty() {
+            return Err(invalid("Unexpected end of tar archive"));
+        }
+        Ok(&b[..(b.len() as u64).min(n) as usize])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if amt == 0 {
+            return;
+        }
+        if self.add_newline {
+            self.add_newline = false;
+            return;
+        }
+        if amt as u64 == self.remaining {
+            // The buffer is already filled, so this does not read from the input
+            if let Ok(b) = self.inner.fill_buf() {
+                self.add_newline = b[amt - 1] != b'\n'
+            }
+        }
+        self.remaining -= amt as u64;
+        self.inner.consume(amt)
+    }
+}
+
+/// FASTA input, either read directly or from the members of a tar archive
+pub enum FastaInput<R: BufRead> {
+    Plain(io::Chain<Cursor<Vec<u8>>, R>),
+    Tar(TarReader<R>),
+}
+
+impl<R: BufRead> FastaInput<R> {
+    /// Check whether the input is a tar archive from the first block
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let mut hdr = Vec::with_capacity(BLOCK);
+        (&mut inner).take(BLOCK as u64).read_to_end(&mut hdr)?;
+        if is_tar_header(&hdr) {
+            debug!("Input is a tar archive");
+            Ok(Self::Tar(TarReader::new(inner, &hdr)?))
+        } else {
+            Ok(Self::Plain(Cursor::new(hdr).chain(inner)))
+        }
+    }
+}
+
+impl<R: BufRead> Read for FastaInput<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(r) => r.read(buf),
+            Self::Tar(r) => r.read(buf),
+        }
+    }
+}
+
+impl<R: BufRead> BufRead for FastaInput<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        match self {
+            Self::Plain(r) => r.fill_buf(),
+            Self::Tar(r) => r.fill_buf(),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match self {
+            Self::Plain(r) => r.consume(amt),
+            Self::Tar(r) => r.consume(amt),
+        }
+    }
+}
+
+/// Open FASTA input from a file (possibly compressed and/or a tar archive) or stdin
+pub fn open_fasta(input: Option<&Path>) -> anyhow::Result<FastaInput<BufReader<Reader>>> {
+    let rdr = CompressIo::new()
+        .opt_path(input)
+        .bufreader()
+        .with_context(|| "Could not open input file/stream")?;
+    FastaInput::new(rdr).with_context(|| "Error reading from input")
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[allow(dead_code)]
+    fn tar_member(name: &str, typ: u8, data: &[u8]) -> Vec<u8> {
+        let mut h = vec![0u8; BLOCK];
+        h[..name.len()].copy_from_slice(name.as_bytes());
+        h[100..108].copy_from_slice(b"0000644\0");
+        h[124..136].copy_from_slice(format!("{:011o}\0", data.len()).as_bytes());
+        h[136..148].copy_from_slice(b"00000000000\0");
+        h[156] = typ;
+        h[257..263].copy_from_slice(b"ustar\0");
+        h[263..265].copy_from_slice(b"00");
+        h[148..156].copy_from_slice(b"        ");
+        let sum: u64 = h.iter().map(|c| *c as u64).sum();
+        h[148..156].copy_from_slice(format!("{sum:06o}\0 ").as_bytes());
+        h.extend_from_slice(data);
+        h.resize(h.len().div_ceil(BLOCK) * BLOCK, 0);
+        h
+    }
+
+    #[test]
+    fn test_tar_reader() {
+        let mut tar = tar_member("ref/chr1.fa", b'0', b">chr1\nACGT\nAC");
+        tar.extend(tar_member("ref/", b'5', b""));
+        tar.extend(tar_member("ref/md5sum.txt", b'0', b"abc  chr1.fa\n"));
+        tar.extend(tar_member("ref/chr2.fasta", b'0', b">chr2\nGGCC\n"));
+        tar.extend(vec![0; 2 * BLOCK]);
+        let mut rdr = FastaInput::new(&tar[..]).unwrap();
+        assert!(matches!(rdr, FastaInput::Tar(_)));
+        let mut s = String::new();
+        rdr.read_to_string(&mut s).unwrap();
+        assert_eq!(s, ">chr1\nACGT\nAC\n>chr2\nGGCC\n");
+        if let FastaInput::Tar(t) = &mut rdr {
+            assert_eq!(t.skipped, ["ref/md5sum.txt"]);
+            t.skipped.clear()
+        }
+
+        let input = b">chr1\nACGT\n";
+        let mut rdr = FastaInput::new(&input[..]).unwrap();
+        let mut s = String::new();
+        rdr.read_to_string(&mut s).unwrap();
+        assert_eq!(s.as_bytes(), input);
+    }
+}
@@ -0,0 +1,92 @@
+//! Export of target GC content as a GATK annotated intervals file (--gatk-intervals)
+//!
+//! GATK does not read a GC distribution directly: the GC correction in DenoiseReadCounts (and
+//! in the gCNV tools) uses the GC content of each counted interval, as written by
+//! AnnotateIntervals.  This writes the same layout for the targets: a SAM style header with the
+//! sequence dictionary, then the columns CONTIG, START and END (1 based, inclusive) and
+//! GC_CONTENT.  The GC content is that of the ACGT bases of the target (as in the target
+//! statistics file), or NaN if there are none.
+use std::io::Write;
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+use rustc_hash::FxHashMap;
+
+use crate::{
+    cli::Config,
+    fasta::{
+        fai::{find_fai, read_fai},
+        Base,
+    },
+    input_check::CharCounts,
+    regions::Regions,
+    target_stats::TargetStats,
+};
+
+/// (name, length) of each contig from the input if it was read completely, otherwise from its
+/// index if present
+pub fn sequence_dictionary(cfg: &Config, counts: &CharCounts) -> Option<Vec<(String, u64)>> {
+    if counts.is_complete() {
+        Some(
+            counts
+                .dictionary()
+                .iter()
+                .map(|(s, l)| (s.to_string(), *l))
+                .collect(),
+        )
+    } else {
+        let entries = cfg
+            .input()
+            .and_then(find_fai)
+            .and_then(|f| read_fai(f).ok())?;
+        Some(
+            entries
+                .iter()
+                .map(|e| (e.name().to_owned(), e.length()))
+                .collect(),
+        )
+    }
+}
+
+/// Write the annotated intervals file.  Targets are written in dictionary order, and targets on
+/// contigs not in the dictionary are skipped
+pub fn write_gatk_intervals(
+    path: &str,
+    reg: &Regions,
+    stats: &TargetStats,
+    dict: &[(String, u64)],
+) -> anyhow::Result<()> {
+    let mut wrt = CompressIo::new()
+        .path(path)
+        .bufwriter()
+        .with_context(|| "Could not open GATK intervals file for output")?;
+    writeln!(wrt, "@HD\tVN:1.6")?;
+    for (name, len) in dict {
+        writeln!(wrt, "@SQ\tSN:{name}\tLN:{len}")?;
+    }
+    writeln!(wrt, "CONTIG\tSTART\tEND\tGC_CONTENT")?;
+    let contigs: FxHashMap<_, _> = reg.iter().collect();
+    let mut written = 0;
+    for (name, _) in dict {
+        let Some(ctg_regs) = contigs.get(name.as_str()) else {
+            continue;
+        };
+        for r in ctg_regs.regions() {
+            let cts = stats.counts(r.idx());
+            let acgt: u64 = cts.iter().sum();
+            write!(wrt, "{name}\t{}\t{}\t", r.start() + 1, r.end())?;
+            if acgt > 0 {
+                let gc = (cts[Base::C as usize] + cts[Base::G as usize]) as f64 / acgt as f64;
+                writeln!(wrt, "{gc:.6}")?
+            } else {
+                writeln!(wrt, "NaN")?
+            }
+            written += 1
+        }
+    }
+    let skipped = reg.n_regions() - written;
+    if skipped > 0 {
+        warn!("{skipped} targets on contigs not in the sequence dictionary not written to {path}")
+    }
+    Ok(())
+}
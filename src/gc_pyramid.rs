@@ -0,0 +1,218 @@
+//! GC content at several window sizes from a single pass over the input
+//!
+//! Windows of the smallest size are read as for the GC track, and are combined into the windows
+//! of each larger size (each size must be a multiple of the next smaller one, so the windows nest).
+//! The output has one row for each window of the smallest size with at least one non gap base,
+//! giving the GC content of that window and of the window containing it at each larger size, so
+//! that a track at any of the sizes can be taken from the columns without rerunning.  With --zarr
+//! the output is instead a Zarr store with an array for each window size for each contig, at the
+//! resolution of that size.
+use std::io::Write;
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+
+use crate::{
+    cli::GcPyramidConfig,
+    fasta::{tar::open_fasta, Base},
+    windows::{read_windows, Window},
+    zarr::{ZarrArray, ZarrStore},
+};
+
+/// GC content of the bases in counts if these cover at least threshold of len, otherwise NaN
+fn level_gc(counts: &[u32; 4], len: u64, threshold: f64) -> f64 {
+    let n: u32 = counts.iter().sum();
+    if n > 0 && n as f64 >= threshold * len as f64 {
+        (counts[Base::C as usize] + counts[Base::G as usize]) as f64 / n as f64
+    } else {
+        f64::NAN
+    }
+}
+
+/// GC content for each window in v (from the same contig and window of the largest size) at each
+/// window size.  If v has the last window of the contig, contig_end is its end (the last non gap
+/// base of the contig)
+fn pyramid(v: &[Window], contig_end: Option<u64>, sizes: &[u64], threshold: f64) -> Vec<Vec<f64>> {
+    let mut res = vec![Vec::with_capacity(sizes.len()); v.len()];
+    for size in sizes {
+        let mut i = 0;
+        while i < v.len() {
+            let k = v[i].start() / size;
+            let j = i + v[i..].iter().take_while(|w| w.start() / size == k).count();
+            let mut counts = [0; 4];
+            for w in &v[i..j] {
+                for (c, x) in counts.iter_mut().zip(w.counts()) {
+                    *c += x
+                }
+            }
+            let end = contig_end.map_or((k + 1) * size, |e| e.min((k + 1) * size));
+            let len = end - k * size;
+            let gc = level_gc(&counts, len, threshold);
+            for r in &mut res[i..j] {
+                r.push(gc)
+            }
+            i = j
+        }
+    }
+    res
+}
+
+/// Zarr output: the store, and the arrays for each window size for the current contig
+struct PyramidZarr {
+    store: ZarrStore,
+    contig: Option<(String, Vec<ZarrArray>)>,
+}
+
+impl PyramidZarr {
+    fn finish_contig(&mut self) -> anyhow::Result<()> {
+        if let Some((_, arrays)) = self.contig.take() {
+            for a in arrays {
+                a.finish()?
+            }
+        }
+        Ok(())
+    }
+
+    fn write_block(&mut self, v: &[Window], gc: &[Vec<f64>], sizes: &[u64]) -> anyhow::Result<()> {
+        let Some(ctg) = v.first().map(|w| w.contig()) else {
+            return Ok(());
+        };
+        if self.contig.as_ref().is_none_or(|(c, _)| c != ctg) {
+            self.finish_contig()?;
+            let group = self.store.add_contig(ctg)?;
+            let arrays = sizes
+                .iter()
+                .map(|s| {
+                    let desc = format!("GC content ({s} bp windows)");
+                    ZarrArray::new(&group, &format!("gc_{s}"), *s, &desc)
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            self.contig = Some((ctg.to_owned(), arrays))
+        }
+        let arrays = &mut self.contig.as_mut().unwrap().1;
+        for (w, x) in v.iter().zip(gc) {
+            for ((a, s), x) in arrays.iter_mut().zip(sizes).zip(x) {
+                // Each window of a larger size is set from the first of its windows in v
+                let ix = (w.start() / s) as usize;
+                if ix >= a.len() {
+                    a.set(ix, *x as f32)?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> anyhow::Result<()> {
+        self.finish_contig()?;
+        self.store.finish()
+    }
+}
+
+pub fn gc_pyramid(cfg: &GcPyramidConfig) -> anyhow::Result<()> {
+    if cfg.zarr() {
+        let name = format!("{}_gc_pyramid.zarr", cfg.prefix());
+        let mut out = PyramidZarr {
+            store: ZarrStore::create(&name)?,
+            contig: None,
+        };
+        let n = read_pyramid(cfg, |v, gc| out.write_block(v, &gc, cfg.windows()))?;
+        out.finish()?;
+        info!(
+            "GC for {n} windows of {} bp written to {name}",
+            cfg.windows()[0]
+        );
+        return Ok(());
+    }
+    let sizes = cfg.windows();
+    let name = format!("{}_gc_pyramid.txt", cfg.prefix());
+    let mut wrt = CompressIo::new()
+        .path(&name)
+        .bufwriter()
+        .with_context(|| format!("Could not open output file {name}"))?;
+    write!(wrt, "contig\tstart\tend")?;
+    for s in sizes {
+        write!(wrt, "\tgc_{s}")?
+    }
+    writeln!(wrt)?;
+
+    let n = read_pyramid(cfg, |v, gc| {
+        for (w, gc) in v.iter().zip(gc) {
+            write!(wrt, "{}\t{}\t{}", w.contig(), w.start(), w.end())?;
+            for x in gc {
+                if x.is_nan() {
+                    write!(wrt, "\tNaN")?
+                } else {
+                    write!(wrt, "\t{x:.4}")?
+                }
+            }
+            writeln!(wrt)?
+        }
+        Ok(())
+    })?;
+    info!("{n} windows of {} bp written to {name}", sizes[0]);
+    wrt.flush()
+        .with_context(|| format!("Error flushing data to {name}"))
+}
+
+/// Read the input, calling f with each block of windows of the smallest size within a window of
+/// the largest size and their GC at each size.  Returns the number of windows read
+fn read_pyramid<F>(cfg: &GcPyramidConfig, mut f: F) -> anyhow::Result<usize>
+where
+    F: FnMut(&[Window], Vec<Vec<f64>>) -> anyhow::Result<()>,
+{
+    let rdr = open_fasta(cfg.input())?;
+    let sizes = cfg.windows();
+    let (fine, top) = (sizes[0], *sizes.last().unwrap());
+    let mut write_block = |v: &[Window], last: bool| -> anyhow::Result<()> {
+        let contig_end = v.last().filter(|_| last).map(|w| w.end());
+        f(v, pyramid(v, contig_end, sizes, cfg.threshold()))
+    };
+
+    info!("Starting to read input");
+    // Windows of the smallest size within the current window of the largest size
+    let mut block: Vec<Window> = Vec::with_capacity((top / fine) as usize);
+    let mut n = 0;
+    read_windows(rdr, fine, |w| {
+        if let Some(b) = block.first() {
+            let new_contig = b.contig() != w.contig();
+            if new_contig || b.start() / top != w.start() / top {
+                write_block(&block, new_contig)?;
+                block.clear()
+            }
+        }
+        block.push(w.clone());
+        n += 1;
+        Ok(())
+    })?;
+    write_block(&block, true)?;
+    info!("Finished reading input");
+    Ok(n)
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_pyramid() {
+        let input = b">chr1\nACGTACGTNNNNNNNNNNNNGGGGCC\nAT\n";
+        let mut v = Vec::new();
+        read_windows(&input[..], 10, |w| {
+            v.push(w.clone());
+            Ok(())
+        })
+        .unwrap();
+        let p = pyramid(&v, Some(28), &[10, 20, 40], 0.5);
+        // Windows 0-10 (ACGTACGTNN) and 20-28 (GGGGCCAT, at the end of the contig)
+        assert_eq!((p[0][0], p[1][0]), (0.5, 0.75));
+        assert!(p[0][1].is_nan());
+        assert_eq!(p[1][1], 0.75);
+        assert_eq!(p[0][2], 10.0 / 16.0);
+        assert_eq!(p[1][2], 10.0 / 16.0);
+        // Not the end of the contig, so the windows have their full size
+        let p = pyramid(&v, None, &[10, 20, 40], 0.5);
+        assert_eq!(p[1][0], 0.75);
+        assert!(p[1][1].is_nan());
+        assert!(p[1][2].is_nan());
+    }
+}
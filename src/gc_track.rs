@@ -0,0 +1,165 @@
+//! Tracks of windowed GC content in bedGraph format for use as covariates by CNV callers
+//!
+//! The GC of each window is converted to its genome wide percentile rank.  The window GC values
+//! are binned into a histogram as the input is read, and the cumulative histogram then gives the
+//! (mid rank) percentile for each bin.  Optionally, tracks of GC skew (G-C)/(G+C) and AT skew
+//! (A-T)/(A+T) are also written.  With --zarr the tracks are written to a Zarr store rather than as
+//! bedGraphs.
+use std::io::Write;
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+
+use crate::{
+    cli::GcTrackConfig,
+    fasta::{tar::open_fasta, Base},
+    windows::{read_windows, Window},
+    zarr::{ZarrArray, ZarrStore},
+};
+
+/// Number of histogram bins for GC
+const GC_BINS: usize = 10000;
+
+fn gc_bin(gc: f64) -> usize {
+    ((gc * GC_BINS as f64).round() as usize).min(GC_BINS)
+}
+
+fn open_track(name: &str, track: &str, desc: &str) -> anyhow::Result<impl Write> {
+    let mut wrt = CompressIo::new()
+        .path(name)
+        .bufwriter()
+        .with_context(|| format!("Could not open output file {name}"))?;
+    writeln!(
+        wrt,
+        "track type=bedGraph name={track} description=\"{desc}\""
+    )?;
+    Ok(wrt)
+}
+
+fn skew(w: &Window, a: Base, b: Base) -> f64 {
+    let (x, y) = (w.count(a) as f64, w.count(b) as f64);
+    if x + y > 0.0 {
+        (x - y) / (x + y)
+    } else {
+        0.0
+    }
+}
+
+/// Mid rank percentile for each bin from the histogram counts
+fn percentiles(hist: &[u64]) -> Vec<f64> {
+    let total: u64 = hist.iter().sum();
+    let mut below = 0;
+    hist.iter()
+        .map(|x| {
+            let p = 100.0 * (below as f64 + 0.5 * *x as f64) / total.max(1) as f64;
+            below += x;
+            p
+        })
+        .collect()
+}
+
+pub fn gc_track(cfg: &GcTrackConfig) -> anyhow::Result<()> {
+    let rdr = open_fasta(cfg.input())?;
+
+    info!("Starting to read input");
+    let mut windows: Vec<Window> = Vec::new();
+    let mut hist = vec![0u64; GC_BINS + 1];
+    read_windows(rdr, cfg.window(), |w| {
+        if w.coverage() >= cfg.threshold() {
+            hist[gc_bin(w.gc().unwrap())] += 1;
+            windows.push(w.clone())
+        }
+        Ok(())
+    })?;
+    info!("Finished reading input");
+    info!("{} windows with sufficient coverage", windows.len());
+
+    let pc = percentiles(&hist);
+    if cfg.zarr() {
+        write_zarr(cfg, &windows, &pc)
+    } else {
+        write_bedgraphs(cfg, &windows, &pc)
+    }
+}
+
+fn write_bedgraphs(cfg: &GcTrackConfig, windows: &[Window], pc: &[f64]) -> anyhow::Result<()> {
+    let desc = |s: &str| format!("{s} ({} bp windows)", cfg.window());
+    let name = format!("{}_gc_percentile.bedGraph", cfg.prefix());
+    let mut wrt = open_track(&name, "gc_percentile", &desc("GC percentile"))?;
+    let mut skew_wrt = if cfg.skew() {
+        let names = [
+            format!("{}_gc_skew.bedGraph", cfg.prefix()),
+            format!("{}_at_skew.bedGraph", cfg.prefix()),
+        ];
+        let w1 = open_track(&names[0], "gc_skew", &desc("GC skew"))?;
+        let w2 = open_track(&names[1], "at_skew", &desc("AT skew"))?;
+        Some((w1, w2))
+    } else {
+        None
+    };
+    for w in windows.iter() {
+        let (ctg, start, end) = (w.contig(), w.start(), w.end());
+        let p = pc[gc_bin(w.gc().unwrap())];
+        writeln!(wrt, "{ctg}\t{start}\t{end}\t{p:.2}")?;
+        if let Some((w1, w2)) = skew_wrt.as_mut() {
+            let gc = skew(w, Base::G, Base::C);
+            writeln!(w1, "{ctg}\t{start}\t{end}\t{gc:.4}")?;
+            let at = skew(w, Base::A, Base::T);
+            writeln!(w2, "{ctg}\t{start}\t{end}\t{at:.4}")?
+        }
+    }
+    if let Some((mut w1, mut w2)) = skew_wrt {
+        w1.flush()
+            .and_then(|_| w2.flush())
+            .with_context(|| "Error flushing data to skew tracks")?
+    }
+    wrt.flush()
+        .with_context(|| format!("Error flushing data to {name}"))
+}
+
+/// Write the tracks as arrays gc_percentile (and gc_skew and at_skew) for each contig
+fn write_zarr(cfg: &GcTrackConfig, windows: &[Window], pc: &[f64]) -> anyhow::Result<()> {
+    let name = format!("{}_gc_track.zarr", cfg.prefix());
+    let mut store = ZarrStore::create(&name)?;
+    let mut tracks = vec![("gc_percentile", "GC percentile")];
+    if cfg.skew() {
+        tracks.extend([("gc_skew", "GC skew"), ("at_skew", "AT skew")])
+    }
+    let mut arrays: Vec<ZarrArray> = Vec::new();
+    let mut ctg: Option<&str> = None;
+    for w in windows.iter() {
+        if ctg != Some(w.contig()) {
+            for a in arrays.drain(..) {
+                a.finish()?
+            }
+            let group = store.add_contig(w.contig())?;
+            for (track, desc) in tracks.iter() {
+                arrays.push(ZarrArray::new(&group, track, cfg.window(), desc)?)
+            }
+            ctg = Some(w.contig())
+        }
+        let ix = (w.start() / cfg.window()) as usize;
+        arrays[0].set(ix, pc[gc_bin(w.gc().unwrap())] as f32)?;
+        if cfg.skew() {
+            arrays[1].set(ix, skew(w, Base::G, Base::C) as f32)?;
+            arrays[2].set(ix, skew(w, Base::A, Base::T) as f32)?
+        }
+    }
+    for a in arrays {
+        a.finish()?
+    }
+    store.finish()?;
+    info!("Tracks written to {name}");
+    Ok(())
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_percentiles() {
+        let p = percentiles(&[1, 0, 2, 1]);
+        assert_eq!(p, vec![12.5, 25.0, 50.0, 87.5]);
+    }
+}
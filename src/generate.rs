@@ -0,0 +1,42 @@
+//! Write a synthetic genome (and optionally a BED file describing its features)
+use std::io::Write;
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+
+use crate::{cli::GenerateConfig, synthetic::write_genome};
+
+pub fn generate(cfg: &GenerateConfig) -> anyhow::Result<()> {
+    let name = format!("{}.fa", cfg.prefix());
+    let mut wrt = CompressIo::new()
+        .path(&name)
+        .bufwriter()
+        .with_context(|| format!("Could not open output file {name}"))?;
+
+    let bed_name = format!("{}.bed", cfg.prefix());
+    let mut bed = if cfg.bed() {
+        Some(
+            CompressIo::new()
+                .path(&bed_name)
+                .bufwriter()
+                .with_context(|| format!("Could not open output file {bed_name}"))?,
+        )
+    } else {
+        None
+    };
+
+    info!("Generating synthetic genome of {} bases", cfg.params().size);
+    write_genome(
+        &mut wrt,
+        bed.as_mut().map(|b| b as &mut dyn Write),
+        cfg.params(),
+    )
+    .with_context(|| "Error writing synthetic genome")?;
+    wrt.flush()
+        .with_context(|| format!("Error flushing data to {name}"))?;
+    if let Some(mut b) = bed {
+        b.flush()
+            .with_context(|| format!("Error flushing data to {bed_name}"))?
+    }
+    Ok(())
+}
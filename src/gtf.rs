@@ -0,0 +1,214 @@
+//! Stratification of GC distributions by annotation class (CDS, UTR, intron and intergenic) using
+//! the features from a GTF file
+//!
+//! Windows are assigned to a class according to the annotation at their midpoint.  Where features
+//! overlap, CDS takes priority over other exonic sequence (UTR, which here includes the exons of
+//! non-coding transcripts), which takes priority over the rest of the transcript or gene spans
+//! (intron).  Positions outside all transcripts and genes are intergenic.
+use std::{io::BufRead, ops::Range, path::Path};
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+use rustc_hash::FxHashMap;
+
+/// Annotation class of a position
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FeatureClass {
+    Cds = 0,
+    Utr,
+    Intron,
+    Intergenic,
+}
+
+/// Annotation classes in the order used for the stratified results
+pub const FEATURE_CLASSES: [FeatureClass; 4] = [
+    FeatureClass::Cds,
+    FeatureClass::Utr,
+    FeatureClass::Intron,
+    FeatureClass::Intergenic,
+];
+
+impl FeatureClass {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Cds => "cds",
+            Self::Utr => "utr",
+            Self::Intron => "intron",
+            Self::Intergenic => "intergenic",
+        }
+    }
+
+    /// Class for a GTF feature type, or None if the feature is not used
+    fn from_feature(s: &str) -> Option<Self> {
+        match s {
+            "CDS" | "start_codon" | "stop_codon" => Some(Self::Cds),
+            "exon" | "UTR" | "five_prime_utr" | "three_prime_utr" => Some(Self::Utr),
+            "transcript" | "gene" => Some(Self::Intron),
+            _ => None,
+        }
+    }
+}
+
+/// Sorted, non overlapping annotated segments for each contig.  Positions not covered by a
+/// segment are intergenic
+pub struct Annotation {
+    contigs: FxHashMap<String, Vec<(Range<u64>, FeatureClass)>>,
+    bases: [u64; 3],
+}
+
+impl Annotation {
+    pub fn get(&self, ctg: &str) -> Option<&[(Range<u64>, FeatureClass)]> {
+        self.contigs.get(ctg).map(|v| v.as_slice())
+    }
+
+    /// Number of annotated bases in class c (not available for intergenic)
+    pub fn bases(&self, c: FeatureClass) -> Option<u64> {
+        self.bases.get(c as usize).copied()
+    }
+}
+
+/// Split overlapping (interval, class) pairs into non overlapping segments, assigning each to the
+/// highest priority class present
+fn segments(mut v: Vec<(Range<u64>, FeatureClass)>) -> Vec<(Range<u64>, FeatureClass)> {
+    let mut ev: Vec<(u64, usize, i32)> = Vec::with_capacity(v.len() * 2);
+    for (r, c) in v.drain(..) {
+        ev.push((r.start, c as usize, 1));
+        ev.push((r.end, c as usize, -1))
+    }
+    ev.sort_unstable();
+    let mut depth = [0i32; 3];
+    let mut out: Vec<(Range<u64>, FeatureClass)> = Vec::new();
+    let mut prev = 0;
+    for (pos, c, d) in ev {
+        if pos > prev {
+            if let Some(i) = depth.iter().position(|x| *x > 0) {
+                let cl = FEATURE_CLASSES[i];
+                match out.last_mut() {
+                    Some((r, c1)) if r.end == prev && *c1 == cl => r.end = pos,
+                    _ => out.push((prev..pos, cl)),
+                }
+            }
+        }
+        depth[c] += d;
+        prev = pos
+    }
+    out
+}
+
+fn parse_gtf<R: BufRead>(rdr: R, path: &Path) -> anyhow::Result<Annotation> {
+    let mut contigs: FxHashMap<String, Vec<(Range<u64>, FeatureClass)>> = FxHashMap::default();
+    let mut n = 0;
+    for (i, l) in rdr.lines().enumerate() {
+        let l = l.with_context(|| format!("Error reading from {}", path.display()))?;
+        if l.is_empty() || l.starts_with('#') {
+            continue;
+        }
+        let v: Vec<_> = l.split('\t').collect();
+        let err = || format!("Error at line {} of {}", i + 1, path.display());
+        if v.len() < 8 {
+            return Err(anyhow!("Too few columns")).with_context(err);
+        }
+        let Some(c) = FeatureClass::from_feature(v[2]) else {
+            continue;
+        };
+        let start = v[3].parse::<u64>().with_context(err)?;
+        let end = v[4].parse::<u64>().with_context(err)?;
+        if start == 0 || end < start {
+            return Err(anyhow!("Illegal feature coordinates")).with_context(err);
+        }
+        contigs
+            .entry(v[0].to_owned())
+            .or_default()
+            .push((start - 1..end, c));
+        n += 1
+    }
+
+    let mut bases = [0; 3];
+    for v in contigs.values_mut() {
+        *v = segments(std::mem::take(v));
+        for (r, c) in v.iter() {
+            bases[*c as usize] += r.end - r.start
+        }
+    }
+    info!(
+        "Read {n} features for {} contigs: CDS {} bases, UTR {} bases, intron {} bases",
+        contigs.len(),
+        bases[0],
+        bases[1],
+        bases[2]
+    );
+    Ok(Annotation { contigs, bases })
+}
+
+/// Read CDS, exon, UTR, transcript and gene features from a GTF file
+pub fn read_gtf<P: AsRef<Path>>(path: P) -> anyhow::Result<Annotation> {
+    let path = path.as_ref();
+    let rdr = CompressIo::new()
+        .path(path)
+        .bufreader()
+        .with_context(|| format!("Could not open GTF file {}", path.display()))?;
+    parse_gtf(rdr, path)
+}
+
+/// Lookup of annotation class for positions that are non decreasing for each of a set of cursors
+/// (one per read length)
+pub struct ClassLookup<'a> {
+    v: &'a [(Range<u64>, FeatureClass)],
+    cursor: Vec<usize>,
+}
+
+impl<'a> ClassLookup<'a> {
+    pub fn new(ann: &'a Annotation, ctg: &str, start: u64, n: usize) -> Self {
+        let v = ann.get(ctg).unwrap_or(&[]);
+        let i = v.partition_point(|(r, _)| r.end <= start);
+        Self {
+            v,
+            cursor: vec![i; n],
+        }
+    }
+
+    pub fn class(&mut self, ix: usize, pos: u64) -> FeatureClass {
+        let j = &mut self.cursor[ix];
+        while *j < self.v.len() && self.v[*j].0.end <= pos {
+            *j += 1
+        }
+        match self.v.get(*j) {
+            Some((r, c)) if r.start <= pos => *c,
+            _ => FeatureClass::Intergenic,
+        }
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_parse_gtf() {
+        let gtf = "#!genome-build test\n\
+            chr1\tt\tgene\t101\t1000\t.\t+\t.\tgene_id \"g1\";\n\
+            chr1\tt\ttranscript\t101\t1000\t.\t+\t.\tgene_id \"g1\";\n\
+            chr1\tt\texon\t101\t300\t.\t+\t.\tgene_id \"g1\";\n\
+            chr1\tt\tCDS\t201\t300\t.\t+\t0\tgene_id \"g1\";\n\
+            chr1\tt\texon\t801\t1000\t.\t+\t.\tgene_id \"g1\";\n\
+            chr1\tt\tCDS\t801\t900\t.\t+\t2\tgene_id \"g1\";\n";
+        let ann = parse_gtf(gtf.as_bytes(), Path::new("test.gtf")).unwrap();
+        let v = ann.get("chr1").unwrap();
+        use FeatureClass::*;
+        let exp = [
+            (100..200, Utr),
+            (200..300, Cds),
+            (300..800, Intron),
+            (800..900, Cds),
+            (900..1000, Utr),
+        ];
+        assert_eq!(v, &exp[..]);
+        assert_eq!(ann.bases(Cds), Some(200));
+        let mut lk = ClassLookup::new(&ann, "chr1", 0, 1);
+        let c: Vec<_> = [50, 150, 250, 500, 999, 1000]
+            .iter()
+            .map(|p| lk.class(0, *p))
+            .collect();
+        assert_eq!(c, [Intergenic, Utr, Cds, Intron, Utr, Intergenic]);
+    }
+}
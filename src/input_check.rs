@@ -0,0 +1,441 @@
+//! Checks on the raw input stream for anomalies that do not stop the analysis: duplicate record
+//! names (handled according to the [`DuplicatePolicy`]), characters other than ACGTN in the
+//! sequence (which are treated as gaps), records with no sequence and input that does not end
+//! with a newline (which may indicate a truncated file).  Anomalies are recorded as warnings when
+//! the end of the input is reached.  Characters other than ACGTN are also counted for each record,
+//! and a record where these exceed a limit stops the run, as this usually indicates a corrupted
+//! file.  Progress can also be logged at the end of each record
+//! (--log-contigs)
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, Read},
+    time::Instant,
+};
+
+use crate::{
+    duplicates::{DupCheck, DuplicatePolicy},
+    fasta::Base,
+    warnings::add_warning,
+};
+
+/// Counts of each character in the sequence lines of the input, and the number of records
+#[derive(Debug, Clone)]
+pub struct CharCounts {
+    counts: Box<[u64; 256]>,
+    records: u64,
+    /// (name, number of sequence characters) for each record
+    dict: Vec<(Box<str>, u64)>,
+    /// (name, number of characters other than ACGTN, number of sequence characters) for each
+    /// record with any characters other than ACGTN
+    other: Vec<(Box<str>, u64, u64)>,
+    complete: bool,
+}
+
+impl Default for CharCounts {
+    fn default() -> Self {
+        Self {
+            counts: Box::new([0; 256]),
+            records: 0,
+            dict: Vec::new(),
+            other: Vec::new(),
+            complete: false,
+        }
+    }
+}
+
+impl CharCounts {
+    /// Number of sequence characters
+    pub fn length(&self) -> u64 {
+        (0..=255u8)
+            .filter(|c| c.is_ascii_graphic())
+            .map(|c| self.counts[c as usize])
+            .sum()
+    }
+
+    /// Number of sequence characters that are read as base b
+    pub fn base(&self, b: Base) -> u64 {
+        (0..=255u8)
+            .filter(|c| c.is_ascii_graphic() && Base::from_u8(*c) == b)
+            .map(|c| self.counts[c as usize])
+            .sum()
+    }
+
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Name and length of each record in input order
+    pub fn dictionary(&self) -> &[(Box<str>, u64)] {
+        &self.dict
+    }
+
+    /// Name, number of characters other than ACGTN and length of each record with any characters
+    /// other than ACGTN
+    pub fn other_bases(&self) -> &[(Box<str>, u64, u64)] {
+        &self.other
+    }
+
+    /// True if the end of the input was reached
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+}
+
+/// Number of records over which the throughput is given by --log-contigs
+const ROLLING_CONTIGS: usize = 10;
+
+/// Logging of records as they are read
+struct ContigLog {
+    start: Instant,
+    /// (time, sequence characters read) at the end of the last ROLLING_CONTIGS records (and the
+    /// start)
+    marks: VecDeque<(Instant, u64)>,
+    /// Counts of (sequence characters, N) at the start of the current record
+    at_start: (u64, u64),
+}
+
+impl ContigLog {
+    fn new() -> Self {
+        let start = Instant::now();
+        Self {
+            start,
+            marks: VecDeque::from([(start, 0)]),
+            at_start: (0, 0),
+        }
+    }
+
+    fn log(&mut self, name: &str, counts: &CharCounts) {
+        let now = Instant::now();
+        let (len, n) = (counts.length(), counts.base(Base::N));
+        let (l, nn) = (len - self.at_start.0, n - self.at_start.1);
+        let (t0, len0) = self.marks.front().copied().unwrap();
+        let rate = (len - len0) as f64 / now.duration_since(t0).as_secs_f64().max(1.0e-6);
+        info!(
+            "Read contig {name}: {l} bp, {:.2}% N, {:.1}s elapsed, {:.1} Mb/s",
+            100.0 * nn as f64 / l.max(1) as f64,
+            now.duration_since(self.start).as_secs_f64(),
+            rate * 1.0e-6
+        );
+        if self.marks.len() > ROLLING_CONTIGS {
+            self.marks.pop_front();
+        }
+        self.marks.push_back((now, len));
+        self.at_start = (len, n)
+    }
+}
+
+/// Pass through reader checking the FASTA input and applying the duplicate policy to the header
+/// lines
+pub struct CheckReader<'a, R: BufRead> {
+    inner: R,
+    dups: DupCheck,
+    header: Vec<u8>,
+    hpos: usize,
+    /// Bytes of the inner buffer that can be passed through before the next header line
+    avail: usize,
+    at_line_start: bool,
+    counts: &'a mut CharCounts,
+    /// Name of current record and whether any sequence has been seen for it
+    record: Option<(String, bool)>,
+    empty: Vec<String>,
+    last: Option<u8>,
+    finished: bool,
+    strict: bool,
+    /// Maximum proportion of characters other than ACGTN in a record (None for no limit)
+    other_limit: Option<f64>,
+    log: Option<ContigLog>,
+    /// Sequence characters, and characters other than ACGTN, before the current record
+    rec_start: u64,
+    rec_other: u64,
+}
+
+impl<'a, R: BufRead> CheckReader<'a, R> {
+    pub fn new(inner: R, policy: DuplicatePolicy, counts: &'a mut CharCounts) -> Self {
+        Self {
+            inner,
+            dups: DupCheck::new(policy),
+            header: Vec::new(),
+            hpos: 0,
+            avail: 0,
+            at_line_start: true,
+            counts,
+            record: None,
+            empty: Vec::new(),
+            last: None,
+            finished: false,
+            strict: false,
+            other_limit: None,
+            log: None,
+            rec_start: 0,
+            rec_other: 0,
+        }
+    }
+
+    /// Treat records with no sequence as an error rather than a warning
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Stop with an error if the proportion of characters other than ACGTN in a record is above
+    /// limit
+    pub fn with_other_limit(mut self, limit: Option<f64>) -> Self {
+        self.other_limit = limit;
+        self
+    }
+
+    /// Log the length, N content, elapsed time and throughput (over the last few records) at the
+    /// end of each record
+    pub fn with_log_contigs(mut self, log: bool) -> Self {
+        self.log = if log { Some(ContigLog::new()) } else { None };
+        self
+    }
+
+    fn end_record(&mut self) -> io::Result<()> {
+        if let Some((name, _)) = self.record.as_ref() {
+            if let Some(l) = self.log.as_mut() {
+                l.log(name, self.counts)
+            }
+            let len = self.counts.length();
+            let rec_len = len - self.rec_start;
+            self.counts.dict.push((name.as_str().into(), rec_len));
+            self.rec_start = len;
+            let other = self.counts.base(Base::Other);
+            let n = other - self.rec_other;
+            self.rec_other = other;
+            if n > 0 {
+                let p = n as f64 / rec_len as f64;
+                if let Some(limit) = self.other_limit.filter(|l| p > *l) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "Record {name} in input has {n} characters other than ACGTN ({:.2}%), above the limit of {}%: use --permissive to treat these as gaps",
+                            100.0 * p,
+                            100.0 * limit
+                        ),
+                    ));
+                }
+                self.counts.other.push((name.as_str().into(), n, rec_len))
+            }
+        }
+        if let Some((name, false)) = self.record.take() {
+            if self.strict {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Record {name} in input has no sequence"),
+                ));
+            }
+            self.empty.push(name)
+        }
+        Ok(())
+    }
+
+    fn read_header(&mut self) -> io::Result<()> {
+        self.end_record()?;
+        self.header.clear();
+        self.hpos = 0;
+        self.inner.read_until(b'\n', &mut self.header)?;
+        let end = self.header[1..]
+            .iter()
+            .position(|c| c.is_ascii_whitespace())
+            .map(|i| i + 1)
+            .unwrap_or(self.header.len());
+        let name = self.header[1..end].to_vec();
+        let name = match self.dups.check(&name)? {
+            Some(new) => {
+                self.header.splice(1..end, new.iter().copied());
+                new
+            }
+            None => name,
+        };
+        self.record = Some((String::from_utf8_lossy(&name).into_owned(), false));
+        self.counts.records += 1;
+        Ok(())
+    }
+
+    /// Record warnings for the anomalies found
+    fn finish(&mut self) -> io::Result<()> {
+        self.end_record()?;
+        self.finished = true;
+        self.counts.complete = true;
+        let unknown: Vec<_> = (0..=255u8)
+            .filter(|c| c.is_ascii_graphic() && Base::from_u8(*c) == Base::Other)
+            .filter(|c| self.counts.counts[*c as usize] > 0)
+            .map(|c| format!("{} ({})", c as char, self.counts.counts[c as usize]))
+            .collect();
+        if !unknown.is_empty() {
+            add_warning(
+                "unknown_bases",
+                format!(
+                    "Characters other than ACGTN in input treated as gaps: {}",
+                    unknown.join(", ")
+                ),
+            )
+        }
+        for (name, n, len) in self.counts.other.iter() {
+            add_warning(
+                "unknown_bases_contig",
+                format!(
+                    "Record {name} in input has {n} characters other than ACGTN ({:.4}%) treated as gaps",
+                    100.0 * *n as f64 / *len as f64
+                ),
+            )
+        }
+        for name in self.empty.drain(..) {
+            add_warning(
+                "empty_record",
+                format!("Record {name} in input has no sequence"),
+            )
+        }
+        if self.last.is_some_and(|c| c != b'\n') {
+            add_warning(
+                "truncated_input",
+                "Input does not end with a newline: last line may be truncated".to_owned(),
+            )
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for CheckReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let b = self.fill_buf()?;
+            let n = b.len().min(buf.len());
+            buf[..n].copy_from_slice(&b[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for CheckReader<'_, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.hpos < self.header.len() {
+            return Ok(&self.header[self.hpos..]);
+        }
+        // Sequence data is passed through up to the start of the next header line
+        if self.avail == 0 {
+            let b = self.inner.fill_buf()?;
+            if b.is_empty() {
+                if !self.finished {
+                    self.finish()?
+                }
+                return Ok(&[]);
+            }
+            if self.at_line_start && b.first() == Some(&b'>') {
+                self.read_header()?;
+                return Ok(&self.header);
+            }
+            let mut n = b.len();
+            let mut i = 0;
+            while let Some(j) = b[i..].iter().position(|c| *c == b'\n') {
+                i += j + 1;
+                if b.get(i) == Some(&b'>') {
+                    n = i;
+                    break;
+                }
+            }
+            self.avail = n
+        }
+        Ok(&self.inner.fill_buf()?[..self.avail])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if amt == 0 {
+            return;
+        }
+        if self.hpos < self.header.len() {
+            self.hpos += amt;
+            self.last = Some(self.header[self.hpos - 1]);
+        } else {
+            // The buffer is already filled, so this does not read from the input
+            if let Ok(b) = self.inner.fill_buf() {
+                for c in b[..amt].iter() {
+                    self.counts.counts[*c as usize] += 1
+                }
+                if let Some((_, seen)) = self.record.as_mut() {
+                    *seen = *seen || b[..amt].iter().any(|c| !c.is_ascii_whitespace())
+                }
+                self.last = Some(b[amt - 1])
+            }
+            self.inner.consume(amt);
+            self.avail -= amt
+        }
+        self.at_line_start = self.last == Some(b'\n')
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_check_reader() {
+        let input = b">chr1 a\nAC\n>chr2\n\n>chr1 b\nTRT\n>chr1_3\nA\n>chr1\nC";
+        let mut s = String::new();
+        let mut counts = CharCounts::default();
+        let mut rdr = CheckReader::new(&input[..], DuplicatePolicy::Rename, &mut counts);
+        rdr.read_to_string(&mut s).unwrap();
+        assert_eq!(
+            s,
+            ">chr1 a\nAC\n>chr2\n\n>chr1_2 b\nTRT\n>chr1_3\nA\n>chr1_4\nC"
+        );
+        assert_eq!(rdr.last, Some(b'C'));
+        assert_eq!(counts.base(Base::Other), 1);
+        assert_eq!((counts.length(), counts.records()), (7, 5));
+        assert_eq!(counts.dictionary()[2], ("chr1_2".into(), 3));
+        assert_eq!(counts.other_bases(), [("chr1_2".into(), 1, 3)]);
+        assert!(counts.is_complete());
+        for (limit, ok) in [(0.5, true), (0.1, false)] {
+            let mut s = String::new();
+            let mut counts = CharCounts::default();
+            let r = CheckReader::new(&input[..], DuplicatePolicy::Rename, &mut counts)
+                .with_other_limit(Some(limit))
+                .read_to_string(&mut s);
+            assert_eq!(r.is_ok(), ok)
+        }
+        let mut s = String::new();
+        let mut counts = CharCounts::default();
+        assert!(
+            CheckReader::new(&input[..], DuplicatePolicy::Error, &mut counts)
+                .read_to_string(&mut s)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_empty_records() {
+        let input = b">chrEmpty\n>chr1\nACGT\n>chr2\n \n>chr3\nNNNN\n>chr4\n";
+        let mut s = String::new();
+        let mut counts = CharCounts::default();
+        let mut rdr = CheckReader::new(&input[..], DuplicatePolicy::Error, &mut counts);
+        rdr.read_to_string(&mut s).unwrap();
+        assert_eq!(s.as_bytes(), input);
+        drop(rdr);
+        assert_eq!(counts.records(), 5);
+        let w: Vec<_> = crate::warnings::warnings()
+            .iter()
+            .filter(|w| w.kind() == "empty_record")
+            .map(|w| w.message().to_owned())
+            .collect();
+        for ctg in ["chrEmpty", "chr2", "chr4"] {
+            assert!(w.contains(&format!("Record {ctg} in input has no sequence")))
+        }
+        let mut s = String::new();
+        let mut counts = CharCounts::default();
+        let e = CheckReader::new(&input[..], DuplicatePolicy::Error, &mut counts)
+            .with_strict(true)
+            .read_to_string(&mut s)
+            .unwrap_err();
+        assert_eq!(e.to_string(), "Record chrEmpty in input has no sequence");
+        let mut s = String::new();
+        let mut counts = CharCounts::default();
+        let e = CheckReader::new(&input[10..], DuplicatePolicy::Error, &mut counts)
+            .with_strict(true)
+            .read_to_string(&mut s)
+            .unwrap_err();
+        assert_eq!(e.to_string(), "Record chr2 in input has no sequence");
+    }
+}
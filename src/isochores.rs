@@ -0,0 +1,176 @@
+//! Segmentation of contigs into regions of homogeneous GC content (isochores)
+//!
+//! GC is calculated in fixed size windows, and each contig is split by recursive binary
+//! segmentation.  At each step the split minimizing the residual sum of squares of the window GC
+//! values is accepted if the reduction is more than penalty * var * ln(n), where var is a robust
+//! estimate of the window to window variance and n the number of windows in the contig, both
+//! parts have at least the minimum number of windows and the mean GC differs by at least the
+//! minimum difference.
+use std::io::Write;
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+
+use crate::{
+    cli::IsochoreConfig,
+    fasta::tar::open_fasta,
+    windows::{read_windows, Window},
+};
+
+/// Isochore family (Bernardi's classification for the human genome) from the GC content
+pub fn isochore_family(gc: f64) -> &'static str {
+    match gc {
+        x if x < 0.37 => "L1",
+        x if x < 0.41 => "L2",
+        x if x < 0.46 => "H1",
+        x if x < 0.53 => "H2",
+        _ => "H3",
+    }
+}
+
+/// Robust estimate of the noise variance from the median absolute difference between
+/// successive values
+fn noise_var(x: &[f64]) -> f64 {
+    let mut d: Vec<f64> = x.windows(2).map(|w| (w[1] - w[0]).abs()).collect();
+    if d.is_empty() {
+        return 0.0;
+    }
+    let k = d.len() / 2;
+    let (_, m, _) = d.select_nth_unstable_by(k, |a, b| a.partial_cmp(b).unwrap());
+    let sd = *m / 0.6745;
+    sd * sd / 2.0
+}
+
+/// Split x into homogeneous segments, returning the (start, end) index ranges in order
+pub fn segment(x: &[f64], min_windows: usize, min_diff: f64, penalty: f64) -> Vec<(usize, usize)> {
+    let n = x.len();
+    if n == 0 {
+        return Vec::new();
+    }
+    let mut s1 = Vec::with_capacity(n + 1);
+    let mut s2 = Vec::with_capacity(n + 1);
+    let (mut a, mut b) = (0.0, 0.0);
+    s1.push(a);
+    s2.push(b);
+    for z in x {
+        a += z;
+        b += z * z;
+        s1.push(a);
+        s2.push(b)
+    }
+    let mean = |i: usize, j: usize| (s1[j] - s1[i]) / (j - i) as f64;
+    let rss = |i: usize, j: usize| s2[j] - s2[i] - (s1[j] - s1[i]).powi(2) / (j - i) as f64;
+
+    // Floor on variance so that noise free input is not split at rounding differences
+    let var = noise_var(x).max(1.0e-8);
+    let limit = penalty * var * (n as f64).ln().max(1.0);
+    let min_windows = min_windows.max(1);
+
+    let mut segs = Vec::new();
+    let mut stack = vec![(0, n)];
+    while let Some((i, j)) = stack.pop() {
+        let tot = rss(i, j);
+        let best = (i + min_windows..=j.saturating_sub(min_windows))
+            .filter(|k| (mean(i, *k) - mean(*k, j)).abs() >= min_diff)
+            .map(|k| (k, tot - rss(i, k) - rss(k, j)))
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        match best {
+            Some((k, gain)) if gain > limit => {
+                stack.push((k, j));
+                stack.push((i, k))
+            }
+            _ => segs.push((i, j)),
+        }
+    }
+    segs
+}
+
+struct Writer<W: Write> {
+    wrt: W,
+    n_segments: usize,
+    family_lengths: [(&'static str, u64); 5],
+}
+
+impl<W: Write> Writer<W> {
+    /// Segment the windows from one contig and write out the segments
+    fn write_contig(&mut self, cfg: &IsochoreConfig, v: &[Window]) -> anyhow::Result<()> {
+        let x: Vec<f64> = v.iter().map(|w| w.gc().unwrap()).collect();
+        for (i, j) in segment(&x, cfg.min_windows(), cfg.min_diff(), cfg.penalty()) {
+            let w = &v[i..j];
+            let (gc, n) = w.iter().fold((0, 0), |(gc, n), w| {
+                let c = w.counts();
+                (gc + (c[1] + c[3]) as u64, n + w.bases() as u64)
+            });
+            let gc = gc as f64 / n as f64;
+            let fam = isochore_family(gc);
+            let (start, end) = (w[0].start(), w[w.len() - 1].end());
+            writeln!(
+                self.wrt,
+                "{}\t{start}\t{end}\t{fam}\t{}\t.\t{gc:.4}",
+                w[0].contig(),
+                (gc * 1000.0).round() as u32,
+            )?;
+            self.n_segments += 1;
+            for f in self.family_lengths.iter_mut() {
+                if f.0 == fam {
+                    f.1 += end - start
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn isochores(cfg: &IsochoreConfig) -> anyhow::Result<()> {
+    let rdr = open_fasta(cfg.input())?;
+
+    let name = format!("{}_isochores.bed", cfg.prefix());
+    let mut wrt = Writer {
+        wrt: CompressIo::new()
+            .path(&name)
+            .bufwriter()
+            .with_context(|| format!("Could not open output file {name}"))?,
+        n_segments: 0,
+        family_lengths: [("L1", 0), ("L2", 0), ("H1", 0), ("H2", 0), ("H3", 0)],
+    };
+
+    info!("Starting to read input");
+    let mut v: Vec<Window> = Vec::new();
+    read_windows(rdr, cfg.window(), |w| {
+        if v.last().map(|x| x.contig() != w.contig()) == Some(true) {
+            wrt.write_contig(cfg, &v)?;
+            v.clear()
+        }
+        if w.coverage() >= cfg.threshold() {
+            v.push(w.clone())
+        }
+        Ok(())
+    })?;
+    wrt.write_contig(cfg, &v)?;
+    info!("Finished reading input");
+
+    info!("{} isochores found", wrt.n_segments);
+    for (f, l) in wrt.family_lengths.iter() {
+        info!("Family {f}: {l} bases")
+    }
+    wrt.wrt
+        .flush()
+        .with_context(|| format!("Error flushing data to {name}"))
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_segment() {
+        let mut x = Vec::new();
+        for (i, m) in [0.35, 0.45, 0.38].iter().enumerate() {
+            for k in 0..20 {
+                x.push(m + 0.002 * (((i * 20 + k) * 7919) % 11) as f64 / 10.0)
+            }
+        }
+        assert_eq!(segment(&x, 3, 0.01, 3.0), vec![(0, 20), (20, 40), (40, 60)]);
+        assert_eq!(segment(&x, 3, 0.2, 3.0), vec![(0, 60)]);
+    }
+}
@@ -15,14 +15,23 @@
 /// redundant_kmers: u64
 /// total_hits: u64
 ///
+/// PROVENANCE BLOCK (version 3.1 onwards)
+///   provenance_length: u32
+///   provenance: provenance_length * u8 (JSON object with command line, working directory,
+///     hostname and reference digest)
+///
+///   Per contig kmer counts are written to a JSON sidecar file (see [`contigs`]) rather than
+///   the header, so the format is unchanged
+///
 /// CONTIG BLOCK (Repeated n_contig times)
 ///   name_length: u16
 ///   name: name_length * u8
 ///
 /// TARGET BLOCK (repeated n_targets times)
 ///   contig_id: u32 (starts from 0)
-///   start: u32
-///   end: u32
+///   start: u64 (u32 before version 3)
+///   end: u64 (u32 before version 3)
+///   strand: u8 (0 - unknown, 1 - plus, 2 - minus; version 3 onwards)
 ///
 /// KMER BLOCK
 ///   type_skip_nhits: u8 (see below)
@@ -72,5 +81,81 @@
 ///   0xff, 0xff,
 ///   0xf0, 0x11, 0x000681c5
 ///
+pub mod contigs;
+pub mod input;
 pub mod output;
-pub use output::output_kmers;
+pub mod update;
+pub use contigs::{contig_kmer_stats, output_contig_stats};
+pub use input::{open_kmcv, KmcvReader, KmcvTarget};
+pub use output::{output_kmers, KmerCompression, KmerWriterOpts};
+
+use crate::kmers::KmerVec;
+
+const MAJOR_VERSION: u8 = 3;
+const MINOR_VERSION: u8 = 1;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum KmerType {
+    Unmapped,
+    UniqueOnTarget,
+    UniqueOffTarget,
+    LowMultiMap(u8),
+    HighMultiMap,
+}
+
+impl KmerType {
+    pub(crate) fn from_kmer_vec(v: &KmerVec) -> Self {
+        if v[0] == 0 {
+            Self::Unmapped
+        } else if (v[0] & 0x80000000) != 0 {
+            Self::HighMultiMap
+        } else if v[1] == 0 {
+            if v[0] == 1 {
+                Self::UniqueOffTarget
+            } else {
+                Self::UniqueOnTarget
+            }
+        } else {
+            let mut n_hits = None;
+            for (i, x) in v[2..].iter().enumerate() {
+                if *x == 0 {
+                    n_hits = Some(i + 2);
+                    break;
+                }
+            }
+            let n_hits = n_hits.unwrap_or(v.len()) as u8;
+            Self::LowMultiMap(n_hits)
+        }
+    }
+
+    pub fn type_code(&self) -> u8 {
+        match self {
+            Self::Unmapped => 15,
+            Self::UniqueOnTarget => 0,
+            Self::LowMultiMap(x) => *x - 1,
+            Self::UniqueOffTarget => 8,
+            Self::HighMultiMap => 9,
+        }
+    }
+
+    /// Inverse of type_code().  Returns None for undefined codes
+    pub fn from_type_code(x: u8) -> Option<Self> {
+        match x {
+            0 => Some(Self::UniqueOnTarget),
+            1..=7 => Some(Self::LowMultiMap(x + 1)),
+            8 => Some(Self::UniqueOffTarget),
+            9 => Some(Self::HighMultiMap),
+            15 => Some(Self::Unmapped),
+            _ => None,
+        }
+    }
+
+    /// Number of target ids stored in the kmer block
+    pub fn n_stored_hits(&self) -> usize {
+        match self {
+            Self::UniqueOnTarget => 1,
+            Self::LowMultiMap(x) => *x as usize,
+            _ => 0,
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! Per contig kmer statistics written as a JSON sidecar to the kmer file, so that consumers can
+//! reason about contig level mappability without decoding the kmer blocks
+//!
+//! For each input contig (in input order) we give the number of kmer positions recorded from the
+//! contig and the number of targets on the contig.  For the target contigs we also give the
+//! number of distinct kmers hitting a target on the contig and the number of those mapping
+//! uniquely (the same definition as for the kmer file).  As off target hits do not record the
+//! contig, the distinct off target kmers per contig are not available.
+use std::{io::Write, path::Path};
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+
+use super::KmerType;
+use crate::{
+    kmers::{KmerVec, KmerWork, KMER_LENGTH},
+    provenance::Provenance,
+    regions::Regions,
+};
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ContigKmerStats {
+    name: String,
+    kmers: u64,
+    targets: u64,
+    on_target_kmers: u64,
+    unique_kmers: u64,
+}
+
+impl ContigKmerStats {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn on_target_kmers(&self) -> u64 {
+        self.on_target_kmers
+    }
+
+    pub fn unique_kmers(&self) -> u64 {
+        self.unique_kmers
+    }
+}
+
+#[derive(Serialize)]
+struct ContigStatsOutput<'a> {
+    program: &'static str,
+    version: &'static str,
+    #[serde(flatten)]
+    provenance: &'a Provenance,
+    kmer_file: &'a str,
+    kmer_length: usize,
+    contigs: &'a [ContigKmerStats],
+}
+
+/// Add the on target counts for a kmer.  Hits are stored as target idx + 1, with 1 for off target
+fn add_hits(v: &KmerVec, target_contig: &[u32], stats: &mut [ContigKmerStats]) {
+    if v[0] == 0 || v[0] & 0x80000000 != 0 {
+        return;
+    }
+    let mut seen = [u32::MAX; 8];
+    for (i, x) in v
+        .iter()
+        .take_while(|x| **x != 0)
+        .filter(|x| **x > 1)
+        .enumerate()
+    {
+        let c = target_contig[(*x - 2) as usize];
+        if !seen[..i].contains(&c) {
+            stats[c as usize].on_target_kmers += 1
+        }
+        seen[i] = c
+    }
+    if KmerType::from_kmer_vec(v) == KmerType::UniqueOnTarget {
+        stats[target_contig[(v[0] - 2) as usize] as usize].unique_kmers += 1
+    }
+}
+
+/// Per contig statistics given the number of kmer positions recorded for each input contig
+pub fn contig_kmer_stats(
+    reg: &Regions,
+    k_work: &KmerWork,
+    contig_kmers: &[(String, u64)],
+) -> Vec<ContigKmerStats> {
+    let mut stats: Vec<_> = contig_kmers
+        .iter()
+        .map(|(name, kmers)| ContigKmerStats {
+            name: name.clone(),
+            kmers: *kmers,
+            ..Default::default()
+        })
+        .collect();
+    let mut ix: FxHashMap<&str, usize> = contig_kmers
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _))| (name.as_str(), i))
+        .collect();
+    // Target contigs not seen in the input are added at the end
+    let mut target_contig = vec![0u32; reg.n_regions()];
+    for (ctg, cr) in reg.iter() {
+        let i = *ix.entry(ctg).or_insert_with(|| {
+            stats.push(ContigKmerStats {
+                name: ctg.to_owned(),
+                ..Default::default()
+            });
+            stats.len() - 1
+        });
+        stats[i].targets = cr.regions().len() as u64;
+        for r in cr.regions() {
+            target_contig[u32::from(r.idx()) as usize - 1] = i as u32
+        }
+    }
+    for (_, v) in k_work.kmers() {
+        add_hits(v, &target_contig, &mut stats)
+    }
+    stats
+}
+
+/// Write the per contig statistics as JSON
+pub fn output_contig_stats<P: AsRef<Path>>(
+    path: P,
+    kmer_file: &str,
+    stats: &[ContigKmerStats],
+    prov: &Provenance,
+) -> anyhow::Result<()> {
+    let mut wrt = CompressIo::new()
+        .path(path)
+        .bufwriter()
+        .with_context(|| "Could not open kmer contig statistics file for output")?;
+    let out = ContigStatsOutput {
+        program: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+        provenance: prov,
+        kmer_file,
+        kmer_length: KMER_LENGTH,
+        contigs: stats,
+    };
+    serde_json::to_writer_pretty(&mut wrt, &out)
+        .with_context(|| "Error writing kmer contig statistics")?;
+    wrt.flush()
+        .with_context(|| "Error flushing data to kmer contig statistics file")
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_add_hits() {
+        let mut stats = vec![ContigKmerStats::default(); 2];
+        // Targets 1 and 2 are on contig 0, target 3 on contig 1
+        let tc = [0, 0, 1];
+        add_hits(&[2, 0, 0, 0, 0, 0, 0, 0], &tc, &mut stats);
+        add_hits(&[2, 3, 1, 0, 0, 0, 0, 0], &tc, &mut stats);
+        add_hits(&[3, 4, 0, 0, 0, 0, 0, 0], &tc, &mut stats);
+        add_hits(&[1, 0, 0, 0, 0, 0, 0, 0], &tc, &mut stats);
+        add_hits(&[0x80000000, 0, 0, 0, 0, 0, 0, 0], &tc, &mut stats);
+        assert_eq!(
+            stats
+                .iter()
+                .map(|s| (s.on_target_kmers, s.unique_kmers))
+                .collect::<Vec<_>>(),
+            [(3, 1), (1, 0)]
+        );
+    }
+}
@@ -0,0 +1,348 @@
+use std::{
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use anyhow::Context;
+use compress_io::compress::{CompressIo, Reader};
+
+use super::{KmerType, MAJOR_VERSION};
+use crate::regions::Strand;
+
+/// Target region as stored in a kmcv file.  Target ids start from 1 (0 is used for off target hits)
+#[derive(Debug, Copy, Clone)]
+pub struct KmcvTarget {
+    contig_id: u32,
+    start: u64,
+    end: u64,
+    strand: Strand,
+}
+
+impl KmcvTarget {
+    pub(crate) fn new(contig_id: u32, start: u64, end: u64, strand: Strand) -> Self {
+        Self {
+            contig_id,
+            start,
+            end,
+            strand,
+        }
+    }
+
+    pub fn contig_id(&self) -> u32 {
+        self.contig_id
+    }
+
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+
+    pub fn strand(&self) -> Strand {
+        self.strand
+    }
+
+    pub fn len(&self) -> u64 {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.end <= self.start
+    }
+}
+
+pub struct KmcvReader<R: BufRead> {
+    r: R,
+    kmer_length: u8,
+    max_hits: u8,
+    rnd_id: u32,
+    mapped_kmers: u64,
+    on_target_kmers: u64,
+    redundant_kmers: u64,
+    total_hits: u64,
+    provenance: Option<String>,
+    contigs: Vec<Box<str>>,
+    targets: Vec<KmcvTarget>,
+    kmers_read: u64,
+    prev_kmer: u32,
+    hits: Vec<u32>,
+}
+
+fn read_u16<R: BufRead>(r: &mut R) -> std::io::Result<u16> {
+    let mut b = [0u8; 2];
+    r.read_exact(&mut b)?;
+    Ok(u16::from_le_bytes(b))
+}
+
+fn read_u32<R: BufRead>(r: &mut R) -> std::io::Result<u32> {
+    let mut b = [0u8; 4];
+    r.read_exact(&mut b)?;
+    Ok(u32::from_le_bytes(b))
+}
+
+fn read_u64<R: BufRead>(r: &mut R) -> std::io::Result<u64> {
+    let mut b = [0u8; 8];
+    r.read_exact(&mut b)?;
+    Ok(u64::from_le_bytes(b))
+}
+
+fn read_u8<R: BufRead>(r: &mut R) -> std::io::Result<u8> {
+    let mut b = [0u8; 1];
+    r.read_exact(&mut b)?;
+    Ok(b[0])
+}
+
+impl<R: BufRead> KmcvReader<R> {
+    pub fn new(mut r: R) -> anyhow::Result<Self> {
+        let mut buf = [0u8; 52];
+        r.read_exact(&mut buf)
+            .with_context(|| "Error reading header from kmer file")?;
+        if &buf[0..4] != b"KMCV" {
+            return Err(anyhow!("Not a kmcv file (bad magic number)"));
+        }
+        // Version 2 files (without target strands) can also be read
+        if buf[4] != MAJOR_VERSION && buf[4] != 2 {
+            return Err(anyhow!(
+                "Unsupported kmcv version {}.{} (expected major version {MAJOR_VERSION})",
+                buf[4],
+                buf[5]
+            ));
+        }
+        let get_u32 = |i: usize| u32::from_le_bytes(buf[i..i + 4].try_into().unwrap());
+        let get_u64 = |i: usize| u64::from_le_bytes(buf[i..i + 8].try_into().unwrap());
+        let rnd_id = get_u32(8);
+        let n_contigs = get_u32(12) as usize;
+        let n_targets = get_u32(16) as usize;
+        let mapped_kmers = get_u64(20);
+
+        let provenance = if (buf[4], buf[5]) >= (3, 1) {
+            let l = read_u32(&mut r).with_context(|| "Error reading provenance length")?;
+            let mut s = vec![0u8; l as usize];
+            r.read_exact(&mut s)
+                .with_context(|| "Error reading provenance block")?;
+            Some(String::from_utf8(s).with_context(|| "Provenance not utf8")?)
+        } else {
+            None
+        };
+
+        let mut contigs = Vec::with_capacity(n_contigs);
+        for _ in 0..n_contigs {
+            let l = read_u16(&mut r).with_context(|| "Error reading contig name length")?;
+            let mut s = vec![0u8; l as usize];
+            r.read_exact(&mut s)
+                .with_context(|| "Error reading contig name")?;
+            contigs.push(
+                String::from_utf8(s)
+                    .with_context(|| "Contig name not utf8")?
+                    .into_boxed_str(),
+            )
+        }
+
+        let mut targets = Vec::with_capacity(n_targets);
+        for _ in 0..n_targets {
+            let contig_id = read_u32(&mut r)?;
+            let (start, end) = if buf[4] > 2 {
+                (read_u64(&mut r)?, read_u64(&mut r)?)
+            } else {
+                (read_u32(&mut r)? as u64, read_u32(&mut r)? as u64)
+            };
+            if contig_id as usize >= n_contigs {
+                return Err(anyhow!("Illegal contig id {contig_id} for target"));
+            }
+            let strand = if buf[4] > 2 {
+                let x = read_u8(&mut r).with_context(|| "Error reading target strand")?;
+                Strand::from_code(x).ok_or_else(|| anyhow!("Illegal strand code {x} for target"))?
+            } else {
+                Strand::Unknown
+            };
+            targets.push(KmcvTarget {
+                contig_id,
+                start,
+                end,
+                strand,
+            })
+        }
+
+        Ok(Self {
+            r,
+            kmer_length: buf[6],
+            max_hits: buf[7],
+            rnd_id,
+            mapped_kmers,
+            on_target_kmers: get_u64(28),
+            redundant_kmers: get_u64(36),
+            total_hits: get_u64(44),
+            provenance,
+            contigs,
+            targets,
+            kmers_read: 0,
+            prev_kmer: 0,
+            hits: Vec::new(),
+        })
+    }
+
+    pub fn kmer_length(&self) -> u8 {
+        self.kmer_length
+    }
+
+    pub fn max_hits(&self) -> u8 {
+        self.max_hits
+    }
+
+    pub fn mapped_kmers(&self) -> u64 {
+        self.mapped_kmers
+    }
+
+    pub fn on_target_kmers(&self) -> u64 {
+        self.on_target_kmers
+    }
+
+    pub fn redundant_kmers(&self) -> u64 {
+        self.redundant_kmers
+    }
+
+    pub fn total_hits(&self) -> u64 {
+        self.total_hits
+    }
+
+    /// Provenance information (JSON) for files from version 3.1 onwards
+    pub fn provenance(&self) -> Option<&str> {
+        self.provenance.as_deref()
+    }
+
+    pub fn contigs(&self) -> &[Box<str>] {
+        &self.contigs
+    }
+
+    /// Target with id ix (ids start from 1)
+    pub fn target(&self, ix: u32) -> Option<&KmcvTarget> {
+        ix.checked_sub(1).and_then(|i| self.targets.get(i as usize))
+    }
+
+    pub fn targets(&self) -> &[KmcvTarget] {
+        &self.targets
+    }
+
+    fn read_skip(&mut self, x: u32) -> std::io::Result<u32> {
+        if x < 0x0f {
+            return Ok(x);
+        }
+        let e = read_u8(&mut self.r)? as u32;
+        if e < 0xff {
+            return Ok(0x0f + e);
+        }
+        let e = read_u16(&mut self.r)? as u32;
+        if e < 0xffff {
+            return Ok(0x0f + 0xff + e);
+        }
+        let e = read_u32(&mut self.r)?;
+        Ok(0x0f + 0xff + 0xffff + e)
+    }
+
+    fn check_close(&mut self) -> anyhow::Result<()> {
+        let id = read_u32(&mut self.r).with_context(|| "Error reading closing block")?;
+        let mut magic = [0u8; 4];
+        self.r
+            .read_exact(&mut magic)
+            .with_context(|| "Error reading closing block")?;
+        if id != self.rnd_id || &magic != b"VCMK" {
+            Err(anyhow!("Corrupt closing block in kmer file"))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns the next mapped kmer with its type and target hits (0 is used for an off target hit).
+    /// Returns None after the last kmer, when the closing block has been checked.
+    pub fn next_kmer(&mut self) -> anyhow::Result<Option<(u32, KmerType, &[u32])>> {
+        if self.kmers_read >= self.mapped_kmers {
+            if self.kmers_read == self.mapped_kmers {
+                self.check_close()?;
+                self.kmers_read += 1;
+            }
+            return Ok(None);
+        }
+        let b = read_u8(&mut self.r).with_context(|| "Error reading kmer block")?;
+        let ktype = KmerType::from_type_code(b & 0x0f)
+            .ok_or_else(|| anyhow!("Illegal kmer type code {}", b & 0x0f))?;
+        let skip = self
+            .read_skip((b >> 4) as u32)
+            .with_context(|| "Error reading kmer skip")?;
+        let kmer = self
+            .prev_kmer
+            .checked_add(skip)
+            .ok_or_else(|| anyhow!("Kmer overflow in kmer file"))?;
+        self.hits.clear();
+        for _ in 0..ktype.n_stored_hits() {
+            self.hits
+                .push(read_u32(&mut self.r).with_context(|| "Error reading kmer hit")?);
+        }
+        if self.hits.iter().any(|x| *x as usize > self.targets.len()) {
+            return Err(anyhow!("Illegal target id for kmer {kmer}"));
+        }
+        self.prev_kmer = kmer;
+        self.kmers_read += 1;
+        Ok(Some((kmer, ktype, &self.hits)))
+    }
+}
+
+pub fn open_kmcv<P: AsRef<Path>>(path: P) -> anyhow::Result<KmcvReader<BufReader<Reader>>> {
+    let path = path.as_ref();
+    let rdr = CompressIo::new()
+        .path(path)
+        .bufreader()
+        .with_context(|| format!("Could not open kmer file {}", path.display()))?;
+    KmcvReader::new(rdr).with_context(|| format!("Error reading from {}", path.display()))
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_kmer_blocks() {
+        // Blocks as produced by output::write_kmer_block, including a 4 byte skip extension
+        let mut v: Vec<u8> = Vec::new();
+        v.extend_from_slice(b"KMCV");
+        v.extend_from_slice(&[MAJOR_VERSION, 1, 31, 8]);
+        v.extend_from_slice(&7u32.to_le_bytes());
+        v.extend_from_slice(&1u32.to_le_bytes());
+        v.extend_from_slice(&1u32.to_le_bytes());
+        v.extend_from_slice(&3u64.to_le_bytes());
+        v.extend_from_slice(&[0; 24]);
+        v.extend_from_slice(&2u32.to_le_bytes());
+        v.extend_from_slice(b"{}");
+        v.extend_from_slice(&4u16.to_le_bytes());
+        v.extend_from_slice(b"chr1");
+        v.extend_from_slice(&0u32.to_le_bytes());
+        for x in [100u64, 200] {
+            v.extend_from_slice(&x.to_le_bytes())
+        }
+        v.push(Strand::Minus.code());
+        v.extend_from_slice(&[0x43]);
+        for x in [1u32, 1, 1, 0] {
+            v.extend_from_slice(&x.to_le_bytes())
+        }
+        v.extend_from_slice(&[0xf8, 0x05]);
+        v.extend_from_slice(&[0xf0, 0xff, 0xff, 0xff, 0x10, 0x00, 0x00, 0x00]);
+        v.extend_from_slice(&1u32.to_le_bytes());
+        v.extend_from_slice(&7u32.to_le_bytes());
+        v.extend_from_slice(b"VCMK");
+
+        let mut rdr = KmcvReader::new(v.as_slice()).unwrap();
+        assert_eq!(rdr.provenance(), Some("{}"));
+        assert_eq!(rdr.contigs()[0].as_ref(), "chr1");
+        assert_eq!(rdr.target(1).unwrap().len(), 100);
+        assert_eq!(rdr.target(1).unwrap().strand(), Strand::Minus);
+        let (k, t, h) = rdr.next_kmer().unwrap().unwrap();
+        assert_eq!((k, t, h), (4, KmerType::LowMultiMap(4), &[1, 1, 1, 0][..]));
+        let (k, t, _) = rdr.next_kmer().unwrap().unwrap();
+        assert_eq!((k, t), (24, KmerType::UniqueOffTarget));
+        let (k, t, h) = rdr.next_kmer().unwrap().unwrap();
+        assert_eq!(k, 24 + 0x0f + 0xff + 0xffff + 16);
+        assert_eq!((t, h), (KmerType::UniqueOnTarget, &[1][..]));
+        assert!(rdr.next_kmer().unwrap().is_none());
+    }
+}
@@ -1,19 +1,92 @@
 use std::{io::Write, path::Path};
 
 use anyhow::Context;
+use clap::{builder::PossibleValue, ValueEnum};
 use compress_io::{
     compress::CompressIo,
     compress_type::{CompressThreads, CompressType},
 };
 use rand::random;
 
+use super::{KmcvTarget, KmerType, MAJOR_VERSION, MINOR_VERSION};
 use crate::{
-    kmers::{KmerVec, KmerWork, KMER_LENGTH, MAX_HITS},
+    kmers::{KType, KmerVec, KmerWork, KMER_LENGTH, MAX_HITS},
+    provenance::Provenance,
     regions::Regions,
 };
 
-const MAJOR_VERSION: u8 = 2;
-const MINOR_VERSION: u8 = 0;
+/// Compression used for kmer files.  Uncompressed files can be memory mapped by consumers
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum KmerCompression {
+    Zstd,
+    Gzip,
+    Bzip2,
+    Xz,
+    None,
+}
+
+impl ValueEnum for KmerCompression {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Zstd, Self::Gzip, Self::Bzip2, Self::Xz, Self::None]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(self.name()))
+    }
+}
+
+impl KmerCompression {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Zstd => "zstd",
+            Self::Gzip => "gzip",
+            Self::Bzip2 => "bzip2",
+            Self::Xz => "xz",
+            Self::None => "none",
+        }
+    }
+
+    fn ctype(&self) -> CompressType {
+        match self {
+            Self::Zstd => CompressType::Zstd,
+            Self::Gzip => CompressType::Gzip,
+            Self::Bzip2 => CompressType::Bzip2,
+            Self::Xz => CompressType::Xz,
+            Self::None => CompressType::NoFilter,
+        }
+    }
+}
+
+/// Compression and number of compression threads for kmer files
+#[derive(Debug, Copy, Clone)]
+pub struct KmerWriterOpts {
+    compression: KmerCompression,
+    threads: Option<usize>,
+}
+
+impl Default for KmerWriterOpts {
+    fn default() -> Self {
+        Self::new(KmerCompression::Zstd, None)
+    }
+}
+
+impl KmerWriterOpts {
+    pub fn new(compression: KmerCompression, threads: Option<usize>) -> Self {
+        Self {
+            compression,
+            threads,
+        }
+    }
+
+    pub fn compression(&self) -> KmerCompression {
+        self.compression
+    }
+
+    /// Number of compression threads (default: number of physical cores)
+    pub fn threads(&self) -> Option<usize> {
+        self.threads
+    }
+}
 
 #[inline]
 fn u32_to_buf(b: &mut [u8], x: u32) {
@@ -25,22 +98,51 @@ fn u64_to_buf(b: &mut [u8], x: u64) {
     b.copy_from_slice(&x.to_le_bytes())
 }
 
-struct KmcvHeader {
+/// Kmer counts stored in the header
+#[derive(Debug, Default, Copy, Clone)]
+pub(super) struct HeaderCounts {
+    pub(super) mapped: u64,
+    pub(super) on_target: u64,
+    pub(super) redundant: u64,
+    pub(super) total_hits: u64,
+}
+
+pub(super) struct KmcvHeader {
     buf: [u8; 52],
 }
 
 impl KmcvHeader {
     fn new(reg: &Regions, k_work: &KmerWork, rnd_id: u32) -> Self {
-        let n_contigs = reg.n_contigs() as u32;
-        let n_targets = reg.n_regions() as u32;
-        let mapped = k_work.mapped_kmers();
-        let redundant = k_work.highly_redundant_kmers();
-        let on_target = k_work.on_target_kmers();
-        let total_hits = k_work.total_hits();
+        let counts = HeaderCounts {
+            mapped: k_work.mapped_kmers(),
+            on_target: k_work.on_target_kmers(),
+            redundant: k_work.highly_redundant_kmers(),
+            total_hits: k_work.total_hits(),
+        };
+        Self::from_counts(
+            reg.n_contigs() as u32,
+            reg.n_regions() as u32,
+            counts,
+            rnd_id,
+        )
+    }
+
+    pub(super) fn from_counts(
+        n_contigs: u32,
+        n_targets: u32,
+        counts: HeaderCounts,
+        rnd_id: u32,
+    ) -> Self {
+        let HeaderCounts {
+            mapped,
+            on_target,
+            redundant,
+            total_hits,
+        } = counts;
 
         let mut buf = [0; 52];
 
-        buf[0..4].copy_from_slice(&[b'K', b'M', b'C', b'V']);
+        buf[0..4].copy_from_slice(b"KMCV");
         buf[4] = MAJOR_VERSION;
         buf[5] = MINOR_VERSION;
         buf[6] = KMER_LENGTH as u8;
@@ -56,29 +158,56 @@ impl KmcvHeader {
         Self { buf }
     }
 
-    fn write<W: Write>(&self, w: &mut W) -> anyhow::Result<()> {
+    pub(super) fn write<W: Write>(&self, w: &mut W) -> anyhow::Result<()> {
         w.write_all(&self.buf)
             .with_context(|| "Error writing header to kmer file")
     }
 }
 
+pub(super) fn write_provenance_block<W: Write>(w: &mut W, prov: &Provenance) -> anyhow::Result<()> {
+    let s = serde_json::to_vec(prov)?;
+    w.write_all(&(s.len() as u32).to_le_bytes())
+        .with_context(|| "Error writing provenance length")?;
+    w.write_all(&s)
+        .with_context(|| "Error writing provenance block")
+}
+
 fn write_target_blocks<W: Write>(w: &mut W, reg: &Regions) -> anyhow::Result<()> {
-    for (ctg_ix, (_, ctg_regs)) in reg.iter().enumerate() {
-        let ix = ctg_ix as u32;
-        for r in ctg_regs.regions() {
-            w.write_all(&ix.to_le_bytes())
-                .with_context(|| "Error writing contig id for target")?;
-            w.write_all(&r.start().to_le_bytes())
-                .with_context(|| "Error writing target start")?;
-            w.write_all(&r.end().to_le_bytes())
-                .with_context(|| "Error writing target end")?;
-        }
+    let targets = reg.iter().enumerate().flat_map(|(ctg_ix, (_, ctg_regs))| {
+        ctg_regs
+            .regions()
+            .iter()
+            .map(move |r| KmcvTarget::new(ctg_ix as u32, r.start(), r.end(), r.strand()))
+    });
+    write_targets(w, targets)
+}
+
+pub(super) fn write_targets<W: Write, I: Iterator<Item = KmcvTarget>>(
+    w: &mut W,
+    targets: I,
+) -> anyhow::Result<()> {
+    for t in targets {
+        w.write_all(&t.contig_id().to_le_bytes())
+            .with_context(|| "Error writing contig id for target")?;
+        w.write_all(&t.start().to_le_bytes())
+            .with_context(|| "Error writing target start")?;
+        w.write_all(&t.end().to_le_bytes())
+            .with_context(|| "Error writing target end")?;
+        w.write_all(&[t.strand().code()])
+            .with_context(|| "Error writing target strand")?;
     }
     Ok(())
 }
 
 fn write_contig_blocks<W: Write>(w: &mut W, reg: &Regions) -> anyhow::Result<()> {
-    for (ctg, _) in reg.iter() {
+    write_contigs(w, reg.iter().map(|(ctg, _)| ctg))
+}
+
+pub(super) fn write_contigs<'a, W: Write, I: Iterator<Item = &'a str>>(
+    w: &mut W,
+    contigs: I,
+) -> anyhow::Result<()> {
+    for ctg in contigs {
         let l = ctg.len();
         if l > u16::MAX as usize {
             return Err(anyhow!(
@@ -94,49 +223,20 @@ fn write_contig_blocks<W: Write>(w: &mut W, reg: &Regions) -> anyhow::Result<()>
     Ok(())
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-enum KmerType {
-    Unmapped,
-    UniqueOnTarget,
-    UniqueOffTarget,
-    LowMultiMap(u8),
-    HighMultiMap,
-}
-
-impl KmerType {
-    fn from_kmer_vec(v: &KmerVec) -> Self {
-        if v[0] == 0 {
-            Self::Unmapped
-        } else if (v[0] & 0x80000000) != 0 {
-            Self::HighMultiMap
-        } else if v[1] == 0 {
-            if v[0] == 1 {
-                Self::UniqueOffTarget
-            } else {
-                Self::UniqueOnTarget
-            }
-        } else {
-            let mut n_hits = None;
-            for (i, x) in v[2..].iter().enumerate() {
-                if *x == 0 {
-                    n_hits = Some(i + 2);
-                    break;
-                }
-            }
-            let n_hits = n_hits.unwrap_or(v.len()) as u8;
-            Self::LowMultiMap(n_hits)
-        }
-    }
-
-    fn type_code(&self) -> u8 {
-        match self {
-            Self::Unmapped => 15,
-            Self::UniqueOnTarget => 0,
-            Self::LowMultiMap(x) => *x - 1,
-            Self::UniqueOffTarget => 8,
-            Self::HighMultiMap => 9,
-        }
+/// Write kmer block with the target hits as stored in the file (0 for an off target hit)
+pub(super) fn write_hits_block<W: Write>(
+    w: &mut W,
+    hits: &[u32],
+    skip: u32,
+    ktype: KmerType,
+) -> anyhow::Result<()> {
+    write_type_skip_nhits(w, skip, ktype)
+        .with_context(|| "Error writing type, skip and nhits for kmer")?;
+    for x in &hits[..ktype.n_stored_hits()] {
+        w.write_all(&x.to_le_bytes())
+            .with_context(|| "Failed to write out kmer hit")?;
     }
+    Ok(())
 }
 
 fn write_type_skip_nhits<W: Write>(w: &mut W, skip: u32, ktype: KmerType) -> std::io::Result<()> {
@@ -192,42 +292,64 @@ fn write_kmer_block<W: Write>(
     Ok(())
 }
 
-fn write_kmer_blocks<W: Write>(w: &mut W, kmers: &[KmerVec]) -> anyhow::Result<()> {
+fn write_kmer_blocks<'a, W: Write, I: Iterator<Item = (KType, &'a KmerVec)>>(
+    w: &mut W,
+    kmers: I,
+) -> anyhow::Result<u64> {
     let mut prev = 0;
-    for (k, v) in kmers.iter().enumerate() {
-        let kmer = k as u32;
+    let mut unique = 0;
+    for (kmer, v) in kmers {
         let ktype = KmerType::from_kmer_vec(v);
         if ktype != KmerType::Unmapped {
             write_kmer_block(w, v, kmer - prev, ktype)?;
             prev = kmer
         }
+        if ktype == KmerType::UniqueOnTarget {
+            unique += 1
+        }
     }
-    Ok(())
+    Ok(unique)
 }
 
-fn write_close<W: Write>(w: &mut W, rnd_id: u32) -> anyhow::Result<()> {
+pub(super) fn write_close<W: Write>(w: &mut W, rnd_id: u32) -> anyhow::Result<()> {
     let mut buf: [u8; 8] = [0, 0, 0, 0, b'V', b'C', b'M', b'K'];
 
     u32_to_buf(&mut buf[0..4], rnd_id);
     w.write_all(&buf)
         .with_context(|| "Error writing closing block to kmer file")
 }
-pub fn output_kmers<P: AsRef<Path>>(
+
+pub(super) fn open_kmer_writer<P: AsRef<Path>>(
     path: P,
-    reg: &Regions,
-    k_work: &KmerWork,
-) -> anyhow::Result<()> {
-    let mut w = CompressIo::new()
+    opts: &KmerWriterOpts,
+) -> anyhow::Result<impl Write> {
+    let threads = match opts.threads() {
+        Some(n) => CompressThreads::Set(n),
+        None => CompressThreads::NPhysCores,
+    };
+    CompressIo::new()
         .path(path)
         .fix_path()
-        .ctype(CompressType::Zstd)
-        .cthreads(CompressThreads::NPhysCores)
+        .ctype(opts.compression().ctype())
+        .cthreads(threads)
         .bufwriter()
-        .with_context(|| "Could not open kmer file for output")?;
+        .with_context(|| "Could not open kmer file for output")
+}
+
+/// Write kmer file, returning the number of kmers that map uniquely on target
+pub fn output_kmers<P: AsRef<Path>>(
+    path: P,
+    reg: &Regions,
+    k_work: &KmerWork,
+    prov: &Provenance,
+    opts: &KmerWriterOpts,
+) -> anyhow::Result<u64> {
+    let mut w = open_kmer_writer(path, opts)?;
 
     let rnd_id: u32 = random();
     let hdr = KmcvHeader::new(reg, k_work, rnd_id);
     hdr.write(&mut w)?;
+    write_provenance_block(&mut w, prov)?;
 
     // Write contig blocks
     write_contig_blocks(&mut w, reg)?;
@@ -236,9 +358,10 @@ pub fn output_kmers<P: AsRef<Path>>(
     write_target_blocks(&mut w, reg)?;
 
     // write kmer blocks
-    write_kmer_blocks(&mut w, k_work.kmers())?;
+    let unique = write_kmer_blocks(&mut w, k_work.kmers())?;
 
     write_close(&mut w, rnd_id)?;
     w.flush()
-        .with_context(|| "Error flushing data to kmer file")
+        .with_context(|| "Error flushing data to kmer file")?;
+    Ok(unique)
 }
@@ -0,0 +1,295 @@
+//! Addition of targets to an existing kmcv file without rescanning the whole reference
+//!
+//! Only the new targets are read from the reference (using the .fai index).  The kmers from the
+//! new targets were recorded as off target hits when the file was generated, and as the file
+//! keeps a single off target entry per kmer we can not tell whether a kmer also occurs elsewhere
+//! off target.  Following the file format, a kmer with only an off target hit is taken to map
+//! once, so its hit is moved to the new target; for other kmers the new target is added to the
+//! existing hits.  New targets must not overlap existing targets, and are numbered after them.
+use std::{
+    io::{BufRead, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use rand::random;
+
+use super::{
+    input::open_kmcv,
+    output::{
+        open_kmer_writer, write_close, write_contigs, write_hits_block, write_provenance_block,
+        write_targets, HeaderCounts, KmcvHeader,
+    },
+    KmcvTarget, KmerType,
+};
+use crate::{
+    cli::UpdateKmersConfig,
+    fasta::{
+        fai::{find_fai, open_span, read_fai},
+        Base,
+    },
+    kmers::{KType, KmerBuilder, KMER_LENGTH, MAX_HITS},
+    provenance::Provenance,
+    regions::read_bed::read_bed,
+};
+
+/// Kmer type for hits as stored in the file
+fn hits_type(hits: &[u32]) -> KmerType {
+    match hits {
+        [] => KmerType::Unmapped,
+        [0] => KmerType::UniqueOffTarget,
+        [_] => KmerType::UniqueOnTarget,
+        v if v.len() <= MAX_HITS => KmerType::LowMultiMap(v.len() as u8),
+        _ => KmerType::HighMultiMap,
+    }
+}
+
+/// Hits after adding the new targets to the existing hits
+fn update_hits(ktype: KmerType, hits: &[u32], new: &[u32]) -> (KmerType, Vec<u32>) {
+    let mut v = match ktype {
+        KmerType::HighMultiMap => return (ktype, Vec::new()),
+        KmerType::UniqueOffTarget => Vec::new(),
+        _ => hits.to_vec(),
+    };
+    for x in new {
+        if !v.contains(x) {
+            v.push(*x)
+        }
+    }
+    let t = hits_type(&v);
+    if t == KmerType::HighMultiMap {
+        v.clear()
+    }
+    (t, v)
+}
+
+/// Contribution of a kmer to the header counts.  Kmers that are already highly redundant are
+/// not changed by an update, and those that become highly redundant always have an on target hit
+fn counts(ktype: KmerType, hits: &[u32]) -> HeaderCounts {
+    match ktype {
+        KmerType::Unmapped => HeaderCounts::default(),
+        KmerType::UniqueOffTarget => HeaderCounts {
+            mapped: 1,
+            ..Default::default()
+        },
+        KmerType::HighMultiMap => HeaderCounts {
+            mapped: 1,
+            on_target: 1,
+            redundant: 1,
+            total_hits: 0,
+        },
+        _ => {
+            let on = hits.iter().any(|x| *x > 0) as u64;
+            HeaderCounts {
+                mapped: 1,
+                on_target: on,
+                redundant: 0,
+                total_hits: hits.len() as u64 - 1 + on,
+            }
+        }
+    }
+}
+
+/// Read kmers from a span of a contig, returning (kmer, target id) for both strands
+fn target_kmers<R: BufRead>(mut rdr: R, id: u32, v: &mut Vec<(KType, u32)>) -> anyhow::Result<()> {
+    let mut kb = KmerBuilder::new();
+    let mut buf = Vec::new();
+    // Skip the header line added by open_span
+    rdr.read_until(b'\n', &mut buf)?;
+    buf.clear();
+    rdr.read_to_end(&mut buf)
+        .with_context(|| "Error reading target sequence")?;
+    for c in buf.iter().filter(|c| !c.is_ascii_whitespace()) {
+        kb.add_base(Base::from_u8(*c), None);
+        if let Some(k) = kb.kmers() {
+            v.push((k[0], id));
+            v.push((k[1], id))
+        }
+    }
+    Ok(())
+}
+
+pub fn update_kmers(cfg: &UpdateKmersConfig) -> anyhow::Result<()> {
+    let output = format!("{}_kmers.km", cfg.prefix());
+    if Path::new(&output)
+        .canonicalize()
+        .is_ok_and(|p| cfg.kmcv().canonicalize().is_ok_and(|q| p == q))
+    {
+        return Err(anyhow!("Output kmer file {output} would overwrite input"));
+    }
+    let fai = find_fai(cfg.input()).ok_or_else(|| {
+        anyhow!(
+            "Uncompressed input with a .fai index is required to update kmer file: {}",
+            cfg.input().display()
+        )
+    })?;
+    let entries = read_fai(&fai)?;
+
+    let mut rdr = open_kmcv(cfg.kmcv())?;
+    if rdr.kmer_length() as usize != KMER_LENGTH {
+        return Err(anyhow!(
+            "Kmer length in kmer file ({}) does not match expected ({KMER_LENGTH})",
+            rdr.kmer_length()
+        ));
+    }
+    let mut contigs: Vec<Box<str>> = rdr.contigs().to_vec();
+    let mut targets: Vec<KmcvTarget> = rdr.targets().to_vec();
+    let n_old = targets.len() as u32;
+
+    let mut reg = read_bed(cfg.targets(), None, false)?;
+    reg.normalize();
+    info!(
+        "Adding {} targets to {}",
+        reg.n_regions(),
+        cfg.kmcv().display()
+    );
+
+    // Collect (kmer, new target id) pairs from the new targets
+    let mut new_kmers = Vec::new();
+    let mut new_targets = Vec::with_capacity(reg.n_regions());
+    for (ctg, cr) in reg.iter() {
+        let e = entries
+            .iter()
+            .find(|e| e.name() == ctg)
+            .ok_or_else(|| anyhow!("Contig {ctg} from target file not found in input"))?;
+        let ctg_id = match contigs.iter().position(|c| c.as_ref() == ctg) {
+            Some(i) => i as u32,
+            None => {
+                contigs.push(ctg.to_owned().into_boxed_str());
+                contigs.len() as u32 - 1
+            }
+        };
+        for r in cr.regions() {
+            if let Some(t) = targets
+                .iter()
+                .find(|t| t.contig_id() == ctg_id && t.start() < r.end() && r.start() < t.end())
+            {
+                return Err(anyhow!(
+                    "New target {ctg}:{}-{} overlaps existing target {ctg}:{}-{}",
+                    r.start() + 1,
+                    r.end(),
+                    t.start() + 1,
+                    t.end()
+                ));
+            }
+            let id = n_old + u32::from(r.idx());
+            // Matches the target assignment used when the kmer file is generated, where the
+            // base at the end coordinate is included
+            let span = open_span(cfg.input(), e, r.start(), r.end() + 1)?;
+            target_kmers(span, id, &mut new_kmers)?;
+            new_targets.push((id, KmcvTarget::new(ctg_id, r.start(), r.end(), r.strand())))
+        }
+    }
+    new_targets.sort_unstable_by_key(|(id, _)| *id);
+    targets.extend(new_targets.into_iter().map(|(_, t)| t));
+    new_kmers.sort_unstable();
+    new_kmers.dedup();
+
+    // Group target ids by kmer
+    let mut new: Vec<(KType, Vec<u32>)> = Vec::new();
+    for (k, id) in new_kmers {
+        match new.last_mut() {
+            Some((k1, v)) if *k1 == k => v.push(id),
+            _ => new.push((k, vec![id])),
+        }
+    }
+    debug!("{} distinct kmers in new targets", new.len());
+
+    // First pass: find the existing hits for the new kmers
+    let mut old: Vec<(KmerType, Vec<u32>)> = vec![(KmerType::Unmapped, Vec::new()); new.len()];
+    let mut i = 0;
+    while let Some((k, t, h)) = rdr.next_kmer()? {
+        while i < new.len() && new[i].0 < k {
+            i += 1
+        }
+        if i == new.len() {
+            break;
+        }
+        if new[i].0 == k {
+            old[i] = (t, h.to_vec())
+        }
+    }
+    let mut hc = HeaderCounts {
+        mapped: rdr.mapped_kmers(),
+        on_target: rdr.on_target_kmers(),
+        redundant: rdr.redundant_kmers(),
+        total_hits: rdr.total_hits(),
+    };
+    let old_digest = rdr
+        .provenance()
+        .and_then(|s| serde_json::from_str::<serde_json::Value>(s).ok())
+        .and_then(|v| v["reference_digest"].as_str().map(|s| s.to_owned()));
+    drop(rdr);
+
+    let mut updates = Vec::with_capacity(new.len());
+    for ((k, ids), (t, h)) in new.iter().zip(old.iter()) {
+        let (t1, h1) = update_hits(*t, h, ids);
+        let (a, b) = (counts(*t, h), counts(t1, &h1));
+        hc.mapped = hc.mapped + b.mapped - a.mapped;
+        hc.on_target = hc.on_target + b.on_target - a.on_target;
+        hc.redundant = hc.redundant + b.redundant - a.redundant;
+        hc.total_hits = hc.total_hits + b.total_hits - a.total_hits;
+        updates.push((*k, t1, h1))
+    }
+
+    // Second pass: copy the existing kmers to the new file with the updates merged in
+    info!("Writing updated kmer file {output}");
+    let mut w = open_kmer_writer(&output, cfg.kmer_writer())?;
+    let rnd_id: u32 = random();
+    KmcvHeader::from_counts(contigs.len() as u32, targets.len() as u32, hc, rnd_id)
+        .write(&mut w)?;
+    write_provenance_block(
+        &mut w,
+        &Provenance::new(None).with_reference_digest(old_digest),
+    )?;
+    write_contigs(&mut w, contigs.iter().map(|c| c.as_ref()))?;
+    write_targets(&mut w, targets.iter().copied())?;
+
+    let mut rdr = open_kmcv(cfg.kmcv())?;
+    let mut prev = 0;
+    let mut upd = updates.iter().peekable();
+    loop {
+        let next = rdr.next_kmer()?;
+        // Kmers from the new targets not in the existing file
+        while let Some((k, t, h)) = upd.next_if(|(k, _, _)| next.as_ref().is_none_or(|x| *k < x.0))
+        {
+            write_hits_block(&mut w, h, k - prev, *t)?;
+            prev = *k
+        }
+        let Some((k, t, h)) = next else { break };
+        match upd.next_if(|(k1, _, _)| *k1 == k) {
+            Some((_, t1, h1)) => write_hits_block(&mut w, h1, k - prev, *t1)?,
+            None => write_hits_block(&mut w, h, k - prev, t)?,
+        }
+        prev = k
+    }
+    write_close(&mut w, rnd_id)?;
+    w.flush()
+        .with_context(|| "Error flushing data to kmer file")?;
+    info!(
+        "Kmer file written with {} targets ({} new)",
+        targets.len(),
+        targets.len() as u32 - n_old
+    );
+    Ok(())
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_update_hits() {
+        let (t, h) = update_hits(KmerType::UniqueOffTarget, &[0], &[5]);
+        assert_eq!((t, h.as_slice()), (KmerType::UniqueOnTarget, &[5][..]));
+        let (t, h) = update_hits(KmerType::LowMultiMap(2), &[0, 3], &[5]);
+        assert_eq!(
+            (t, h.as_slice()),
+            (KmerType::LowMultiMap(3), &[0, 3, 5][..])
+        );
+        let (t, h) = update_hits(KmerType::LowMultiMap(8), &[0, 1, 2, 3, 4, 6, 7, 8], &[5]);
+        assert_eq!((t, h.len()), (KmerType::HighMultiMap, 0));
+        let (t, _) = update_hits(KmerType::Unmapped, &[], &[5, 6]);
+        assert_eq!(t, KmerType::LowMultiMap(2));
+    }
+}
@@ -0,0 +1,176 @@
+//! Cross tabulation of kmer uniqueness against the GC content of the surrounding window
+//!
+//! As for the repeat partitions, two passes over the input (shared with the other kmer uniqueness
+//! options; see [`crate::rescan`]) are needed: the first counts all canonical kmers in the genome
+//! and the second classifies each kmer of the sequence used in the main analysis (so respecting
+//! --region and the contig length filters) as unique, low multiplicity or high multiplicity, and
+//! adds it to the table for each read length at the GC of the window of that length centred on
+//! the kmer.  Windows with fewer non gap bases than the threshold are not counted.
+use std::{io::Write, path::Path};
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+
+use crate::{
+    cli::Config,
+    fasta::{Base, Seq},
+    kmers::{KmerBuilder, KmerCounts, KMER_LENGTH},
+};
+
+/// Kmers with at least this many copies are classed as high multiplicity
+pub const HIGH_MULTI: u8 = 10;
+
+/// Number of GC bins (percentage GC rounded to the nearest integer)
+const GC_BINS: usize = 101;
+
+fn class(count: u8) -> usize {
+    match count {
+        0 | 1 => 0,
+        x if x < HIGH_MULTI => 1,
+        _ => 2,
+    }
+}
+
+/// Counts of (unique, low multi, high multi) kmers per GC bin for each read length
+pub struct KmerGcTable {
+    read_lengths: Vec<u32>,
+    counts: Vec<Vec<[u64; 3]>>,
+}
+
+impl KmerGcTable {
+    pub fn new(read_lengths: &[u32]) -> Self {
+        Self {
+            read_lengths: read_lengths.to_vec(),
+            counts: vec![vec![[0; 3]; GC_BINS]; read_lengths.len()],
+        }
+    }
+
+    /// Counts of (unique, low multi, high multi) kmers for each GC bin for read length index ix
+    pub fn counts(&self, ix: usize) -> &[[u64; 3]] {
+        &self.counts[ix]
+    }
+
+    /// Add the kmers of s, classified using the genome wide counts (which need at least 4 bits
+    /// per kmer)
+    pub fn add_seq(&mut self, cfg: &Config, s: &Seq, counts: &KmerCounts) {
+        let rl = cfg.read_lengths();
+        let thresholds: Vec<u32> = rl
+            .iter()
+            .map(|l| (*l as f64 * cfg.threshold()).ceil() as u32)
+            .collect();
+        let mut win: Vec<_> = rl
+            .iter()
+            .map(|_| SlidingCounts {
+                lo: 0,
+                hi: 0,
+                counts: [0; 4],
+            })
+            .collect();
+        let k = KMER_LENGTH;
+        let len = s.len();
+        let mut builder = KmerBuilder::new();
+        for (i, b) in s.iter().enumerate() {
+            builder.add_base(*b, None);
+            if let Some(km) = builder.kmers() {
+                let cl = class(counts.count(km));
+                let centre = i + 1 - k + k / 2;
+                for (ix, l) in rl.iter().enumerate() {
+                    let l = *l as usize;
+                    let lo = centre.saturating_sub(l >> 1);
+                    let hi = (centre + l - (l >> 1)).min(len);
+                    win[ix].advance(s, lo, hi);
+                    if let Some(bin) = win[ix].gc_bin(thresholds[ix]) {
+                        self.counts[ix][bin][cl] += 1
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, name: P) -> anyhow::Result<()> {
+        let mut wrt = CompressIo::new()
+            .path(name)
+            .bufwriter()
+            .with_context(|| "Could not open output kmer GC file")?;
+        writeln!(
+            wrt,
+            "read_length\tgc\tunique\tlow_multi\thigh_multi\tunique_fraction"
+        )?;
+        for (rl, v) in self.read_lengths.iter().zip(self.counts.iter()) {
+            for (i, c) in v.iter().enumerate() {
+                let n: u64 = c.iter().sum();
+                if n > 0 {
+                    let f = c[0] as f64 / n as f64;
+                    writeln!(
+                        wrt,
+                        "{rl}\t{}\t{}\t{}\t{}\t{f:.6}",
+                        i as f64 / 100.0,
+                        c[0],
+                        c[1],
+                        c[2]
+                    )?
+                }
+            }
+        }
+        wrt.flush()
+            .with_context(|| "Error writing out kmer GC table")
+    }
+}
+
+/// Sliding window of base counts over a sequence
+struct SlidingCounts {
+    lo: usize,
+    hi: usize,
+    counts: [u32; 4],
+}
+
+impl SlidingCounts {
+    /// Move window to cover [lo, hi) of s (neither may decrease)
+    fn advance(&mut self, s: &[Base], lo: usize, hi: usize) {
+        while self.hi < hi {
+            if let Some(b) = s.get(self.hi).filter(|b| !b.is_gap()) {
+                self.counts[*b as usize] += 1
+            }
+            self.hi += 1
+        }
+        while self.lo < lo {
+            if let Some(b) = s.get(self.lo).filter(|b| !b.is_gap()) {
+                self.counts[*b as usize] -= 1
+            }
+            self.lo += 1
+        }
+    }
+
+    /// GC bin if the window has at least t non gap bases
+    fn gc_bin(&self, t: u32) -> Option<usize> {
+        let c = &self.counts;
+        let n = c.iter().sum::<u32>();
+        (n >= t.max(1)).then(|| {
+            let gc = c[Base::C as usize] + c[Base::G as usize];
+            (100.0 * gc as f64 / n as f64).round() as usize
+        })
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_sliding_counts() {
+        let s: Vec<Base> = b"ACGTNGGCC".iter().map(|c| Base::from_u8(*c)).collect();
+        let mut w = SlidingCounts {
+            lo: 0,
+            hi: 0,
+            counts: [0; 4],
+        };
+        w.advance(&s, 0, 4);
+        assert_eq!(w.gc_bin(4), Some(50));
+        w.advance(&s, 3, 9);
+        assert_eq!(w.gc_bin(4), Some(80));
+        assert_eq!(w.gc_bin(6), None);
+        assert_eq!(class(1), 0);
+        assert_eq!(class(HIGH_MULTI - 1), 1);
+        assert_eq!(class(15), 2);
+    }
+}
@@ -0,0 +1,135 @@
+//! Plain text export of the kmer table for debugging
+//!
+//! Each selected kmer is written on one line with its sequence, the number of hits and the
+//! target ids hit (as in ``<prefix>_target_map.txt``), with ``off`` for an off target hit.
+//! Highly redundant kmers (more than [`MAX_HITS`] hits) do not record the targets, so are
+//! written with ``>8`` hits and ``.`` for the targets.
+use std::{io::Write, path::Path};
+
+use anyhow::Context;
+use clap::{builder::PossibleValue, ValueEnum};
+use compress_io::compress::CompressIo;
+
+use crate::{
+    kmcv::KmerType,
+    kmers::{KType, KmerVec, KmerWork, KMER_LENGTH, MAX_HITS},
+};
+
+/// Selection of kmers for the text export
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum KmerTextFilter {
+    /// All kmers found in the reference
+    All,
+    /// Kmers with at least one on target hit
+    OnTarget,
+    /// Kmers mapping uniquely on target
+    Unique,
+    /// Kmers with more than one hit
+    MultiMapping,
+}
+
+impl ValueEnum for KmerTextFilter {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::All, Self::OnTarget, Self::Unique, Self::MultiMapping]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(self.name()))
+    }
+}
+
+impl KmerTextFilter {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::All => "all",
+            Self::OnTarget => "on-target",
+            Self::Unique => "unique",
+            Self::MultiMapping => "multi-mapping",
+        }
+    }
+
+    fn matches(&self, v: &KmerVec) -> bool {
+        let kt = KmerType::from_kmer_vec(v);
+        match self {
+            Self::All => kt != KmerType::Unmapped,
+            Self::OnTarget => v[0] & 0x80000000 == 0 && v.iter().any(|x| *x > 1),
+            Self::Unique => kt == KmerType::UniqueOnTarget,
+            Self::MultiMapping => {
+                matches!(kt, KmerType::LowMultiMap(_) | KmerType::HighMultiMap)
+            }
+        }
+    }
+}
+
+/// Sequence of a kmer (the first base in the high bits)
+fn kmer_seq(kmer: KType) -> String {
+    (0..KMER_LENGTH)
+        .rev()
+        .map(|i| ['A', 'C', 'T', 'G'][((kmer >> (i << 1)) & 3) as usize])
+        .collect()
+}
+
+/// Number of hits and target ids for a kmer.  Hits are stored as target idx + 1, with 1 for off
+/// target
+fn kmer_hits(v: &KmerVec) -> (String, String) {
+    if v[0] & 0x80000000 != 0 {
+        return (format!(">{MAX_HITS}"), ".".to_owned());
+    }
+    let t: Vec<_> = v
+        .iter()
+        .take_while(|x| **x != 0)
+        .map(|x| match x {
+            1 => "off".to_owned(),
+            _ => (x - 1).to_string(),
+        })
+        .collect();
+    (t.len().to_string(), t.join(","))
+}
+
+/// Write the kmers selected by filter as text, returning the number of kmers written
+pub fn output_kmer_text<P: AsRef<Path>>(
+    path: P,
+    k_work: &KmerWork,
+    filter: KmerTextFilter,
+) -> anyhow::Result<u64> {
+    info!("Writing {} kmers as text", filter.name());
+    let mut wrt = CompressIo::new()
+        .path(path)
+        .bufwriter()
+        .with_context(|| "Could not open kmer text file for output")?;
+    writeln!(wrt, "kmer\thits\ttargets")?;
+    let mut n = 0;
+    for (k, v) in k_work.kmers().filter(|(_, v)| filter.matches(v)) {
+        let (hits, targets) = kmer_hits(v);
+        writeln!(wrt, "{}\t{hits}\t{targets}", kmer_seq(k))?;
+        n += 1
+    }
+    wrt.flush()
+        .with_context(|| "Error flushing data to kmer text file")?;
+    info!("{n} kmers written");
+    Ok(n)
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_kmer_text() {
+        assert_eq!(kmer_seq(0), "A".repeat(KMER_LENGTH));
+        assert_eq!(
+            kmer_seq(0b01_10_11),
+            format!("{}CTG", "A".repeat(KMER_LENGTH - 3))
+        );
+        let v = [3, 1, 0, 0, 0, 0, 0, 0];
+        assert_eq!(kmer_hits(&v), ("2".to_owned(), "2,off".to_owned()));
+        let hr = [0x80000000, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(kmer_hits(&hr), (">8".to_owned(), ".".to_owned()));
+        let f = KmerTextFilter::MultiMapping;
+        assert!(f.matches(&v) && f.matches(&hr));
+        assert!(!f.matches(&[2, 0, 0, 0, 0, 0, 0, 0]));
+        assert!(KmerTextFilter::OnTarget.matches(&v));
+        assert!(!KmerTextFilter::OnTarget.matches(&[1, 0, 0, 0, 0, 0, 0, 0]));
+        assert!(!KmerTextFilter::All.matches(&[0; MAX_HITS]));
+    }
+}
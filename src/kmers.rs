@@ -1,14 +1,71 @@
 use std::{collections::VecDeque, fmt, num::NonZeroU32};
 
-use crate::reader::Base;
+use crate::fasta::Base;
 
 pub type KType = u32;
 pub const KMER_LENGTH: usize = 15;
 pub const MAX_HITS: usize = 8;
 pub type KmerVec = [u32; MAX_HITS];
 
+/// Bisulfite conversion for converted (three letter) kmer tables
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Conversion {
+    CtoT,
+    GtoA,
+}
+
+impl Conversion {
+    pub fn convert(&self, b: Base) -> Base {
+        match (self, b) {
+            (Self::CtoT, Base::C) => Base::T,
+            (Self::GtoA, Base::G) => Base::A,
+            _ => b,
+        }
+    }
+
+    /// Converted kmers are indexed using a base 3 representation.  The mapping between
+    /// the remaining 3 bases and the base 3 digits preserves order, so the index order is the
+    /// same as the kmer order
+    fn kmer_to_index(&self, kmer: KType) -> usize {
+        let mut ix = 0;
+        for i in (0..KMER_LENGTH).rev() {
+            let x = ((kmer >> (i << 1)) & 3) as usize;
+            let d = match self {
+                Self::CtoT => x - (x > 0) as usize,
+                Self::GtoA => x,
+            };
+            debug_assert!(d < 3, "Unconverted base in converted kmer");
+            ix = ix * 3 + d
+        }
+        ix
+    }
+
+    fn index_to_kmer(&self, ix: usize) -> KType {
+        let mut kmer = 0;
+        let mut ix = ix;
+        for i in 0..KMER_LENGTH {
+            let d = (ix % 3) as KType;
+            ix /= 3;
+            let x = match self {
+                Self::CtoT => d + (d > 0) as KType,
+                Self::GtoA => d,
+            };
+            kmer |= x << (i << 1)
+        }
+        kmer
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::CtoT => "ct",
+            Self::GtoA => "ga",
+        }
+    }
+}
+
 pub struct KmerWork {
     kmers: Vec<KmerVec>,
+    conversion: Option<Conversion>,
     max_region: usize,
     on_target_kmers: u64,
     mapped_kmers: u64,
@@ -30,11 +87,27 @@ impl fmt::Display for KmerWork {
     }
 }
 
+impl Default for KmerWork {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl KmerWork {
     #[inline]
     pub fn new() -> Self {
+        Self::make(None, 1 << (KMER_LENGTH << 1))
+    }
+
+    /// Table for bisulfite converted kmers
+    pub fn new_converted(conversion: Conversion) -> Self {
+        Self::make(Some(conversion), 3usize.pow(KMER_LENGTH as u32))
+    }
+
+    fn make(conversion: Option<Conversion>, n: usize) -> Self {
         Self {
-            kmers: vec![[0; MAX_HITS]; 1 << (KMER_LENGTH << 1)],
+            kmers: vec![[0; MAX_HITS]; n],
+            conversion,
             // Get maximum region (note regions start from 1)
             max_region: (1 << 31) - 2,
             // Counters
@@ -44,11 +117,19 @@ impl KmerWork {
             total_hits: 0,
         }
     }
+    #[inline]
+    fn index(&self, kmer: KType) -> usize {
+        match self.conversion {
+            Some(c) => c.kmer_to_index(kmer),
+            None => kmer as usize,
+        }
+    }
+
     pub fn add_kmer(&mut self, kmer: KType, region: Option<NonZeroU32>) {
         let r: u32 = region.map(|x| x.into()).unwrap_or(0);
         assert!(r as usize <= self.max_region, "Region id too large!");
 
-        let km = kmer as usize;
+        let km = self.index(kmer);
         let v = &mut self.kmers[km];
         if v[0] == 0 {
             self.mapped_kmers += 1;
@@ -79,8 +160,26 @@ impl KmerWork {
         }
     }
 
-    pub fn kmers(&self) -> &[KmerVec] {
-        &self.kmers
+    /// True if kmer maps once, on target
+    pub fn is_unique(&self, kmer: KType) -> bool {
+        let v = &self.kmers[self.index(kmer)];
+        v[0] > 1 && v[1] == 0
+    }
+
+    /// Iterator over (kmer, hits) in kmer order
+    pub fn kmers(&self) -> impl Iterator<Item = (KType, &KmerVec)> {
+        let conv = self.conversion;
+        self.kmers.iter().enumerate().map(move |(i, v)| {
+            let k = match conv {
+                Some(c) => c.index_to_kmer(i),
+                None => i as KType,
+            };
+            (k, v)
+        })
+    }
+
+    pub fn conversion(&self) -> Option<Conversion> {
+        self.conversion
     }
     pub fn mapped_kmers(&self) -> u64 {
         self.mapped_kmers
@@ -96,6 +195,64 @@ impl KmerWork {
     }
 }
 
+/// Saturating counts (by default 2 bit, so 0, 1, 2 or 3+) for each canonical kmer, used to find
+/// kmers that are unique in the genome.  With 2 bits this takes 1/128 of the memory of
+/// [`KmerWork`]
+pub struct KmerCounts {
+    v: Vec<u8>,
+    bits: u32,
+}
+
+impl Default for KmerCounts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KmerCounts {
+    pub fn new() -> Self {
+        Self::with_bits(2)
+    }
+
+    /// Counts using bits (1, 2, 4 or 8) bits per kmer, saturating at 2^bits - 1
+    pub fn with_bits(bits: u32) -> Self {
+        assert!(matches!(bits, 1 | 2 | 4 | 8), "Illegal bits per kmer count");
+        Self {
+            v: vec![0; ((1 << (KMER_LENGTH << 1)) * bits as usize) >> 3],
+            bits,
+        }
+    }
+
+    /// Index and shift for forward and reverse kmers (as from [`KmerBuilder::kmers`])
+    #[inline]
+    fn locate(&self, k: [KType; 2]) -> (usize, u32) {
+        let i = k[0].min(k[1]) as usize;
+        let per = 8 / self.bits as usize;
+        (i / per, (i % per) as u32 * self.bits)
+    }
+
+    #[inline]
+    fn max(&self) -> u8 {
+        ((1u32 << self.bits) - 1) as u8
+    }
+
+    pub fn add(&mut self, k: [KType; 2]) {
+        let (i, sh) = self.locate(k);
+        if (self.v[i] >> sh) & self.max() < self.max() {
+            self.v[i] += 1 << sh
+        }
+    }
+
+    pub fn count(&self, k: [KType; 2]) -> u8 {
+        let (i, sh) = self.locate(k);
+        (self.v[i] >> sh) & self.max()
+    }
+
+    pub fn is_unique(&self, k: [KType; 2]) -> bool {
+        self.count(k) == 1
+    }
+}
+
 /// Returns (x, valid)
 /// Where x is 0, 1, 2, 3 for A, C, T, G and 0 otherwise (with valid being false)
 fn decode_base(b: Base) -> (u8, u8) {
@@ -112,6 +269,12 @@ pub struct KmerBuilder {
     valid_mask: KType,
 }
 
+impl Default for KmerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl KmerBuilder {
     const REV_SHIFT: usize = (KMER_LENGTH - 1) << 1;
     pub fn new() -> Self {
@@ -178,3 +341,21 @@ impl KmerBuilder {
         }
     }
 }
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_converted_index() {
+        for c in [Conversion::CtoT, Conversion::GtoA] {
+            let mut prev = None;
+            for ix in [0, 1, 2, 3, 1000, 3usize.pow(KMER_LENGTH as u32) - 1] {
+                let k = c.index_to_kmer(ix);
+                assert_eq!(c.kmer_to_index(k), ix);
+                assert!(prev.map(|p| p < k).unwrap_or(true));
+                prev = Some(k)
+            }
+        }
+    }
+}
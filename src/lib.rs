@@ -0,0 +1,70 @@
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate anyhow;
+
+pub mod bands;
+pub mod bench;
+pub mod betabin;
+pub mod bias;
+pub mod bootstrap;
+pub mod checksum;
+pub mod cli;
+pub mod cohort;
+pub mod composition;
+pub mod contig_filter;
+pub mod contig_groups;
+pub mod conversion;
+pub mod coverage;
+pub mod cross_validation;
+pub mod dist_table;
+pub mod dropout_risk;
+pub mod dup_rate;
+pub mod duplicates;
+pub mod error;
+pub mod fasta;
+pub mod gatk;
+pub mod gc_pyramid;
+pub mod gc_track;
+pub mod generate;
+pub mod gtf;
+pub mod input_check;
+pub mod isochores;
+pub mod kmcv;
+pub mod kmer_gc;
+pub mod kmer_text;
+pub mod kmers;
+pub mod lint;
+pub mod loess;
+pub mod mappability;
+pub mod matched_controls;
+pub mod memory;
+pub mod observe;
+pub mod output;
+pub mod performance;
+pub mod plot_script;
+pub mod process;
+pub mod provenance;
+pub mod reader;
+pub mod reads;
+pub mod reference_id;
+pub mod regions;
+pub mod registry;
+pub mod repeat_scan;
+pub mod rescan;
+pub mod rmsk;
+pub mod saturation;
+pub mod shared_kmers;
+pub mod shuffle;
+pub mod simulate;
+pub mod summary;
+pub mod synthetic;
+pub mod tap;
+pub mod target_gc_length;
+pub mod target_stats;
+pub mod unique_bed;
+pub mod utils;
+pub mod warnings;
+pub mod window_sample;
+pub mod windows;
+pub mod zarr;
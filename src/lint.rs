@@ -0,0 +1,454 @@
+//! Sanity check of a FASTA file without any analysis
+//!
+//! The input is read line by line, and problems are reported with their line number and (for
+//! sequence characters) the 1 offset position in the contig.  Issues that would cause the analysis
+//! to fail (illegal characters, sequence before the first header and duplicate names) are errors;
+//! the others are warnings.  Per contig base composition, line lengths and gap (non ACGT) run
+//! statistics are also collected, and everything is written to ``<prefix>_lint.json``.
+use std::{collections::BTreeMap, io::BufRead, path::Path};
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+use rustc_hash::FxHashSet;
+use serde::Serialize;
+
+use crate::{
+    cli::LintConfig,
+    fasta::{tar::open_fasta, Base},
+};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum IssueKind {
+    NoHeader,
+    EmptyName,
+    DuplicateName,
+    EmptyRecord,
+    IllegalCharacter,
+    UnknownBase,
+    BlankLine,
+    InconsistentLineLength,
+    NoFinalNewline,
+}
+
+impl IssueKind {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::NoHeader => "no_header",
+            Self::EmptyName => "empty_name",
+            Self::DuplicateName => "duplicate_name",
+            Self::EmptyRecord => "empty_record",
+            Self::IllegalCharacter => "illegal_character",
+            Self::UnknownBase => "unknown_base",
+            Self::BlankLine => "blank_line",
+            Self::InconsistentLineLength => "inconsistent_line_length",
+            Self::NoFinalNewline => "no_final_newline",
+        }
+    }
+
+    /// Issues that would stop the analysis
+    fn is_error(&self) -> bool {
+        matches!(
+            self,
+            Self::NoHeader | Self::DuplicateName | Self::IllegalCharacter
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Issue {
+    kind: &'static str,
+    error: bool,
+    line: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    contig: Option<String>,
+    /// 1 offset position in contig
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pos: Option<u64>,
+    message: String,
+}
+
+/// Base composition, line length and gap statistics for a contig (or the whole input)
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct LintStats {
+    length: u64,
+    acgt: u64,
+    soft_masked: u64,
+    n: u64,
+    unknown: u64,
+    gaps: u64,
+    gap_bases: u64,
+    longest_gap: u64,
+}
+
+impl LintStats {
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// Number of runs of non ACGT characters
+    pub fn gaps(&self) -> u64 {
+        self.gaps
+    }
+
+    pub fn gap_bases(&self) -> u64 {
+        self.gap_bases
+    }
+
+    fn add(&mut self, other: &Self) {
+        self.length += other.length;
+        self.acgt += other.acgt;
+        self.soft_masked += other.soft_masked;
+        self.n += other.n;
+        self.unknown += other.unknown;
+        self.gaps += other.gaps;
+        self.gap_bases += other.gap_bases;
+        self.longest_gap = self.longest_gap.max(other.longest_gap)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ContigLint {
+    name: String,
+    /// Length of sequence lines (None if not consistent)
+    line_length: Option<u64>,
+    #[serde(flatten)]
+    stats: LintStats,
+    #[serde(skip)]
+    consistent: bool,
+    #[serde(skip)]
+    short_line_seen: bool,
+    #[serde(skip)]
+    gap_run: u64,
+}
+
+impl ContigLint {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            line_length: None,
+            stats: LintStats::default(),
+            consistent: true,
+            short_line_seen: false,
+            gap_run: 0,
+        }
+    }
+
+    fn end_gap(&mut self) {
+        if self.gap_run > 0 {
+            let s = &mut self.stats;
+            s.gaps += 1;
+            s.gap_bases += self.gap_run;
+            s.longest_gap = s.longest_gap.max(self.gap_run);
+            self.gap_run = 0
+        }
+    }
+
+    /// All lines apart from the last must have the same length, and the last line must not be
+    /// longer.  Returns true for the first line inconsistent with the previous lines
+    fn check_line_length(&mut self, len: u64) -> bool {
+        let bad = match self.line_length {
+            None => {
+                self.line_length = Some(len);
+                false
+            }
+            Some(l) => self.short_line_seen || len > l,
+        };
+        if self.line_length.is_some_and(|l| len < l) {
+            self.short_line_seen = true
+        }
+        let first = bad && self.consistent;
+        self.consistent &= !bad;
+        first
+    }
+}
+
+/// Results of checking the input
+#[derive(Serialize)]
+pub struct LintRes {
+    summary: LintStats,
+    records: usize,
+    errors: u64,
+    warnings: u64,
+    issue_counts: BTreeMap<&'static str, u64>,
+    issues: Vec<Issue>,
+    contigs: Vec<ContigLint>,
+    #[serde(skip)]
+    max_issues: usize,
+}
+
+impl LintRes {
+    fn new(max_issues: usize) -> Self {
+        Self {
+            summary: LintStats::default(),
+            records: 0,
+            errors: 0,
+            warnings: 0,
+            issue_counts: BTreeMap::new(),
+            issues: Vec::new(),
+            contigs: Vec::new(),
+            max_issues,
+        }
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    pub fn warnings(&self) -> u64 {
+        self.warnings
+    }
+
+    pub fn contigs(&self) -> &[ContigLint] {
+        &self.contigs
+    }
+
+    /// Record issue, storing the details if the limit for this kind has not been reached
+    fn add_issue(&mut self, kind: IssueKind, line: u64, pos: Option<u64>, message: String) {
+        if kind.is_error() {
+            self.errors += 1
+        } else {
+            self.warnings += 1
+        }
+        let n = self.issue_counts.entry(kind.name()).or_insert(0);
+        *n += 1;
+        if *n as usize <= self.max_issues {
+            let contig = self.contigs.last().map(|c| c.name.clone());
+            self.issues.push(Issue {
+                kind: kind.name(),
+                error: kind.is_error(),
+                line,
+                contig,
+                pos,
+                message,
+            })
+        }
+    }
+
+    fn end_record(&mut self, line: u64) {
+        if let Some(c) = self.contigs.last_mut() {
+            c.end_gap();
+            if !c.consistent {
+                c.line_length = None
+            }
+            if c.stats.length == 0 {
+                let m = format!("Record {} has no sequence", c.name);
+                self.add_issue(IssueKind::EmptyRecord, line, None, m)
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        for c in self.contigs.iter() {
+            self.summary.add(&c.stats)
+        }
+        self.records = self.contigs.len()
+    }
+}
+
+fn describe(c: u8) -> String {
+    match c {
+        b'\r' => "carriage return (DOS line ending)".to_owned(),
+        b'\t' => "tab".to_owned(),
+        b' ' => "space".to_owned(),
+        c if c.is_ascii_graphic() => format!("'{}'", c as char),
+        c => format!("byte 0x{c:02x}"),
+    }
+}
+
+/// Check the FASTA input
+pub fn lint_input<R: BufRead>(mut rdr: R, max_issues: usize) -> anyhow::Result<LintRes> {
+    let mut res = LintRes::new(max_issues);
+    let mut names: FxHashSet<String> = FxHashSet::default();
+    let mut buf = Vec::new();
+    let mut line = 0;
+    let mut no_header_seen = false;
+    loop {
+        buf.clear();
+        if rdr
+            .read_until(b'\n', &mut buf)
+            .with_context(|| "Error reading input")?
+            == 0
+        {
+            break;
+        }
+        line += 1;
+        let l = match buf.strip_suffix(b"\n") {
+            Some(l) => l,
+            None => {
+                let m = "Input does not end with a newline".to_owned();
+                res.add_issue(IssueKind::NoFinalNewline, line, None, m);
+                &buf
+            }
+        };
+        if let Some(hdr) = l.strip_prefix(b">") {
+            res.end_record(line - 1);
+            let end = hdr
+                .iter()
+                .position(|c| c.is_ascii_whitespace())
+                .unwrap_or(hdr.len());
+            let name = String::from_utf8_lossy(&hdr[..end]).into_owned();
+            res.contigs.push(ContigLint::new(name.clone()));
+            if let Some(c) = hdr
+                .iter()
+                .find(|c| !c.is_ascii() || (c.is_ascii_control() && **c != b'\t'))
+            {
+                let m = format!("Illegal character {} in header", describe(*c));
+                res.add_issue(IssueKind::IllegalCharacter, line, None, m)
+            }
+            if name.is_empty() {
+                res.add_issue(IssueKind::EmptyName, line, None, "Empty name".to_owned())
+            } else if !names.insert(name.clone()) {
+                let m = format!("Duplicate name {name}");
+                res.add_issue(IssueKind::DuplicateName, line, None, m)
+            }
+            continue;
+        }
+        let Some(ctg) = res.contigs.last_mut() else {
+            if !no_header_seen {
+                no_header_seen = true;
+                let m = "Sequence before first header line".to_owned();
+                res.add_issue(IssueKind::NoHeader, line, None, m)
+            }
+            continue;
+        };
+        if l.is_empty() {
+            ctg.short_line_seen = ctg.line_length.is_some();
+            res.add_issue(IssueKind::BlankLine, line, None, "Blank line".to_owned());
+            continue;
+        }
+        if ctg.check_line_length(l.len() as u64) {
+            let m = format!(
+                "Line length {} inconsistent with previous lines of {}",
+                l.len(),
+                ctg.name
+            );
+            let pos = Some(ctg.stats.length + 1);
+            res.add_issue(IssueKind::InconsistentLineLength, line, pos, m);
+        }
+        let ctg = res.contigs.last_mut().unwrap();
+        // Issues are collected and reported after the line to avoid borrowing res twice
+        let mut found = Vec::new();
+        for c in l.iter().copied() {
+            if !c.is_ascii_graphic() {
+                found.push((IssueKind::IllegalCharacter, ctg.stats.length + 1, c));
+                continue;
+            }
+            let s = &mut ctg.stats;
+            s.length += 1;
+            match Base::from_u8(c) {
+                Base::Other => {
+                    s.unknown += 1;
+                    ctg.gap_run += 1;
+                    found.push((IssueKind::UnknownBase, s.length, c))
+                }
+                Base::N => {
+                    s.n += 1;
+                    ctg.gap_run += 1
+                }
+                _ => {
+                    s.acgt += 1;
+                    if c.is_ascii_lowercase() {
+                        s.soft_masked += 1
+                    }
+                    ctg.end_gap()
+                }
+            }
+        }
+        for (kind, pos, c) in found {
+            let m = match kind {
+                IssueKind::UnknownBase => format!("Unknown base {} (treated as gap)", describe(c)),
+                _ => format!("Illegal character {}", describe(c)),
+            };
+            res.add_issue(kind, line, Some(pos), m)
+        }
+    }
+    res.end_record(line);
+    res.finish();
+    Ok(res)
+}
+
+fn output_lint<P: AsRef<Path>>(name: P, cfg: &LintConfig, res: &LintRes) -> anyhow::Result<()> {
+    #[derive(Serialize)]
+    struct LintOutput<'a> {
+        program: &'static str,
+        version: &'static str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        input: Option<&'a Path>,
+        #[serde(flatten)]
+        res: &'a LintRes,
+    }
+
+    let wrt = CompressIo::new()
+        .path(name)
+        .bufwriter()
+        .with_context(|| "Could not open output lint file")?;
+    let out = LintOutput {
+        program: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+        input: cfg.input(),
+        res,
+    };
+    serde_json::to_writer_pretty(wrt, &out).with_context(|| "Error writing out lint results")
+}
+
+pub fn lint(cfg: &LintConfig) -> anyhow::Result<()> {
+    let rdr = open_fasta(cfg.input())?;
+
+    info!("Starting to read input");
+    let res = lint_input(rdr, cfg.max_issues())?;
+    info!("Finished reading input");
+
+    let s = &res.summary;
+    info!(
+        "{} records, {} bases, {} gaps covering {} bases (longest {})",
+        res.records, s.length, s.gaps, s.gap_bases, s.longest_gap
+    );
+    for (k, n) in res.issue_counts.iter() {
+        info!("{k}: {n}")
+    }
+    let name = format!("{}_lint.json", cfg.prefix());
+    output_lint(&name, cfg, &res)?;
+    if res.errors > 0 {
+        Err(anyhow!(
+            "{} errors and {} warnings found in input (see {name})",
+            res.errors,
+            res.warnings
+        ))
+    } else {
+        info!("No errors and {} warnings found in input", res.warnings);
+        Ok(())
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_lint() {
+        let input = b"ACGT\n>chr1\nACGT\nAC\nACGT\n>chr2 x\n\n>chr1\nNNRa\r\nAC";
+        let res = lint_input(&input[..], 100).unwrap();
+        let n = |k: &str| res.issue_counts.get(k).copied().unwrap_or(0);
+        assert_eq!(n("no_header"), 1);
+        assert_eq!(n("inconsistent_line_length"), 1);
+        assert_eq!(n("blank_line"), 1);
+        assert_eq!(n("empty_record"), 1);
+        assert_eq!(n("duplicate_name"), 1);
+        assert_eq!(n("unknown_base"), 1);
+        assert_eq!(n("illegal_character"), 1);
+        assert_eq!(n("no_final_newline"), 1);
+        assert_eq!(res.errors(), 3);
+        let c = &res.contigs()[2];
+        assert_eq!(c.stats.length(), 6);
+        assert_eq!((c.stats.gaps(), c.stats.gap_bases()), (1, 3));
+        assert_eq!(c.line_length, Some(5));
+        let c = &res.contigs()[0];
+        assert_eq!((c.stats.length(), c.line_length), (10, None));
+        let r = res
+            .issues
+            .iter()
+            .find(|i| i.kind == "unknown_base")
+            .unwrap();
+        assert_eq!((r.line, r.pos), (9, Some(3)));
+    }
+}
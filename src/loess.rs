@@ -0,0 +1,51 @@
+//! Weighted local linear (LOESS) smoothing with tricube kernel
+
+/// Smooth y at each point of x using weighted local linear regression over the nearest
+/// span * n points.  var gives the sampling variance of each y value, which is propagated
+/// through the smoother.  Returns the fitted values and their variances
+pub fn loess(x: &[f64], y: &[f64], w: &[f64], var: &[f64], span: f64) -> (Vec<f64>, Vec<f64>) {
+    let n = x.len();
+    assert!(y.len() == n && w.len() == n && var.len() == n);
+    let k = ((span * n as f64).ceil() as usize).clamp(2.min(n), n);
+    let mut fit = Vec::with_capacity(n);
+    let mut fit_var = Vec::with_capacity(n);
+    let mut kw = vec![0.0; n];
+    let mut dist: Vec<f64> = Vec::with_capacity(n);
+    for x0 in x.iter() {
+        // Bandwidth is the distance to the kth nearest point
+        dist.clear();
+        dist.extend(x.iter().map(|xj| (xj - x0).abs()));
+        let mut d = dist.clone();
+        let h = *d
+            .select_nth_unstable_by(k - 1, |a, b| a.partial_cmp(b).unwrap())
+            .1
+            * 1.000001;
+        let (mut s0, mut s1, mut s2) = (0.0, 0.0, 0.0);
+        for j in 0..n {
+            let u = if h > 0.0 { dist[j] / h } else { 0.0 };
+            kw[j] = if u < 1.0 {
+                w[j] * (1.0 - u * u * u).powi(3)
+            } else {
+                0.0
+            };
+            let dx = x[j] - x0;
+            s0 += kw[j];
+            s1 += kw[j] * dx;
+            s2 += kw[j] * dx * dx;
+        }
+        let det = s0 * s2 - s1 * s1;
+        let l: Vec<f64> = if s0 <= 0.0 {
+            vec![0.0; n]
+        } else if det.abs() <= f64::EPSILON * s0 * s2 {
+            // Degenerate case - fall back to local mean
+            kw.iter().map(|k| k / s0).collect()
+        } else {
+            (0..n)
+                .map(|j| kw[j] * (s2 - (x[j] - x0) * s1) / det)
+                .collect()
+        };
+        fit.push(l.iter().zip(y.iter()).map(|(a, b)| a * b).sum());
+        fit_var.push(l.iter().zip(var.iter()).map(|(a, v)| a * a * v).sum());
+    }
+    (fit, fit_var)
+}
@@ -1,20 +1,49 @@
-#[macro_use]
-extern crate log;
-#[macro_use]
-extern crate anyhow;
+use anyhow::Context;
 
-mod betabin;
-mod cli;
-mod kmcv;
-mod kmers;
-mod output;
-mod process;
-mod reader;
-mod regions;
-mod utils;
+use analyze_ref_gc::{
+    bands, bench, bias,
+    cli::{self, Task},
+    cohort, coverage, dup_rate,
+    error::{exit_code, OutputError},
+    gc_pyramid, gc_track, generate, isochores, kmcv, lint, memory, observe, output, process,
+    saturation, simulate, summary, unique_bed,
+};
 
-fn main() -> anyhow::Result<()> {
-    let cfg = cli::handle_cli()?;
-    let res = process::process(&cfg)?;
-    output::output(&cfg, &res)
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {e:?}");
+        std::process::exit(exit_code(&e))
+    }
+}
+
+fn run() -> anyhow::Result<()> {
+    match cli::handle_cli()? {
+        Task::Analyze(cfg) => {
+            memory::check_memory(&cfg)?;
+            let res = process::process(&cfg)?;
+            output::output(&cfg, &res).with_context(|| {
+                OutputError::new(format!("output files with prefix {}", cfg.prefix()))
+            })?;
+            if cfg.summary() {
+                summary::print_summary(&cfg, &res)
+            }
+            Ok(())
+        }
+        Task::Observe(cfg) => observe::observe(&cfg),
+        Task::Bias(cfg) => bias::bias(&cfg),
+        Task::Simulate(cfg) => simulate::simulate(&cfg),
+        Task::Coverage(cfg) => coverage::estimate_coverage(&cfg),
+        Task::Bench(mut cfg) => bench::bench(&mut cfg),
+        Task::Generate(cfg) => generate::generate(&cfg),
+        Task::Isochores(cfg) => isochores::isochores(&cfg),
+        Task::Bands(cfg) => bands::bands(&cfg),
+        Task::GcTrack(cfg) => gc_track::gc_track(&cfg),
+        Task::Lint(cfg) => lint::lint(&cfg),
+        Task::DupRate(cfg) => dup_rate::dup_rate(&cfg),
+        Task::UniqueBed(cfg) => unique_bed::unique_bed(&cfg),
+        Task::UpdateKmers(cfg) => kmcv::update::update_kmers(&cfg),
+        Task::Cohort(cfg) => cohort::cohort_summary(&cfg),
+        Task::Saturation(cfg) => saturation::saturation(&cfg),
+        Task::GcPyramid(cfg) => gc_pyramid::gc_pyramid(&cfg),
+    }
 }
@@ -0,0 +1,118 @@
+//! Mappability track for the targets, computed from the kmer table of the same run
+//!
+//! For each position in a target the track gives the number of kmers overlapping the position
+//! (from 0 to the kmer length) that map uniquely, i.e., occur on one strand of one target and
+//! nowhere else in the reference.  This is equivalent to a GEM style mappability track
+//! restricted to the targets.  Kmers that extend outside of a target are counted as off target
+//! when the kmer table is built, so values fall towards the target edges.  The input is read
+//! again (in a pass shared with the matched control regions) to find the kmers at each position.
+use std::io::{BufWriter, Write};
+
+use anyhow::Context;
+use compress_io::compress::{CompressIo, Writer};
+
+use crate::{
+    fasta::Seq,
+    kmers::{KmerBuilder, KmerWork, KMER_LENGTH},
+    regions::Regions,
+};
+
+/// Number of uniquely mapping kmers overlapping each position of s
+fn unique_coverage(s: &Seq, k_work: &KmerWork) -> Vec<u32> {
+    let k = KMER_LENGTH;
+    let mut diff = vec![0i32; s.len() + 1];
+    let mut builder = KmerBuilder::new();
+    for (i, b) in s.iter().enumerate() {
+        builder.add_base(*b, None);
+        if builder.kmers().is_some_and(|km| k_work.is_unique(km[0])) {
+            diff[i + 1 - k] += 1;
+            diff[i + 1] -= 1
+        }
+    }
+    let mut x = 0;
+    diff[..s.len()]
+        .iter()
+        .map(|d| {
+            x += d;
+            x as u32
+        })
+        .collect()
+}
+
+/// Write runs of equal coverage for the part of [start, end) of the contig covered by s
+fn write_runs<W: Write>(
+    w: &mut W,
+    s: &Seq,
+    cov: &[u32],
+    start: u64,
+    end: u64,
+) -> std::io::Result<()> {
+    let a = start.max(s.start()).saturating_sub(s.start()) as usize;
+    let b = end.saturating_sub(s.start()).min(cov.len() as u64) as usize;
+    let mut i = a;
+    while i < b {
+        let j = cov[i..b]
+            .iter()
+            .position(|x| *x != cov[i])
+            .map(|j| i + j)
+            .unwrap_or(b);
+        let off = s.start();
+        writeln!(
+            w,
+            "{}\t{}\t{}\t{}",
+            s.contig(),
+            off + i as u64,
+            off + j as u64,
+            cov[i]
+        )?;
+        i = j
+    }
+    Ok(())
+}
+
+/// Mappability track written as bedGraph from the sequences of the input
+pub struct MappabilityTrack<'a> {
+    reg: &'a Regions,
+    k_work: &'a KmerWork,
+    wrt: BufWriter<Writer>,
+}
+
+impl<'a> MappabilityTrack<'a> {
+    pub fn new(reg: &'a Regions, k_work: &'a KmerWork, name: &str) -> anyhow::Result<Self> {
+        let mut wrt = CompressIo::new()
+            .path(name)
+            .bufwriter()
+            .with_context(|| format!("Could not open output mappability file {name}"))?;
+        writeln!(
+            wrt,
+            "track type=bedGraph name=mappability description=\"Unique {KMER_LENGTH}-mers overlapping position\""
+        )?;
+        Ok(Self { reg, k_work, wrt })
+    }
+
+    /// Write the track for the targets overlapping sequence s
+    pub fn add_seq(&mut self, s: &Seq) -> anyhow::Result<()> {
+        let Some(cr) = self.reg.get(s.contig()) else {
+            return Ok(());
+        };
+        let end = s.end();
+        let mut regions = cr
+            .regions()
+            .iter()
+            .filter(|r| r.start() < end && r.end() > s.start())
+            .peekable();
+        if regions.peek().is_some() {
+            let cov = unique_coverage(s, self.k_work);
+            for r in regions {
+                write_runs(&mut self.wrt, s, &cov, r.start(), r.end())?
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> anyhow::Result<()> {
+        self.wrt
+            .flush()
+            .with_context(|| "Error writing out mappability track")
+    }
+}
@@ -0,0 +1,353 @@
+//! Off target control regions matched in length and GC to the targets
+//!
+//! The requested number of controls are assigned to targets in a pseudo random order (cycling
+//! through the targets if more controls than targets are requested).  Target lengths are
+//! grouped into classes 5% wide and GC into 2% bins.  The input is read again (in a pass shared
+//! with the mappability track) in blocks of [`BLOCK`] bases as for the GC track, and candidate
+//! windows of the length of each class starting every [`STEP`] blocks that do not overlap a
+//! (padded) target and pass the coverage threshold are added to the cell for their length class
+//! and GC bin.  Each cell keeps the candidates with the smallest hash keys, so the selection does
+//! not depend on the input order.  Controls therefore match the target lengths to within a block
+//! and 5%.
+use std::{
+    collections::BinaryHeap,
+    hash::{Hash, Hasher},
+    io::Write,
+    num::NonZeroU32,
+};
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+use rustc_hash::{FxHashMap, FxHasher};
+
+use crate::{
+    cli::Config,
+    fasta::{Base, Seq},
+    regions::Regions,
+    target_stats::TargetStats,
+    warnings::add_warning,
+    windows::{Window, WindowScan},
+};
+
+/// Block size in bases
+const BLOCK: u64 = 20;
+
+/// Candidate windows start every STEP blocks
+const STEP: usize = 5;
+
+/// Number of GC bins
+const GC_BINS: usize = 50;
+
+/// Ratio between successive length classes
+const LEN_RATIO: f64 = 1.05;
+
+fn gc_bin(gc: f64) -> usize {
+    ((gc * GC_BINS as f64) as usize).min(GC_BINS - 1)
+}
+
+/// Number of blocks for the length class of a target of length len
+fn len_blocks(len: u64) -> usize {
+    let c = ((len.max(1) as f64).ln() / LEN_RATIO.ln()).round();
+    ((LEN_RATIO.powf(c) / BLOCK as f64).round() as usize).max(1)
+}
+
+fn hash_key<T: Hash>(x: T) -> u64 {
+    let mut h = FxHasher::default();
+    x.hash(&mut h);
+    // Finalizer from splitmix64, as the low bits of FxHasher are poorly mixed
+    let mut k = h.finish();
+    k = (k ^ (k >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    k = (k ^ (k >> 27)).wrapping_mul(0x94d049bb133111eb);
+    k ^ (k >> 31)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Candidate {
+    key: u64,
+    contig: u32,
+    start: u64,
+    end: u64,
+}
+
+/// Candidates for one length class and GC bin, keeping the k with the smallest keys
+struct Cell {
+    k: usize,
+    heap: BinaryHeap<Candidate>,
+}
+
+impl Cell {
+    fn push(&mut self, c: Candidate) {
+        if self.heap.len() < self.k {
+            self.heap.push(c)
+        } else if self.heap.peek().is_some_and(|x| c.key < x.key) {
+            self.heap.pop();
+            self.heap.push(c)
+        }
+    }
+}
+
+struct Controls {
+    seed: u64,
+    threshold: f64,
+    cells: Vec<Cell>,
+    /// (number of blocks, cell for each GC bin) for each length class
+    classes: Vec<(usize, Vec<Option<usize>>)>,
+    /// (target idx, cell) for each requested control
+    requests: Vec<(NonZeroU32, usize)>,
+}
+
+impl Controls {
+    /// Controls for n requests, given (target idx, length, gc) for the targets
+    fn new(seed: u64, threshold: f64, targets: &[(NonZeroU32, u64, f64)], n: usize) -> Self {
+        let mut order: Vec<_> = targets.iter().collect();
+        order.sort_unstable_by_key(|(idx, _, _)| hash_key((seed, *idx)));
+        let mut cell_ix: FxHashMap<(usize, usize), usize> = FxHashMap::default();
+        let mut cells = Vec::new();
+        let requests = order
+            .iter()
+            .cycle()
+            .take(if order.is_empty() { 0 } else { n })
+            .map(|(idx, len, gc)| {
+                let key = (len_blocks(*len), gc_bin(*gc));
+                let i = *cell_ix.entry(key).or_insert_with(|| {
+                    cells.push(Cell {
+                        k: 0,
+                        heap: BinaryHeap::new(),
+                    });
+                    cells.len() - 1
+                });
+                cells[i].k += 1;
+                (*idx, i)
+            })
+            .collect();
+        let mut classes: Vec<(usize, Vec<Option<usize>>)> = Vec::new();
+        let mut cl: Vec<_> = cell_ix.into_iter().collect();
+        cl.sort_unstable();
+        for ((m, g), i) in cl {
+            if classes.last().map(|(x, _)| *x) != Some(m) {
+                classes.push((m, vec![None; GC_BINS]))
+            }
+            classes.last_mut().unwrap().1[g] = Some(i)
+        }
+        Self {
+            seed,
+            threshold,
+            cells,
+            classes,
+            requests,
+        }
+    }
+
+    /// Add candidates from a contig given the (ACGT, GC) counts and whether a target overlaps
+    /// for each block, with end the end of the last non gap base
+    fn add_contig(&mut self, contig: u32, blocks: &[(u32, u32, bool)], end: u64) {
+        let mut cum = Vec::with_capacity(blocks.len() + 1);
+        cum.push((0, 0, 0));
+        for (n, gc, t) in blocks {
+            let (a, b, c) = cum.last().copied().unwrap();
+            cum.push((a + *n as u64, b + *gc as u64, c + *t as u32))
+        }
+        for (m, gc_cells) in self.classes.iter() {
+            let min_bases = self.threshold * (*m as u64 * BLOCK) as f64;
+            for i in (0..=blocks.len().saturating_sub(*m)).step_by(STEP) {
+                let j = i + m;
+                if j > blocks.len() {
+                    break;
+                }
+                let (n, gc, t) = (
+                    cum[j].0 - cum[i].0,
+                    cum[j].1 - cum[i].1,
+                    cum[j].2 - cum[i].2,
+                );
+                if t > 0 || n == 0 || (n as f64) < min_bases {
+                    continue;
+                }
+                if let Some(ci) = gc_cells[gc_bin(gc as f64 / n as f64)] {
+                    let start = i as u64 * BLOCK;
+                    self.cells[ci].push(Candidate {
+                        key: hash_key((self.seed, contig, start, m)),
+                        contig,
+                        start,
+                        end: (j as u64 * BLOCK).min(end),
+                    })
+                }
+            }
+        }
+    }
+
+    /// Selected controls as (contig, start, end, target idx) sorted by position
+    fn finish(self) -> Vec<(u32, u64, u64, NonZeroU32)> {
+        let mut cands: Vec<_> = self
+            .cells
+            .into_iter()
+            .map(|c| c.heap.into_sorted_vec().into_iter())
+            .collect();
+        let mut v: Vec<_> = self
+            .requests
+            .iter()
+            .filter_map(|(idx, ci)| cands[*ci].next().map(|c| (c.contig, c.start, c.end, *idx)))
+            .collect();
+        v.sort_unstable();
+        v
+    }
+}
+
+/// Mark blocks overlapping targets on contig (padded by pad bases)
+fn mark_targets(reg: &Regions, contig: &str, pad: u64, blocks: &mut [(u32, u32, bool)]) {
+    if let Some(cr) = reg.get(contig) {
+        for r in cr.regions() {
+            let a = (r.start().saturating_sub(pad) / BLOCK) as usize;
+            let b = (r.end() + pad).div_ceil(BLOCK) as usize;
+            for x in blocks.iter_mut().take(b).skip(a) {
+                x.2 = true
+            }
+        }
+    }
+}
+
+/// Block counts for the current contig, passed to the control selection at the end of each
+/// contig
+struct ContigBlocks<'a> {
+    reg: &'a Regions,
+    pad: u64,
+    ctrl: Controls,
+    contigs: Vec<String>,
+    blocks: Vec<(u32, u32, bool)>,
+    end: u64,
+}
+
+impl ContigBlocks<'_> {
+    fn end_contig(&mut self) {
+        if let Some(c) = self.contigs.last() {
+            mark_targets(self.reg, c, self.pad, &mut self.blocks);
+            self.ctrl
+                .add_contig(self.contigs.len() as u32 - 1, &self.blocks, self.end);
+            self.blocks.clear()
+        }
+    }
+
+    fn add_window(&mut self, w: &Window) {
+        if self.contigs.last().map(|s| s.as_str()) != Some(w.contig()) {
+            self.end_contig();
+            self.contigs.push(w.contig().to_owned())
+        }
+        let i = (w.start() / BLOCK) as usize;
+        self.blocks.resize(i, (0, 0, false));
+        self.blocks
+            .push((w.bases(), w.count(Base::C) + w.count(Base::G), false));
+        self.end = w.end();
+    }
+}
+
+/// Selection of n off target control regions matched to the targets from the sequences of the
+/// input
+pub struct MatchedControls<'a> {
+    n: usize,
+    windows: WindowScan,
+    blocks: ContigBlocks<'a>,
+}
+
+impl<'a> MatchedControls<'a> {
+    pub fn new(cfg: &Config, reg: &'a Regions, stats: &TargetStats, n: usize) -> Self {
+        let mut targets = Vec::with_capacity(reg.n_regions());
+        for (_, cr) in reg.iter() {
+            for r in cr.regions() {
+                let c = stats.counts(r.idx());
+                let acgt: u64 = c.iter().sum();
+                if acgt > 0 {
+                    let gc = (c[Base::C as usize] + c[Base::G as usize]) as f64 / acgt as f64;
+                    targets.push((r.idx(), r.end() - r.start(), gc))
+                }
+            }
+        }
+        Self {
+            n,
+            windows: WindowScan::new(BLOCK),
+            blocks: ContigBlocks {
+                reg,
+                pad: cfg.padding().unwrap_or(0),
+                ctrl: Controls::new(cfg.seed(), cfg.threshold(), &targets, n),
+                contigs: Vec::new(),
+                blocks: Vec::new(),
+                end: 0,
+            },
+        }
+    }
+
+    pub fn add_seq(&mut self, s: &Seq) -> anyhow::Result<()> {
+        let blocks = &mut self.blocks;
+        self.windows.add_seq(s, |w| {
+            blocks.add_window(w);
+            Ok(())
+        })
+    }
+
+    /// Write the control regions as BED, returning the number written.  The name column gives
+    /// the id of the matched target
+    pub fn finish(self, name: &str) -> anyhow::Result<usize> {
+        let (n, mut blocks) = (self.n, self.blocks);
+        self.windows.finish(|w| {
+            blocks.add_window(w);
+            Ok(())
+        })?;
+        blocks.end_contig();
+
+        let controls = blocks.ctrl.finish();
+        let mut wrt = CompressIo::new()
+            .path(name)
+            .bufwriter()
+            .with_context(|| format!("Could not open output control region file {name}"))?;
+        for (ctg, start, end, idx) in controls.iter() {
+            writeln!(
+                wrt,
+                "{}\t{start}\t{end}\t{idx}",
+                blocks.contigs[*ctg as usize]
+            )?
+        }
+        wrt.flush()
+            .with_context(|| "Error writing out control regions")?;
+        if controls.len() < n {
+            add_warning(
+                "matched_controls",
+                format!(
+                    "Only {} of {n} matched control regions found",
+                    controls.len()
+                ),
+            )
+        }
+        info!("{} matched control regions written", controls.len());
+        Ok(controls.len())
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_controls() {
+        assert_eq!(len_blocks(200), 10);
+        assert_eq!(len_blocks(1), 1);
+        let t = |i: u32| NonZeroU32::new(i).unwrap();
+        // Two targets of 200 bp, one at 50% GC and one at 20% GC
+        let targets = [(t(1), 200, 0.5), (t(2), 200, 0.2)];
+        let mut ctrl = Controls::new(0, 0.5, &targets, 3);
+        assert_eq!(ctrl.cells.iter().map(|c| c.k).sum::<usize>(), 3);
+        // 50% GC for the first 100 blocks, 20% GC for the next 15 (which overlap a target)
+        // and 20% GC for the last 20
+        let mut blocks = vec![(20, 10, false); 100];
+        blocks.extend([(20, 4, true); 15]);
+        blocks.extend([(20, 4, false); 20]);
+        ctrl.add_contig(0, &blocks, 135 * BLOCK);
+        let v = ctrl.finish();
+        assert_eq!(v.len(), 3);
+        for (_, start, end, idx) in v {
+            assert_eq!(end - start, 200);
+            if idx == t(1) {
+                assert!(end <= 2000)
+            } else {
+                assert!(start >= 2300)
+            }
+        }
+    }
+}
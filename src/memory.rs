@@ -0,0 +1,216 @@
+//! Estimation of peak memory use before analysis starts, so that runs that would exhaust memory
+//! fail early with a clear message
+use std::{fmt, fs, mem::size_of, path::Path};
+
+use crate::{
+    bootstrap::BOOTSTRAP_GROUPS,
+    cli::Config,
+    fasta::fai::read_fai,
+    kmers::{KmerVec, KMER_LENGTH},
+    process::GcCounts,
+};
+
+/// Length assumed for sequence blocks when it can not be found from the input
+const DEFAULT_BLOCK_SIZE: u64 = 250_000_000;
+
+/// Estimated memory use (in bytes) of the main data structures
+pub struct MemEstimate {
+    kmers: u64,
+    in_flight: u64,
+    histograms: u64,
+}
+
+impl MemEstimate {
+    pub fn new(cfg: &Config) -> Self {
+        let kmers = if cfg.target_regions().is_some() {
+            let k = KMER_LENGTH as u32;
+            let mut n = 4u64.pow(k);
+            if cfg.bisulfite() {
+                n += 2 * 3u64.pow(k)
+            }
+            n * size_of::<KmerVec>() as u64
+        } else {
+            0
+        };
+
+        // Blocks in the queue, being processed (parsed and unparsed) and being read
+        let nt = cfg.threads() as u64;
+        let n_blocks = cfg.queue_size() as u64 + 2 * nt + 1;
+        let mut block = block_size(cfg.input());
+        if let Some(c) = cfg.chunk_size() {
+            // Chunks overlap by up to the maximum read length
+            let max_rl = cfg.read_lengths().iter().max().copied().unwrap_or(0) as u64;
+            block = block.min(c as u64 + max_rl)
+        }
+        let in_flight = n_blocks * block;
+
+        // One set of histograms per process thread plus the merged results, with extra sets for
+        // the repeat and non repeat partitions, annotation classes, contig groups, bootstrap groups, off target windows,
+        // padded targets and each threshold in a sweep
+        let n_set = if cfg.bisulfite() { 2 } else { 1 };
+        let mut n_hist = n_set;
+        if cfg.repeat_mask().is_some() {
+            n_hist += 2 * n_set
+        }
+        if cfg.annotation().is_some() {
+            n_hist += 4 * n_set
+        }
+        if let Some(g) = cfg.contig_groups() {
+            n_hist += g.groups().len() as u64 * n_set
+        }
+        if cfg.bootstrap().is_some() {
+            n_hist += BOOTSTRAP_GROUPS as u64 * n_set
+        }
+        if cfg.off_target_too() {
+            n_hist += n_set
+        }
+        if cfg.padding().is_some() {
+            n_hist += n_set
+        }
+        n_hist += n_set * cfg.threshold_sweep().map(|v| v.len() as u64).unwrap_or(0);
+        let histograms = (nt + 1)
+            * n_hist
+            * cfg
+                .read_lengths()
+                .iter()
+                .map(|l| GcCounts::mem_size(*l, cfg.long_reads()))
+                .sum::<u64>();
+
+        Self {
+            kmers,
+            in_flight,
+            histograms,
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.kmers + self.in_flight + self.histograms
+    }
+}
+
+impl fmt::Display for MemEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} (kmer tables: {}, sequence in flight: {}, histograms: {})",
+            MemSize(self.total()),
+            MemSize(self.kmers),
+            MemSize(self.in_flight),
+            MemSize(self.histograms)
+        )
+    }
+}
+
+/// Memory size displayed with binary units
+struct MemSize(u64);
+
+impl fmt::Display for MemSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+        let mut x = self.0 as f64;
+        let mut i = 0;
+        while x >= 1024.0 && i + 1 < units.len() {
+            x /= 1024.0;
+            i += 1
+        }
+        if i == 0 {
+            write!(f, "{} {}", self.0, units[0])
+        } else {
+            write!(f, "{x:.1} {}", units[i])
+        }
+    }
+}
+
+/// Largest sequence block expected from the input.  This is the longest contig from the index if
+/// present, otherwise the size of the input file (or a default for stdin)
+fn block_size(input: Option<&Path>) -> u64 {
+    let Some(p) = input else {
+        return DEFAULT_BLOCK_SIZE;
+    };
+    let mut fai = p.as_os_str().to_owned();
+    fai.push(".fai");
+    if let Ok(v) = read_fai(Path::new(&fai)) {
+        v.iter().map(|e| e.length()).max().unwrap_or(0)
+    } else {
+        fs::metadata(p)
+            .map(|m| m.len())
+            .unwrap_or(DEFAULT_BLOCK_SIZE)
+    }
+}
+
+/// Parse memory size with optional K, M, G or T suffix (binary units)
+pub fn parse_mem_size(s: &str) -> anyhow::Result<u64> {
+    let s = s.trim();
+    let (num, mult) = match s.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => {
+            let m = match c.to_ascii_uppercase() {
+                'K' => 1u64 << 10,
+                'M' => 1 << 20,
+                'G' => 1 << 30,
+                'T' => 1 << 40,
+                _ => return Err(anyhow!("Unknown suffix in memory size {s}")),
+            };
+            (&s[..i], m)
+        }
+        _ => (s, 1),
+    };
+    let x = num
+        .parse::<f64>()
+        .map_err(|_| anyhow!("Bad memory size {s}"))?;
+    if x > 0.0 {
+        Ok((x * mult as f64) as u64)
+    } else {
+        Err(anyhow!("Memory size must be positive"))
+    }
+}
+
+/// Available memory from /proc/meminfo (Linux only)
+fn available_memory() -> Option<u64> {
+    let s = fs::read_to_string("/proc/meminfo").ok()?;
+    s.lines()
+        .find_map(|l| l.strip_prefix("MemAvailable:"))
+        .and_then(|l| l.trim().strip_suffix("kB"))
+        .and_then(|x| x.trim().parse::<u64>().ok())
+        .map(|x| x << 10)
+}
+
+/// Check estimated memory use against --max-mem, or the available memory if --max-mem not set
+pub fn check_memory(cfg: &Config) -> anyhow::Result<()> {
+    let est = MemEstimate::new(cfg);
+    info!("Estimated peak memory use: {est}");
+    let (limit, desc) = match cfg.max_mem() {
+        Some(x) => (x, "memory limit (--max-mem)"),
+        None => match available_memory() {
+            Some(x) => (x, "available memory"),
+            None => return Ok(()),
+        },
+    };
+    if est.total() > limit {
+        let hint = if est.kmers > limit {
+            "The kmer tables used with target regions do not fit"
+        } else {
+            "Try reducing the number of threads or analyzing part of the input (--region)"
+        };
+        Err(anyhow!(
+            "Estimated memory use {est} exceeds {desc} of {}.  {hint}, or set a higher limit with --max-mem",
+            MemSize(limit)
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_parse_mem_size() {
+        assert_eq!(parse_mem_size("1000").unwrap(), 1000);
+        assert_eq!(parse_mem_size("16G").unwrap(), 16 << 30);
+        assert_eq!(parse_mem_size("1.5k").unwrap(), 1536);
+        assert!(parse_mem_size("12X").is_err());
+        assert!(parse_mem_size("0").is_err());
+        assert_eq!(format!("{}", MemSize(3 << 29)), "1.5 GiB");
+    }
+}
@@ -0,0 +1,99 @@
+//! Generate observed GC distributions from sequenced reads, using the same binning and smoothing
+//! as for the expected distributions so that the two are directly comparable
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+
+use crate::{
+    betabin::{write_hist, Smoother},
+    cli::ObserveConfig,
+    dist_table::DistFormat,
+    fasta::Base,
+    process::GcRes,
+    reads::{infer_read_lengths, open_reads, ReadRec},
+};
+
+/// Reads that are unmapped, secondary, QC fail, duplicate or supplementary are skipped
+const FILTER_FLAGS: u16 = 0xf04;
+
+fn base_counts(seq: &[u8]) -> [u32; 4] {
+    let mut cts = [0; 4];
+    for b in seq.iter().map(|c| Base::from_u8(*c)) {
+        if !b.is_gap() {
+            cts[b as usize] += 1
+        }
+    }
+    cts
+}
+
+fn add_read(res: &mut GcRes, rec: &ReadRec, threshold: f64, bisulfite: bool) -> bool {
+    let l = rec.seq().len() as u32;
+    let cts = base_counts(rec.seq());
+    let (a, c, t, g) = (
+        cts[Base::A as usize],
+        cts[Base::C as usize],
+        cts[Base::T as usize],
+        cts[Base::G as usize],
+    );
+    if a + c + g + t < ((l as f64) * threshold).ceil() as u32 {
+        return false;
+    }
+    res.add_count(l, (a + t, c + g), 1.0);
+    if bisulfite {
+        // C depleted reads are compared with G:A, G depleted reads with C:T
+        if c < g {
+            res.add_bs_count(l, (a, g), 1.0)
+        } else {
+            res.add_bs_count(l, (t, c), 1.0)
+        }
+    }
+    true
+}
+
+pub fn observe(cfg: &ObserveConfig) -> anyhow::Result<()> {
+    let rl = match cfg.read_lengths() {
+        Some(v) => v.to_vec(),
+        None => infer_read_lengths(cfg.input())?,
+    };
+
+    let mut res = GcRes::new(&rl, cfg.bisulfite(), false);
+    let mut rdr = open_reads(cfg.input())?;
+    let mut rec = ReadRec::default();
+    let mut n_reads = vec![0; rl.len()];
+
+    info!("Reading input from {}", cfg.input().display());
+    while rdr.next_read(&mut rec)? {
+        if (rec.flag() & FILTER_FLAGS) == 0 {
+            let l = rec.seq().len() as u32;
+            if let Some(ix) = rl.iter().position(|x| *x == l) {
+                if add_read(&mut res, &rec, cfg.threshold(), cfg.bisulfite()) {
+                    n_reads[ix] += 1
+                }
+            }
+        }
+    }
+    for (l, n) in rl.iter().zip(n_reads.iter()) {
+        if *n == 0 {
+            warn!("No reads used for read length {l}")
+        } else {
+            info!("Read length {l}: {n} reads used")
+        }
+    }
+
+    let name = format!("{}_observed_dist.txt", cfg.prefix());
+    debug!("Writing observed GC distributions output");
+    let mut wrt = CompressIo::new()
+        .path(&name)
+        .bufwriter()
+        .with_context(|| "Could not open output observed distribution file")?;
+
+    write_hist(
+        &mut wrt,
+        &rl,
+        None,
+        &res,
+        cfg.bisulfite(),
+        Smoother::default(),
+        DistFormat::Wide,
+    )
+    .with_context(|| format!("Error writing observed distribution to {name}"))
+}
@@ -1,26 +1,142 @@
-use std::path::Path;
+use std::{collections::BTreeMap, io::Write, path::Path};
 
 use anyhow::Context;
+use clap::{builder::PossibleValue, ValueEnum};
 use compress_io::compress::CompressIo;
 use serde::Serialize;
 
-use crate::{betabin::write_hist, cli::Config, process::GcRes};
+use crate::{
+    betabin::{gc_hist_table, write_hist, Smoothing},
+    bootstrap::{band_columns, BootstrapOpts},
+    checksum::ContigSum,
+    cli::Config,
+    contig_filter::FilteredContig,
+    conversion::Chemistry,
+    cross_validation::{cv_divergence, CvDivergence},
+    dropout_risk::RiskSummary,
+    fasta::{fai::is_compressed, tar::is_tar, Base},
+    gtf::FEATURE_CLASSES,
+    kmer_gc::KmerGcTable,
+    performance::{performance, Performance},
+    plot_script::write_plot_script,
+    process::{GcRes, SkewHist, SKEW_BINS},
+    provenance::Provenance,
+    regions::TargetOverlaps,
+    registry::write_registry,
+    repeat_scan::RepeatRes,
+    summary::add_output_file,
+    warnings::{add_warning, warnings, Warning},
+    window_sample::output_window_sample,
+};
+
+/// Format for the main results file
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum OutputFormat {
+    /// Pretty printed JSON
+    Json,
+    /// MessagePack (compact binary with the same structure as the JSON)
+    Msgpack,
+}
+
+impl ValueEnum for OutputFormat {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Json, Self::Msgpack]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(self.name()))
+    }
+}
+
+impl OutputFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Msgpack => "msgpack",
+        }
+    }
+}
 
 #[derive(Serialize)]
 struct JsOutput<'a, 'b> {
     program: &'static str,
     version: &'static str,
     date: String,
+    #[serde(flatten)]
+    provenance: Provenance,
     #[serde(skip_serializing_if = "Option::is_none")]
-    identifier: Option<&'a str>,
+    identifier: Option<&'b str>,
     #[serde(skip_serializing_if = "Option::is_none")]
     input: Option<&'a Path>,
     threads: usize,
     threshold: f64,
     bisulfite: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chemistry: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    conversion_rate: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    methylation_level: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sample_fraction: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stride: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    null_model: Option<&'static str>,
+    end_policy: &'static str,
+    /// Passing partial windows at the ends of sequences for each read length
+    partial_windows: BTreeMap<u32, u64>,
+    smoothing: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kde_bandwidth: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    padding: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target_overlaps: Option<&'a TargetOverlaps>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dropout_risk: Option<&'b RiskSummary>,
     read_lengths: &'a [u32],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    read_length_weights: Option<&'a [f64]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filtered_contigs: Option<&'a [FilteredContig]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cross_validation: Option<Vec<CvDivergence>>,
     #[serde(flatten)]
     results: &'b GcRes,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    performance: Option<Performance>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<Warning>,
+}
+
+/// Total passing partial windows for each read length over all contigs
+fn partial_windows(res: &GcRes) -> BTreeMap<u32, u64> {
+    let mut m = BTreeMap::new();
+    for (_, h) in res.contig_window_counts() {
+        for (l, c) in h.iter() {
+            *m.entry(*l).or_insert(0) += c.partial()
+        }
+    }
+    m
+}
+
+/// Conversion chemistry if bisulfite analysis is active and it is not the default
+fn chemistry(cfg: &Config) -> Option<&'static str> {
+    let chem = cfg.conversion().chemistry();
+    (cfg.bisulfite() && chem != Chemistry::Bisulfite).then(|| chem.name())
+}
+
+/// Conversion rate if bisulfite analysis is active and the conversion model is used
+fn conversion_rate(cfg: &Config) -> Option<f64> {
+    let conv = cfg.conversion();
+    (cfg.bisulfite() && !conv.is_complete()).then(|| conv.rate())
+}
+
+/// CpG methylation level if bisulfite analysis is active and it is non zero
+fn methylation_level(cfg: &Config) -> Option<f64> {
+    let conv = cfg.conversion();
+    (cfg.bisulfite() && conv.uses_cpg()).then(|| conv.methylation())
 }
 
 impl<'a, 'b> JsOutput<'a, 'b> {
@@ -29,17 +145,131 @@ impl<'a, 'b> JsOutput<'a, 'b> {
             program: env!("CARGO_PKG_NAME"),
             version: env!("CARGO_PKG_VERSION"),
             date: cfg.date().to_rfc2822(),
-            identifier: cfg.identifier(),
+            provenance: Provenance::new(results.contigs())
+                .with_input_digest(results.input_digest()),
+            identifier: results.identifier(),
             input: cfg.input(),
             threads: cfg.threads(),
             threshold: cfg.threshold(),
             bisulfite: cfg.bisulfite(),
+            chemistry: chemistry(cfg),
+            conversion_rate: conversion_rate(cfg),
+            methylation_level: methylation_level(cfg),
+            sample_fraction: cfg.sample_fraction(),
+            stride: Some(cfg.stride()).filter(|x| *x > 1),
+            null_model: cfg.null_model().map(|m| m.name()),
+            end_policy: cfg.end_policy().name(),
+            partial_windows: partial_windows(results),
+            smoothing: cfg.smoothing().name(),
+            kde_bandwidth: (cfg.smoothing() == Smoothing::Kde).then(|| cfg.kde_bandwidth()),
+            padding: cfg.padding(),
+            target_overlaps: cfg.target_regions().map(|r| r.overlaps()),
+            dropout_risk: results.target_summary().and_then(|t| t.risk()),
             read_lengths: cfg.read_lengths(),
+            read_length_weights: cfg.read_length_weights(),
+            filtered_contigs: cfg.contig_filter().map(|f| f.filtered()),
+            cross_validation: results
+                .cv_parts()
+                .map(|p| cv_divergence(cfg.read_lengths(), p, cfg.smoother())),
             results,
+            performance: performance(),
+            warnings: warnings(),
         }
     }
 }
 
+/// Expected GC distributions for a single read length in gemBS layout
+#[derive(Serialize)]
+struct GembsReadLength<'a> {
+    read_length: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    weight: Option<f64>,
+    non_converted: &'a [f64],
+    /// Pooled over C->T and G->A converted strands
+    #[serde(skip_serializing_if = "Option::is_none")]
+    converted: Option<&'a [f64]>,
+}
+
+#[derive(Serialize)]
+struct GembsGcModel<'a> {
+    program: &'static str,
+    version: &'static str,
+    date: String,
+    reference: &'a str,
+    bisulfite: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chemistry: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    conversion_rate: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    methylation_level: Option<f64>,
+    threshold: f64,
+    gc: &'a [f64],
+    distributions: Vec<GembsReadLength<'a>>,
+}
+
+#[derive(Serialize)]
+struct GembsOutput<'a> {
+    gc_model: GembsGcModel<'a>,
+}
+
+fn output_gembs<P: AsRef<Path>>(name: P, cfg: &Config, res: &GcRes) -> anyhow::Result<()> {
+    debug!("Writing gemBS JSON output");
+    let reference = res
+        .identifier()
+        .or_else(|| {
+            cfg.input()
+                .and_then(|p| p.file_name())
+                .and_then(|s| s.to_str())
+        })
+        .unwrap_or_else(|| {
+            warn!("No reference identifier for gemBS output: use --identifier to set one");
+            "unknown"
+        });
+
+    let tab = gc_hist_table(
+        cfg.read_lengths(),
+        None,
+        res,
+        cfg.bisulfite(),
+        cfg.smoother(),
+    );
+    let distributions = cfg
+        .read_lengths()
+        .iter()
+        .enumerate()
+        .map(|(i, rl)| GembsReadLength {
+            read_length: *rl,
+            weight: cfg.read_length_weights().map(|w| w[i]),
+            non_converted: tab.column(&format!("read_len:{rl}bp")).unwrap(),
+            converted: tab.column(&format!("bisulfite_read_len:{rl}bp")),
+        })
+        .collect();
+
+    let out = GembsOutput {
+        gc_model: GembsGcModel {
+            program: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            date: cfg.date().to_rfc2822(),
+            reference,
+            bisulfite: cfg.bisulfite(),
+            chemistry: chemistry(cfg),
+            conversion_rate: conversion_rate(cfg),
+            methylation_level: methylation_level(cfg),
+            threshold: cfg.threshold(),
+            gc: tab.gc(),
+            distributions,
+        },
+    };
+
+    let wrt = CompressIo::new()
+        .path(name)
+        .bufwriter()
+        .with_context(|| "Could not open output gemBS JSON file")?;
+
+    serde_json::to_writer_pretty(wrt, &out).with_context(|| "Error writing out gemBS JSON file")
+}
+
 fn output_json<P: AsRef<Path>>(name: P, cfg: &Config, res: &GcRes) -> anyhow::Result<()> {
     debug!("Writing JSON output");
     let wrt = CompressIo::new()
@@ -53,10 +283,311 @@ fn output_json<P: AsRef<Path>>(name: P, cfg: &Config, res: &GcRes) -> anyhow::Re
         .with_context(|| "Error writing out JSON file with results")
 }
 
-fn output_dist<P: AsRef<Path>>(
+/// Results as for the JSON output, with structs encoded as maps so that the field names are kept
+fn output_msgpack<P: AsRef<Path>>(name: P, cfg: &Config, res: &GcRes) -> anyhow::Result<()> {
+    debug!("Writing MessagePack output");
+    let mut wrt = CompressIo::new()
+        .path(name)
+        .bufwriter()
+        .with_context(|| "Could not open output MessagePack file")?;
+
+    let out = JsOutput::make(cfg, res);
+
+    rmp_serde::encode::write_named(&mut wrt, &out)
+        .with_context(|| "Error writing out MessagePack file with results")?;
+    wrt.flush()
+        .with_context(|| "Error flushing data to MessagePack file")
+}
+
+/// Sequence dictionary in the Picard/samtools dict layout
+fn output_dict<P: AsRef<Path>>(
+    name: P,
+    cfg: &Config,
+    contigs: &[ContigSum],
+    identifier: Option<&str>,
+) -> anyhow::Result<()> {
+    debug!("Writing sequence dictionary");
+    let mut wrt = CompressIo::new()
+        .path(name)
+        .bufwriter()
+        .with_context(|| "Could not open output dictionary file")?;
+
+    let ur = cfg.input().and_then(|p| p.canonicalize().ok());
+    writeln!(wrt, "@HD\tVN:1.0\tSO:unsorted")?;
+    for c in contigs {
+        write!(
+            wrt,
+            "@SQ\tSN:{}\tLN:{}\tM5:{}",
+            c.name(),
+            c.length(),
+            c.md5()
+        )?;
+        if let Some(p) = ur.as_ref() {
+            write!(wrt, "\tUR:file:{}", p.display())?
+        }
+        if let Some(id) = identifier {
+            write!(wrt, "\tAS:{id}")?
+        }
+        writeln!(wrt)?
+    }
+    wrt.flush()
+        .with_context(|| "Error writing out dictionary file")
+}
+
+/// Index in the samtools faidx layout.  This is only possible for uncompressed input files (not
+/// tar archives) where the line lengths within each contig are consistent
+fn output_fai<P: AsRef<Path>>(name: P, cfg: &Config, contigs: &[ContigSum]) -> anyhow::Result<()> {
+    if cfg.input().and_then(is_compressed) != Some(false)
+        || cfg.input().and_then(is_tar) != Some(false)
+    {
+        warn!("Index file not written: input is not an uncompressed FASTA file");
+        return Ok(());
+    }
+    if let Some(c) = contigs.iter().find(|c| c.fai().is_none()) {
+        warn!(
+            "Index file not written: inconsistent line lengths for contig {}",
+            c.name()
+        );
+        return Ok(());
+    }
+    debug!("Writing index file");
+    add_output_file(&name.as_ref().to_string_lossy());
+    let mut wrt = CompressIo::new()
+        .path(name)
+        .bufwriter()
+        .with_context(|| "Could not open output index file")?;
+    for c in contigs {
+        let (offset, bases, width) = c.fai().unwrap();
+        writeln!(
+            wrt,
+            "{}\t{}\t{}\t{}\t{}",
+            c.name(),
+            c.length(),
+            offset,
+            bases,
+            width
+        )?
+    }
+    wrt.flush().with_context(|| "Error writing out index file")
+}
+
+/// Skew distributions, normalized to sum to 1 for each read length
+fn output_skew<P: AsRef<Path>>(name: P, read_lengths: &[u32], res: &GcRes) -> anyhow::Result<()> {
+    debug!("Writing skew distributions output");
+    let mut wrt = CompressIo::new()
+        .path(name)
+        .bufwriter()
+        .with_context(|| "Could not open output skew file")?;
+
+    let mut columns = Vec::new();
+    for rl in read_lengths {
+        let h = res.skew(*rl).expect("Missing skew histogram");
+        for (s, v) in [("gc", h.gc_skew()), ("at", h.at_skew())] {
+            let tot: f64 = v.iter().sum();
+            let mean = v
+                .iter()
+                .enumerate()
+                .map(|(i, x)| SkewHist::bin_centre(i) * x)
+                .sum::<f64>()
+                / tot;
+            info!("Read length {rl}: mean {s} skew {mean:.4}");
+            columns.push((
+                format!("{s}_skew_read_len:{rl}bp"),
+                v.iter().map(|x| x / tot).collect::<Vec<_>>(),
+            ))
+        }
+    }
+    write!(wrt, "skew")?;
+    for (name, _) in columns.iter() {
+        write!(wrt, "\t{name}")?
+    }
+    writeln!(wrt)?;
+    for i in 0..SKEW_BINS {
+        write!(wrt, "{}", SkewHist::bin_centre(i))?;
+        for (_, v) in columns.iter() {
+            write!(wrt, "\t{}", v[i])?
+        }
+        writeln!(wrt)?
+    }
+    wrt.flush()
+        .with_context(|| "Error writing out skew distributions")
+}
+
+/// Base composition by position for each read length
+fn output_per_base<P: AsRef<Path>>(
     name: P,
     read_lengths: &[u32],
     res: &GcRes,
+) -> anyhow::Result<()> {
+    debug!("Writing per base composition output");
+    let mut wrt = CompressIo::new()
+        .path(name)
+        .bufwriter()
+        .with_context(|| "Could not open output per base composition file")?;
+
+    writeln!(wrt, "read_length\tposition\tA\tC\tG\tT")?;
+    for rl in read_lengths {
+        let h = res.per_base(*rl).expect("Missing per base composition");
+        for (i, p) in h.proportions().iter().enumerate() {
+            writeln!(
+                wrt,
+                "{rl}\t{}\t{}\t{}\t{}\t{}",
+                i + 1,
+                p[Base::A as usize],
+                p[Base::C as usize],
+                p[Base::G as usize],
+                p[Base::T as usize]
+            )?
+        }
+    }
+    wrt.flush()
+        .with_context(|| "Error writing out per base composition")
+}
+
+/// Repeat runs in BED format with the motif as name and the number of copies as score
+fn output_repeats<P: AsRef<Path>>(name: P, res: &RepeatRes) -> anyhow::Result<()> {
+    debug!("Writing repeat runs output");
+    let mut wrt = CompressIo::new()
+        .path(name)
+        .bufwriter()
+        .with_context(|| "Could not open output repeat file")?;
+
+    for r in res.runs() {
+        let motif = res.motif(r.motif());
+        let copies = (r.end() - r.start()) / motif.len() as u64;
+        let strand = if r.reverse() { '-' } else { '+' };
+        writeln!(
+            wrt,
+            "{}\t{}\t{}\t{motif}\t{copies}\t{strand}",
+            r.contig(),
+            r.start(),
+            r.end()
+        )?
+    }
+    wrt.flush().with_context(|| "Error writing out repeat runs")
+}
+
+/// Total count and mean GC proportion of windows for read length rl
+pub(crate) fn mean_gc(res: &GcRes, rl: u32) -> (f64, f64) {
+    let (n, gc) = res
+        .get_gc_hist(rl)
+        .map(|h| {
+            h.counts().iter().fold((0.0, 0.0), |(n, gc), (k, x)| {
+                let (a, g) = k.counts();
+                (n + x, gc + x * g as f64 / (a + g).max(1) as f64)
+            })
+        })
+        .unwrap_or((0.0, 0.0));
+    (n, if n > 0.0 { gc / n } else { f64::NAN })
+}
+
+/// Log the proportion of unique kmers in low, medium and high GC windows
+fn log_kmer_gc(read_lengths: &[u32], t: &KmerGcTable) {
+    for (ix, rl) in read_lengths.iter().enumerate() {
+        let v = t.counts(ix);
+        let fracs: Vec<_> = [0..30, 30..61, 61..v.len()]
+            .into_iter()
+            .map(|r| {
+                let (u, n) = v[r]
+                    .iter()
+                    .fold((0, 0), |(u, n), c| (u + c[0], n + c.iter().sum::<u64>()));
+                u as f64 / n.max(1) as f64
+            })
+            .collect();
+        info!(
+            "Read length {rl}: unique kmer fraction GC < 30% {:.4}, 30-60% {:.4}, > 60% {:.4}",
+            fracs[0], fracs[1], fracs[2]
+        )
+    }
+}
+
+/// Log the proportion and GC of windows in repeats, and the kmer uniqueness of each partition
+fn log_repeat_parts(read_lengths: &[u32], res: &GcRes, parts: &[GcRes; 2]) {
+    for rl in read_lengths {
+        let (n0, gc0) = mean_gc(&parts[0], *rl);
+        let (n1, gc1) = mean_gc(&parts[1], *rl);
+        let f = n1 / (n0 + n1).max(1.0);
+        info!("Read length {rl}: {f:.4} of windows in repeats, mean GC repeat {gc1:.4}, non-repeat {gc0:.4}")
+    }
+    if let Some(k) = res.repeatmasker().and_then(|r| r.kmers()) {
+        for (p, u) in [("Non-repeat", k.get(false)), ("Repeat", k.get(true))] {
+            let f = u.unique_kmers() as f64 / u.kmers().max(1) as f64;
+            info!(
+                "{p} kmers: {}, unique: {} ({f:.4})",
+                u.kmers(),
+                u.unique_kmers()
+            )
+        }
+    }
+}
+
+/// Log the proportion and GC of windows in each contig group, and the kmer uniqueness of the group
+fn log_group_parts(read_lengths: &[u32], res: &GcRes, parts: &[GcRes]) {
+    let Some(groups) = res.contig_groups() else {
+        return;
+    };
+    for rl in read_lengths {
+        let v: Vec<_> = parts.iter().map(|r| mean_gc(r, *rl)).collect();
+        let tot = v.iter().map(|(n, _)| n).sum::<f64>().max(1.0);
+        for (g, (n, gc)) in groups.iter().zip(v.iter()) {
+            info!(
+                "Read length {rl}: {:.4} of windows in group {}, mean GC {gc:.4}",
+                n / tot,
+                g.name()
+            )
+        }
+    }
+    for g in groups {
+        info!(
+            "Group {}: {} contigs, {} bases, {} kmers, unique: {} ({:.4})",
+            g.name(),
+            g.contigs(),
+            g.bases(),
+            g.kmers(),
+            g.unique_kmers(),
+            g.unique_kmers() as f64 / g.kmers().max(1) as f64
+        )
+    }
+}
+
+/// Log the divergence between the distributions from the two halves of the genome
+fn log_cv_parts(cfg: &Config, parts: &[GcRes; 2]) {
+    let v = cv_divergence(cfg.read_lengths(), parts, cfg.smoother());
+    if v.len() < cfg.read_lengths().len() {
+        add_warning(
+            "cross_validation",
+            "Cross-validation half with no windows (try a smaller --cv-block-size)".to_owned(),
+        )
+    }
+    for d in v {
+        info!(
+            "Read length {}: cross-validation Jensen-Shannon divergence {:.6}, total variation {:.6}",
+            d.read_length(),
+            d.jensen_shannon(),
+            d.total_variation()
+        )
+    }
+}
+
+/// Log the proportion and GC of windows in each annotation class
+fn log_class_parts(read_lengths: &[u32], parts: &[GcRes; 4]) {
+    for rl in read_lengths {
+        let v: Vec<_> = parts.iter().map(|r| mean_gc(r, *rl)).collect();
+        let tot = v.iter().map(|(n, _)| n).sum::<f64>().max(1.0);
+        for (c, (n, gc)) in FEATURE_CLASSES.iter().zip(v.iter()) {
+            info!(
+                "Read length {rl}: {:.4} of windows {}, mean GC {gc:.4}",
+                n / tot,
+                c.name()
+            )
+        }
+    }
+}
+
+fn output_dist<P: AsRef<Path>>(
+    name: P,
+    cfg: &Config,
+    res: &GcRes,
     bisulfite: bool,
 ) -> anyhow::Result<()> {
     debug!("Writing expected GC distributions output");
@@ -65,13 +596,247 @@ fn output_dist<P: AsRef<Path>>(
         .bufwriter()
         .with_context(|| "Could not open output distribution file")?;
 
-    write_hist(&mut wrt, read_lengths, res, bisulfite)
+    write_hist(
+        &mut wrt,
+        cfg.read_lengths(),
+        cfg.read_length_weights(),
+        res,
+        bisulfite,
+        cfg.smoother(),
+        cfg.dist_format(),
+    )
+}
+
+/// Main distribution output with bootstrap bands
+fn output_dist_bands<P: AsRef<Path>>(
+    name: P,
+    cfg: &Config,
+    res: &GcRes,
+    parts: &[GcRes],
+    boot: BootstrapOpts,
+) -> anyhow::Result<()> {
+    debug!(
+        "Calculating bootstrap bands from {} replicates",
+        boot.replicates()
+    );
+    let mut tab = gc_hist_table(
+        cfg.read_lengths(),
+        cfg.read_length_weights(),
+        res,
+        cfg.bisulfite(),
+        cfg.smoother(),
+    );
+    tab.add_columns(band_columns(
+        cfg.read_lengths(),
+        parts,
+        cfg.bisulfite(),
+        cfg.smoother(),
+        boot.replicates(),
+        cfg.seed(),
+    ));
+    debug!("Writing expected GC distributions output");
+    let mut wrt = CompressIo::new()
+        .path(name)
+        .bufwriter()
+        .with_context(|| "Could not open output distribution file")?;
+    tab.write(&mut wrt, cfg.dist_format())
 }
 
 pub fn output(cfg: &Config, res: &GcRes) -> anyhow::Result<()> {
-    let name = format!("{}.json", cfg.prefix());
-    output_json(name, cfg, res)?;
+    // Before the JSON output so that any warning is included
+    if let Some(parts) = res.cv_parts() {
+        log_cv_parts(cfg, parts)
+    }
+
+    let name = match cfg.output_format() {
+        OutputFormat::Json => {
+            let name = format!("{}.json", cfg.prefix());
+            output_json(&name, cfg, res)?;
+            name
+        }
+        OutputFormat::Msgpack => {
+            let name = format!("{}.msgpack", cfg.prefix());
+            output_msgpack(&name, cfg, res)?;
+            name
+        }
+    };
+    add_output_file(&name);
+
+    if cfg.gembs() {
+        let name = format!("{}_gembs.json", cfg.prefix());
+        output_gembs(&name, cfg, res)?;
+        add_output_file(&name)
+    }
+
+    if let Some(dir) = cfg.registry() {
+        for p in write_registry(dir, cfg, res)? {
+            add_output_file(&p.to_string_lossy())
+        }
+    }
+
+    if cfg.dict() {
+        let contigs = res.contigs().expect("Missing checksums");
+        let name = format!("{}.dict", cfg.prefix());
+        output_dict(&name, cfg, contigs, res.identifier())?;
+        add_output_file(&name);
+        output_fai(format!("{}.fai", cfg.prefix()), cfg, contigs)?;
+    }
+
+    if cfg.skew() {
+        let name = format!("{}_skew.txt", cfg.prefix());
+        output_skew(&name, cfg.read_lengths(), res)?;
+        add_output_file(&name)
+    }
+
+    if cfg.per_base() {
+        let name = format!("{}_per_base.txt", cfg.prefix());
+        output_per_base(&name, cfg.read_lengths(), res)?;
+        add_output_file(&name)
+    }
+
+    if let Some(w) = res.window_sample() {
+        let name = format!("{}_windows.txt", cfg.prefix());
+        output_window_sample(&name, w)
+            .with_context(|| format!("Could not write window sample to {name}"))?;
+        info!("Wrote sample of {} windows", w.len());
+        add_output_file(&name)
+    }
+
+    if cfg.n_content() {
+        for rl in cfg.read_lengths() {
+            let h = res.n_content(*rl).expect("Missing gap content");
+            info!(
+                "Read length {rl}: windows rejected due to Ns {}, due to long gaps or contig ends {}",
+                h.rejected_n(),
+                h.rejected_gap()
+            )
+        }
+    }
+
+    if let Some(r) = res.repeats() {
+        for t in r.totals() {
+            info!(
+                "Repeat {}: {} runs, {} bases",
+                t.motif(),
+                t.runs(),
+                t.bases()
+            )
+        }
+        let name = format!("{}_repeats.bed", cfg.prefix());
+        output_repeats(&name, r)?;
+        add_output_file(&name)
+    }
+
+    // Distribution files written, with plot titles
+    let mut dists: Vec<(String, String)> = Vec::new();
+
+    if let Some(null) = res.null() {
+        let name = format!("{}_null_dist.txt", cfg.prefix());
+        output_dist(&name, cfg, null, false)?;
+        add_output_file(&name);
+        dists.push((name, "Null model GC distribution".to_owned()))
+    }
+
+    if let Some(off) = res.off_target() {
+        for rl in cfg.read_lengths() {
+            let (_, gc0) = mean_gc(res, *rl);
+            let (_, gc1) = mean_gc(off, *rl);
+            info!("Read length {rl}: mean GC on target {gc0:.4}, off target {gc1:.4}")
+        }
+        let name = format!("{}_off_target_dist.txt", cfg.prefix());
+        output_dist(&name, cfg, off, cfg.bisulfite())?;
+        add_output_file(&name);
+        dists.push((name, "Off target GC distribution".to_owned()))
+    }
+
+    if let Some(t) = res.kmer_gc() {
+        log_kmer_gc(cfg.read_lengths(), t);
+        let name = format!("{}_kmer_gc.txt", cfg.prefix());
+        t.write(&name)?;
+        add_output_file(&name)
+    }
+
+    if let Some(padded) = res.padded() {
+        for rl in cfg.read_lengths() {
+            let (_, gc0) = mean_gc(res, *rl);
+            let (_, gc1) = mean_gc(padded, *rl);
+            info!("Read length {rl}: mean GC targets {gc0:.4}, padded targets {gc1:.4}")
+        }
+        let name = format!("{}_padded_dist.txt", cfg.prefix());
+        output_dist(&name, cfg, padded, cfg.bisulfite())?;
+        add_output_file(&name);
+        dists.push((name, "Padded target GC distribution".to_owned()))
+    }
+
+    for (t, r) in res.sweep().into_iter().flatten() {
+        for rl in cfg.read_lengths() {
+            let (n, gc) = mean_gc(r, *rl);
+            info!("Read length {rl}, threshold {t}: {n:.0} windows, mean GC {gc:.4}")
+        }
+        let name = format!("{}_T{t}_dist.txt", cfg.prefix());
+        output_dist(&name, cfg, r, cfg.bisulfite())?;
+        add_output_file(&name);
+        dists.push((name, format!("GC distribution for threshold {t}")))
+    }
+
+    if let Some(parts) = res.repeat_parts() {
+        log_repeat_parts(cfg.read_lengths(), res, parts);
+        let parts_desc = [
+            ("non_repeat", "Non repeat GC distribution"),
+            ("repeat", "Repeat GC distribution"),
+        ];
+        for ((p, desc), r) in parts_desc.iter().zip(parts.iter()) {
+            let name = format!("{}_{p}_dist.txt", cfg.prefix());
+            output_dist(&name, cfg, r, cfg.bisulfite())?;
+            add_output_file(&name);
+            dists.push((name, desc.to_string()))
+        }
+    }
+
+    if let Some(parts) = res.cv_parts() {
+        for (i, r) in parts.iter().enumerate() {
+            let name = format!("{}_half{}_dist.txt", cfg.prefix(), i + 1);
+            output_dist(&name, cfg, r, cfg.bisulfite())?;
+            add_output_file(&name);
+            dists.push((
+                name,
+                format!("Cross-validation half {} GC distribution", i + 1),
+            ))
+        }
+    }
+
+    if let Some(parts) = res.class_parts() {
+        log_class_parts(cfg.read_lengths(), parts);
+        for (c, r) in FEATURE_CLASSES.iter().zip(parts.iter()) {
+            let name = format!("{}_{}_dist.txt", cfg.prefix(), c.name());
+            output_dist(&name, cfg, r, cfg.bisulfite())?;
+            add_output_file(&name);
+            dists.push((name, format!("GC distribution for {} windows", c.name())))
+        }
+    }
+
+    if let (Some(parts), Some(groups)) = (res.group_parts(), cfg.contig_groups()) {
+        log_group_parts(cfg.read_lengths(), res, parts);
+        for (g, r) in groups.groups().iter().zip(parts.iter()) {
+            let name = format!("{}_group_{g}_dist.txt", cfg.prefix());
+            output_dist(&name, cfg, r, cfg.bisulfite())?;
+            add_output_file(&name);
+            dists.push((name, format!("GC distribution for contig group {g}")))
+        }
+    }
 
     let name = format!("{}_dist.txt", cfg.prefix());
-    output_dist(name, cfg.read_lengths(), res, cfg.bisulfite())
+    match (res.boot_parts(), cfg.bootstrap()) {
+        (Some(parts), Some(boot)) => output_dist_bands(&name, cfg, res, parts, boot)?,
+        _ => output_dist(&name, cfg, res, cfg.bisulfite())?,
+    }
+    add_output_file(&name);
+
+    if let Some(script) = cfg.plot_script() {
+        dists.insert(0, (name, "Expected GC distribution".to_owned()));
+        let name = write_plot_script(cfg.prefix(), script, &dists)?;
+        add_output_file(&name);
+        info!("Plot script written to {name}")
+    }
+    Ok(())
 }
@@ -0,0 +1,181 @@
+//! Timing of the processing stages and of the process threads (--performance), so that users
+//! can tell whether the reader or the process threads limit the run
+//!
+//! Times are collected in global counters (as for the warnings) while timing is enabled.  The
+//! read and kmer stages are timed in the reader thread, with read including the time spent
+//! waiting to send blocks to a full queue (send_wait).  Decode (parsing of records passed
+//! unparsed to the process threads) and count are summed over the process threads.  The busy time
+//! of each process thread is the time spent processing blocks, and the idle time is the rest of
+//! the processing wall time.
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+use serde::Serialize;
+
+/// Processing stages timed
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(usize)]
+pub enum Stage {
+    Read,
+    SendWait,
+    Decode,
+    Count,
+    Kmers,
+}
+
+const N_STAGES: usize = 5;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static STAGES: [AtomicU64; N_STAGES] = [const { AtomicU64::new(0) }; N_STAGES];
+static WALL: AtomicU64 = AtomicU64::new(0);
+static BUSY: Mutex<Vec<u64>> = Mutex::new(Vec::new());
+
+/// Enable timing for n process threads
+pub fn enable(n: usize) {
+    *BUSY.lock().expect("Timing lock poisoned") = vec![0; n];
+    ENABLED.store(true, Ordering::Relaxed)
+}
+
+/// Start time if timing is enabled
+pub fn start() -> Option<Instant> {
+    Some(Instant::now()).filter(|_| ENABLED.load(Ordering::Relaxed))
+}
+
+fn elapsed(t: Instant) -> u64 {
+    t.elapsed().as_nanos() as u64
+}
+
+/// Add the time since t to stage
+pub fn add_time(stage: Stage, t: Option<Instant>) {
+    if let Some(t) = t {
+        STAGES[stage as usize].fetch_add(elapsed(t), Ordering::Relaxed);
+    }
+}
+
+/// Add the time since t to the busy time of process thread ix
+pub fn add_busy(ix: usize, t: Option<Instant>) {
+    if let Some(t) = t {
+        let mut v = BUSY.lock().expect("Timing lock poisoned");
+        if let Some(x) = v.get_mut(ix) {
+            *x += elapsed(t)
+        }
+    }
+}
+
+/// Set the processing wall time to the time since t
+pub fn set_wall(t: Option<Instant>) {
+    if let Some(t) = t {
+        WALL.store(elapsed(t), Ordering::Relaxed)
+    }
+}
+
+fn secs(ns: u64) -> f64 {
+    ns as f64 * 1.0e-9
+}
+
+/// Stage times (seconds)
+#[derive(Debug, Clone, Serialize)]
+pub struct StageTimes {
+    read: f64,
+    send_wait: f64,
+    decode: f64,
+    count: f64,
+    kmers: f64,
+}
+
+/// Busy and idle times (seconds) for a process thread
+#[derive(Debug, Clone, Serialize)]
+pub struct ThreadTimes {
+    busy: f64,
+    idle: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Performance {
+    wall_time: f64,
+    stages: StageTimes,
+    threads: Vec<ThreadTimes>,
+    /// Mean proportion of the wall time the process threads were busy
+    thread_utilization: f64,
+    /// Proportion of the wall time the reader was busy (reading and not waiting on the queue)
+    reader_utilization: f64,
+    /// reader if the reader was busy for a larger proportion of the wall time than the process
+    /// threads, otherwise process_threads
+    bottleneck: &'static str,
+}
+
+impl Performance {
+    fn new(wall: u64, stages: [u64; N_STAGES], busy: &[u64]) -> Self {
+        let threads: Vec<_> = busy
+            .iter()
+            .map(|b| ThreadTimes {
+                busy: secs(*b),
+                idle: secs(wall.saturating_sub(*b)),
+            })
+            .collect();
+        let wall_f = wall.max(1) as f64;
+        let thread_utilization =
+            (busy.iter().sum::<u64>() as f64 / (wall_f * busy.len().max(1) as f64)).min(1.0);
+        let [read, send_wait, decode, count, kmers] = stages;
+        let reader_utilization = (read.saturating_sub(send_wait) as f64 / wall_f).min(1.0);
+        Self {
+            wall_time: secs(wall),
+            stages: StageTimes {
+                read: secs(read),
+                send_wait: secs(send_wait),
+                decode: secs(decode),
+                count: secs(count),
+                kmers: secs(kmers),
+            },
+            threads,
+            thread_utilization,
+            reader_utilization,
+            bottleneck: if reader_utilization > thread_utilization {
+                "reader"
+            } else {
+                "process_threads"
+            },
+        }
+    }
+
+    pub fn bottleneck(&self) -> &'static str {
+        self.bottleneck
+    }
+}
+
+/// Timing statistics if timing was enabled
+pub fn performance() -> Option<Performance> {
+    if ENABLED.load(Ordering::Relaxed) {
+        let stages = std::array::from_fn(|i| STAGES[i].load(Ordering::Relaxed));
+        let busy = BUSY.lock().expect("Timing lock poisoned");
+        Some(Performance::new(
+            WALL.load(Ordering::Relaxed),
+            stages,
+            &busy,
+        ))
+    } else {
+        None
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_performance() {
+        let s = 1_000_000_000;
+        let p = Performance::new(10 * s, [8 * s, 4 * s, 0, 14 * s, 0], &[9 * s, 7 * s]);
+        assert!((p.thread_utilization - 0.8).abs() < 1.0e-12);
+        assert!((p.reader_utilization - 0.4).abs() < 1.0e-12);
+        assert_eq!(p.bottleneck(), "process_threads");
+        assert!((p.threads[1].idle - 3.0).abs() < 1.0e-12);
+        let p = Performance::new(10 * s, [10 * s, 0, 0, 5 * s, 0], &[3 * s, 2 * s]);
+        assert_eq!(p.bottleneck(), "reader");
+    }
+}
@@ -0,0 +1,141 @@
+//! Generation of gnuplot or R (ggplot2) scripts to plot the distribution files written by a run
+//!
+//! One plot is made for each distribution file, with a line for each column (read length and
+//! conversion).  The file names in the script are as written, so the script should be run from
+//! the directory where analyze_ref_gc was run.  The R script also handles distribution files in
+//! the long format.
+use std::{io::Write, path::Path};
+
+use anyhow::Context;
+use clap::{builder::PossibleValue, ValueEnum};
+use compress_io::compress::CompressIo;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PlotScript {
+    Gnuplot,
+    R,
+}
+
+impl ValueEnum for PlotScript {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Gnuplot, Self::R]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(self.name()))
+    }
+}
+
+impl PlotScript {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Gnuplot => "gnuplot",
+            Self::R => "r",
+        }
+    }
+
+    /// Extension for the script file
+    fn ext(&self) -> &'static str {
+        match self {
+            Self::Gnuplot => "gp",
+            Self::R => "R",
+        }
+    }
+}
+
+/// Name of plot for distribution file (replacing the .txt suffix)
+fn plot_name(file: &str, ext: &str) -> String {
+    format!("{}.{ext}", file.strip_suffix(".txt").unwrap_or(file))
+}
+
+/// Quote string for use in the scripts
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn write_gnuplot<W: Write>(w: &mut W, files: &[(String, String)]) -> std::io::Result<()> {
+    writeln!(w, "set terminal pngcairo size 1000,700 noenhanced")?;
+    writeln!(w, "set datafile separator \"\\t\"")?;
+    writeln!(w, "set key autotitle columnhead")?;
+    writeln!(
+        w,
+        "set xlabel \"GC\"\nset ylabel \"Density\"\nset xrange [0:1]"
+    )?;
+    for (file, title) in files {
+        writeln!(
+            w,
+            "\nset output {}\nset title {}",
+            quote(&plot_name(file, "png")),
+            quote(title)
+        )?;
+        writeln!(
+            w,
+            "plot for [i=2:*] {} using 1:i with lines lw 2",
+            quote(file)
+        )?;
+    }
+    writeln!(w, "unset output")
+}
+
+fn write_r<W: Write>(w: &mut W, files: &[(String, String)]) -> std::io::Result<()> {
+    writeln!(
+        w,
+        r#"library(ggplot2)
+
+plot_dist <- function(file, title, out) {{
+  d <- read.delim(file, check.names = FALSE)
+  if ("density" %in% names(d)) {{
+    long <- data.frame(gc = d$gc, column = paste(d$mode, d$read_length), density = d$density)
+  }} else {{
+    long <- data.frame(
+      gc = rep(d$gc, ncol(d) - 1),
+      column = rep(names(d)[-1], each = nrow(d)),
+      density = unlist(d[-1], use.names = FALSE)
+    )
+  }}
+  p <- ggplot(long, aes(gc, density, colour = column)) +
+    geom_line() +
+    labs(title = title, x = "GC", y = "Density", colour = NULL) +
+    theme_bw()
+  ggsave(out, p, width = 8, height = 5)
+}}
+"#
+    )?;
+    for (file, title) in files {
+        writeln!(
+            w,
+            "plot_dist({}, {}, {})",
+            quote(file),
+            quote(title),
+            quote(&plot_name(file, "pdf"))
+        )?;
+    }
+    Ok(())
+}
+
+/// Write plot script for the distribution files, given as (file name, plot title).  Returns the
+/// name of the script
+pub fn write_plot_script(
+    prefix: &str,
+    script: PlotScript,
+    files: &[(String, String)],
+) -> anyhow::Result<String> {
+    let name = format!("{prefix}_plot.{}", script.ext());
+    let mut wrt = CompressIo::new()
+        .path(Path::new(&name))
+        .bufwriter()
+        .with_context(|| format!("Could not open plot script {name}"))?;
+    writeln!(
+        wrt,
+        "# Generated by {} {}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    )?;
+    match script {
+        PlotScript::Gnuplot => write_gnuplot(&mut wrt, files),
+        PlotScript::R => write_r(&mut wrt, files),
+    }
+    .and_then(|_| wrt.flush())
+    .with_context(|| format!("Error writing plot script {name}"))?;
+    Ok(name)
+}
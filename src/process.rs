@@ -1,15 +1,44 @@
 use std::{
-    collections::{BTreeMap, HashMap, VecDeque},
-    ops::AddAssign,
+    collections::BTreeMap,
+    ops::{AddAssign, Range},
+    sync::Mutex,
 };
 
+use anyhow::Context;
+use clap::{builder::PossibleValue, ValueEnum};
+use core_affinity::CoreId;
 use crossbeam_channel::{bounded, Receiver};
 use crossbeam_utils::thread;
-use serde::{Serialize, Serializer};
+use rayon::{
+    iter::{ParallelBridge, ParallelIterator},
+    ThreadPoolBuilder,
+};
+use rustc_hash::FxHashMap;
+use serde::{ser::SerializeMap, Serialize, Serializer};
 
 use crate::{
+    bootstrap::{boot_group, BOOTSTRAP_GROUPS},
+    checksum::{ContigSum, InputDigest},
     cli::Config,
-    reader::{self, Base, Seq},
+    composition::CompositionSummary,
+    contig_groups::{GroupCounts, GroupSummary},
+    conversion::{Chemistry, Conversion},
+    cross_validation::cv_half,
+    fasta::{Base, Seq},
+    gtf::ClassLookup,
+    input_check::CharCounts,
+    kmer_gc::KmerGcTable,
+    performance,
+    reader::{self, split_targets, InputInfo, SeqBlock},
+    reference_id::derive_identifier,
+    regions::{Region, Strand},
+    repeat_scan::RepeatRes,
+    rescan::{count_kmers, rescan_selected},
+    rmsk::{MaskLookup, PartitionKmers, RepeatMaskRes},
+    shuffle::shuffle_seq,
+    summary::TargetSummary,
+    target_gc_length::TargetGcLength,
+    window_sample::WindowSample,
 };
 
 #[derive(Copy, Clone, Eq, PartialOrd, PartialEq, Hash)]
@@ -30,72 +59,817 @@ impl Serialize for GcHistKey {
     }
 }
 
+/// Largest read length for which dense count tables are used
+const MAX_DENSE_LEN: u32 = 1000;
+
+/// Maximum number of GC fraction bins used for long reads
+const LONG_READ_BINS: usize = 10001;
+
+/// Weighted counts of windows for each (AT, GC) pair.  For a read length L the pairs satisfy
+/// AT + GC <= L, so for moderate L the counts are kept in a dense triangular array indexed
+/// arithmetically rather than a hash.  In long read mode, counts for longer reads are collapsed
+/// into at most [`LONG_READ_BINS`] bins of GC fraction, keeping the weighted sum of AT + GC for
+/// each bin (in `n`) so that a bin is reported as the (AT, GC) pair for its mean window size
+pub enum GcCounts {
+    Dense { len: u32, v: Vec<f64> },
+    Sparse(FxHashMap<GcHistKey, f64>),
+    Binned { v: Vec<f64>, n: Vec<f64> },
+}
+
+impl GcCounts {
+    /// Approximate memory used by the counts for read length len (sparse tables are assumed to
+    /// stay at their initial size)
+    pub(crate) fn mem_size(len: u32, long_reads: bool) -> u64 {
+        let len = len as u64;
+        if len <= MAX_DENSE_LEN as u64 {
+            (len + 1) * (len + 2) / 2 * 8
+        } else if long_reads {
+            Self::n_bins(len as u32) as u64 * 16
+        } else {
+            len * 24
+        }
+    }
+
+    fn new(len: u32, long_reads: bool) -> Self {
+        if len <= MAX_DENSE_LEN {
+            let n = (len as usize + 1) * (len as usize + 2) / 2;
+            Self::Dense {
+                len,
+                v: vec![0.0; n],
+            }
+        } else if long_reads {
+            Self::Binned {
+                v: vec![0.0; Self::n_bins(len)],
+                n: vec![0.0; Self::n_bins(len)],
+            }
+        } else {
+            Self::Sparse(FxHashMap::with_capacity_and_hasher(
+                len as usize,
+                Default::default(),
+            ))
+        }
+    }
+
+    /// Number of GC fraction bins for read length len in long read mode
+    fn n_bins(len: u32) -> usize {
+        (len as usize + 1).min(LONG_READ_BINS)
+    }
+
+    /// GC fraction bin for (at, gc) with nb bins
+    #[inline]
+    fn gc_bin(nb: usize, at: u32, gc: u32) -> usize {
+        let n = (at + gc).max(1) as f64;
+        (gc as f64 * (nb - 1) as f64 / n).round() as usize
+    }
+
+    /// (AT, GC) pair reported for bin i with nb bins and mean window size n
+    fn bin_key(nb: usize, i: usize, n: f64) -> GcHistKey {
+        let n = n.round() as u32;
+        let gc = ((i as f64 * n as f64 / (nb - 1) as f64).round() as u32).min(n);
+        GcHistKey(n - gc, gc)
+    }
+
+    /// Index of (at, gc) in the dense array (rows of decreasing length for each value of at)
+    #[inline]
+    fn dense_idx(len: u32, at: u32, gc: u32) -> usize {
+        assert!(at + gc <= len, "Base counts exceed read length");
+        let (l, a) = (len as usize, at as usize);
+        a * (l + 1) - a * a.saturating_sub(1) / 2 + gc as usize
+    }
+
+    #[inline]
+    fn add(&mut self, cts: (u32, u32), w: f64) {
+        match self {
+            Self::Dense { len, v } => v[Self::dense_idx(*len, cts.0, cts.1)] += w,
+            Self::Sparse(h) => *h.entry(GcHistKey(cts.0, cts.1)).or_insert(0.0) += w,
+            Self::Binned { v, n } => {
+                let i = Self::gc_bin(v.len(), cts.0, cts.1);
+                v[i] += w;
+                n[i] += w * (cts.0 + cts.1) as f64
+            }
+        }
+    }
+
+    fn add_counts(&mut self, other: &Self) {
+        match (self, other) {
+            (Self::Dense { v, .. }, Self::Dense { v: v1, .. }) => {
+                for (x, y) in v.iter_mut().zip(v1.iter()) {
+                    *x += y
+                }
+            }
+            (Self::Binned { v, n }, Self::Binned { v: v1, n: n1 }) => {
+                for (x, y) in v
+                    .iter_mut()
+                    .zip(v1.iter())
+                    .chain(n.iter_mut().zip(n1.iter()))
+                {
+                    *x += y
+                }
+            }
+            (s, o) => {
+                for (k, x) in o.iter() {
+                    s.add(k.counts(), x)
+                }
+            }
+        }
+    }
+
+    fn scale(&mut self, x: f64) {
+        match self {
+            Self::Dense { v, .. } => v.iter_mut().for_each(|y| *y *= x),
+            Self::Binned { v, n } => v.iter_mut().chain(n.iter_mut()).for_each(|y| *y *= x),
+            Self::Sparse(h) => h.values_mut().for_each(|y| *y *= x),
+        }
+    }
+
+    /// Number of (AT, GC) pairs with non-zero counts
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Dense { v, .. } | Self::Binned { v, .. } => {
+                v.iter().filter(|x| **x != 0.0).count()
+            }
+            Self::Sparse(h) => h.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over (AT, GC) pairs with non-zero counts in order of (AT, GC)
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (GcHistKey, f64)> + '_> {
+        match self {
+            Self::Dense { len, v } => Box::new(
+                (0..=*len)
+                    .flat_map(move |a| (0..=(*len - a)).map(move |g| GcHistKey(a, g)))
+                    .zip(v.iter().copied())
+                    .filter(|(_, x)| *x != 0.0),
+            ),
+            Self::Binned { v, n } => {
+                let mut u: Vec<_> = v
+                    .iter()
+                    .zip(n.iter())
+                    .enumerate()
+                    .filter(|(_, (x, _))| **x != 0.0)
+                    .map(|(i, (x, m))| (Self::bin_key(v.len(), i, m / x), *x))
+                    .collect();
+                u.sort_unstable_by_key(|(k, _)| k.counts());
+                // Bins with different mean sizes can give the same pair
+                u.dedup_by(|(k, x), (k1, x1)| {
+                    let same = k == k1;
+                    if same {
+                        *x1 += *x
+                    }
+                    same
+                });
+                Box::new(u.into_iter())
+            }
+            Self::Sparse(h) => {
+                // Sorted so that output does not depend on the hash order
+                let mut v: Vec<_> = h.iter().map(|(k, x)| (*k, *x)).collect();
+                v.sort_unstable_by_key(|(k, _)| k.counts());
+                Box::new(v.into_iter())
+            }
+        }
+    }
+}
+
+/// Counts are only fractional when region weights are used, so whole counts are written as
+/// integers to keep the output unchanged otherwise
+impl Serialize for GcCounts {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut m = serializer.serialize_map(Some(self.len()))?;
+        for (k, v) in self.iter() {
+            if v.fract() == 0.0 && v >= 0.0 && v < u64::MAX as f64 {
+                m.serialize_entry(&k, &(v as u64))?
+            } else {
+                m.serialize_entry(&k, &v)?
+            }
+        }
+        m.end()
+    }
+}
+
 #[derive(Serialize)]
 pub struct GcHist {
-    counts: HashMap<GcHistKey, u64>,
+    counts: GcCounts,
     #[serde(skip_serializing_if = "Option::is_none")]
-    bisulfite_counts: Option<HashMap<GcHistKey, u64>>,
+    bisulfite_counts: Option<GcCounts>,
 }
 
 impl GcHist {
     fn add(&mut self, other: &Self) {
-        for (k, v) in other.counts.iter() {
-            let e = self.counts.entry(*k).or_insert(0);
-            *e += v
+        self.counts.add_counts(&other.counts);
+        if let Some(ct) = self.bisulfite_counts.as_mut() {
+            ct.add_counts(other.bisulfite_counts.as_ref().unwrap())
         }
+    }
+
+    fn scale(&mut self, x: f64) {
+        self.counts.scale(x);
         if let Some(ct) = self.bisulfite_counts.as_mut() {
-            let ct1 = other.bisulfite_counts.as_ref().unwrap();
-            for (k, v) in ct1.iter() {
-                let e = ct.entry(*k).or_insert(0);
-                *e += v
-            }
+            ct.scale(x)
         }
     }
 
-    fn new(bisulfite: bool) -> Self {
-        let bisulfite_counts = if bisulfite {
-            Some(HashMap::new())
-        } else {
-            None
-        };
+    fn new(len: u32, bisulfite: bool, long_reads: bool) -> Self {
         Self {
-            counts: HashMap::new(),
-            bisulfite_counts,
+            counts: GcCounts::new(len, long_reads),
+            bisulfite_counts: if bisulfite {
+                Some(GcCounts::new(len, long_reads))
+            } else {
+                None
+            },
         }
     }
-    pub fn hash(&self) -> &HashMap<GcHistKey, u64> {
+
+    pub fn counts(&self) -> &GcCounts {
         &self.counts
     }
 
-    pub fn bisulfite_hash(&self) -> Option<&HashMap<GcHistKey, u64>> {
+    pub fn bisulfite_counts(&self) -> Option<&GcCounts> {
         self.bisulfite_counts.as_ref()
     }
 }
+/// Number of bins for skew histograms, covering -1 to 1
+pub const SKEW_BINS: usize = 200;
+
+/// Histograms of GC skew (G-C)/(G+C) and AT skew (A-T)/(A+T) for windows passing the threshold
+#[derive(Clone, Serialize)]
+pub struct SkewHist {
+    gc_skew: Vec<f64>,
+    at_skew: Vec<f64>,
+}
+
+impl SkewHist {
+    fn new() -> Self {
+        Self {
+            gc_skew: vec![0.0; SKEW_BINS],
+            at_skew: vec![0.0; SKEW_BINS],
+        }
+    }
+
+    fn bin(x: f64) -> usize {
+        (((x + 1.0) * 0.5 * SKEW_BINS as f64) as usize).min(SKEW_BINS - 1)
+    }
+
+    /// Centre of bin i
+    pub fn bin_centre(i: usize) -> f64 {
+        (i as f64 + 0.5) * 2.0 / SKEW_BINS as f64 - 1.0
+    }
+
+    fn add(&mut self, skews: (Option<f64>, Option<f64>), w: f64) {
+        if let Some(x) = skews.0 {
+            self.gc_skew[Self::bin(x)] += w
+        }
+        if let Some(x) = skews.1 {
+            self.at_skew[Self::bin(x)] += w
+        }
+    }
+
+    fn add_hist(&mut self, other: &Self) {
+        for (a, b) in self.gc_skew.iter_mut().zip(other.gc_skew.iter()) {
+            *a += *b
+        }
+        for (a, b) in self.at_skew.iter_mut().zip(other.at_skew.iter()) {
+            *a += *b
+        }
+    }
+
+    fn scale(&mut self, x: f64) {
+        self.gc_skew.iter_mut().for_each(|a| *a *= x);
+        self.at_skew.iter_mut().for_each(|a| *a *= x)
+    }
+
+    pub fn gc_skew(&self) -> &[f64] {
+        &self.gc_skew
+    }
+
+    pub fn at_skew(&self) -> &[f64] {
+        &self.at_skew
+    }
+}
+
+/// Base counts (A, C, T, G) at each position of windows passing the threshold, for reads from
+/// the forward strand.  Runs of consecutive windows are handled together by sliding the range of
+/// sequence positions that fall at each read position
+pub struct PerBase {
+    counts: Vec<[f64; 4]>,
+}
+
+impl PerBase {
+    fn new(len: u32) -> Self {
+        Self {
+            counts: vec![[0.0; 4]; len as usize],
+        }
+    }
+
+    /// Add windows with the last base at offsets first..=last of s, each with weight w
+    fn add_run(&mut self, s: &[Base], first: usize, last: usize, w: f64) {
+        let len = self.counts.len() as i64;
+        let base = |p: i64| {
+            usize::try_from(p)
+                .ok()
+                .and_then(|p| s.get(p))
+                .map(|b| *b as usize)
+                .filter(|b| *b < 4)
+        };
+        let (first, last) = (first as i64, last as i64);
+        let mut c = [0u64; 4];
+        for p in first + 1 - len..=last + 1 - len {
+            if let Some(b) = base(p) {
+                c[b] += 1
+            }
+        }
+        for (j, v) in self.counts.iter_mut().enumerate() {
+            let j = j as i64;
+            if j > 0 {
+                if let Some(b) = base(first - len + j) {
+                    c[b] -= 1
+                }
+                if let Some(b) = base(last + 1 - len + j) {
+                    c[b] += 1
+                }
+            }
+            for (x, y) in v.iter_mut().zip(c.iter()) {
+                *x += w * *y as f64
+            }
+        }
+    }
+
+    fn add_per_base(&mut self, other: &Self) {
+        for (a, b) in self.counts.iter_mut().zip(other.counts.iter()) {
+            for (x, y) in a.iter_mut().zip(b.iter()) {
+                *x += *y
+            }
+        }
+    }
+
+    fn scale(&mut self, x: f64) {
+        self.counts.iter_mut().flatten().for_each(|a| *a *= x)
+    }
+
+    /// Proportions of (A, C, T, G) at each position for reads from both strands, where position
+    /// j of a read from the - strand is the complement of the base at position len - 1 - j
+    pub fn proportions(&self) -> Vec<[f64; 4]> {
+        let n = self.counts.len();
+        (0..n)
+            .map(|j| {
+                let (f, r) = (&self.counts[j], &self.counts[n - 1 - j]);
+                // Complement of A, C, T, G is found at index ^ 2
+                let v: [f64; 4] = std::array::from_fn(|b| f[b] + r[b ^ 2]);
+                let t = v.iter().sum::<f64>().max(f64::MIN_POSITIVE);
+                v.map(|x| x / t)
+            })
+            .collect()
+    }
+}
+
+/// Gap content of windows overlapping the sequence: the number of windows passing the threshold
+/// with each count of gap characters, and the number of windows failing the threshold that would
+/// have passed if the gap characters were counted (rejected due to Ns) or that would have failed
+/// anyway because they extend into long gaps or past the contig ends.  Counts are not scaled when
+/// subsampling
+#[derive(Clone, Serialize)]
+pub struct NContent {
+    n_bases: Vec<u64>,
+    rejected_n: u64,
+    rejected_gap: u64,
+}
+
+impl NContent {
+    fn new(len: u32, threshold: u32) -> Self {
+        Self {
+            n_bases: vec![0; (len + 1 - threshold.min(len)) as usize],
+            rejected_n: 0,
+            rejected_gap: 0,
+        }
+    }
+
+    fn add(&mut self, c: &Counts) {
+        if c.passes() {
+            self.n_bases[c.gaps as usize] += 1
+        } else if c.passes_with_gaps() {
+            self.rejected_n += 1
+        } else {
+            self.rejected_gap += 1
+        }
+    }
+
+    fn add_n_content(&mut self, other: &Self) {
+        for (a, b) in self.n_bases.iter_mut().zip(other.n_bases.iter()) {
+            *a += *b
+        }
+        self.rejected_n += other.rejected_n;
+        self.rejected_gap += other.rejected_gap;
+    }
+
+    /// Number of passing windows with i gap characters is at index i
+    pub fn n_bases(&self) -> &[u64] {
+        &self.n_bases
+    }
+
+    pub fn rejected_n(&self) -> u64 {
+        self.rejected_n
+    }
+
+    pub fn rejected_gap(&self) -> u64 {
+        self.rejected_gap
+    }
+}
+
+/// Number of windows overlapping the sequence that passed or failed the base count threshold, and
+/// the number of passing windows that overhang the ends of the sequence
+#[derive(Default, Debug, Copy, Clone, Serialize)]
+pub struct WindowCounts {
+    passed: u64,
+    skipped: u64,
+    partial: u64,
+}
+
+impl WindowCounts {
+    pub fn passed(&self) -> u64 {
+        self.passed
+    }
+
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Passing windows overhanging the ends of the sequence (included in passed)
+    pub fn partial(&self) -> u64 {
+        self.partial
+    }
+
+    fn add(&mut self, other: &Self) {
+        self.passed += other.passed;
+        self.skipped += other.skipped;
+        self.partial += other.partial;
+    }
+}
+
 #[derive(Serialize)]
 pub struct GcRes {
     read_length_specific_counts: BTreeMap<u32, GcHist>,
+    contig_window_counts: BTreeMap<Box<str>, BTreeMap<u32, WindowCounts>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    skew: Option<BTreeMap<u32, SkewHist>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n_content: Option<BTreeMap<u32, NContent>>,
+    #[serde(skip)]
+    per_base: Option<BTreeMap<u32, PerBase>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    contigs: Option<Vec<ContigSum>>,
+    #[serde(rename = "repeat_scan", skip_serializing_if = "Option::is_none")]
+    repeats: Option<RepeatRes>,
+    #[serde(rename = "composition", skip_serializing_if = "Option::is_none")]
+    composition_summary: Option<CompositionSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target_gc_length: Option<TargetGcLength>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    repeatmasker: Option<RepeatMaskRes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    contig_groups: Option<Vec<GroupSummary>>,
+    #[serde(skip)]
+    null: Option<Box<GcRes>>,
+    #[serde(skip)]
+    repeat_parts: Option<Box<[GcRes; 2]>>,
+    #[serde(skip)]
+    class_parts: Option<Box<[GcRes; 4]>>,
+    #[serde(skip)]
+    cv_parts: Option<Box<[GcRes; 2]>>,
+    #[serde(skip)]
+    boot_parts: Option<Vec<GcRes>>,
+    #[serde(skip)]
+    group_parts: Option<Vec<GcRes>>,
+    #[serde(skip)]
+    off_target: Option<Box<GcRes>>,
+    #[serde(skip)]
+    padded: Option<Box<GcRes>>,
+    #[serde(skip)]
+    kmer_gc: Option<KmerGcTable>,
+    #[serde(skip)]
+    sweep: Option<Vec<(f64, GcRes)>>,
+    #[serde(skip)]
+    composition: Option<CharCounts>,
+    #[serde(skip)]
+    target_summary: Option<TargetSummary>,
+    #[serde(skip)]
+    input_digest: Option<InputDigest>,
+    #[serde(skip)]
+    window_sample: Option<WindowSample>,
+    #[serde(skip)]
+    identifier: Option<String>,
 }
 
 impl GcRes {
-    pub fn new(rl: &[u32], bisulfite: bool) -> Self {
-        let inner: BTreeMap<_, _> = rl.iter().map(|l| (*l, GcHist::new(bisulfite))).collect();
+    pub fn new(rl: &[u32], bisulfite: bool, long_reads: bool) -> Self {
+        let inner: BTreeMap<_, _> = rl
+            .iter()
+            .map(|l| (*l, GcHist::new(*l, bisulfite, long_reads)))
+            .collect();
         Self {
             read_length_specific_counts: inner,
+            contig_window_counts: BTreeMap::new(),
+            skew: None,
+            n_content: None,
+            per_base: None,
+            contigs: None,
+            repeats: None,
+            composition_summary: None,
+            target_gc_length: None,
+            repeatmasker: None,
+            contig_groups: None,
+            null: None,
+            repeat_parts: None,
+            class_parts: None,
+            cv_parts: None,
+            boot_parts: None,
+            group_parts: None,
+            off_target: None,
+            padded: None,
+            kmer_gc: None,
+            sweep: None,
+            composition: None,
+            target_summary: None,
+            input_digest: None,
+            window_sample: None,
+            identifier: None,
         }
     }
 
-    fn add_count(&mut self, ix: u32, cts: (u32, u32)) {
-        let e = self
+    /// Also collect counts (non-converted only) from shuffled sequences
+    pub fn with_null(mut self) -> Self {
+        let rl: Vec<u32> = self.read_length_specific_counts.keys().copied().collect();
+        self.null = Some(Box::new(Self::new(&rl, false, self.long_reads())));
+        self
+    }
+
+    /// True if counts for long reads are binned by GC fraction
+    fn long_reads(&self) -> bool {
+        self.read_length_specific_counts
+            .values()
+            .any(|h| matches!(h.counts, GcCounts::Binned { .. }))
+    }
+
+    fn new_like(&self) -> Self {
+        let rl: Vec<u32> = self.read_length_specific_counts.keys().copied().collect();
+        let bisulfite = self
             .read_length_specific_counts
+            .values()
+            .any(|h| h.bisulfite_counts.is_some());
+        Self::new(&rl, bisulfite, self.long_reads())
+    }
+
+    /// Also collect counts separately for windows in and outside repeats
+    pub fn with_repeat_parts(mut self) -> Self {
+        self.repeat_parts = Some(Box::new([self.new_like(), self.new_like()]));
+        self
+    }
+
+    /// Also collect counts separately for each annotation class
+    pub fn with_class_parts(mut self) -> Self {
+        self.class_parts = Some(Box::new(std::array::from_fn(|_| self.new_like())));
+        self
+    }
+
+    /// Also collect counts separately for the two halves of a cross-validation split
+    pub fn with_cv_parts(mut self) -> Self {
+        self.cv_parts = Some(Box::new([self.new_like(), self.new_like()]));
+        self
+    }
+
+    /// Also collect counts separately for each bootstrap group
+    pub fn with_boot_parts(mut self) -> Self {
+        self.boot_parts = Some((0..BOOTSTRAP_GROUPS).map(|_| self.new_like()).collect());
+        self
+    }
+
+    /// Also collect counts separately for each of n contig groups
+    pub fn with_group_parts(mut self, n: usize) -> Self {
+        self.group_parts = Some((0..n).map(|_| self.new_like()).collect());
+        self
+    }
+
+    /// Also collect counts for off target windows
+    pub fn with_off_target(mut self) -> Self {
+        self.off_target = Some(Box::new(self.new_like()));
+        self
+    }
+
+    /// Also collect counts for padded targets
+    pub fn with_padded(mut self) -> Self {
+        self.padded = Some(Box::new(self.new_like()));
+        self
+    }
+
+    /// Also collect counts using each of the thresholds in sweep
+    pub fn with_sweep(mut self, sweep: &[f64]) -> Self {
+        self.sweep = Some(sweep.iter().map(|t| (*t, self.new_like())).collect());
+        self
+    }
+
+    /// Also collect skew histograms
+    pub fn with_skew(mut self) -> Self {
+        self.skew = Some(
+            self.read_length_specific_counts
+                .keys()
+                .map(|l| (*l, SkewHist::new()))
+                .collect(),
+        );
+        self
+    }
+
+    /// Also collect base composition by position in windows
+    pub fn with_per_base(mut self) -> Self {
+        self.per_base = Some(
+            self.read_length_specific_counts
+                .keys()
+                .map(|l| (*l, PerBase::new(*l)))
+                .collect(),
+        );
+        self
+    }
+
+    /// Also keep a random sample of n windows
+    pub fn with_window_sample(mut self, n: usize, seed: u64) -> Self {
+        self.window_sample = Some(WindowSample::new(n, seed));
+        self
+    }
+
+    /// Sample of evaluated windows (if requested)
+    pub fn window_sample(&self) -> Option<&WindowSample> {
+        self.window_sample.as_ref()
+    }
+
+    /// Also collect gap content of windows
+    pub fn with_n_content(mut self, threshold: f64) -> Self {
+        let rl: Vec<u32> = self.read_length_specific_counts.keys().copied().collect();
+        let t = base_thresholds(&rl, threshold);
+        self.n_content = Some(
+            rl.iter()
+                .zip(t.iter())
+                .map(|(l, t)| (*l, NContent::new(*l, *t)))
+                .collect(),
+        );
+        self
+    }
+
+    /// Gap content of windows for read length ix (if requested)
+    pub fn n_content(&self, ix: u32) -> Option<&NContent> {
+        self.n_content.as_ref().and_then(|h| h.get(&ix))
+    }
+
+    /// Base composition by position for read length ix (if requested)
+    pub fn per_base(&self, ix: u32) -> Option<&PerBase> {
+        self.per_base.as_ref().and_then(|h| h.get(&ix))
+    }
+
+    /// Skew histograms for read length ix (if requested)
+    pub fn skew(&self, ix: u32) -> Option<&SkewHist> {
+        self.skew.as_ref().and_then(|h| h.get(&ix))
+    }
+
+    /// Contig lengths and checksums (if requested)
+    pub fn contigs(&self) -> Option<&[ContigSum]> {
+        self.contigs.as_deref()
+    }
+
+    /// Repeat runs and totals (if requested)
+    pub fn repeats(&self) -> Option<&RepeatRes> {
+        self.repeats.as_ref()
+    }
+
+    /// Repeat annotation summary (if requested)
+    pub fn repeatmasker(&self) -> Option<&RepeatMaskRes> {
+        self.repeatmasker.as_ref()
+    }
+
+    /// Results from shuffled sequences
+    pub fn null(&self) -> Option<&GcRes> {
+        self.null.as_deref()
+    }
+
+    /// Results for windows (outside, in) repeats
+    pub fn repeat_parts(&self) -> Option<&[GcRes; 2]> {
+        self.repeat_parts.as_deref()
+    }
+
+    /// Results for the two halves of a cross-validation split if requested
+    pub fn cv_parts(&self) -> Option<&[GcRes; 2]> {
+        self.cv_parts.as_deref()
+    }
+
+    /// Results for each of the bootstrap block groups if requested, from which the bootstrap
+    /// replicates are resampled
+    pub fn boot_parts(&self) -> Option<&[GcRes]> {
+        self.boot_parts.as_deref()
+    }
+
+    /// Results for each contig group if requested
+    pub fn group_parts(&self) -> Option<&[GcRes]> {
+        self.group_parts.as_deref()
+    }
+
+    /// Contig, base and kmer counts for each contig group if requested
+    pub fn contig_groups(&self) -> Option<&[GroupSummary]> {
+        self.contig_groups.as_deref()
+    }
+
+    /// Results for each annotation class (in the order of [`crate::gtf::FEATURE_CLASSES`]) if requested
+    pub fn class_parts(&self) -> Option<&[GcRes; 4]> {
+        self.class_parts.as_deref()
+    }
+
+    /// Results for off target windows
+    pub fn off_target(&self) -> Option<&GcRes> {
+        self.off_target.as_deref()
+    }
+
+    /// Results for padded targets
+    pub fn padded(&self) -> Option<&GcRes> {
+        self.padded.as_deref()
+    }
+
+    /// Results for each threshold in the threshold sweep
+    pub fn sweep(&self) -> Option<&[(f64, GcRes)]> {
+        self.sweep.as_deref()
+    }
+
+    /// Kmer uniqueness by window GC (if requested)
+    pub fn kmer_gc(&self) -> Option<&KmerGcTable> {
+        self.kmer_gc.as_ref()
+    }
+
+    /// Reference identifier, from --identifier or derived from the contigs
+    pub fn identifier(&self) -> Option<&str> {
+        self.identifier.as_deref()
+    }
+
+    /// Input composition (if the whole input was read)
+    pub fn composition(&self) -> Option<&CharCounts> {
+        self.composition.as_ref()
+    }
+
+    /// Target coverage summary (if targets were given)
+    pub fn target_summary(&self) -> Option<&TargetSummary> {
+        self.target_summary.as_ref()
+    }
+
+    /// Composition summary of the input and targets (if requested)
+    pub fn composition_summary(&self) -> Option<&CompositionSummary> {
+        self.composition_summary.as_ref()
+    }
+
+    pub fn target_gc_length(&self) -> Option<&TargetGcLength> {
+        self.target_gc_length.as_ref()
+    }
+
+    /// Digest of the raw input (if requested)
+    pub fn input_digest(&self) -> Option<&InputDigest> {
+        self.input_digest.as_ref()
+    }
+
+    fn add_window_counts(&mut self, ctg: &str, rl: &[u32], wc: &[WindowCounts]) {
+        if !self.contig_window_counts.contains_key(ctg) {
+            self.contig_window_counts
+                .insert(ctg.to_owned().into_boxed_str(), BTreeMap::new());
+        }
+        let h = self.contig_window_counts.get_mut(ctg).unwrap();
+        for (l, c) in rl.iter().zip(wc.iter()) {
+            h.entry(*l).or_default().add(c)
+        }
+    }
+
+    /// Per contig, per read length counts of windows passing and failing the threshold
+    pub fn contig_window_counts(
+        &self,
+    ) -> impl Iterator<Item = (&str, &BTreeMap<u32, WindowCounts>)> {
+        self.contig_window_counts
+            .iter()
+            .map(|(c, h)| (c.as_ref(), h))
+    }
+
+    /// Add a window with counts cts for read length ix.  w is the (possibly fractional)
+    /// contribution of the window
+    pub(crate) fn add_count(&mut self, ix: u32, cts: (u32, u32), w: f64) {
+        self.read_length_specific_counts
             .get_mut(&ix)
             .unwrap()
             .counts
-            .entry(GcHistKey(cts.0, cts.1))
-            .or_insert(0);
-        *e += 1
+            .add(cts, w)
     }
 
-    fn add_bs_count(&mut self, ix: u32, cts: (u32, u32)) {
+    pub(crate) fn add_skew(&mut self, ix: u32, skews: (Option<f64>, Option<f64>), w: f64) {
+        if let Some(h) = self.skew.as_mut() {
+            h.get_mut(&ix).unwrap().add(skews, w)
+        }
+    }
+
+    pub(crate) fn add_bs_count(&mut self, ix: u32, cts: (u32, u32), w: f64) {
         if let Some(c) = self
             .read_length_specific_counts
             .get_mut(&ix)
@@ -103,8 +877,51 @@ impl GcRes {
             .bisulfite_counts
             .as_mut()
         {
-            let e = c.entry(GcHistKey(cts.0, cts.1)).or_insert(0);
-            *e += 1
+            c.add(cts, w)
+        }
+    }
+
+    /// Multiply all histogram counts by x (window counts are not changed)
+    pub(crate) fn scale(&mut self, x: f64) {
+        for h in self.read_length_specific_counts.values_mut() {
+            h.scale(x)
+        }
+        for h in self.skew.iter_mut().flat_map(|s| s.values_mut()) {
+            h.scale(x)
+        }
+        for h in self.per_base.iter_mut().flat_map(|s| s.values_mut()) {
+            h.scale(x)
+        }
+        if let Some(r) = self.null.as_mut() {
+            r.scale(x)
+        }
+        for r in self
+            .repeat_parts
+            .iter_mut()
+            .chain(self.cv_parts.iter_mut())
+            .flat_map(|p| p.iter_mut())
+        {
+            r.scale(x)
+        }
+        for r in self.class_parts.iter_mut().flat_map(|p| p.iter_mut()) {
+            r.scale(x)
+        }
+        for r in self
+            .group_parts
+            .iter_mut()
+            .chain(self.boot_parts.iter_mut())
+            .flatten()
+        {
+            r.scale(x)
+        }
+        for r in [&mut self.off_target, &mut self.padded]
+            .into_iter()
+            .flatten()
+        {
+            r.scale(x)
+        }
+        for (_, r) in self.sweep.iter_mut().flat_map(|v| v.iter_mut()) {
+            r.scale(x)
         }
     }
 
@@ -127,40 +944,98 @@ impl AddAssign for GcRes {
             assert_eq!(*p_key, *q_key);
             p_val.add(q_val)
         }
+        for (ctg, h) in rhs.contig_window_counts.iter() {
+            let e = self.contig_window_counts.entry(ctg.clone()).or_default();
+            for (l, c) in h.iter() {
+                e.entry(*l).or_default().add(c)
+            }
+        }
+        if let (Some(a), Some(b)) = (self.skew.as_mut(), rhs.skew.as_ref()) {
+            for (p, q) in a.values_mut().zip(b.values()) {
+                p.add_hist(q)
+            }
+        }
+        if let (Some(a), Some(b)) = (self.per_base.as_mut(), rhs.per_base.as_ref()) {
+            for (p, q) in a.values_mut().zip(b.values()) {
+                p.add_per_base(q)
+            }
+        }
+        if let (Some(a), Some(b)) = (self.n_content.as_mut(), rhs.n_content.as_ref()) {
+            for (p, q) in a.values_mut().zip(b.values()) {
+                p.add_n_content(q)
+            }
+        }
+        if let (Some(a), Some(b)) = (self.window_sample.as_mut(), rhs.window_sample) {
+            a.merge(b)
+        }
+        if let (Some(a), Some(b)) = (self.null.as_mut(), rhs.null) {
+            **a += *b
+        }
+        if let (Some(a), Some(b)) = (self.repeat_parts.as_mut(), rhs.repeat_parts) {
+            let [b0, b1] = *b;
+            a[0] += b0;
+            a[1] += b1
+        }
+        if let (Some(a), Some(b)) = (self.class_parts.as_mut(), rhs.class_parts) {
+            for (p, q) in a.iter_mut().zip(*b) {
+                *p += q
+            }
+        }
+        if let (Some(a), Some(b)) = (self.cv_parts.as_mut(), rhs.cv_parts) {
+            let [b0, b1] = *b;
+            a[0] += b0;
+            a[1] += b1
+        }
+        if let (Some(a), Some(b)) = (self.group_parts.as_mut(), rhs.group_parts) {
+            for (p, q) in a.iter_mut().zip(b) {
+                *p += q
+            }
+        }
+        if let (Some(a), Some(b)) = (self.boot_parts.as_mut(), rhs.boot_parts) {
+            for (p, q) in a.iter_mut().zip(b) {
+                *p += q
+            }
+        }
+        if let (Some(a), Some(b)) = (self.off_target.as_mut(), rhs.off_target) {
+            **a += *b
+        }
+        if let (Some(a), Some(b)) = (self.padded.as_mut(), rhs.padded) {
+            **a += *b
+        }
+        if let (Some(a), Some(b)) = (self.sweep.as_mut(), rhs.sweep) {
+            for ((_, p), (_, q)) in a.iter_mut().zip(b) {
+                *p += q
+            }
+        }
     }
 }
 
 #[derive(Copy, Clone)]
 struct Counts {
     counts: [u32; 4],
+    /// Gap characters within the sequence (not counting positions past the sequence ends)
+    gaps: u32,
+    /// CpG dinucleotides within the window
+    cpg: u32,
+    /// Positions of the window within the sequence (less than the read length for partial
+    /// windows)
+    span: u32,
     threshold: u32,
 }
 
 impl Counts {
-    fn new(threshold: u32) -> Self {
-        assert!(threshold > 0);
-        Self {
-            counts: [0; 4],
-            threshold,
-        }
-    }
-
-    fn remove_base(&mut self, base: &Base) {
-        if !base.is_gap() {
-            let i = *base as usize;
-            assert!(self.counts[i] > 0);
-            self.counts[i] -= 1
-        }
+    fn passes(&self) -> bool {
+        self.counts.iter().sum::<u32>() >= self.threshold
     }
 
-    fn add_base(&mut self, base: &Base) {
-        if !base.is_gap() {
-            self.counts[*base as usize] += 1
-        }
+    /// True if the window would pass the threshold if gap characters within the sequence were
+    /// counted as valid bases
+    fn passes_with_gaps(&self) -> bool {
+        self.counts.iter().sum::<u32>() + self.gaps >= self.threshold
     }
 
     fn get_counts(&self) -> Option<(u32, u32)> {
-        if self.counts.iter().sum::<u32>() >= self.threshold {
+        if self.passes() {
             Some((
                 self.counts[Base::A as usize] + self.counts[Base::T as usize],
                 self.counts[Base::C as usize] + self.counts[Base::G as usize],
@@ -170,21 +1045,55 @@ impl Counts {
         }
     }
 
-    fn get_bs_counts(&self) -> Option<((u32, u32), (u32, u32))> {
-        if self.counts.iter().sum::<u32>() >= self.threshold {
-            Some((
-                (self.counts[Base::T as usize], self.counts[Base::C as usize]),
-                (self.counts[Base::A as usize], self.counts[Base::G as usize]),
-            ))
+    /// Threshold for a window of length l with proportion threshold, scaled for partial windows
+    /// with [`EndPolicy::Shrink`]
+    fn scaled_threshold(&self, l: u32, threshold: u32, shrink: bool) -> u32 {
+        if shrink && self.span < l {
+            ((self.span as f64) * (threshold as f64) / (l as f64))
+                .ceil()
+                .max(1.0) as u32
+        } else {
+            threshold
+        }
+    }
+
+    /// (GC skew, AT skew), with None where the denominator is zero
+    fn skews(&self) -> (Option<f64>, Option<f64>) {
+        let skew = |a: u32, b: u32| (a + b > 0).then(|| (a as f64 - b as f64) / (a + b) as f64);
+        let c = &self.counts;
+        (
+            skew(c[Base::G as usize], c[Base::C as usize]),
+            skew(c[Base::A as usize], c[Base::T as usize]),
+        )
+    }
+
+    /// (weak, strong, convertible) base counts for reads from the converted - and + strands.
+    /// Unconverted bases are counted as strong bases, and with TAPS converted bases are counted
+    /// as weak bases
+    fn get_bs_counts(&self, chemistry: Chemistry) -> Option<[(u32, u32, u32); 2]> {
+        if self.passes() {
+            let [a, c, t, g] =
+                [Base::A, Base::C, Base::T, Base::G].map(|b| self.counts[b as usize]);
+            Some(if chemistry.converts_methylated() {
+                [(a + t, c, g), (a + t, g, c)]
+            } else {
+                [(t, c, g), (a, g, c)]
+            })
         } else {
             None
         }
     }
 }
 
+/// Number of window end positions handled per block of prefix sums
+const PREFIX_BLOCK: usize = 1 << 16;
+
 struct Work {
-    buf: VecDeque<Base>,
-    counts: Vec<Counts>,
+    /// Cumulative base counts (A, C, T, G, gap) and CpG count (by the position of the G) for a
+    /// block of positions, preceded by the max_len positions before the block
+    cum: Vec<[u32; 6]>,
+    thresholds: Vec<u32>,
+    max_len: usize,
 }
 
 impl Work {
@@ -194,115 +1103,964 @@ impl Work {
             .max()
             .map(|l| *l as usize)
             .expect("Empty read length vector");
-        let mut buf = VecDeque::with_capacity(max_len);
-        buf.resize_with(max_len, Base::default);
-        let counts: Vec<_> = read_len
-            .iter()
-            .map(|l| Counts::new(((*l as f64) * threshold).ceil() as u32))
-            .collect();
+        Self {
+            cum: Vec::with_capacity(PREFIX_BLOCK + max_len + 1),
+            thresholds: base_thresholds(read_len, threshold),
+            max_len,
+        }
+    }
+}
+
+/// Minimum number of non gap bases for each read length
+fn base_thresholds(read_len: &[u32], threshold: f64) -> Vec<u32> {
+    read_len
+        .iter()
+        .map(|l| {
+            let t = ((*l as f64) * threshold).ceil() as u32;
+            assert!(t > 0);
+            t
+        })
+        .collect()
+}
+
+/// A single evaluated window, as passed to the callback in [`process_windows`]
+#[derive(Debug, Copy, Clone)]
+pub struct Window<'a> {
+    contig: &'a str,
+    pos: u64,
+    read_length: u32,
+    at: u32,
+    gc: u32,
+}
+
+impl<'a> Window<'a> {
+    pub fn contig(&self) -> &'a str {
+        self.contig
+    }
 
-        Self { buf, counts }
+    /// Start of window (zero offset from start of contig). Windows overhanging the start of a
+    /// contig are reported at position 0
+    pub fn pos(&self) -> u64 {
+        self.pos
     }
 
-    fn clear(&mut self) {
-        let l = self.buf.len();
-        self.buf.clear();
-        self.buf.resize_with(l, Base::default);
-        for c in self.counts.iter_mut() {
-            c.counts = [0, 0, 0, 0];
+    pub fn read_length(&self) -> u32 {
+        self.read_length
+    }
+
+    /// Returns (AT, GC) counts
+    pub fn counts(&self) -> (u32, u32) {
+        (self.at, self.gc)
+    }
+}
+
+/// Visit windows for all read lengths over s with the last base in eval, calling f(read length
+/// index, offset of last base of window in s, counts) for each window
+fn process_seq<F>(cfg: &Config, s: &Seq, eval: &Range<u64>, work: &mut Work, mut f: F)
+where
+    F: FnMut(usize, usize, &Counts),
+{
+    let rl = cfg.read_lengths();
+    let stride = cfg.stride() as u64;
+    let policy = cfg.end_policy();
+    let max_len = work.max_len;
+    let cum = &mut work.cum;
+    // Windows overhanging the end of the sequence are also visited
+    let len = s.len();
+    let total = len + max_len;
+    let rel = |x: u64| x.saturating_sub(s.start()).min(total as u64) as usize;
+    let (eval_start, eval_end) = (rel(eval.start), rel(eval.end));
+
+    // cum[k] holds the base counts for s[..p] where p = k + i - max_len for the block starting at
+    // i, with prefixes before the start of the sequence being empty
+    cum.clear();
+    cum.resize(max_len + 1, [0; 6]);
+    let mut i = 0;
+    while i < eval_end {
+        let end = (i + PREFIX_BLOCK).min(eval_end);
+        let mut c = *cum.last().unwrap();
+        for j in i..end {
+            // N and Other are both counted as gaps
+            if let Some(b) = s.get(j) {
+                c[(*b as usize).min(4)] += 1;
+                if *b == Base::G && j > 0 && s.get(j - 1) == Some(&Base::C) {
+                    c[5] += 1
+                }
+            }
+            cum.push(c)
+        }
+        for j in i.max(eval_start)..end.min(eval_end) {
+            // Only evaluate windows ending at multiples of the stride
+            if !(s.start() + j as u64).is_multiple_of(stride) {
+                continue;
+            }
+            let hi = &cum[j + 1 + max_len - i];
+            for (ix, (l, t)) in rl.iter().zip(work.thresholds.iter()).enumerate() {
+                let l = *l as usize;
+                // Positions of the window within the sequence
+                let span = (j + 1).min(len).saturating_sub((j + 1).saturating_sub(l)) as u32;
+                if policy == EndPolicy::Drop && (span as usize) < l {
+                    continue;
+                }
+                let k = j + 1 + max_len - i - l;
+                let lo = &cum[k];
+                let mut counts = Counts {
+                    counts: [hi[0] - lo[0], hi[1] - lo[1], hi[2] - lo[2], hi[3] - lo[3]],
+                    gaps: hi[4] - lo[4],
+                    // Do not count a CpG with the G at the first position of the window
+                    cpg: hi[5] - cum[k + 1][5],
+                    span,
+                    threshold: *t,
+                };
+                counts.threshold =
+                    counts.scaled_threshold(l as u32, *t, policy == EndPolicy::Shrink);
+                f(ix, j, &counts)
+            }
+        }
+        cum.drain(..end - i);
+        i = end
+    }
+}
+
+/// Tracks the target region closest to the window midpoint for weighting windows and for
+/// assigning them to a converted strand.  As windows for a given read length are visited in order,
+/// we keep a separate cursor per read length
+struct RegionLookup<'a> {
+    regions: &'a [Region],
+    cursor: Vec<usize>,
+}
+
+impl<'a> RegionLookup<'a> {
+    fn new(cfg: &'a Config, s: &Seq) -> Option<Self> {
+        let regions = cfg
+            .target_regions()
+            .filter(|r| r.is_weighted() || (cfg.bisulfite() && r.is_stranded()))
+            .and_then(|r| r.get(s.contig()))
+            .map(|r| r.regions())
+            .filter(|v| !v.is_empty())?;
+        let i = regions
+            .partition_point(|r| r.end() <= s.start())
+            .min(regions.len() - 1);
+        Some(Self {
+            regions,
+            cursor: vec![i; cfg.read_lengths().len()],
+        })
+    }
+
+    fn region(&mut self, ix: usize, mid: u64) -> &Region {
+        let dist = |r: &Region| {
+            if mid < r.start() {
+                r.start() - mid
+            } else if mid >= r.end() {
+                mid + 1 - r.end()
+            } else {
+                0
+            }
+        };
+        let j = &mut self.cursor[ix];
+        while *j + 1 < self.regions.len() && dist(&self.regions[*j + 1]) <= dist(&self.regions[*j])
+        {
+            *j += 1
         }
+        &self.regions[*j]
     }
 }
 
-fn process_seq(cfg: &Config, s: &Seq, res: &mut GcRes, work: &mut Work) {
+/// Add counts for a window for read length rl, returning false if the window did not pass the
+/// threshold
+fn add_window(
+    res: &mut GcRes,
+    rl: u32,
+    c: &Counts,
+    bisulfite: Option<&Conversion>,
+    region: Option<(f64, Strand)>,
+) -> bool {
+    if let Some(conv) = bisulfite {
+        if let (Some(cts), Some([minus, plus])) =
+            (c.get_counts(), c.get_bs_counts(conv.chemistry()))
+        {
+            let (w, strand) = region.unwrap_or((1.0, Strand::Unknown));
+            res.add_count(rl, cts, w);
+            let taps = conv.chemistry().converts_methylated();
+            let mut add_bs = |(weak, strong, n): (u32, u32, u32)| {
+                for &(k, p) in conv.unconverted(n, c.cpg).iter() {
+                    let weak = if taps { weak + n - k } else { weak };
+                    res.add_bs_count(rl, (weak, strong + k), w * p);
+                }
+            };
+            // Reads from the converted + strand are C depleted (G:A), and from the - strand
+            // are G depleted (C:T).  Unconverted Cs (Gs) are counted with the G (C) bases
+            if strand != Strand::Plus {
+                add_bs(minus)
+            }
+            if strand != Strand::Minus {
+                add_bs(plus)
+            }
+            true
+        } else {
+            false
+        }
+    } else if let Some(cts) = c.get_counts() {
+        res.add_count(rl, cts, region.map(|(w, _)| w).unwrap_or(1.0));
+        true
+    } else {
+        false
+    }
+}
+
+/// Add windows from s to res.  If targets is set, target regions are used for window weights
+/// and strands
+fn add_seq(
+    cfg: &Config,
+    s: &Seq,
+    eval: &Range<u64>,
+    res: &mut GcRes,
+    work: &mut Work,
+    targets: bool,
+) {
     let rl = cfg.read_lengths();
-    work.clear();
-    let buf = &mut work.buf;
-    let ct = &mut work.counts;
-    let max_len = buf.len();
-    let bnone = [Base::default()];
-    let end = bnone.iter().cycle().take(max_len);
-
-    for b in s.iter().chain(end) {
-        // Decrement counts from bases at start of reads
-        for (l, c) in rl.iter().map(|l| *l as usize).zip(ct.iter_mut()) {
-            assert!(l <= max_len);
-            c.remove_base(buf.get(max_len - l).unwrap());
-        }
-        // Remove base from start and add new base to end
-        buf.pop_front();
-        buf.push_back(*b);
-        // Increment counts
-        for (ix, c) in ct.iter_mut().enumerate() {
-            c.add_base(b);
-            if cfg.bisulfite() {
-                if let Some((cts1, cts2)) = c.get_bs_counts() {
-                    let cts = (cts1.0 + cts2.0, cts1.1 + cts2.1);
-                    res.add_count(rl[ix], cts);
-                    res.add_bs_count(rl[ix], cts1);
-                    res.add_bs_count(rl[ix], cts2);
+    let bisulfite = cfg.bisulfite().then(|| cfg.conversion());
+    let len = s.len();
+    let mut wc = vec![WindowCounts::default(); rl.len()];
+    // Current run of consecutive passing windows (first, last, weight) for each read length
+    let mut runs: Vec<Option<(usize, usize, f64)>> = vec![None; rl.len()];
+    let mut lookup = RegionLookup::new(cfg, s).filter(|_| targets);
+    let mut mask = cfg
+        .repeat_mask()
+        .filter(|_| res.repeat_parts.is_some())
+        .map(|m| MaskLookup::new(m, s.contig(), s.start(), rl.len()));
+    let mut classes = cfg
+        .annotation()
+        .filter(|_| res.class_parts.is_some())
+        .map(|a| ClassLookup::new(a, s.contig(), s.start(), rl.len()));
+    let group = cfg
+        .contig_groups()
+        .filter(|_| res.group_parts.is_some())
+        .and_then(|g| g.group(s.contig()));
+    let sweep: Vec<_> = res
+        .sweep
+        .iter()
+        .flatten()
+        .map(|(t, _)| base_thresholds(rl, *t))
+        .collect();
+    let shrink = cfg.end_policy() == EndPolicy::Shrink;
+    process_seq(cfg, s, eval, work, |ix, i, c| {
+        let mid = (s.start() + i as u64 + 1).saturating_sub((rl[ix] >> 1) as u64);
+        let region = lookup.as_mut().map(|lk| {
+            let r = lk.region(ix, mid);
+            (r.weight() as f64, r.strand())
+        });
+        if let Some(v) = res.sweep.as_mut() {
+            for ((_, r), t) in v.iter_mut().zip(sweep.iter()) {
+                let c = Counts {
+                    threshold: c.scaled_threshold(rl[ix], t[ix], shrink),
+                    ..*c
+                };
+                add_window(r, rl[ix], &c, bisulfite, region);
+            }
+        }
+        if add_window(res, rl[ix], c, bisulfite, region) {
+            if res.skew.is_some() {
+                let w = region.map(|(w, _)| w).unwrap_or(1.0);
+                res.add_skew(rl[ix], c.skews(), w)
+            }
+            if let (Some(ws), Some((at, gc))) = (res.window_sample.as_mut(), c.get_counts()) {
+                let pos = (s.start() + i as u64 + 1).saturating_sub(rl[ix] as u64);
+                ws.add(s.contig(), pos, rl[ix], at, gc)
+            }
+            if let Some(h) = res.per_base.as_mut() {
+                let w = region.map(|(w, _)| w).unwrap_or(1.0);
+                match runs[ix].as_mut() {
+                    Some((_, last, w1)) if *last + 1 == i && *w1 == w => *last = i,
+                    _ => {
+                        if let Some((first, last, w1)) = runs[ix].replace((i, i, w)) {
+                            h.get_mut(&rl[ix]).unwrap().add_run(s, first, last, w1)
+                        }
+                    }
                 }
-            } else if let Some(cts) = c.get_counts() {
-                res.add_count(rl[ix], cts)
+            }
+            if let Some(m) = mask.as_mut() {
+                let k = m.is_masked(ix, mid) as usize;
+                let parts = res.repeat_parts.as_mut().unwrap();
+                add_window(&mut parts[k], rl[ix], c, bisulfite, region);
+            }
+            if let Some(lk) = classes.as_mut() {
+                let k = lk.class(ix, mid) as usize;
+                let parts = res.class_parts.as_mut().unwrap();
+                add_window(&mut parts[k], rl[ix], c, bisulfite, region);
+            }
+            if let Some(k) = group {
+                let parts = res.group_parts.as_mut().unwrap();
+                add_window(&mut parts[k], rl[ix], c, bisulfite, region);
+            }
+            if let (Some(parts), Some(b)) = (res.cv_parts.as_mut(), cfg.cross_validate()) {
+                let k = cv_half(cfg.seed(), b, s.contig(), mid);
+                add_window(&mut parts[k], rl[ix], c, bisulfite, region);
+            }
+            if let (Some(parts), Some(b)) = (res.boot_parts.as_mut(), cfg.bootstrap()) {
+                let k = boot_group(cfg.seed(), b.block_size(), s.contig(), mid);
+                add_window(&mut parts[k], rl[ix], c, bisulfite, region);
+            }
+            wc[ix].passed += 1;
+            if c.span < rl[ix] {
+                wc[ix].partial += 1
+            }
+        } else if i + 1 < len + rl[ix] as usize {
+            // Only count windows that overlap the sequence
+            wc[ix].skipped += 1
+        }
+        if i + 1 < len + rl[ix] as usize {
+            if let Some(h) = res.n_content.as_mut() {
+                h.get_mut(&rl[ix]).unwrap().add(c)
+            }
+        }
+    });
+    if let Some(h) = res.per_base.as_mut() {
+        for (ix, r) in runs.into_iter().enumerate() {
+            if let Some((first, last, w)) = r {
+                h.get_mut(&rl[ix]).unwrap().add_run(s, first, last, w)
             }
         }
     }
+    res.add_window_counts(s.contig(), rl, &wc)
 }
 
-fn process_thread(cfg: &Config, ix: usize, rx: Receiver<Seq>) -> anyhow::Result<GcRes> {
-    debug!("Process task {ix} starting up");
-    let mut res = GcRes::new(cfg.read_lengths(), cfg.bisulfite());
-    let mut work = Work::new(cfg.read_lengths(), cfg.threshold());
-    while let Ok(s) = rx.recv() {
+fn process_block(
+    cfg: &Config,
+    ix: usize,
+    b: SeqBlock,
+    res: &mut GcRes,
+    work: &mut Work,
+) -> anyhow::Result<()> {
+    let t = performance::start();
+    b.for_each_seq(cfg, |s, eval| {
         trace!(
             "Process thread {ix} received new sequence of length {}",
             s.len()
         );
-        process_seq(cfg, &s, &mut res, &mut work);
+        let mut add = |s: &Seq| {
+            add_seq(cfg, s, eval, res, work, true);
+            if let Some(m) = cfg.null_model() {
+                let t = shuffle_seq(s, m, cfg.seed());
+                add_seq(cfg, &t, eval, res.null.as_mut().unwrap(), work, true)
+            }
+        };
+        match cfg.target_regions().filter(|_| cfg.split_targets()) {
+            Some(reg) => {
+                let (on, off) = split_targets(s, reg, 0);
+                add(&on);
+                if let Some(r) = res.off_target.as_mut() {
+                    add_seq(cfg, &off, eval, r, work, false)
+                }
+                if let (Some(p), Some(r)) = (cfg.padding(), res.padded.as_mut()) {
+                    let (on, _) = split_targets(s, reg, p);
+                    add_seq(cfg, &on, eval, r, work, true)
+                }
+            }
+            None => add(s),
+        }
+    })?;
+    performance::add_busy(ix, t);
+    Ok(())
+}
+
+fn process_thread(
+    cfg: &Config,
+    ix: usize,
+    rx: Receiver<SeqBlock>,
+    acc: &Mutex<GcRes>,
+) -> anyhow::Result<GcRes> {
+    debug!("Process task {ix} starting up");
+    let mut res = new_res(cfg);
+    let mut work = Work::new(cfg.read_lengths(), cfg.threshold());
+    let mut blocks = 0;
+    while let Ok(b) = rx.recv() {
+        process_block(cfg, ix, b, &mut res, &mut work)?;
+        // Fold results into the shared accumulator so that per thread contig counts stay small
+        blocks += 1;
+        if cfg.flush_blocks().is_some_and(|n| blocks % n == 0) {
+            trace!("Process thread {ix} flushing results after {blocks} blocks");
+            let r = std::mem::replace(&mut res, new_res(cfg));
+            *acc.lock().expect("Accumulator lock poisoned") += r
+        }
     }
     debug!("Process task {ix} shutting down");
     Ok(res)
 }
 
-pub fn process(cfg: &Config) -> anyhow::Result<GcRes> {
+fn window_thread<F>(cfg: &Config, ix: usize, rx: Receiver<SeqBlock>, f: &F) -> anyhow::Result<()>
+where
+    F: Fn(&Window),
+{
+    debug!("Window task {ix} starting up");
+    let mut work = Work::new(cfg.read_lengths(), cfg.threshold());
+    while let Ok(b) = rx.recv() {
+        window_block(cfg, b, &mut work, f)?
+    }
+    debug!("Window task {ix} shutting down");
+    Ok(())
+}
+
+fn window_block<F>(cfg: &Config, b: SeqBlock, work: &mut Work, f: &F) -> anyhow::Result<()>
+where
+    F: Fn(&Window),
+{
+    let rl = cfg.read_lengths();
+    b.for_each_seq(cfg, |s, eval| {
+        process_seq(cfg, s, eval, work, |ix, i, c| {
+            if let Some((at, gc)) = c.get_counts() {
+                let read_length = rl[ix];
+                f(&Window {
+                    contig: s.contig(),
+                    pos: (s.start() + i as u64 + 1).saturating_sub(read_length as u64),
+                    read_length,
+                    at,
+                    gc,
+                })
+            }
+        })
+    })
+}
+
+/// Treatment of partial windows overhanging the ends of a sequence (the contig ends, or gaps
+/// long enough to split the sequence)
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum EndPolicy {
+    /// Positions past the end count as gaps, so the window is used if it passes the threshold
+    Threshold,
+    /// Partial windows are not used
+    Drop,
+    /// The window is shrunk to the bases within the sequence, with the threshold scaled to match
+    Shrink,
+}
+
+impl ValueEnum for EndPolicy {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Threshold, Self::Drop, Self::Shrink]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(self.name()))
+    }
+}
+
+impl EndPolicy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Threshold => "threshold",
+            Self::Drop => "drop",
+            Self::Shrink => "shrink",
+        }
+    }
+}
+
+/// Execution engine for the process threads
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Engine {
+    /// Process threads each take sequence blocks from a shared channel
+    Channel,
+    /// Sequence blocks are processed by parallel iteration on a rayon thread pool
+    Rayon,
+}
+
+impl ValueEnum for Engine {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Channel, Self::Rayon]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(match self {
+            Self::Channel => "channel",
+            Self::Rayon => "rayon",
+        }))
+    }
+}
+
+/// Core ids for pinning process threads if requested
+fn core_ids(cfg: &Config) -> Option<Vec<CoreId>> {
+    if cfg.pin_threads() {
+        let c = core_affinity::get_core_ids().filter(|v| !v.is_empty());
+        if c.is_none() {
+            warn!("Could not get core ids: process threads will not be pinned")
+        }
+        c
+    } else {
+        None
+    }
+}
+
+fn pin_thread(ix: usize, cores: Option<&[CoreId]>) {
+    if let Some(c) = cores.map(|v| v[ix % v.len()]) {
+        if core_affinity::set_for_current(c) {
+            debug!("Process thread {ix} pinned to core {}", c.id)
+        } else {
+            warn!("Could not pin process thread {ix} to core {}", c.id)
+        }
+    }
+}
+
+/// Spawn process threads running f, feed them from the reader and collect their results (and
+/// the contig checksums from the reader if requested)
+fn run_threads<T, F>(cfg: &Config, f: F) -> anyhow::Result<(Vec<T>, InputInfo)>
+where
+    T: Send,
+    F: Fn(usize, Receiver<SeqBlock>) -> anyhow::Result<T> + Sync,
+{
     let nt = cfg.threads();
 
-    let mut error = false;
-    let mut res = GcRes::new(cfg.read_lengths(), cfg.bisulfite());
+    // The first error is returned and any others logged.  Errors from the process threads are
+    // taken before any from the reader, as when a process thread fails the reader will in turn
+    // fail to send to it
+    let mut error: Option<anyhow::Error> = None;
+    let mut add_error = |e: anyhow::Error| match error {
+        None => error = Some(e),
+        Some(_) => error!("{:?}", e),
+    };
+    let mut res = Vec::with_capacity(nt);
+    let mut info = InputInfo::default();
+    let cores = core_ids(cfg);
 
     thread::scope(|scope| {
         // Channel used to send sequences to process threads
-        let (seq_send, seq_recv) = bounded(nt * 4);
+        let (seq_send, seq_recv) = bounded(cfg.queue_size());
 
         let mut process_tasks = Vec::with_capacity(nt);
         for ix in 0..nt {
             let rx = seq_recv.clone();
-            let cfg = &cfg;
-            process_tasks.push(scope.spawn(move |_| process_thread(cfg, ix, rx)));
+            let f = &f;
+            let cores = cores.as_deref();
+            process_tasks.push(scope.spawn(move |_| {
+                pin_thread(ix, cores);
+                f(ix, rx)
+            }));
         }
         drop(seq_recv);
 
-        if let Err(e) = reader::reader(&cfg, seq_send) {
-            error!("{:?}", e);
-            error = true;
-        }
+        let rdr_res = reader::reader(cfg, seq_send);
 
         // Wait for analysis threads
         for jh in process_tasks.drain(..) {
             match jh.join().expect("Error joining analysis thread") {
-                Err(e) => {
-                    error!("{:?}", e);
-                    error = true
-                }
-                Ok(r) => res += r,
+                Err(e) => add_error(e),
+                Ok(r) => res.push(r),
             }
         }
+        match rdr_res {
+            Err(e) => add_error(e),
+            Ok(s) => info = s,
+        }
     })
     .expect("Error in scope generation");
 
-    if error {
-        Err(anyhow!("Error occurred during processing"))
+    match error {
+        Some(e) => Err(e.context("Error occurred during processing")),
+        None => Ok((res, info)),
+    }
+}
+
+/// Feed the blocks from the reader to a rayon thread pool, where they are processed with f by
+/// parallel iteration (with work stealing) into states from init.  Returns the states and the
+/// information from the reader as for [`run_threads`]
+fn run_rayon<T, I, F>(cfg: &Config, init: I, f: F) -> anyhow::Result<(Vec<T>, InputInfo)>
+where
+    T: Send,
+    I: Fn() -> T + Sync + Send,
+    F: Fn(&mut T, SeqBlock) -> anyhow::Result<()> + Sync + Send,
+{
+    let cores = core_ids(cfg);
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(cfg.threads())
+        .start_handler(move |ix| pin_thread(ix, cores.as_deref()))
+        .build()
+        .with_context(|| "Could not build thread pool")?;
+    let (seq_send, seq_recv) = bounded(cfg.queue_size());
+    let (res, rdr_res) = thread::scope(|scope| {
+        let jh = scope.spawn(|_| {
+            pool.install(|| {
+                seq_recv
+                    .into_iter()
+                    .par_bridge()
+                    .try_fold(&init, |mut t, b| f(&mut t, b).map(|_| t))
+                    .collect::<anyhow::Result<Vec<T>>>()
+            })
+        });
+        let rdr_res = reader::reader(cfg, seq_send);
+        (jh.join().expect("Error joining thread pool"), rdr_res)
+    })
+    .expect("Error in scope generation");
+
+    // As for run_threads, a processing error is reported before the reader error it causes
+    let e = match (res, rdr_res) {
+        (Ok(v), Ok(info)) => return Ok((v, info)),
+        (Err(e), Err(e1)) => {
+            error!("{:?}", e1);
+            e
+        }
+        (Err(e), _) | (_, Err(e)) => e,
+    };
+    Err(e.context("Error occurred during processing"))
+}
+
+fn new_res(cfg: &Config) -> GcRes {
+    let mut res = GcRes::new(cfg.read_lengths(), cfg.bisulfite(), cfg.long_reads());
+    if cfg.per_base() {
+        res = res.with_per_base()
+    }
+    if cfg.skew() {
+        res = res.with_skew()
+    }
+    if cfg.repeat_mask().is_some() {
+        res = res.with_repeat_parts()
+    }
+    if cfg.annotation().is_some() {
+        res = res.with_class_parts()
+    }
+    if let Some(g) = cfg.contig_groups() {
+        res = res.with_group_parts(g.groups().len())
+    }
+    if cfg.cross_validate().is_some() {
+        res = res.with_cv_parts()
+    }
+    if cfg.bootstrap().is_some() {
+        res = res.with_boot_parts()
+    }
+    if cfg.off_target_too() {
+        res = res.with_off_target()
+    }
+    if cfg.padding().is_some() {
+        res = res.with_padded()
+    }
+    if cfg.n_content() {
+        res = res.with_n_content(cfg.threshold())
+    }
+    if let Some(n) = cfg.dump_windows() {
+        res = res.with_window_sample(n, cfg.seed())
+    }
+    if let Some(v) = cfg.threshold_sweep() {
+        res = res.with_sweep(v)
+    }
+    if cfg.null_model().is_some() {
+        res.with_null()
     } else {
-        Ok(res)
+        res
+    }
+}
+
+/// Kmer uniqueness for the repeat partitions, contig groups and kmer GC table.  The options share
+/// one pass over the input to count the kmers and a second to classify them
+fn kmer_uniqueness(cfg: &Config, res: &mut GcRes) -> anyhow::Result<()> {
+    let mask = cfg.repeat_mask().filter(|_| cfg.repeat_kmers());
+    if mask.is_none() && cfg.contig_groups().is_none() && !cfg.kmer_gc() {
+        return Ok(());
+    }
+    // The kmer GC table distinguishes low and high multiplicity kmers
+    let counts = count_kmers(cfg, if cfg.kmer_gc() { 4 } else { 2 })?;
+    let mut partitions = mask.map(|_| PartitionKmers::default());
+    let mut groups = cfg.contig_groups().map(GroupCounts::new);
+    let mut kmer_gc = cfg.kmer_gc().then(|| KmerGcTable::new(cfg.read_lengths()));
+    info!("Re-reading input for kmer uniqueness");
+    rescan_selected(cfg, |s| {
+        if let (Some(p), Some(m)) = (partitions.as_mut(), mask) {
+            p.add_seq(m, s, &counts)
+        }
+        if let Some(g) = groups.as_mut() {
+            g.add_seq(s, &counts)
+        }
+        if let Some(t) = kmer_gc.as_mut() {
+            t.add_seq(cfg, s, &counts)
+        }
+        Ok(())
+    })?;
+    info!("Finished reading input");
+    if let (Some(p), Some(r)) = (partitions, res.repeatmasker.as_mut()) {
+        r.set_kmers(p)
+    }
+    res.contig_groups = groups.map(|g| g.finish());
+    res.kmer_gc = kmer_gc;
+    Ok(())
+}
+
+pub fn process(cfg: &Config) -> anyhow::Result<GcRes> {
+    let acc = Mutex::new(new_res(cfg));
+    if cfg.performance() {
+        performance::enable(cfg.threads())
+    }
+    let t = performance::start();
+    let (v, info) = match cfg.engine() {
+        Engine::Channel => run_threads(cfg, |ix, rx| process_thread(cfg, ix, rx, &acc))?,
+        Engine::Rayon => {
+            let init = || (new_res(cfg), Work::new(cfg.read_lengths(), cfg.threshold()));
+            let f = |(res, work): &mut (GcRes, Work), b| {
+                let ix = rayon::current_thread_index().unwrap_or(0);
+                process_block(cfg, ix, b, res, work)
+            };
+            let (v, info) = run_rayon(cfg, init, f)?;
+            (v.into_iter().map(|(r, _)| r).collect(), info)
+        }
+    };
+    performance::set_wall(t);
+    if let Some(p) = performance::performance() {
+        info!(
+            "Processing limited by the {}",
+            p.bottleneck().replace('_', " ")
+        )
+    }
+    let mut res = acc.into_inner().expect("Accumulator lock poisoned");
+    for r in v {
+        res += r
+    }
+    res.contigs = info.contigs;
+    res.repeats = info.repeats;
+    res.composition_summary = info.composition_summary;
+    res.target_gc_length = info.target_gc_length;
+    res.composition = info.composition;
+    res.identifier = cfg
+        .identifier()
+        .map(|s| s.to_owned())
+        .or_else(|| derive_identifier(cfg, res.composition.as_ref()));
+    res.target_summary = info.targets;
+    res.input_digest = info.digest;
+    res.repeatmasker = cfg.repeat_mask().map(RepeatMaskRes::new);
+    kmer_uniqueness(cfg, &mut res)?;
+    // Scale up counts from a sampled subset of the input and/or of the windows
+    let x = cfg.sample_fraction().unwrap_or(1.0) / (cfg.stride() as f64);
+    if x < 1.0 {
+        res.scale(1.0 / x)
+    }
+    for (ctg, h) in res.contig_window_counts() {
+        for (l, c) in h.iter() {
+            if c.skipped > c.passed {
+                warn!(
+                    "Contig {ctg}: {} of {} windows skipped for read length {l}",
+                    c.skipped,
+                    c.skipped + c.passed
+                )
+            }
+        }
+    }
+    Ok(res)
+}
+
+/// Stream all windows passing the threshold to f instead of aggregating them into a [`GcRes`].
+/// Note that f is called concurrently from all process threads, and that the order of windows
+/// is not defined
+pub fn process_windows<F>(cfg: &Config, f: F) -> anyhow::Result<()>
+where
+    F: Fn(&Window) + Sync,
+{
+    match cfg.engine() {
+        Engine::Channel => run_threads(cfg, |ix, rx| window_thread(cfg, ix, rx, &f)).map(|_| ()),
+        Engine::Rayon => {
+            let init = || Work::new(cfg.read_lengths(), cfg.threshold());
+            run_rayon(cfg, init, |work, b| window_block(cfg, b, work, &f)).map(|_| ())
+        }
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use crate::{
+        cli::config_from_args,
+        fasta::{FastaReader, GapPolicy},
+        reader::{send_records, send_seq},
+    };
+    #[allow(unused_imports)]
+    use crossbeam_channel::{unbounded, Sender};
+    #[allow(unused_imports)]
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    /// FASTA record with n random bases, with runs of Ns at the given (start, length)
+    #[allow(dead_code)]
+    fn test_fasta(n: usize, gaps: &[(usize, usize)]) -> Vec<u8> {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut v: Vec<u8> = (0..n).map(|_| b"ACGT"[rng.gen_range(0..4)]).collect();
+        for (s, l) in gaps {
+            v[*s..s + l].fill(b'N')
+        }
+        let mut f = b">chr1\n".to_vec();
+        for l in v.chunks(60) {
+            f.extend_from_slice(l);
+            f.push(b'\n')
+        }
+        f
+    }
+
+    #[allow(dead_code)]
+    fn test_cfg(args: &[&str]) -> Config {
+        let v = ["analyze_ref_gc", "-r", "20", "50", "--n-content"];
+        config_from_args(v.iter().chain(args)).unwrap()
+    }
+
+    /// Results from processing the blocks sent by f
+    #[allow(dead_code)]
+    fn run_blocks<F: FnOnce(&Sender<SeqBlock>)>(cfg: &Config, f: F) -> serde_json::Value {
+        let (snd, rcv) = unbounded();
+        f(&snd);
+        drop(snd);
+        let mut res = new_res(cfg);
+        let mut work = Work::new(cfg.read_lengths(), cfg.threshold());
+        while let Ok(b) = rcv.recv() {
+            process_block(cfg, 0, b, &mut res, &mut work).unwrap()
+        }
+        serde_json::to_value(&res).unwrap()
+    }
+
+    /// Check that results are the same with and without chunking, both for unparsed records and
+    /// for sequences from the reader (which are not split at gaps, as for masked off target
+    /// bases)
+    #[allow(dead_code)]
+    fn check_chunks(args: &[&str]) {
+        // Gaps at the contig ends, short gaps within the sequence and a long gap that splits it
+        // (and covers a whole chunk)
+        let gaps = [(0, 7), (985, 30), (1990, 10), (2500, 1200), (4990, 10)];
+        let fasta = test_fasta(5000, &gaps);
+        let cfg = test_cfg(args);
+        let chunk_args: Vec<_> = args
+            .iter()
+            .chain(&["--chunk-size", "500"])
+            .copied()
+            .collect();
+        let chunked = test_cfg(&chunk_args);
+        let whole = run_blocks(&cfg, |snd| send_records(&cfg, &fasta[..], snd).unwrap());
+        let res = run_blocks(&chunked, |snd| {
+            send_records(&chunked, &fasta[..], snd).unwrap()
+        });
+        assert_eq!(whole, res);
+
+        let s = FastaReader::new(&fasta[..], GapPolicy::Never)
+            .next()
+            .unwrap()
+            .unwrap();
+        let whole = run_blocks(&cfg, |snd| send_seq(snd, s.clone(), None).unwrap());
+        let res = run_blocks(&cfg, |snd| {
+            send_seq(snd, s.clone(), Some((500, 50))).unwrap()
+        });
+        assert_eq!(whole, res);
+    }
+
+    #[test]
+    fn test_process_seq() {
+        // Longer than a prefix block, with gaps (including one across the block boundary)
+        let gaps = [(100, 3), (5000, 40), (PREFIX_BLOCK - 10, 25), (69000, 1)];
+        let fasta = test_fasta(70000, &gaps);
+        let s = FastaReader::new(&fasta[..], GapPolicy::Never)
+            .next()
+            .unwrap()
+            .unwrap();
+        let args = ["analyze_ref_gc", "-r", "7", "50", "101", "--stride", "3"];
+        let cfg = config_from_args(args).unwrap();
+        let rl = cfg.read_lengths();
+        let mut work = Work::new(rl, cfg.threshold());
+        let mut v = Vec::new();
+        process_seq(&cfg, &s, &(0..u64::MAX), &mut work, |ix, j, c| {
+            v.push((ix, j, c.counts, c.gaps, c.cpg, c.span, c.threshold))
+        });
+        v.sort_unstable();
+
+        // Naive count of each window, including those overhanging the end of the sequence (up to
+        // the maximum read length)
+        let mut expected = Vec::new();
+        for (ix, l) in rl.iter().enumerate() {
+            let l = *l as usize;
+            for j in (0..s.len() + 101).filter(|j| j % 3 == 0) {
+                let end = (j + 1).min(s.len());
+                let w = &s[(j + 1).saturating_sub(l).min(end)..end];
+                let mut counts = [0; 4];
+                for b in w.iter().filter(|b| !b.is_gap()) {
+                    counts[*b as usize] += 1
+                }
+                let gaps = w.iter().filter(|b| b.is_gap()).count() as u32;
+                let cpg = w
+                    .windows(2)
+                    .filter(|p| p[0] == Base::C && p[1] == Base::G)
+                    .count() as u32;
+                let t = ((l as f64) * cfg.threshold()).ceil() as u32;
+                expected.push((ix, j, counts, gaps, cpg, w.len() as u32, t))
+            }
+        }
+        expected.sort_unstable();
+        assert_eq!(v, expected);
+    }
+
+    #[test]
+    fn test_chunks() {
+        check_chunks(&[]);
+        check_chunks(&["--stride", "3"])
+    }
+
+    #[test]
+    fn test_end_policy_chunks() {
+        check_chunks(&["--end-policy", "drop"]);
+        check_chunks(&["--end-policy", "shrink"])
+    }
+
+    #[test]
+    fn test_dense_counts() {
+        let mut d = GcCounts::new(4, false);
+        let mut h = GcCounts::Sparse(FxHashMap::default());
+        for (i, cts) in [(0, 0), (0, 4), (1, 3), (2, 2), (4, 0), (1, 3)]
+            .iter()
+            .enumerate()
+        {
+            d.add(*cts, i as f64 + 1.0);
+            h.add(*cts, i as f64 + 1.0);
+        }
+        assert_eq!(GcCounts::dense_idx(4, 4, 0), 14);
+        let v1: Vec<_> = d.iter().map(|(k, x)| (k.counts(), x)).collect();
+        let v2: Vec<_> = h.iter().map(|(k, x)| (k.counts(), x)).collect();
+        assert_eq!(v1, v2);
+        assert_eq!(d.len(), 5);
+    }
+
+    #[test]
+    fn test_scaled_threshold() {
+        let c = Counts {
+            counts: [20, 10, 5, 5],
+            gaps: 0,
+            cpg: 0,
+            span: 40,
+            threshold: 80,
+        };
+        assert_eq!(c.scaled_threshold(100, 80, false), 80);
+        assert_eq!(c.scaled_threshold(100, 80, true), 32);
+        assert_eq!(c.scaled_threshold(40, 32, true), 32);
+        assert_eq!(Counts { span: 1, ..c }.scaled_threshold(100, 80, true), 1);
+    }
+
+    #[test]
+    fn test_per_base() {
+        use Base::*;
+        let s = [A, C, G, T, N, A];
+        let mut h = PerBase::new(3);
+        // Windows ACG, CGT and GTN
+        h.add_run(&s, 2, 4, 1.0);
+        assert_eq!(
+            h.counts,
+            vec![
+                [1.0, 1.0, 0.0, 1.0],
+                [0.0, 1.0, 1.0, 1.0],
+                [0.0, 0.0, 1.0, 1.0]
+            ]
+        );
+        let p = h.proportions();
+        // Reverse complements of the windows are CGT, ACG and NAC
+        assert_eq!(p[0], [2.0 / 5.0, 2.0 / 5.0, 0.0, 1.0 / 5.0]);
+        assert_eq!(p[1], [1.0 / 6.0, 2.0 / 6.0, 1.0 / 6.0, 2.0 / 6.0]);
+    }
+
+    #[test]
+    fn test_binned_counts() {
+        let mut b = GcCounts::new(20000, true);
+        b.add((10000, 10000), 1.0);
+        b.add((9000, 9000), 3.0);
+        b.add((15000, 5000), 1.0);
+        let v: Vec<_> = b.iter().map(|(k, x)| (k.counts(), x)).collect();
+        assert_eq!(v, vec![((9250, 9250), 4.0), ((15000, 5000), 1.0)]);
+        // Short windows are kept exactly
+        let mut b = GcCounts::new(5000, true);
+        b.add((2000, 3000), 1.0);
+        assert_eq!(b.iter().next().map(|(k, _)| k.counts()), Some((2000, 3000)));
     }
 }
@@ -0,0 +1,66 @@
+//! Information on how and where outputs were generated, so that output files are self-describing
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::checksum::{ContigSum, InputDigest};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Provenance {
+    command_line: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    working_dir: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hostname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reference_digest: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input_digest: Option<InputDigest>,
+}
+
+impl Provenance {
+    /// Provenance for the current process.  The reference digest is only available if checksums
+    /// were calculated
+    pub fn new(contigs: Option<&[ContigSum]>) -> Self {
+        let args: Vec<_> = std::env::args_os()
+            .map(|s| s.to_string_lossy().into_owned())
+            .collect();
+        Self {
+            command_line: shell_words::join(args),
+            working_dir: std::env::current_dir().ok(),
+            hostname: gethostname::gethostname().into_string().ok(),
+            reference_digest: contigs.map(reference_digest),
+            input_digest: None,
+        }
+    }
+
+    /// Keep the reference digest from an earlier provenance record (when updating an existing file)
+    pub fn with_reference_digest(mut self, digest: Option<String>) -> Self {
+        self.reference_digest = self.reference_digest.or(digest);
+        self
+    }
+
+    /// Add the digest of the raw input stream (if calculated)
+    pub fn with_input_digest(mut self, digest: Option<&InputDigest>) -> Self {
+        self.input_digest = digest.cloned();
+        self
+    }
+
+    pub fn command_line(&self) -> &str {
+        &self.command_line
+    }
+
+    pub fn reference_digest(&self) -> Option<&str> {
+        self.reference_digest.as_deref()
+    }
+}
+
+/// Digest for the whole reference: the MD5 of the lines `name<TAB>length<TAB>md5<LF>` for each
+/// contig in input order
+pub fn reference_digest(contigs: &[ContigSum]) -> String {
+    let mut ctx = md5::Context::new();
+    for c in contigs {
+        ctx.consume(format!("{}\t{}\t{}\n", c.name(), c.length(), c.md5()))
+    }
+    format!("{:x}", ctx.compute())
+}
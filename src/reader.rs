@@ -1,44 +1,50 @@
 use anyhow::Context;
 use compress_io::compress::CompressIo;
 use crossbeam_channel::Sender;
-use std::{io::BufRead, num::NonZeroU32, ops::Deref};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rustc_hash::FxHashSet;
+use std::{
+    io::{BufRead, Cursor},
+    num::NonZeroU32,
+    ops::Range,
+    path::Path,
+};
 
 use crate::{
+    checksum::{ChecksumState, ContigSum, DigestState, InputDigest},
     cli::Config,
+    composition::{CompositionScan, CompositionSummary},
+    contig_filter::ContigFilter,
+    dropout_risk::{target_risk, RiskSummary},
+    error::OutputError,
+    fasta::{
+        fai::{find_fai, open_span, read_fai},
+        tar::FastaInput,
+        Base, BaseFilter, FastaReader, GapPolicy, NoFilter, Seq,
+    },
+    gatk::{sequence_dictionary, write_gatk_intervals},
+    input_check::{CharCounts, CheckReader},
     kmcv,
-    kmers::{KmerBuilder, KmerWork},
-    regions::{Region, Regions},
+    kmer_text::output_kmer_text,
+    kmers::{Conversion, KmerBuilder, KmerWork},
+    mappability::MappabilityTrack,
+    matched_controls::MatchedControls,
+    performance::{self, Stage},
+    provenance::Provenance,
+    regions::{
+        span::{Span, Spans},
+        Region, Regions,
+    },
+    repeat_scan::{RepeatRes, RepeatScan},
+    rescan::rescan_selected,
+    shared_kmers::output_shared_kmers,
+    summary::{add_output_file, TargetSummary},
+    tap::{StreamTap, TapReader},
+    target_gc_length::TargetGcLength,
+    target_stats::{output_target_map, output_target_stats, TargetStats},
+    warnings::add_warning,
 };
 
-#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
-#[repr(u8)]
-pub enum Base {
-    A = 0,
-    C,
-    T,
-    G,
-    N,
-    #[default]
-    Other,
-}
-
-impl Base {
-    pub fn from_u8(c: u8) -> Self {
-        match c {
-            b'A' | b'a' => Self::A,
-            b'C' | b'c' => Self::C,
-            b'G' | b'g' => Self::G,
-            b'T' | b't' => Self::T,
-            b'N' | b'n' => Self::N,
-            _ => Self::Other,
-        }
-    }
-
-    pub fn is_gap(&self) -> bool {
-        ((*self as usize) & 4) == 4
-    }
-}
-
 struct RegionState<'a> {
     regions: &'a Regions,
     region_slice: Option<&'a [Region]>,
@@ -73,7 +79,7 @@ impl<'a> RegionState<'a> {
     }
 
     /// Returns true if position matches a target region
-    fn check_pos(&mut self, pos: u32) -> Option<NonZeroU32> {
+    fn check_pos(&mut self, pos: u64) -> Option<NonZeroU32> {
         while let Some(v) = self.region_slice {
             // As long as we use the API, v should always be non-empty
             let r = v[0];
@@ -91,443 +97,894 @@ impl<'a> RegionState<'a> {
     }
 }
 
-#[derive(Debug)]
-pub struct Seq(Vec<Base>);
+/// Kmer tables for C->T and G->A converted sequence.  Converted reads can come from either
+/// original strand, so the reverse complement kmers from the C->T converted forward strand go into
+/// the G->A table and vice versa
+struct ConvTables {
+    ct_build: KmerBuilder,
+    ga_build: KmerBuilder,
+    ct_work: KmerWork,
+    ga_work: KmerWork,
+}
 
-impl Deref for Seq {
-    type Target = [Base];
+impl ConvTables {
+    fn new() -> Self {
+        Self {
+            ct_build: KmerBuilder::new(),
+            ga_build: KmerBuilder::new(),
+            ct_work: KmerWork::new_converted(Conversion::CtoT),
+            ga_work: KmerWork::new_converted(Conversion::GtoA),
+        }
+    }
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+    fn clear(&mut self) {
+        self.ct_build.clear();
+        self.ga_build.clear();
     }
-}
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-enum RdrState {
-    Start,
-    StartSeqId,
-    InSeqId,
-    StartSeq,
-    StartSeqAfterNewLine,
-    StartGap,
-    InGap,
-    InGapAfterNewLine,
-    InSeq,
-    InSeqAfterNewLine,
-    InLongGap,
-    InLongGapAfterNewLine,
-    EndSeq,
-    EndSeqAfterLongGap,
-    StartSeqAfterInitialGap,
-    NewContig,
+    fn add_base(&mut self, b: Base, idx: Option<NonZeroU32>) {
+        self.ct_build.add_base(Conversion::CtoT.convert(b), idx);
+        if let Some(k) = self.ct_build.kmers() {
+            let idx = self.ct_build.target_idx();
+            self.ct_work.add_kmer(k[0], idx);
+            self.ga_work.add_kmer(k[1], idx);
+        }
+        self.ga_build.add_base(Conversion::GtoA.convert(b), idx);
+        if let Some(k) = self.ga_build.kmers() {
+            let idx = self.ga_build.target_idx();
+            self.ga_work.add_kmer(k[0], idx);
+            self.ct_work.add_kmer(k[1], idx);
+        }
+    }
 }
 
-struct Rdr<'a, R: BufRead> {
-    r: R,
-    state: RdrState,
-    seq_id: String,
-    max_read_length: u32,
-    pos: u32,
-    target_state: Option<RegionState<'a>>,
+/// Masks off target bases (unless off target distributions are also wanted, in which case the
+/// process threads split the sequence) and records kmers for the target regions
+struct TargetFilter<'a> {
+    target_state: RegionState<'a>,
+    mask: bool,
     k_work: KmerWork,
-    kmer_build: KmerBuilder,
+    k_build: KmerBuilder,
+    conv: Option<ConvTables>,
+    stats: TargetStats,
+    /// Contigs from the target file seen in the input
+    found: FxHashSet<String>,
+    /// Contigs from which kmers are recorded (all if None)
+    kmer_contigs: Option<&'a FxHashSet<String>>,
+    /// Whether kmers are recorded for the current contig
+    record_kmers: bool,
+    /// Kmer positions recorded for each contig, in input order
+    contig_kmers: Vec<(String, u64)>,
 }
 
-struct SeqWork<'a> {
-    v: Vec<Base>,
-    k_work: &'a mut KmerWork,
-    k_build: &'a mut KmerBuilder,
+impl<'a> TargetFilter<'a> {
+    fn new(
+        regions: &'a Regions,
+        bisulfite: bool,
+        mask: bool,
+        kmer_contigs: Option<&'a FxHashSet<String>>,
+    ) -> Self {
+        Self {
+            target_state: RegionState {
+                regions,
+                region_slice: None,
+            },
+            mask,
+            k_work: KmerWork::new(),
+            k_build: KmerBuilder::new(),
+            conv: if bisulfite {
+                Some(ConvTables::new())
+            } else {
+                None
+            },
+            stats: TargetStats::new(regions.n_regions()),
+            found: FxHashSet::default(),
+            kmer_contigs,
+            record_kmers: true,
+            contig_kmers: Vec::new(),
+        }
+    }
 }
 
-impl<'a, R: BufRead> Rdr<'a, R> {
-    fn new(r: R, max_read_length: u32, target_regions: Option<&'a Regions>) -> Self {
-        let state = RdrState::Start;
-        let seq_id = String::new();
+impl<'a> BaseFilter for TargetFilter<'a> {
+    fn new_contig(&mut self, name: &str) {
+        if self.target_state.regions.get(name).is_some() {
+            self.found.insert(name.to_owned());
+        }
+        self.target_state.new_contig(name);
+        self.record_kmers = self.kmer_contigs.is_none_or(|s| s.contains(name));
+        if self.record_kmers {
+            self.contig_kmers.push((name.to_owned(), 0))
+        }
+        self.k_build.clear();
+        if let Some(c) = self.conv.as_mut() {
+            c.clear()
+        }
+    }
 
-        let target_state = target_regions.map(|r| RegionState {
-            regions: r,
-            region_slice: None,
-        });
+    fn filter(&mut self, pos: u64, b: Base) -> Base {
+        let idx = self.target_state.check_pos(pos);
+        if self.record_kmers {
+            self.k_build.add_base(b, idx);
+            trace!(
+                "base: {:?}, kmers: {:?}, idx: {:?}",
+                b,
+                self.k_build.kmers(),
+                self.k_build.target_idx()
+            );
+            if let Some(k) = self.k_build.kmers() {
+                let idx = self.k_build.target_idx();
+                self.k_work.add_kmer(k[0], idx);
+                self.k_work.add_kmer(k[1], idx);
+                if let Some((_, n)) = self.contig_kmers.last_mut() {
+                    *n += 1
+                }
+            }
+            if let Some(c) = self.conv.as_mut() {
+                c.add_base(b, idx)
+            }
+        }
+        // Off target bases are masked
+        if let Some(i) = idx {
+            self.stats.add_base(i, b);
+            b
+        } else if self.mask {
+            Base::N
+        } else {
+            b
+        }
+    }
+}
 
-        let k_work = KmerWork::new();
+/// Masks bases outside of the spans selected with --region.  Masked bases are passed on to the
+/// inner filter as gaps so that kmers and target statistics are restricted to the spans
+struct SpanFilter<'a, F: BaseFilter> {
+    spans: &'a Spans,
+    current: &'a [Span],
+    contigs_found: usize,
+    inner: F,
+}
 
+impl<'a, F: BaseFilter> SpanFilter<'a, F> {
+    fn new(spans: &'a Spans, inner: F) -> Self {
         Self {
-            r,
-            state,
-            seq_id,
-            max_read_length,
-            pos: 0,
-            target_state,
-            k_work,
-            kmer_build: KmerBuilder::new(),
+            spans,
+            current: &[],
+            contigs_found: 0,
+            inner,
         }
     }
+}
 
-    fn get_seq(&mut self) -> anyhow::Result<Option<Seq>> {
-        let v = Vec::new();
-        let mut gap = 0;
-        let mut ts = self.target_state.take();
-        let mut seq_work = SeqWork {
-            v,
-            k_work: &mut self.k_work,
-            k_build: &mut self.kmer_build,
-        };
+impl<'a, F: BaseFilter> BaseFilter for SpanFilter<'a, F> {
+    fn new_contig(&mut self, name: &str) {
+        self.current = self.spans.get(name).unwrap_or(&[]);
+        if !self.current.is_empty() {
+            self.contigs_found += 1
+        }
+        self.inner.new_contig(name)
+    }
 
-        loop {
-            let buf = self.r.fill_buf()?;
-            if buf.is_empty() {
-                break;
-            }
-            let mut used = 0;
-            let mut seq_ready = false;
-            for (ix, c) in buf.iter().enumerate() {
-                let idx = if let Some(t) = ts.as_mut() {
-                    t.check_pos(self.pos)
-                } else {
-                    // If not targets are set, everything is on target!
-                    None
-                };
-                trace!(
-                    "pos = {}, idx = {:?}, state = {:?}, kmer = {:?}",
-                    self.pos,
-                    idx,
-                    self.state,
-                    seq_work.k_build.kmers(),
-                );
-                let (new_state, inc_pos) = match self.state {
-                    RdrState::Start => (proc_start(*c)?, false),
-                    RdrState::StartSeqId => (proc_start_seq_id(*c, &mut self.seq_id)?, false),
-                    RdrState::StartSeqAfterNewLine => proc_start_seq_after_new_line(*c)?,
-                    RdrState::InSeqId => (proc_in_seq_id(*c, &mut self.seq_id)?, false),
-                    RdrState::NewContig => {
-                        debug!("Starting reading contig {}", self.seq_id);
-                        if let Some(regs) = ts.as_mut() {
-                            regs.new_contig(&self.seq_id)
-                        }
-                        seq_work.k_build.clear();
-                        self.pos = 0;
-                        proc_start_seq(*c)?
-                    }
-                    RdrState::StartSeq => proc_start_seq(*c)?,
-                    RdrState::InSeq => {
-                        gap = 0;
-                        proc_in_seq(*c, Some(&mut seq_work), idx)?
-                    }
-                    RdrState::InSeqAfterNewLine => {
-                        proc_after_new_line(*c, Some(&mut seq_work), proc_in_seq, idx)?
-                    }
-                    RdrState::InGapAfterNewLine => {
-                        proc_after_new_line(*c, Some(&mut seq_work), proc_in_gap, idx)?
-                    }
-                    RdrState::InLongGapAfterNewLine => {
-                        proc_after_new_line(*c, None, proc_in_long_gap, idx)?
-                    }
-                    RdrState::StartGap => {
-                        gap = 1;
-                        proc_in_gap(*c, Some(&mut seq_work), idx)?
-                    }
-                    RdrState::InGap => {
-                        gap += 1;
-                        if gap >= self.max_read_length {
-                            assert!(seq_work.v.len() > gap as usize);
-                            seq_work.v.truncate(seq_work.v.len() - gap as usize);
-                            gap = 0;
-                            proc_in_long_gap(*c, None, idx)?
-                        } else {
-                            proc_in_gap(*c, Some(&mut seq_work), idx)?
-                        }
-                    }
-                    RdrState::InLongGap => proc_in_long_gap(*c, None, idx)?,
-                    RdrState::EndSeq => {
-                        used = ix;
-                        seq_ready = true;
-                        (RdrState::StartSeqId, false)
-                    }
-                    RdrState::EndSeqAfterLongGap => {
-                        used = if ix > 0 { ix - 1 } else { ix };
-                        if self.pos > 0 {
-                            self.pos -= 1;
-                        }
-                        seq_ready = true;
-                        (RdrState::StartSeq, false)
-                    }
-                    RdrState::StartSeqAfterInitialGap => {
-                        used = if ix > 0 { ix - 1 } else { ix };
-                        if self.pos > 0 {
-                            self.pos -= 1;
-                        }
-                        seq_ready = true;
-                        (RdrState::InSeq, false)
-                    }
-                };
-                self.state = new_state;
-                if inc_pos {
-                    self.pos += 1
-                }
-                if seq_ready {
-                    break;
-                }
-            }
-            let used = if seq_ready {
-                used
-            } else if self.state == RdrState::EndSeqAfterLongGap {
-                buf.len() - 1
-            } else {
-                buf.len()
-            };
-            self.r.consume(used);
-            if seq_ready && !seq_work.v.is_empty() {
+    fn filter(&mut self, pos: u64, b: Base) -> Base {
+        while let Some(s) = self.current.first() {
+            if pos < s.end() {
                 break;
             }
+            self.current = &self.current[1..]
+        }
+        if self
+            .current
+            .first()
+            .map(|s| s.contains(pos))
+            .unwrap_or(false)
+        {
+            self.inner.filter(pos, b)
+        } else {
+            self.inner.filter(pos, Base::N);
+            Base::N
         }
+    }
+}
 
-        self.target_state = ts;
-        let SeqWork {
-            mut v,
-            k_work: _,
-            k_build: _,
-        } = seq_work;
+/// Block of input sent to the process threads.  Long sequences may be split into chunks (with
+/// --chunk-size); chunks overlap by at least the maximum read length - 1 bases and each block only
+/// evaluates windows whose last base lies in its eval range (contig coordinates), so that windows
+/// crossing chunk boundaries are visited exactly once
+pub enum SeqBlock {
+    /// Sequence parsed by the reader
+    Seq(Seq, Range<u64>),
+    /// Unparsed FASTA record (header and sequence lines), where the first base is at contig
+    /// position start.  Records are sent unparsed when no per base work is needed in the reader,
+    /// so that parsing is done in parallel by the process threads.  The lines and bytes of input
+    /// before the record (as if the header directly preceded the sequence lines) are given in
+    /// input_pos for error reporting
+    Record {
+        data: Vec<u8>,
+        start: u64,
+        eval: Range<u64>,
+        input_pos: (u64, u64),
+    },
+}
 
-        if gap > 0 {
-            assert!(v.len() >= gap as usize);
-            v.truncate(v.len() - gap as usize);
+/// Eval range for an unsplit sequence
+const ALL: Range<u64> = 0..u64::MAX;
+
+impl SeqBlock {
+    /// Call f for each sequence in the block with the eval range, parsing the record if required
+    pub fn for_each_seq<F: FnMut(&Seq, &Range<u64>)>(
+        self,
+        cfg: &Config,
+        mut f: F,
+    ) -> anyhow::Result<()> {
+        match self {
+            Self::Seq(s, eval) => {
+                let t = performance::start();
+                f(&s, &eval);
+                performance::add_time(Stage::Count, t)
+            }
+            Self::Record {
+                data,
+                start,
+                eval,
+                input_pos: (line, offset),
+            } => {
+                let mut rdr = FastaReader::new(Cursor::new(data), gap_policy(cfg))
+                    .with_contig_start(start)
+                    .with_input_position(Some(line), offset);
+                loop {
+                    let t = performance::start();
+                    let s = rdr.next();
+                    performance::add_time(Stage::Decode, t);
+                    let Some(s) = s else { break };
+                    let t = performance::start();
+                    f(&s.with_context(|| "Error reading input sequence")?, &eval);
+                    performance::add_time(Stage::Count, t)
+                }
+            }
         }
-
-        Ok(if v.is_empty() { None } else { Some(Seq(v)) })
+        Ok(())
     }
 }
 
-fn proc_in_gen(
-    c: u8,
-    sw: Option<&mut SeqWork>,
-    s1: RdrState,
-    s2: RdrState,
-    s3: RdrState,
-    target_idx: Option<NonZeroU32>,
-) -> anyhow::Result<(RdrState, bool)> {
-    if c == b'\n' {
-        Ok((s1, false))
-    } else if c.is_ascii_graphic() {
-        let gc = Base::from_u8(c);
-        if let Some(s) = sw {
-            s.v.push(if target_idx.is_some() { gc } else { Base::N });
-            s.k_build.add_base(gc, target_idx);
-            trace!(
-                "base: {:?}, kmers: {:?}, idx: {:?}",
-                gc,
-                s.k_build.kmers(),
-                s.k_build.target_idx()
-            );
-            if let Some(k) = s.k_build.kmers() {
-                let idx = s.k_build.target_idx();
-                s.k_work.add_kmer(k[0], idx);
-                s.k_work.add_kmer(k[1], idx);
-            }
+/// Copies of s with the off target and on target bases masked respectively, where targets are
+/// extended by pad bases on each side.  With no padding, this is the same rule as used by the
+/// [`TargetFilter`]
+pub(crate) fn split_targets(s: &Seq, regions: &Regions, pad: u64) -> (Seq, Seq) {
+    let v = regions.get(s.contig()).map(|r| r.regions()).unwrap_or(&[]);
+    let mut i = v.partition_point(|r| r.end() + pad < s.start());
+    let (mut on, mut off) = (s.clone(), s.clone());
+    for ((pos, a), b) in (s.start()..)
+        .zip(on.bases_mut().iter_mut())
+        .zip(off.bases_mut().iter_mut())
+    {
+        while i < v.len() && v[i].end() + pad < pos {
+            i += 1
+        }
+        if v.get(i).map(|r| r.start().saturating_sub(pad) <= pos) == Some(true) {
+            *b = Base::N
         } else {
-            trace!("No SeqWork. Base: {:?}", gc);
+            *a = Base::N
         }
-        Ok(if gc.is_gap() { (s2, true) } else { (s3, true) })
-    } else {
-        Err(anyhow!("Illegal character in sequence"))
     }
+    (on, off)
 }
 
-fn proc_in_gap(
-    c: u8,
-    sw: Option<&mut SeqWork>,
-    target_idx: Option<NonZeroU32>,
-) -> anyhow::Result<(RdrState, bool)> {
-    proc_in_gen(
-        c,
-        sw,
-        RdrState::InGapAfterNewLine,
-        RdrState::InGap,
-        RdrState::InSeq,
-        target_idx,
-    )
+/// Records are split at gaps long enough that no window of any read length can span them
+pub fn gap_policy(cfg: &Config) -> GapPolicy {
+    GapPolicy::MinGap(max_read_length(cfg))
 }
 
-fn proc_in_long_gap(
-    c: u8,
-    sw: Option<&mut SeqWork>,
-    target_idx: Option<NonZeroU32>,
-) -> anyhow::Result<(RdrState, bool)> {
-    proc_in_gen(
-        c,
-        sw,
-        RdrState::InLongGapAfterNewLine,
-        RdrState::InLongGap,
-        RdrState::EndSeqAfterLongGap,
-        target_idx,
-    )
+fn max_read_length(cfg: &Config) -> u32 {
+    *cfg.read_lengths().iter().max().unwrap()
 }
 
-fn proc_after_new_line(
-    c: u8,
-    sw: Option<&mut SeqWork>,
-    f: fn(
-        c: u8,
-        v: Option<&mut SeqWork>,
-        target_idx: Option<NonZeroU32>,
-    ) -> anyhow::Result<(RdrState, bool)>,
-    target_idx: Option<NonZeroU32>,
-) -> anyhow::Result<(RdrState, bool)> {
-    if c == b'>' {
-        Ok((RdrState::EndSeq, false))
-    } else {
-        f(c, sw, target_idx)
+/// Number of bases of a record (excluding line ending)
+fn line_bases(l: &[u8]) -> usize {
+    l.iter()
+        .rposition(|c| *c != b'\n' && *c != b'\r')
+        .map(|i| i + 1)
+        .unwrap_or(0)
+}
+
+/// Split input into records without parsing and send them for processing.  If a chunk size is
+/// set, records are split (at line boundaries) into chunks of at least that many bases.  Each
+/// chunk starts with enough lines from the end of the previous chunk to cover twice the maximum
+/// read length before its eval range, and extends for at least the maximum read length past the
+/// end of its eval range, so that the parser sees whether gaps at either end of the chunk are
+/// long enough to split the sequence
+pub(crate) fn send_records<R: BufRead>(
+    cfg: &Config,
+    mut rdr: R,
+    snd: &Sender<SeqBlock>,
+) -> anyhow::Result<()> {
+    let max_len = max_read_length(cfg) as usize;
+    let chunk = cfg.chunk_size().unwrap_or(usize::MAX);
+    let send = |data, start, eval, input_pos| {
+        let t = performance::start();
+        let r = snd
+            .send(SeqBlock::Record {
+                data,
+                start,
+                eval,
+                input_pos,
+            })
+            .with_context(|| "Error sending record for processing");
+        performance::add_time(Stage::SendWait, t);
+        r
+    };
+
+    // Current block, with the header line, the contig position of the first base, the eval range
+    // (the end is set once enough bases are read), and the (end offset, cumulative bases) of each
+    // sequence line
+    let mut rec = Vec::new();
+    let mut hdr_len = 0;
+    let mut start = 0;
+    let mut eval_start = 0;
+    let mut eval_end = None;
+    let mut lines: Vec<(usize, usize)> = Vec::new();
+    // Set while skipping a record excluded by the contig length filters
+    let mut skip = false;
+    // Lines and bytes read, and the input position of the current block
+    let mut input_pos = (0, 0);
+    let mut rec_pos = (0, 0);
+    loop {
+        let l = rec.len();
+        let n = rdr
+            .read_until(b'\n', &mut rec)
+            .with_context(|| "Error reading input")?;
+        if n == 0 {
+            break;
+        }
+        let line_pos = input_pos;
+        input_pos = (input_pos.0 + 1, input_pos.1 + n as u64);
+        if rec[l] == b'>' {
+            let next = rec.split_off(l);
+            if l > 0 {
+                send(
+                    std::mem::replace(&mut rec, next),
+                    start,
+                    eval_start..u64::MAX,
+                    rec_pos,
+                )?
+            } else {
+                rec = next
+            }
+            rec_pos = line_pos;
+            skip = cfg.contig_filter().is_some_and(|f| {
+                let name = rec[1..].split(|c| c.is_ascii_whitespace()).next();
+                f.is_excluded(&String::from_utf8_lossy(name.unwrap_or(&[])))
+            });
+            if skip {
+                rec.clear()
+            }
+            hdr_len = rec.len();
+            (start, eval_start, eval_end) = (0, 0, None);
+            lines.clear();
+        } else if skip {
+            rec.truncate(l)
+        } else if hdr_len > 0 {
+            let n = lines.last().map(|(_, n)| *n).unwrap_or(0) + line_bases(&rec[l..]);
+            lines.push((rec.len(), n));
+            if eval_end.is_none() && n - (eval_start - start) as usize >= chunk {
+                eval_end = Some(n)
+            }
+            if let Some(e) = eval_end.filter(|e| n >= e + max_len) {
+                // Find the lines to carry over to the next chunk.  These cover twice the maximum
+                // read length so that if the parser trims a gap at the start of the chunk, the gap
+                // is long enough to split the sequence without chunking
+                let i = lines.partition_point(|(_, m)| *m + 2 * max_len <= e + 1);
+                let (off, m) = if i > 0 { lines[i - 1] } else { (hdr_len, 0) };
+                let mut next = rec[..hdr_len].to_vec();
+                next.extend_from_slice(&rec[off..]);
+                let end = start + e as u64;
+                send(
+                    std::mem::replace(&mut rec, next),
+                    start,
+                    eval_start..end,
+                    rec_pos,
+                )?;
+                rec_pos = (rec_pos.0 + i as u64, rec_pos.1 + (off - hdr_len) as u64);
+                (start, eval_start, eval_end) = (start + m as u64, end, None);
+                lines.retain(|(_, x)| *x > m);
+                for (o, x) in lines.iter_mut() {
+                    (*o, *x) = (*o - off + hdr_len, *x - m)
+                }
+            }
+        }
+    }
+    if !rec.is_empty() {
+        send(rec, start, eval_start..u64::MAX, rec_pos)?
     }
+    Ok(())
 }
 
-fn proc_in_seq(
-    c: u8,
-    sw: Option<&mut SeqWork>,
-    target_idx: Option<NonZeroU32>,
-) -> anyhow::Result<(RdrState, bool)> {
-    proc_in_gen(
-        c,
-        sw,
-        RdrState::InSeqAfterNewLine,
-        RdrState::StartGap,
-        RdrState::InSeq,
-        target_idx,
-    )
+/// Size of the blocks selected at random with --sample-fraction
+const SAMPLE_BLOCK: u64 = 100_000;
+
+/// Sends sequences for processing, keeping only a random subset of blocks if sampling
+struct SeqSender<'a> {
+    snd: &'a Sender<SeqBlock>,
+    contig_filter: Option<&'a ContigFilter>,
+    sample: Option<(f64, StdRng)>,
+    chunk: Option<(usize, usize)>,
 }
 
-fn proc_start_seq_after_new_line(c: u8) -> anyhow::Result<(RdrState, bool)> {
-    if c == b'>' {
-        Ok((RdrState::StartSeqId, false))
-    } else {
-        proc_start_seq(c)
+impl<'a> SeqSender<'a> {
+    fn new(cfg: &'a Config, snd: &'a Sender<SeqBlock>) -> Self {
+        let sample = cfg.sample_fraction().map(|f| {
+            info!("Sampling {f} of the input (seed {})", cfg.seed());
+            (f, StdRng::seed_from_u64(cfg.seed()))
+        });
+        let chunk = cfg.chunk_size().map(|c| (c, max_read_length(cfg) as usize));
+        Self {
+            snd,
+            contig_filter: cfg.contig_filter(),
+            sample,
+            chunk,
+        }
     }
-}
 
-fn proc_start_seq(c: u8) -> anyhow::Result<(RdrState, bool)> {
-    if c == b'\n' {
-        Ok((RdrState::StartSeqAfterNewLine, false))
-    } else if c.is_ascii_graphic() {
-        let gc = Base::from_u8(c);
-        Ok((
-            if gc.is_gap() {
-                RdrState::StartSeq
-            } else {
-                RdrState::StartSeqAfterInitialGap
-            },
-            true,
-        ))
-    } else {
-        Err(anyhow!("Illegal character in sequence"))
+    fn send(&mut self, s: Seq) -> anyhow::Result<()> {
+        if self
+            .contig_filter
+            .is_some_and(|f| f.is_excluded(s.contig()))
+        {
+            return Ok(());
+        }
+        if let Some((f, rng)) = self.sample.as_mut() {
+            let end = s.end();
+            let mut x = s.start();
+            while x < end {
+                if rng.gen_bool(*f) {
+                    if let Some(t) = s.subseq(x, x + SAMPLE_BLOCK) {
+                        send_seq(self.snd, t, self.chunk)?
+                    }
+                }
+                x += SAMPLE_BLOCK
+            }
+            Ok(())
+        } else {
+            send_seq(self.snd, s, self.chunk)
+        }
     }
 }
 
-fn proc_in_seq_id(c: u8, s: &mut String) -> anyhow::Result<RdrState> {
-    if c == b'\n' {
-        if let Some(i) = s.find(char::is_whitespace) {
-            s.truncate(i)
+/// Send sequence, splitting into chunks if chunk (size, max read length) is set.  Chunks are
+/// extended by the maximum read length - 1 before and the maximum read length after the eval
+/// range.  Gaps at the ends of chunks are kept (and chunks that are all gap are still sent), so
+/// that only the ends of the sequence are seen as sequence ends by the process threads
+pub(crate) fn send_seq(
+    snd: &Sender<SeqBlock>,
+    s: Seq,
+    chunk: Option<(usize, usize)>,
+) -> anyhow::Result<()> {
+    let send = |s, eval| {
+        let t = performance::start();
+        let r = snd
+            .send(SeqBlock::Seq(s, eval))
+            .with_context(|| "Error sending sequence for processing");
+        performance::add_time(Stage::SendWait, t);
+        r
+    };
+    match chunk {
+        Some((size, max_len)) if s.len() > size => {
+            let (start, end) = (s.start(), s.end());
+            let mut x = start;
+            while x < end {
+                let y = x + size as u64;
+                let eval = if x == start { 0 } else { x }..if y >= end { u64::MAX } else { y };
+                let l = max_len as u64;
+                if let Some(t) = s.slice((x + 1).saturating_sub(l), y + l) {
+                    send(t, eval)?
+                }
+                x = y
+            }
+            Ok(())
         }
-        Ok(RdrState::NewContig)
-    } else if c.is_ascii() && (c == b'\t' || !c.is_ascii_control()) {
-        s.push(c as char);
-        Ok(RdrState::InSeqId)
-    } else {
-        Err(anyhow!("Illegal character in sequence name"))
+        _ => send(s, ALL),
     }
 }
-fn proc_start_seq_id(c: u8, s: &mut String) -> anyhow::Result<RdrState> {
-    s.clear();
-    proc_in_seq_id(c, s)
+
+fn send_seqs<R: BufRead, F: BaseFilter>(
+    rdr: &mut FastaReader<R, F>,
+    spans: Option<&Spans>,
+    snd: &mut SeqSender,
+) -> anyhow::Result<()> {
+    while let Some(s) = rdr
+        .next_seq()
+        .with_context(|| "Error reading input sequence")?
+    {
+        // When reading sequentially with spans selected, only the parts of the sequence in the
+        // spans are sent
+        if let Some(sp) = spans {
+            for x in sp.get(s.contig()).unwrap_or(&[]) {
+                if let Some(t) = s.subseq(x.start(), x.end()) {
+                    snd.send(t)?
+                }
+            }
+        } else {
+            snd.send(s)?
+        }
+    }
+    Ok(())
 }
-fn proc_start(c: u8) -> anyhow::Result<RdrState> {
-    if c == b'>' {
-        Ok(RdrState::StartSeqId)
-    } else {
-        Err(anyhow!("Bad FASTA format: expecting '>'"))
+
+/// Read the selected spans from an uncompressed input file using the index
+fn read_indexed_spans<F: BaseFilter>(
+    input: &Path,
+    fai: &Path,
+    spans: &Spans,
+    policy: GapPolicy,
+    mut filter: F,
+    snd: &mut SeqSender,
+) -> anyhow::Result<F> {
+    info!("Using index {} to read selected regions", fai.display());
+    let entries = read_fai(fai)?;
+    if let Some(c) = spans
+        .contigs()
+        .find(|c| !entries.iter().any(|e| e.name() == *c))
+    {
+        return Err(anyhow!("Contig {c} from --region not found in input"));
     }
+    for e in entries.iter() {
+        for s in spans.get(e.name()).unwrap_or(&[]) {
+            if s.start() >= e.length() {
+                add_warning(
+                    "region_past_end",
+                    format!(
+                        "Region {}:{}- is past the end of the contig",
+                        e.name(),
+                        s.start() + 1
+                    ),
+                );
+                continue;
+            }
+            let r = open_span(input, e, s.start(), s.end())?;
+            // The header added by open_span is counted as directly preceding the span
+            let offset = e
+                .byte_offset(s.start())
+                .saturating_sub(e.name().len() as u64 + 2);
+            let mut rdr = FastaReader::with_filter(r, policy, filter)
+                .with_contig_start(s.start())
+                .with_input_position(None, offset);
+            send_seqs(&mut rdr, None, snd)?;
+            filter = rdr.into_base_filter()
+        }
+    }
+    Ok(filter)
 }
 
-pub fn reader(cfg: &Config, snd: Sender<Seq>) -> anyhow::Result<()> {
+/// Read input and send sequences for processing, returning the base filter.  If regions were
+/// selected, the index is used to seek to them when present, otherwise the input is read
+/// sequentially with bases outside of the regions masked
+fn read_input<F: BaseFilter>(
+    cfg: &Config,
+    filter: F,
+    snd: Sender<SeqBlock>,
+    (raw_taps, taps): (Vec<&mut dyn StreamTap>, Vec<&mut dyn StreamTap>),
+    counts: &mut CharCounts,
+) -> anyhow::Result<F> {
+    let snd = &mut SeqSender::new(cfg, &snd);
     debug!(
         "Opening {} for input",
         cfg.input().and_then(|s| s.to_str()).unwrap_or("<stdin>")
     );
-    let brdr = CompressIo::new()
-        .opt_path(cfg.input())
-        .bufreader()
-        .with_context(|| "Could not open input file/stream")?;
-
-    let max_rl = cfg.read_lengths().iter().max().unwrap();
-    let mut rdr = Rdr::new(brdr, *max_rl, cfg.target_regions());
+    let policy = gap_policy(cfg);
 
     info!("Starting to read input");
-    while let Some(s) = rdr
-        .get_seq()
-        .with_context(|| "Error reading input sequence")?
+    // Taps (checksums, repeat scan, composition, input digest) need the whole input to be read, so in that case
+    // we do not seek to the spans
+    if let Some(spans) = cfg
+        .spans()
+        .filter(|_| taps.is_empty() && raw_taps.is_empty())
     {
-        snd.send(s)
-            .with_context(|| "Error sending sequence for processing")?;
+        if let Some((input, fai)) = cfg.input().and_then(|p| find_fai(p).map(|f| (p, f))) {
+            let filter = read_indexed_spans(input, &fai, spans, policy, filter, snd)?;
+            info!("Finished reading input");
+            return Ok(filter);
+        }
     }
+    let brdr = TapReader::new(
+        CheckReader::new(
+            FastaInput::new(TapReader::new(
+                CompressIo::new()
+                    .opt_path(cfg.input())
+                    .bufreader()
+                    .with_context(|| "Could not open input file/stream")?,
+                raw_taps,
+            ))
+            .with_context(|| "Error reading from input")?,
+            cfg.on_duplicate(),
+            counts,
+        )
+        .with_strict(cfg.strict())
+        .with_other_limit(cfg.other_limit())
+        .with_log_contigs(cfg.log_contigs()),
+        taps,
+    );
+    let filter = if let Some(spans) = cfg.spans() {
+        let mut rdr = FastaReader::with_filter(brdr, policy, SpanFilter::new(spans, filter));
+        send_seqs(&mut rdr, Some(spans), snd)?;
+        let sf = rdr.into_base_filter();
+        if sf.contigs_found < spans.contigs().count() {
+            add_warning(
+                "unmatched_region_contig",
+                "Not all contigs from --region were found in input".to_owned(),
+            )
+        }
+        sf.inner
+    } else {
+        let mut rdr = FastaReader::with_filter(brdr, policy, filter);
+        send_seqs(&mut rdr, None, snd)?;
+        rdr.into_base_filter()
+    };
     info!("Finished reading input");
-    let k_work = rdr.k_work;
-    info!("{k_work}");
-    if let Some(reg) = cfg.target_regions() {
-        info!("Outputting information on kmers");
+    Ok(filter)
+}
 
-        let output = format!("{}_kmers.km", cfg.prefix());
+/// Information collected from the raw input stream
+#[derive(Default)]
+pub struct InputInfo {
+    pub(crate) contigs: Option<Vec<ContigSum>>,
+    pub(crate) repeats: Option<RepeatRes>,
+    /// Only present if the whole input was read sequentially
+    pub(crate) composition: Option<CharCounts>,
+    pub(crate) targets: Option<TargetSummary>,
+    pub(crate) digest: Option<InputDigest>,
+    pub(crate) composition_summary: Option<CompositionSummary>,
+    pub(crate) target_gc_length: Option<TargetGcLength>,
+}
 
-        kmcv::output_kmers(&output, reg, &k_work)
-            .with_context(|| format!("Could not generate output kmer file {output}"))?;
-    }
-    Ok(())
+/// Optional taps on the input stream
+struct Taps<'a> {
+    digest: Option<DigestState>,
+    sums: Option<ChecksumState>,
+    scan: Option<RepeatScan>,
+    composition: Option<CompositionScan<'a>>,
 }
 
-mod test {
-    #[allow(unused_imports)]
-    use super::*;
-    #[allow(unused_imports)]
-    use std::io::BufReader;
-
-    #[test]
-    fn test1() {
-        let s = ">seq1\nACTNNCCGT\nNACCAGTNNNNC\n>seq2\nNNN\n>seq3\nNNNNNNNNN\nNNNACTCNNN\n";
-        let b = BufReader::new(s.as_bytes());
-        let mut rdr = Rdr::new(b, 4, None);
-        let exp_len = [16, 1, 4];
-        for l in exp_len {
-            let a = rdr.get_seq().unwrap().unwrap();
-            println!("{:?}", a);
-            assert_eq!(a.len(), l);
-        }
-        let a = rdr.get_seq().unwrap();
-        assert!(a.is_none());
+impl<'a> Taps<'a> {
+    fn new(cfg: &'a Config) -> Self {
+        Self {
+            digest: cfg.input_digest().then(DigestState::default),
+            sums: cfg.checksums().then(ChecksumState::new),
+            scan: cfg
+                .repeat_motifs()
+                .map(|m| RepeatScan::new(m, cfg.min_repeat_length())),
+            composition: cfg
+                .composition()
+                .then(|| CompositionScan::new(cfg.target_regions())),
+        }
     }
 
-    #[test]
-    fn test2() {
-        let s = ">seq1\nACTNNCCGT\nNACCAGTNNNNC\n>seq2\nNNN\n>seq3\nNNNNNNNNN\nNNNACTCNNN\n";
-        let b = BufReader::with_capacity(16, s.as_bytes());
-        let mut rdr = Rdr::new(b, 4, None);
-        let exp_len = [16, 1, 4];
-        for l in exp_len {
-            let a = rdr.get_seq().unwrap().unwrap();
-            println!("{:?}", a);
-            assert_eq!(a.len(), l);
-        }
-        let a = rdr.get_seq().unwrap();
-        assert!(a.is_none());
+    /// Taps on the raw input stream and on the stream after checking for duplicate records
+    fn as_vecs(&mut self) -> (Vec<&mut dyn StreamTap>, Vec<&mut dyn StreamTap>) {
+        let mut raw: Vec<&mut dyn StreamTap> = Vec::new();
+        if let Some(s) = self.digest.as_mut() {
+            raw.push(s)
+        }
+        let mut v: Vec<&mut dyn StreamTap> = Vec::new();
+        if let Some(s) = self.sums.as_mut() {
+            v.push(s)
+        }
+        if let Some(s) = self.scan.as_mut() {
+            v.push(s)
+        }
+        if let Some(s) = self.composition.as_mut() {
+            v.push(s)
+        }
+        (raw, v)
     }
 
-    #[test]
-    fn test3() {
-        let s = ">seq1\nACTNNCCGT\nNACCAGTNNNNC\n>seq2\nNNN\n>seq3\nNNNNNNNNN\nNNNACTCNNN\n";
-        let b = BufReader::with_capacity(30, s.as_bytes());
-        let mut rdr = Rdr::new(b, 4, None);
-        let exp_len = [16, 1, 4];
-        for l in exp_len {
-            let a = rdr.get_seq().unwrap().unwrap();
-            println!("{:?}", a);
-            assert_eq!(a.len(), l);
-        }
-        let a = rdr.get_seq().unwrap();
-        assert!(a.is_none());
+    fn finish(self) -> InputInfo {
+        InputInfo {
+            contigs: self.sums.map(|s| s.finish()),
+            repeats: self.scan.map(|s| s.finish()),
+            digest: self.digest.map(|s| s.finish()),
+            composition_summary: self.composition.map(|s| s.finish()),
+            ..Default::default()
+        }
     }
 }
+
+/// Read the input and send it for processing, returning information collected from the input
+pub fn reader(cfg: &Config, snd: Sender<SeqBlock>) -> anyhow::Result<InputInfo> {
+    let mut taps = Taps::new(cfg);
+    let mut counts = CharCounts::default();
+    let t = performance::start();
+    let mut info = if let Some(reg) = cfg.target_regions() {
+        // The kmer table is large, so we only allocate it when we have targets
+        let filter = read_input(
+            cfg,
+            TargetFilter::new(
+                reg,
+                cfg.bisulfite(),
+                !cfg.split_targets(),
+                cfg.kmer_contigs(),
+            ),
+            snd,
+            taps.as_vecs(),
+            &mut counts,
+        )?;
+        performance::add_time(Stage::Read, t);
+        let t = performance::start();
+        // With --region only part of the input is seen
+        if cfg.spans().is_none() {
+            let missing: Vec<_> = reg
+                .iter()
+                .map(|(c, _)| c)
+                .filter(|c| !filter.found.contains(*c))
+                .collect();
+            if !missing.is_empty() {
+                add_warning(
+                    "unmatched_target_contig",
+                    format!(
+                        "Contigs from target file not found in input: {}",
+                        missing.join(", ")
+                    ),
+                )
+            }
+        }
+        let mut info = taps.finish();
+        let prov = Provenance::new(info.contigs.as_deref()).with_input_digest(info.digest.as_ref());
+        let k_work = &filter.k_work;
+        info!("{k_work}");
+        info!("Outputting information on kmers");
+
+        let output = format!("{}_kmers.km", cfg.prefix());
+
+        let unique = kmcv::output_kmers(&output, reg, k_work, &prov, cfg.kmer_writer())
+            .with_context(|| OutputError::new(format!("output kmer file {output}")))?;
+        add_output_file(&output);
+        let stats = kmcv::contig_kmer_stats(reg, k_work, &filter.contig_kmers);
+        let kmer_file = output;
+        let output = format!("{}_kmers_contigs.json", cfg.prefix());
+        kmcv::output_contig_stats(&output, &kmer_file, &stats, &prov)
+            .with_context(|| OutputError::new(format!("kmer contig statistics file {output}")))?;
+        add_output_file(&output);
+        if let Some(f) = cfg.shared_kmers() {
+            let output = format!("{}_shared_kmers.txt", cfg.prefix());
+            output_shared_kmers(&output, reg, k_work, f)
+                .with_context(|| OutputError::new(format!("shared kmer file {output}")))?;
+            add_output_file(&output)
+        }
+        if let Some(f) = cfg.kmer_text() {
+            let output = format!("{}_kmers.txt", cfg.prefix());
+            output_kmer_text(&output, k_work, f)
+                .with_context(|| OutputError::new(format!("kmer text file {output}")))?;
+            add_output_file(&output)
+        }
+        let risk = target_risk(reg, k_work, &filter.stats, cfg.risk_weights());
+        info.targets = Some(
+            TargetSummary::new(
+                reg.n_regions(),
+                filter.stats.covered(),
+                k_work.on_target_kmers(),
+                unique,
+            )
+            .with_risk(RiskSummary::new(
+                &risk,
+                *cfg.risk_weights(),
+                cfg.risk_threshold(),
+            )),
+        );
+
+        if let Some(c) = filter.conv.as_ref() {
+            for w in [&c.ct_work, &c.ga_work] {
+                let conv = w.conversion().unwrap().name();
+                info!("Converted ({conv}) {w}");
+                let output = format!("{}_kmers_{conv}.km", cfg.prefix());
+                kmcv::output_kmers(&output, reg, w, &prov, cfg.kmer_writer()).with_context(
+                    || OutputError::new(format!("output converted kmer file {output}")),
+                )?;
+                add_output_file(&output)
+            }
+        }
+
+        let output = format!("{}_targets.txt", cfg.prefix());
+        output_target_stats(&output, reg, &filter.stats, &risk)
+            .with_context(|| OutputError::new(format!("target statistics file {output}")))?;
+        add_output_file(&output);
+
+        if cfg.gatk_intervals() {
+            let output = format!("{}_gatk_intervals.tsv", cfg.prefix());
+            match sequence_dictionary(cfg, &counts) {
+                Some(dict) => {
+                    write_gatk_intervals(&output, reg, &filter.stats, &dict).with_context(
+                        || OutputError::new(format!("GATK intervals file {output}")),
+                    )?;
+                    add_output_file(&output)
+                }
+                None => add_warning(
+                    "gatk_intervals_no_dictionary",
+                    "GATK intervals file not written: contig lengths are not known (input not read completely and no index found)"
+                        .to_owned(),
+                ),
+            }
+        }
+
+        if cfg.target_gc_length() {
+            let hist = TargetGcLength::new(reg, &filter.stats);
+            let output = format!("{}_target_gc_length.txt", cfg.prefix());
+            hist.write(&output)
+                .with_context(|| OutputError::new(format!("target GC length file {output}")))?;
+            add_output_file(&output);
+            info.target_gc_length = Some(hist)
+        }
+
+        let output = format!("{}_target_map.txt", cfg.prefix());
+        output_target_map(&output, reg)
+            .with_context(|| OutputError::new(format!("target map file {output}")))?;
+        add_output_file(&output);
+
+        // The mappability track and matched controls share a pass over the input
+        let map_output = format!("{}_mappability.bedGraph", cfg.prefix());
+        let mut mappability = if cfg.mappability() {
+            Some(MappabilityTrack::new(reg, k_work, &map_output)?)
+        } else {
+            None
+        };
+        let mut controls = cfg
+            .matched_controls()
+            .map(|n| MatchedControls::new(cfg, reg, &filter.stats, n));
+        if mappability.is_some() || controls.is_some() {
+            info!("Re-reading input for mappability track and matched control regions");
+            rescan_selected(cfg, |s| {
+                if let Some(m) = mappability.as_mut() {
+                    m.add_seq(s)?
+                }
+                if let Some(c) = controls.as_mut() {
+                    c.add_seq(s)?
+                }
+                Ok(())
+            })?
+        }
+        if let Some(m) = mappability {
+            m.finish()?;
+            add_output_file(&map_output)
+        }
+        if let Some(c) = controls {
+            let output = format!("{}_matched_controls.bed", cfg.prefix());
+            c.finish(&output)?;
+            add_output_file(&output)
+        }
+        performance::add_time(Stage::Kmers, t);
+        info
+    } else if cfg.spans().is_some() || cfg.sample_fraction().is_some() {
+        read_input(cfg, NoFilter, snd, taps.as_vecs(), &mut counts)?;
+        performance::add_time(Stage::Read, t);
+        taps.finish()
+    } else {
+        // No per base work needed in the reader, so records are parsed by the process threads
+        debug!(
+            "Opening {} for input",
+            cfg.input().and_then(|s| s.to_str()).unwrap_or("<stdin>")
+        );
+        let (raw_taps, stream_taps) = taps.as_vecs();
+        let brdr = TapReader::new(
+            CheckReader::new(
+                FastaInput::new(TapReader::new(
+                    CompressIo::new()
+                        .opt_path(cfg.input())
+                        .bufreader()
+                        .with_context(|| "Could not open input file/stream")?,
+                    raw_taps,
+                ))
+                .with_context(|| "Error reading from input")?,
+                cfg.on_duplicate(),
+                &mut counts,
+            )
+            .with_strict(cfg.strict())
+            .with_other_limit(cfg.other_limit())
+            .with_log_contigs(cfg.log_contigs()),
+            stream_taps,
+        );
+        info!("Starting to read input");
+        send_records(cfg, brdr, &snd)?;
+        info!("Finished reading input");
+        performance::add_time(Stage::Read, t);
+        taps.finish()
+    };
+    info.composition = Some(counts).filter(|c| c.is_complete());
+    Ok(info)
+}
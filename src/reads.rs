@@ -0,0 +1,319 @@
+//! Reading of sequence reads from FASTQ, SAM or BAM files (compressed or uncompressed).  CRAM files
+//! are detected and rejected, as decoding them needs the reference used for the alignments
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+};
+
+use anyhow::Context;
+use compress_io::compress::{CompressIo, Reader};
+
+/// Number of reads sampled when inferring read lengths
+const INFER_SAMPLE_SIZE: usize = 100_000;
+
+/// Minimum proportion of sampled reads for a read length to be selected
+const INFER_MIN_FREQ: f64 = 0.05;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ReadFormat {
+    Fastq,
+    Sam,
+    Bam,
+}
+
+/// A single read.  For FASTQ input the flag is always 0
+#[derive(Debug, Default)]
+pub struct ReadRec {
+    seq: Vec<u8>,
+    flag: u16,
+}
+
+impl ReadRec {
+    pub fn seq(&self) -> &[u8] {
+        &self.seq
+    }
+
+    pub fn flag(&self) -> u16 {
+        self.flag
+    }
+
+    /// Returns false for secondary and supplementary alignments
+    pub fn is_primary(&self) -> bool {
+        (self.flag & 0x900) == 0
+    }
+}
+
+pub struct ReadRdr<R: BufRead> {
+    r: R,
+    format: ReadFormat,
+    buf: Vec<u8>,
+}
+
+fn read_u32<R: BufRead>(r: &mut R) -> std::io::Result<u32> {
+    let mut b = [0u8; 4];
+    r.read_exact(&mut b)?;
+    Ok(u32::from_le_bytes(b))
+}
+
+fn skip_bytes<R: BufRead>(r: &mut R, n: u64) -> std::io::Result<()> {
+    let k = std::io::copy(&mut Read::take(r, n), &mut std::io::sink())?;
+    if k < n {
+        Err(std::io::ErrorKind::UnexpectedEof.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Guess format from the start of the (uncompressed) input
+fn detect_format(buf: &[u8]) -> anyhow::Result<ReadFormat> {
+    if buf.starts_with(b"CRAM") {
+        Err(anyhow!(
+            "CRAM input is not supported (reference based decoding required): convert to BAM (e.g., samtools view -b -T <reference> -o <out.bam> <in.cram>)"
+        ))
+    } else if buf.starts_with(b"BAM\x01") {
+        Ok(ReadFormat::Bam)
+    } else if buf.len() >= 4
+        && buf[0] == b'@'
+        && buf[1].is_ascii_uppercase()
+        && buf[2].is_ascii_uppercase()
+        && buf[3] == b'\t'
+    {
+        // SAM header line
+        Ok(ReadFormat::Sam)
+    } else if buf.starts_with(b"@") {
+        Ok(ReadFormat::Fastq)
+    } else if buf
+        .split(|c| *c == b'\n')
+        .next()
+        .map(|l| l.split(|c| *c == b'\t').count() >= 11)
+        .unwrap_or(false)
+    {
+        // SAM without header
+        Ok(ReadFormat::Sam)
+    } else {
+        Err(anyhow!("Could not determine format of read file"))
+    }
+}
+
+impl<R: BufRead> ReadRdr<R> {
+    pub fn new(mut r: R) -> anyhow::Result<Self> {
+        let format = detect_format(r.fill_buf()?)?;
+        debug!("Read file format: {format:?}");
+        let mut rdr = Self {
+            r,
+            format,
+            buf: Vec::new(),
+        };
+        if format == ReadFormat::Bam {
+            rdr.skip_bam_header()
+                .with_context(|| "Error reading BAM header")?
+        }
+        Ok(rdr)
+    }
+
+    pub fn format(&self) -> ReadFormat {
+        self.format
+    }
+
+    fn skip_bam_header(&mut self) -> std::io::Result<()> {
+        skip_bytes(&mut self.r, 4)?;
+        let l_text = read_u32(&mut self.r)?;
+        skip_bytes(&mut self.r, l_text as u64)?;
+        let n_ref = read_u32(&mut self.r)?;
+        for _ in 0..n_ref {
+            let l_name = read_u32(&mut self.r)?;
+            skip_bytes(&mut self.r, l_name as u64 + 4)?;
+        }
+        Ok(())
+    }
+
+    /// Read next line into self.buf (without the trailing newline).  Returns false on EOF
+    fn next_line(&mut self) -> anyhow::Result<bool> {
+        self.buf.clear();
+        if self.r.read_until(b'\n', &mut self.buf)? == 0 {
+            Ok(false)
+        } else {
+            if self.buf.last() == Some(&b'\n') {
+                self.buf.pop();
+            }
+            if self.buf.last() == Some(&b'\r') {
+                self.buf.pop();
+            }
+            Ok(true)
+        }
+    }
+
+    fn next_fastq(&mut self, rec: &mut ReadRec) -> anyhow::Result<bool> {
+        if !self.next_line()? {
+            return Ok(false);
+        }
+        if !self.buf.starts_with(b"@") {
+            return Err(anyhow!("Bad FASTQ format: expecting '@'"));
+        }
+        if !self.next_line()? {
+            return Err(anyhow!("Truncated FASTQ record"));
+        }
+        rec.seq.clear();
+        rec.seq.extend_from_slice(&self.buf);
+        rec.flag = 0;
+        if !self.next_line()? || !self.buf.starts_with(b"+") {
+            return Err(anyhow!("Bad FASTQ format: expecting '+'"));
+        }
+        if !self.next_line()? {
+            return Err(anyhow!("Truncated FASTQ record"));
+        }
+        Ok(true)
+    }
+
+    fn next_sam(&mut self, rec: &mut ReadRec) -> anyhow::Result<bool> {
+        loop {
+            if !self.next_line()? {
+                return Ok(false);
+            }
+            if !self.buf.starts_with(b"@") && !self.buf.is_empty() {
+                break;
+            }
+        }
+        let mut itr = self.buf.split(|c| *c == b'\t');
+        let flag = itr.nth(1).ok_or_else(|| anyhow!("Short SAM line"))?;
+        rec.flag = std::str::from_utf8(flag)?
+            .parse::<u16>()
+            .with_context(|| "Bad SAM flag")?;
+        let seq = itr.nth(7).ok_or_else(|| anyhow!("Short SAM line"))?;
+        rec.seq.clear();
+        if seq != b"*" {
+            rec.seq.extend_from_slice(seq)
+        }
+        Ok(true)
+    }
+
+    fn next_bam(&mut self, rec: &mut ReadRec) -> anyhow::Result<bool> {
+        const NT16: &[u8; 16] = b"=ACMGRSVTWYHKDBN";
+
+        let block_size = match read_u32(&mut self.r) {
+            Ok(x) => x as usize,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        if block_size < 32 {
+            return Err(anyhow!("Bad BAM record size"));
+        }
+        self.buf.resize(block_size, 0);
+        self.r
+            .read_exact(&mut self.buf)
+            .with_context(|| "Truncated BAM record")?;
+        let b = &self.buf;
+        let l_read_name = b[8] as usize;
+        let n_cigar_op = u16::from_le_bytes([b[12], b[13]]) as usize;
+        rec.flag = u16::from_le_bytes([b[14], b[15]]);
+        let l_seq = u32::from_le_bytes([b[16], b[17], b[18], b[19]]) as usize;
+        let off = 32 + l_read_name + 4 * n_cigar_op;
+        if off + ((l_seq + 1) >> 1) > block_size {
+            return Err(anyhow!("Bad BAM record"));
+        }
+        rec.seq.clear();
+        rec.seq.extend(
+            b[off..off + ((l_seq + 1) >> 1)]
+                .iter()
+                .flat_map(|x| [NT16[(x >> 4) as usize], NT16[(x & 15) as usize]])
+                .take(l_seq),
+        );
+        Ok(true)
+    }
+
+    /// Read next read into rec.  Returns false at the end of the input
+    pub fn next_read(&mut self, rec: &mut ReadRec) -> anyhow::Result<bool> {
+        match self.format {
+            ReadFormat::Fastq => self.next_fastq(rec),
+            ReadFormat::Sam => self.next_sam(rec),
+            ReadFormat::Bam => self.next_bam(rec),
+        }
+    }
+}
+
+pub fn open_reads<P: AsRef<Path>>(path: P) -> anyhow::Result<ReadRdr<BufReader<Reader>>> {
+    let path = path.as_ref();
+    let rdr = CompressIo::new()
+        .path(path)
+        .bufreader()
+        .with_context(|| format!("Could not open read file {}", path.display()))?;
+    ReadRdr::new(rdr).with_context(|| format!("Error reading from {}", path.display()))
+}
+
+/// Count read lengths of up to n primary reads from path
+pub fn sample_read_lengths<P: AsRef<Path>>(
+    path: P,
+    n: usize,
+) -> anyhow::Result<BTreeMap<u32, u64>> {
+    let mut rdr = open_reads(path)?;
+    let mut rec = ReadRec::default();
+    let mut hist = BTreeMap::new();
+    let mut k = 0;
+    while k < n && rdr.next_read(&mut rec)? {
+        if rec.is_primary() && !rec.seq().is_empty() {
+            *hist.entry(rec.seq().len() as u32).or_insert(0) += 1;
+            k += 1
+        }
+    }
+    Ok(hist)
+}
+
+/// Pick the dominant read lengths from a sample of reads
+pub fn infer_read_lengths<P: AsRef<Path>>(path: P) -> anyhow::Result<Vec<u32>> {
+    let path = path.as_ref();
+    info!("Inferring read lengths from {}", path.display());
+    let hist = sample_read_lengths(path, INFER_SAMPLE_SIZE)?;
+    let total = hist.values().sum::<u64>();
+    if total == 0 {
+        return Err(anyhow!("No reads found in {}", path.display()));
+    }
+    let mut v: Vec<_> = hist
+        .iter()
+        .filter(|(_, n)| (**n as f64) / (total as f64) >= INFER_MIN_FREQ)
+        .map(|(l, _)| *l)
+        .collect();
+    // Fall back to the modal length if no length is sufficiently common
+    if v.is_empty() {
+        v.push(*hist.iter().max_by_key(|(_, n)| **n).unwrap().0)
+    }
+    for l in v.iter() {
+        info!(
+            "Read length {l}: {:.2}% of {total} sampled reads",
+            100.0 * (hist[l] as f64) / (total as f64)
+        )
+    }
+    Ok(v)
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_fastq() {
+        let s = "@r1\nACGT\n+\nIIII\n@r2\nACGTAC\n+r2\nIIIIII\n";
+        let mut rdr = ReadRdr::new(s.as_bytes()).unwrap();
+        assert_eq!(rdr.format(), ReadFormat::Fastq);
+        let mut rec = ReadRec::default();
+        for l in [4, 6] {
+            assert!(rdr.next_read(&mut rec).unwrap());
+            assert_eq!(rec.seq().len(), l);
+        }
+        assert!(!rdr.next_read(&mut rec).unwrap());
+    }
+
+    #[test]
+    fn test_sam() {
+        let s = "@HD\tVN:1.6\nr1\t0\tchr1\t1\t60\t4M\t*\t0\t0\tACGT\tIIII\nr1\t256\tchr1\t9\t0\t4M\t*\t0\t0\t*\t*\n";
+        let mut rdr = ReadRdr::new(s.as_bytes()).unwrap();
+        assert_eq!(rdr.format(), ReadFormat::Sam);
+        let mut rec = ReadRec::default();
+        assert!(rdr.next_read(&mut rec).unwrap());
+        assert_eq!(rec.seq(), b"ACGT");
+        assert!(rec.is_primary());
+        assert!(rdr.next_read(&mut rec).unwrap());
+        assert!(!rec.is_primary());
+        assert!(!rdr.next_read(&mut rec).unwrap());
+    }
+}
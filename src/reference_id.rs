@@ -0,0 +1,79 @@
+//! Reference identifier derived from the sequence dictionary when --identifier is not given
+//!
+//! The identifier has the form ``GRCh38-like, 195 contigs, md5:<digest>``, where the assembly is
+//! guessed from the length of chr1 (or 1) for a few common assemblies, and the digest is the MD5
+//! of the lines ``name<TAB>length<LF>`` for each contig in input order.  The digest changes with
+//! any change to the contig names, order or lengths, so can be used to detect a mismatch between
+//! a model and a reference.
+use std::fmt::Write;
+
+use crate::{
+    cli::Config,
+    fasta::fai::{find_fai, read_fai},
+    input_check::CharCounts,
+};
+
+/// Length of chr1 for common assemblies
+const ASSEMBLIES: [(&str, u64); 5] = [
+    ("GRCh38", 248956422),
+    ("GRCh37", 249250621),
+    ("T2T-CHM13", 248387328),
+    ("GRCm39", 195154279),
+    ("GRCm38", 195471971),
+];
+
+fn assembly<'a, I: Iterator<Item = (&'a str, u64)>>(mut dict: I) -> Option<&'static str> {
+    let (_, len) = dict.find(|(name, _)| *name == "chr1" || *name == "1")?;
+    ASSEMBLIES.iter().find(|(_, l)| *l == len).map(|(a, _)| *a)
+}
+
+/// Identifier from the (name, length) of each contig
+pub fn identifier_from_dict<'a, I>(dict: I) -> String
+where
+    I: Iterator<Item = (&'a str, u64)> + Clone,
+{
+    let mut ctx = md5::Context::new();
+    let mut n = 0;
+    for (name, len) in dict.clone() {
+        ctx.consume(format!("{name}\t{len}\n"));
+        n += 1
+    }
+    let mut s = String::new();
+    if let Some(a) = assembly(dict) {
+        write!(s, "{a}-like, ").unwrap()
+    }
+    write!(s, "{n} contigs, md5:{:x}", ctx.compute()).unwrap();
+    s
+}
+
+/// Identifier from the contigs seen when the whole input was read, or otherwise from the index of
+/// the input if present
+pub fn derive_identifier(cfg: &Config, counts: Option<&CharCounts>) -> Option<String> {
+    let id = if let Some(c) = counts.filter(|c| !c.dictionary().is_empty()) {
+        identifier_from_dict(c.dictionary().iter().map(|(s, l)| (s.as_ref(), *l)))
+    } else {
+        let entries = cfg
+            .input()
+            .and_then(find_fai)
+            .and_then(|f| read_fai(f).ok())?;
+        identifier_from_dict(entries.iter().map(|e| (e.name(), e.length())))
+    };
+    info!("Reference identifier (derived): {id}");
+    Some(id)
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_identifier() {
+        let dict = [("chr1", 248956422), ("chr2", 242193529)];
+        let id = identifier_from_dict(dict.iter().copied());
+        assert!(id.starts_with("GRCh38-like, 2 contigs, md5:"));
+        assert_eq!(id.len(), "GRCh38-like, 2 contigs, md5:".len() + 32);
+        let id1 = identifier_from_dict([("chr1", 1000), ("chr2", 242193529)].into_iter());
+        assert!(id1.starts_with("2 contigs, md5:"));
+        assert_ne!(id[id.len() - 32..], id1[id1.len() - 32..]);
+    }
+}
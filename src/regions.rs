@@ -1,4 +1,6 @@
-pub mod regions;
 pub mod read_bed;
+#[allow(clippy::module_inception)]
+pub mod regions;
+pub mod span;
 
 pub use regions::*;
@@ -1,11 +1,68 @@
-use std::{io::BufRead, num::NonZeroU32, path::Path};
+use std::{io::BufRead, path::Path};
 
 use anyhow::Context;
 use compress_io::compress::CompressIo;
 
-use super::{Region, Regions};
+use super::{Region, RegionOrigin, Regions, Strand};
+use crate::error::RegionError;
 
-pub fn read_bed<P: AsRef<Path>>(path: P) -> anyhow::Result<Regions> {
+/// Parse comma separated list of integers from BED12 blockSizes or blockStarts
+fn parse_block_list(s: &str, n: usize) -> anyhow::Result<Vec<u64>> {
+    let v = s
+        .trim_end_matches(',')
+        .split(',')
+        .map(|x| x.parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()?;
+    if v.len() != n {
+        Err(anyhow!("Expected {n} values, found {}", v.len()))
+    } else {
+        Ok(v)
+    }
+}
+
+/// Get (start, end) of blocks from BED12 line
+fn bed12_blocks(fields: &[&str], start: u64, end: u64) -> anyhow::Result<Vec<(u64, u64)>> {
+    if fields.len() < 12 {
+        return Err(anyhow!("Not a BED12 line"));
+    }
+    let n = fields[9]
+        .parse::<usize>()
+        .with_context(|| "Bad blockCount value")?;
+    let sizes = parse_block_list(fields[10], n).with_context(|| "Bad blockSizes value")?;
+    let starts = parse_block_list(fields[11], n).with_context(|| "Bad blockStarts value")?;
+    sizes
+        .iter()
+        .zip(starts.iter())
+        .map(|(sz, st)| {
+            let s = start + st;
+            let e = s + sz;
+            if *sz == 0 || e > end {
+                Err(anyhow!("Block outside of region"))
+            } else {
+                Ok((s, e))
+            }
+        })
+        .collect()
+}
+
+/// Read regions from a BED file.  If weight_col is set, region weights are taken from that
+/// column (1 based).  If blocks is set, the blocks from BED12 lines are used rather than the
+/// whole span.  Errors have a [`RegionError`] as context
+pub fn read_bed<P: AsRef<Path>>(
+    path: P,
+    weight_col: Option<usize>,
+    blocks: bool,
+) -> anyhow::Result<Regions> {
+    let path = path.as_ref();
+    parse_bed(path, weight_col, blocks).with_context(|| {
+        RegionError::new(format!(
+            "Error reading target regions from {}",
+            path.display()
+        ))
+    })
+}
+
+fn parse_bed(path: &Path, weight_col: Option<usize>, blocks: bool) -> anyhow::Result<Regions> {
     let mut rdr = CompressIo::new()
         .path(path)
         .bufreader()
@@ -21,7 +78,8 @@ pub fn read_bed<P: AsRef<Path>>(path: P) -> anyhow::Result<Regions> {
         .with_context(|| format!("Error reading line {} from bed file", line + 1))?
         > 0
     {
-        let mut itr = buf.trim_end().split('\t');
+        let fields: Vec<_> = buf.trim_end().split('\t').collect();
+        let mut itr = fields.iter().copied();
 
         let ctg = itr
             .next()
@@ -29,12 +87,12 @@ pub fn read_bed<P: AsRef<Path>>(path: P) -> anyhow::Result<Regions> {
         let start = itr
             .next()
             .ok_or_else(|| anyhow!("Missing start information at line {}", line + 1))?
-            .parse::<u32>()
+            .parse::<u64>()
             .with_context(|| format!("Bad start value at line {}", line + 1))?;
         let end = itr
             .next()
             .ok_or_else(|| anyhow!("Missing end information at line {}", line + 1))?
-            .parse::<u32>()
+            .parse::<u64>()
             .with_context(|| format!("Bad end value at line {}", line + 1))?;
 
         if end <= start {
@@ -44,21 +102,81 @@ pub fn read_bed<P: AsRef<Path>>(path: P) -> anyhow::Result<Regions> {
             ));
         }
 
+        let weight = match weight_col {
+            Some(c) => {
+                let w = fields
+                    .get(c - 1)
+                    .ok_or_else(|| anyhow!("Missing weight column at line {}", line + 1))?
+                    .parse::<f32>()
+                    .with_context(|| format!("Bad weight value at line {}", line + 1))?;
+                if !(w.is_finite() && w >= 0.0) {
+                    return Err(anyhow!(
+                        "Weight values should be non-negative at line {}",
+                        line + 1
+                    ));
+                }
+                w
+            }
+            None => 1.0,
+        };
+
+        let strand = match fields.get(5) {
+            Some(s) => Strand::from_bed(s)
+                .ok_or_else(|| anyhow!("Bad strand value at line {}", line + 1))?,
+            None => Strand::Unknown,
+        };
+
+        let spans = if blocks {
+            bed12_blocks(&fields, start, end)
+                .with_context(|| format!("Error reading blocks at line {}", line + 1))?
+        } else {
+            vec![(start, end)]
+        };
+
         line += 1;
-        regs.get_or_insert_contig_regions(ctg)
-            .add_region(Region::new(
-                start,
-                end - start,
-                NonZeroU32::try_from(line).unwrap(),
-            ));
+        let name = fields.get(3).copied();
+        for (s, e) in spans {
+            let idx = regs.add_origin(RegionOrigin::new(line, name, s, e));
+            regs.get_or_insert_contig_regions(ctg).add_region(
+                Region::new(s, e - s, idx)
+                    .with_weight(weight)
+                    .with_strand(strand),
+            );
+        }
 
         buf.clear();
     }
 
+    regs.set_weighted(weight_col.is_some());
     debug!("Read in {line} regions. Normalizing regions");
     let n_regs = regs.normalize();
 
     debug!("Normalizing complete with {n_regs} non-overlapping regions retained");
+    let ov = regs.overlaps();
+    if ov.intervals_merged() > 0 {
+        info!(
+            "{} of {} target intervals merged, with {} bp of overlap (largest cluster {} intervals)",
+            ov.intervals_merged(),
+            ov.input_intervals(),
+            ov.overlap_bp(),
+            ov.largest_cluster()
+        )
+    }
 
     Ok(regs)
 }
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_bed12_blocks() {
+        let l = "chr1\t1000\t2000\ttx1\t0\t+\t1000\t2000\t0\t3\t100,200,50,\t0,400,950,";
+        let fields: Vec<_> = l.split('\t').collect();
+        let v = bed12_blocks(&fields, 1000, 2000).unwrap();
+        assert_eq!(v, vec![(1000, 1100), (1400, 1600), (1950, 2000)]);
+        assert!(bed12_blocks(&fields, 1000, 1900).is_err());
+        assert!(bed12_blocks(&fields[..6], 1000, 2000).is_err());
+    }
+}
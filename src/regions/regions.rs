@@ -1,12 +1,65 @@
-use std::{cmp::Ordering, collections::HashMap, num::NonZeroU32};
+use std::{cmp::Ordering, num::NonZeroU32};
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Strand {
+    #[default]
+    Unknown,
+    Plus,
+    Minus,
+}
+
+impl Strand {
+    /// Parse strand from BED column 6
+    pub fn from_bed(s: &str) -> Option<Self> {
+        match s {
+            "+" => Some(Self::Plus),
+            "-" => Some(Self::Minus),
+            "." => Some(Self::Unknown),
+            _ => None,
+        }
+    }
+
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::Unknown => 0,
+            Self::Plus => 1,
+            Self::Minus => 2,
+        }
+    }
+
+    /// Strand as written in BED column 6
+    pub fn bed_str(&self) -> &'static str {
+        match self {
+            Self::Unknown => ".",
+            Self::Plus => "+",
+            Self::Minus => "-",
+        }
+    }
+
+    pub fn from_code(x: u8) -> Option<Self> {
+        match x {
+            0 => Some(Self::Unknown),
+            1 => Some(Self::Plus),
+            2 => Some(Self::Minus),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Region {
-    start: u32, // zero offset from start of contig
-    size: u32,
+    start: u64, // zero offset from start of contig
+    size: u64,
     idx: NonZeroU32,
+    weight: f32,
+    strand: Strand,
 }
 
+impl Eq for Region {}
+
 impl PartialOrd for Region {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
@@ -23,12 +76,39 @@ impl Ord for Region {
 }
 
 impl Region {
-    pub fn new(start: u32, size: u32, idx: NonZeroU32) -> Self {
-        Self { start, size, idx }
+    pub fn new(start: u64, size: u64, idx: NonZeroU32) -> Self {
+        Self {
+            start,
+            size,
+            idx,
+            weight: 1.0,
+            strand: Strand::Unknown,
+        }
+    }
+
+    pub fn with_strand(mut self, strand: Strand) -> Self {
+        self.strand = strand;
+        self
+    }
+
+    #[inline]
+    pub fn strand(&self) -> Strand {
+        self.strand
+    }
+
+    pub fn with_weight(mut self, weight: f32) -> Self {
+        self.weight = weight;
+        self
     }
 
+    /// Weight of region contribution to the GC histograms (1 unless set from the BED file)
     #[inline]
-    pub fn start(&self) -> u32 {
+    pub fn weight(&self) -> f32 {
+        self.weight
+    }
+
+    #[inline]
+    pub fn start(&self) -> u64 {
         self.start
     }
 
@@ -38,7 +118,7 @@ impl Region {
     }
 
     #[inline]
-    pub fn end(&self) -> u32 {
+    pub fn end(&self) -> u64 {
         self.start + self.size
     }
 }
@@ -57,44 +137,158 @@ impl ContigRegions {
         &self.regions
     }
 
-    pub(super) fn sort_and_merge(&mut self, mut ix: u32) -> u32 {
+    /// Sort and merge overlapping regions, renumbering the merged regions from ix + 1.  Pairs of
+    /// (merged idx, original idx) are added to merge_map, and the merged clusters are added to
+    /// overlaps
+    pub(super) fn sort_and_merge(
+        &mut self,
+        contig: &str,
+        mut ix: u32,
+        merge_map: &mut Vec<(NonZeroU32, NonZeroU32)>,
+        overlaps: &mut TargetOverlaps,
+    ) -> u32 {
         if !self.regions.is_empty() {
             let mut r = Vec::new();
             self.regions.sort_unstable();
 
             let mut pending: Option<Region> = None;
+            let mut pending_orig = Vec::new();
+            let mut input_bp = 0;
             for reg in self.regions.drain(..) {
+                input_bp += reg.size;
                 if let Some(mut p) = pending.take() {
                     // Check for overlap when regions are extended
                     if p.end() >= reg.start() {
                         if p.end() < reg.end() {
                             p.size = reg.end() - p.start
                         }
+                        // Merged regions take the largest weight
+                        p.weight = p.weight.max(reg.weight);
+                        if p.strand != reg.strand {
+                            p.strand = Strand::Unknown
+                        }
+                        pending_orig.push(reg.idx);
                         pending = Some(p)
                     } else {
                         ix += 1;
                         p.idx = ix.try_into().unwrap();
+                        overlaps.add_cluster(contig, &p, pending_orig.len());
+                        merge_map.extend(pending_orig.drain(..).map(|o| (p.idx, o)));
                         r.push(p);
+                        pending_orig.push(reg.idx);
                         pending = Some(reg)
                     }
                 } else {
+                    pending_orig.push(reg.idx);
                     pending = Some(reg)
                 }
             }
             if let Some(mut p) = pending.take() {
                 ix += 1;
                 p.idx = NonZeroU32::try_from(ix).unwrap();
+                overlaps.add_cluster(contig, &p, pending_orig.len());
+                merge_map.extend(pending_orig.drain(..).map(|o| (p.idx, o)));
                 r.push(p)
             }
+            overlaps.overlap_bp += input_bp - r.iter().map(|x| x.size).sum::<u64>();
             self.regions = r
         }
         ix
     }
 }
 
+/// Overlaps between the input regions found when merging.  Overlapping (or abutting) regions
+/// are merged into clusters, and overlap_bp counts the input bases lost by merging (so bases
+/// covered by 3 regions count twice)
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct TargetOverlaps {
+    input_intervals: usize,
+    merged_intervals: usize,
+    /// Input intervals in clusters of more than one interval
+    intervals_merged: usize,
+    overlap_bp: u64,
+    /// Number of input intervals in the largest cluster
+    largest_cluster: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    largest_cluster_region: Option<String>,
+}
+
+impl TargetOverlaps {
+    fn add_cluster(&mut self, contig: &str, r: &Region, n: usize) {
+        self.input_intervals += n;
+        self.merged_intervals += 1;
+        if n > 1 {
+            self.intervals_merged += n;
+            if n > self.largest_cluster {
+                self.largest_cluster_region =
+                    Some(format!("{contig}:{}-{}", r.start() + 1, r.end()))
+            }
+        }
+        self.largest_cluster = self.largest_cluster.max(n)
+    }
+
+    pub fn input_intervals(&self) -> usize {
+        self.input_intervals
+    }
+
+    pub fn intervals_merged(&self) -> usize {
+        self.intervals_merged
+    }
+
+    pub fn overlap_bp(&self) -> u64 {
+        self.overlap_bp
+    }
+
+    pub fn largest_cluster(&self) -> usize {
+        self.largest_cluster
+    }
+}
+
+/// Original (pre-merge) region from the input file
+#[derive(Debug, Clone)]
+pub struct RegionOrigin {
+    line: u32,
+    name: Option<Box<str>>,
+    start: u64,
+    end: u64,
+}
+
+impl RegionOrigin {
+    pub fn new(line: u32, name: Option<&str>, start: u64, end: u64) -> Self {
+        Self {
+            line,
+            name: name.map(|s| s.to_owned().into_boxed_str()),
+            start,
+            end,
+        }
+    }
+
+    /// Line number in input file (from 1)
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+}
+
 #[derive(Default)]
 pub struct Regions {
-    hash: HashMap<Box<str>, ContigRegions>,
+    hash: FxHashMap<Box<str>, ContigRegions>,
+    weighted: bool,
+    stranded: bool,
+    origins: Vec<RegionOrigin>,
+    merge_map: Vec<(NonZeroU32, NonZeroU32)>,
+    overlaps: TargetOverlaps,
 }
 
 impl Regions {
@@ -111,11 +305,34 @@ impl Regions {
         self.hash.entry(ctg).or_default()
     }
 
+    /// Record the origin of a region, returning the idx to be used for the region before
+    /// normalization
+    pub fn add_origin(&mut self, origin: RegionOrigin) -> NonZeroU32 {
+        self.origins.push(origin);
+        NonZeroU32::try_from(self.origins.len() as u32).unwrap()
+    }
+
+    /// Iterator over (merged region idx, origin) in order of merged idx.  Only regions added
+    /// with an idx from add_origin() are included
+    pub fn merge_map(&self) -> impl Iterator<Item = (NonZeroU32, &RegionOrigin)> {
+        self.merge_map.iter().filter_map(|(m, o)| {
+            self.origins
+                .get(u32::from(*o) as usize - 1)
+                .map(|x| (*m, x))
+        })
+    }
+
     pub fn normalize(&mut self) -> u32 {
         let mut ix = 0;
-        for r in self.hash.values_mut() {
-            ix = r.sort_and_merge(ix)
+        self.merge_map.clear();
+        self.overlaps = TargetOverlaps::default();
+        for (ctg, r) in self.hash.iter_mut() {
+            ix = r.sort_and_merge(ctg, ix, &mut self.merge_map, &mut self.overlaps)
         }
+        self.stranded = self
+            .hash
+            .values()
+            .any(|r| r.regions().iter().any(|x| x.strand != Strand::Unknown));
         ix
     }
 
@@ -127,7 +344,55 @@ impl Regions {
         self.hash.values().map(|r| r.regions().len()).sum()
     }
 
+    /// True if region weights were set
+    pub fn is_weighted(&self) -> bool {
+        self.weighted
+    }
+
+    pub fn set_weighted(&mut self, weighted: bool) {
+        self.weighted = weighted
+    }
+
+    /// True if any region has strand information
+    pub fn is_stranded(&self) -> bool {
+        self.stranded
+    }
+
     pub fn n_contigs(&self) -> usize {
         self.hash.len()
     }
+
+    /// Overlaps between the input regions found by the last normalize()
+    pub fn overlaps(&self) -> &TargetOverlaps {
+        &self.overlaps
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_overlaps() {
+        let mut regs = Regions::default();
+        for (ctg, s, e) in [
+            ("chr1", 100, 200),
+            ("chr1", 150, 250),
+            ("chr1", 180, 220),
+            ("chr1", 500, 600),
+            ("chr2", 100, 200),
+            ("chr2", 200, 300),
+        ] {
+            let idx = regs.add_origin(RegionOrigin::new(1, None, s, e));
+            regs.get_or_insert_contig_regions(ctg)
+                .add_region(Region::new(s, e - s, idx))
+        }
+        assert_eq!(regs.normalize(), 3);
+        let ov = regs.overlaps();
+        assert_eq!(ov.input_intervals(), 6);
+        assert_eq!(ov.intervals_merged(), 5);
+        assert_eq!(ov.overlap_bp(), 90);
+        assert_eq!(ov.largest_cluster(), 3);
+        assert_eq!(ov.largest_cluster_region.as_deref(), Some("chr1:101-250"));
+    }
 }
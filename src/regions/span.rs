@@ -0,0 +1,170 @@
+//! Contig spans selected on the command line with --region (samtools style chr:start-end)
+use std::{collections::HashMap, str::FromStr};
+
+use anyhow::Context;
+
+use crate::error::RegionError;
+
+/// Zero offset, half open span of a contig.  An open ended span has end == u64::MAX
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Span {
+    start: u64,
+    end: u64,
+}
+
+impl Span {
+    pub fn new(start: u64, end: u64) -> Self {
+        Self { start, end }
+    }
+
+    #[inline]
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    #[inline]
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+
+    pub fn contains(&self, pos: u64) -> bool {
+        pos >= self.start && pos < self.end
+    }
+}
+
+/// Parse a (1 based, inclusive) coordinate allowing commas as thousands separators
+fn parse_coord(s: &str) -> anyhow::Result<u64> {
+    let s: String = s.chars().filter(|c| *c != ',').collect();
+    s.parse::<u64>()
+        .with_context(|| format!("Bad coordinate {s}"))
+}
+
+/// Parse start[-end] part of a region description
+fn parse_range(s: &str) -> anyhow::Result<Span> {
+    let (a, b) = match s.split_once('-') {
+        Some((a, b)) => (a, Some(b)),
+        None => (s, None),
+    };
+    let start = parse_coord(a)?;
+    if start == 0 {
+        return Err(anyhow!("Region start must be at least 1"));
+    }
+    let end = match b {
+        Some(b) if !b.is_empty() => parse_coord(b)?,
+        _ => u64::MAX,
+    };
+    if end < start {
+        Err(anyhow!("Region end is before start"))
+    } else {
+        Ok(Span::new(start - 1, end))
+    }
+}
+
+/// A single region from the command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContigSpan {
+    contig: String,
+    span: Span,
+}
+
+impl ContigSpan {
+    pub fn contig(&self) -> &str {
+        &self.contig
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl FromStr for ContigSpan {
+    type Err = anyhow::Error;
+
+    /// Accepts chr, chr:start or chr:start-end.  If the part after the last ':' is not a valid
+    /// range then the whole string is taken as the contig name
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(RegionError::new("Empty region").into());
+        }
+        if let Some((ctg, r)) = s.rsplit_once(':') {
+            if !ctg.is_empty() && r.starts_with(|c: char| c.is_ascii_digit()) {
+                let span = parse_range(r)
+                    .with_context(|| RegionError::new(format!("Error parsing region {s}")))?;
+                return Ok(Self {
+                    contig: ctg.to_owned(),
+                    span,
+                });
+            }
+        }
+        Ok(Self {
+            contig: s.to_owned(),
+            span: Span::new(0, u64::MAX),
+        })
+    }
+}
+
+/// Set of spans per contig, sorted with overlapping spans merged
+#[derive(Debug, Default)]
+pub struct Spans {
+    hash: HashMap<Box<str>, Vec<Span>>,
+}
+
+impl Spans {
+    pub fn get(&self, contig: &str) -> Option<&[Span]> {
+        self.hash.get(contig).map(|v| v.as_slice())
+    }
+
+    pub fn contigs(&self) -> impl Iterator<Item = &str> {
+        self.hash.keys().map(|s| s.as_ref())
+    }
+}
+
+impl FromIterator<ContigSpan> for Spans {
+    fn from_iter<I: IntoIterator<Item = ContigSpan>>(iter: I) -> Self {
+        let mut hash: HashMap<Box<str>, Vec<Span>> = HashMap::new();
+        for cs in iter {
+            hash.entry(cs.contig.into_boxed_str())
+                .or_default()
+                .push(cs.span)
+        }
+        for v in hash.values_mut() {
+            v.sort_unstable();
+            let mut merged: Vec<Span> = Vec::with_capacity(v.len());
+            for s in v.drain(..) {
+                match merged.last_mut() {
+                    Some(p) if p.end >= s.start => p.end = p.end.max(s.end),
+                    _ => merged.push(s),
+                }
+            }
+            *v = merged
+        }
+        Self { hash }
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_parse_region() {
+        let r: ContigSpan = "chr1:1,000,001-2,000,000".parse().unwrap();
+        assert_eq!(r.contig(), "chr1");
+        assert_eq!(r.span(), Span::new(1000000, 2000000));
+        let r: ContigSpan = "chr2:101".parse().unwrap();
+        assert_eq!(r.span(), Span::new(100, u64::MAX));
+        let r: ContigSpan = "chrUn:abc".parse().unwrap();
+        assert_eq!(r.contig(), "chrUn:abc");
+        assert!("chr1:200-100".parse::<ContigSpan>().is_err());
+        assert!("chr1:0-100".parse::<ContigSpan>().is_err());
+
+        let s: Spans = ["chr1:1-100", "chr1:50-200", "chr1:300-400"]
+            .iter()
+            .map(|x| x.parse().unwrap())
+            .collect();
+        assert_eq!(
+            s.get("chr1").unwrap(),
+            &[Span::new(0, 200), Span::new(299, 400)]
+        );
+    }
+}
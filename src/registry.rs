@@ -0,0 +1,208 @@
+//! On disk registry of GC models keyed by reference and read length (--registry), so that
+//! aligners and correctors can fetch the model for a reference programmatically
+//!
+//! The model for each read length is written to ``<dir>/<key>/<read length>.json``, where the
+//! key is the reference identifier with characters other than ASCII letters, digits, ``.``,
+//! ``-`` and ``_`` replaced by ``_``.  Models are read back with [`lookup`].
+use std::{
+    fs,
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{betabin::gc_hist_table, cli::Config, process::GcRes};
+
+/// GC model for one reference and read length
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryModel {
+    program: String,
+    version: String,
+    date: String,
+    identifier: String,
+    read_length: u32,
+    threshold: f64,
+    bisulfite: bool,
+    /// GC bin centres
+    gc: Vec<f64>,
+    /// Expected density over the GC bins (non converted reads)
+    density: Vec<f64>,
+    /// Expected density for converted reads pooled over both strands
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    converted: Option<Vec<f64>>,
+}
+
+impl RegistryModel {
+    pub fn identifier(&self) -> &str {
+        &self.identifier
+    }
+
+    pub fn read_length(&self) -> u32 {
+        self.read_length
+    }
+
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    pub fn gc(&self) -> &[f64] {
+        &self.gc
+    }
+
+    pub fn density(&self) -> &[f64] {
+        &self.density
+    }
+
+    pub fn converted(&self) -> Option<&[f64]> {
+        self.converted.as_deref()
+    }
+}
+
+/// Directory name in the registry for a reference identifier
+pub fn registry_key(identifier: &str) -> String {
+    identifier
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn model_path(dir: &Path, identifier: &str, read_length: u32) -> PathBuf {
+    dir.join(registry_key(identifier))
+        .join(format!("{read_length}.json"))
+}
+
+/// Write the model for each read length to the registry in dir, returning the files written
+pub fn write_registry(dir: &Path, cfg: &Config, res: &GcRes) -> anyhow::Result<Vec<PathBuf>> {
+    let identifier = res.identifier().ok_or_else(|| {
+        anyhow!("No reference identifier for the model registry: use --identifier to set one")
+    })?;
+    let ref_dir = dir.join(registry_key(identifier));
+    fs::create_dir_all(&ref_dir)
+        .with_context(|| format!("Could not create registry directory {}", ref_dir.display()))?;
+    let tab = gc_hist_table(
+        cfg.read_lengths(),
+        None,
+        res,
+        cfg.bisulfite(),
+        cfg.smoother(),
+    );
+    let mut files = Vec::with_capacity(cfg.read_lengths().len());
+    for rl in cfg.read_lengths() {
+        let model = RegistryModel {
+            program: env!("CARGO_PKG_NAME").to_owned(),
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            date: cfg.date().to_rfc2822(),
+            identifier: identifier.to_owned(),
+            read_length: *rl,
+            threshold: cfg.threshold(),
+            bisulfite: cfg.bisulfite(),
+            gc: tab.gc().to_vec(),
+            density: tab.column(&format!("read_len:{rl}bp")).unwrap().to_vec(),
+            converted: tab
+                .column(&format!("bisulfite_read_len:{rl}bp"))
+                .map(|v| v.to_vec()),
+        };
+        let path = model_path(dir, identifier, *rl);
+        let mut wrt = BufWriter::new(
+            fs::File::create(&path)
+                .with_context(|| format!("Could not create registry file {}", path.display()))?,
+        );
+        serde_json::to_writer_pretty(&mut wrt, &model)
+            .with_context(|| format!("Error writing registry file {}", path.display()))?;
+        wrt.flush()?;
+        files.push(path)
+    }
+    Ok(files)
+}
+
+/// Model for the reference and read length from the registry in dir, or None if not present
+pub fn lookup(
+    dir: &Path,
+    identifier: &str,
+    read_length: u32,
+) -> anyhow::Result<Option<RegistryModel>> {
+    let path = model_path(dir, identifier, read_length);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let rdr = BufReader::new(
+        fs::File::open(&path)
+            .with_context(|| format!("Could not open registry file {}", path.display()))?,
+    );
+    let model: RegistryModel = serde_json::from_reader(rdr)
+        .with_context(|| format!("Error reading registry file {}", path.display()))?;
+    // Different identifiers can map to the same key
+    if model.identifier != identifier {
+        return Err(anyhow!(
+            "Registry file {} is for reference {}",
+            path.display(),
+            model.identifier
+        ));
+    }
+    Ok(Some(model))
+}
+
+/// Read lengths with models for the reference in the registry in dir (sorted)
+pub fn read_lengths(dir: &Path, identifier: &str) -> anyhow::Result<Vec<u32>> {
+    let ref_dir = dir.join(registry_key(identifier));
+    if !ref_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut v: Vec<u32> = fs::read_dir(&ref_dir)
+        .with_context(|| format!("Could not read registry directory {}", ref_dir.display()))?
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            e.file_name()
+                .to_str()
+                .and_then(|s| s.strip_suffix(".json"))
+                .and_then(|s| s.parse().ok())
+        })
+        .collect();
+    v.sort_unstable();
+    Ok(v)
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        assert_eq!(
+            registry_key("GRCh38-like, 2 contigs, md5:ab"),
+            "GRCh38-like__2_contigs__md5_ab"
+        );
+        let dir =
+            std::env::temp_dir().join(format!("analyze_ref_gc_registry_{}", std::process::id()));
+        let id = "ref 1";
+        let model = RegistryModel {
+            program: String::new(),
+            version: String::new(),
+            date: String::new(),
+            identifier: id.to_owned(),
+            read_length: 100,
+            threshold: 0.8,
+            bisulfite: false,
+            gc: vec![0.25, 0.75],
+            density: vec![0.4, 0.6],
+            converted: None,
+        };
+        fs::create_dir_all(dir.join(registry_key(id))).unwrap();
+        let f = fs::File::create(model_path(&dir, id, 100)).unwrap();
+        serde_json::to_writer(f, &model).unwrap();
+        let m = lookup(&dir, id, 100).unwrap().unwrap();
+        assert_eq!(m.density(), [0.4, 0.6]);
+        assert!(lookup(&dir, id, 150).unwrap().is_none());
+        assert!(lookup(&dir, "ref_1", 100).is_err());
+        assert_eq!(read_lengths(&dir, id).unwrap(), [100]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,359 @@
+//! Detection of runs of short tandem repeats (such as telomeric TTAGGG repeats or satellite
+//! motifs) in the raw input as it is read
+//!
+//! For each motif period p, runs are found where every base matches the base p positions
+//! earlier.  The repeat unit at the start of a run is encoded using the 2 bit base encoding used
+//! for kmers and looked up in a table with all rotations of the motifs and their reverse
+//! complements.  Runs of the same motif separated by less than p bases (as caused by a single
+//! base change) are merged.
+use std::sync::Arc;
+
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+
+use crate::{fasta::Base, tap::StreamTap};
+
+/// Canonical telomere repeat with common variant repeats
+pub const TELOMERE_MOTIFS: [&str; 4] = ["TTAGGG", "TCAGGG", "TGAGGG", "TTGGGG"];
+
+/// Maximum motif length (so that the unit fits in a u64)
+pub const MAX_MOTIF_LEN: usize = 32;
+
+/// 2 bit code for base (as used for kmers) or None for gaps
+fn code(c: u8) -> Option<u8> {
+    let b = Base::from_u8(c);
+    (!b.is_gap()).then_some(b as u8 & 3)
+}
+
+fn encode(s: &[u8]) -> Option<u64> {
+    s.iter()
+        .try_fold(0u64, |k, c| code(*c).map(|x| (k << 2) | x as u64))
+}
+
+#[derive(Debug, Clone)]
+pub struct RepeatRun {
+    contig: Arc<str>,
+    start: u64,
+    end: u64,
+    motif: usize,
+    reverse: bool,
+}
+
+impl RepeatRun {
+    pub fn contig(&self) -> &str {
+        &self.contig
+    }
+
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+
+    /// Index of motif
+    pub fn motif(&self) -> usize {
+        self.motif
+    }
+
+    /// Run matches the reverse complement of the motif
+    pub fn reverse(&self) -> bool {
+        self.reverse
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RepeatTotal {
+    motif: String,
+    runs: u64,
+    bases: u64,
+}
+
+impl RepeatTotal {
+    pub fn motif(&self) -> &str {
+        &self.motif
+    }
+
+    pub fn runs(&self) -> u64 {
+        self.runs
+    }
+
+    pub fn bases(&self) -> u64 {
+        self.bases
+    }
+}
+
+/// Repeat runs found and totals per motif
+#[derive(Debug, Clone, Serialize)]
+pub struct RepeatRes {
+    min_length: u64,
+    motifs: Vec<RepeatTotal>,
+    #[serde(skip)]
+    runs: Vec<RepeatRun>,
+}
+
+impl RepeatRes {
+    pub fn runs(&self) -> &[RepeatRun] {
+        &self.runs
+    }
+
+    /// Totals for each motif
+    pub fn totals(&self) -> &[RepeatTotal] {
+        &self.motifs
+    }
+
+    pub fn motif(&self, ix: usize) -> &str {
+        &self.motifs[ix].motif
+    }
+}
+
+/// Scan state for one period
+struct PeriodState {
+    period: usize,
+    matching: u64,
+    start: u64,
+    unit: Option<u64>,
+    pending: Option<RepeatRun>,
+}
+
+pub struct RepeatScan {
+    motifs: Vec<String>,
+    /// (period, unit) -> (motif index, reverse)
+    table: FxHashMap<(usize, u64), (usize, bool)>,
+    min_length: u64,
+    periods: Vec<PeriodState>,
+    hist: Vec<Option<u8>>,
+    contig: Option<Arc<str>>,
+    name: Vec<u8>,
+    pos: u64,
+    at_line_start: bool,
+    in_header: bool,
+    name_done: bool,
+    runs: Vec<RepeatRun>,
+}
+
+impl RepeatScan {
+    pub fn new(motifs: &[String], min_length: u64) -> Self {
+        let mut table = FxHashMap::default();
+        let mut periods: Vec<usize> = Vec::new();
+        for (ix, m) in motifs.iter().enumerate() {
+            let m = m.as_bytes();
+            let p = m.len();
+            let rc: Vec<u8> = m
+                .iter()
+                .rev()
+                .map(|c| match c.to_ascii_uppercase() {
+                    b'A' => b'T',
+                    b'C' => b'G',
+                    b'G' => b'C',
+                    _ => b'A',
+                })
+                .collect();
+            for (s, rev) in [(m, false), (rc.as_slice(), true)] {
+                for i in 0..p {
+                    let rot: Vec<u8> = s[i..].iter().chain(s[..i].iter()).copied().collect();
+                    let k = encode(&rot).expect("Illegal motif");
+                    table.entry((p, k)).or_insert((ix, rev));
+                }
+            }
+            if !periods.contains(&p) {
+                periods.push(p)
+            }
+        }
+        let max_p = periods.iter().max().copied().unwrap_or(1);
+        Self {
+            motifs: motifs.to_vec(),
+            table,
+            min_length,
+            periods: periods
+                .into_iter()
+                .map(|period| PeriodState {
+                    period,
+                    matching: 0,
+                    start: 0,
+                    unit: None,
+                    pending: None,
+                })
+                .collect(),
+            hist: vec![None; max_p],
+            contig: None,
+            name: Vec::new(),
+            pos: 0,
+            at_line_start: true,
+            in_header: false,
+            name_done: false,
+            runs: Vec::new(),
+        }
+    }
+
+    /// Unit of length p ending before the current position
+    fn unit(&self, p: usize) -> Option<u64> {
+        let n = self.hist.len() as u64;
+        (self.pos - p as u64..self.pos).try_fold(0u64, |k, i| {
+            self.hist[(i % n) as usize].map(|x| (k << 2) | x as u64)
+        })
+    }
+
+    /// Store the pending run for period ix if it is long enough
+    fn flush_pending(&mut self, ix: usize) {
+        if let Some(r) = self.periods[ix].pending.take() {
+            if r.end - r.start >= self.min_length {
+                self.runs.push(r)
+            }
+        }
+    }
+
+    /// End of a run of matches for period ix.  If the unit matches a motif, the run is merged
+    /// with the pending run if possible, otherwise it replaces it
+    fn end_run(&mut self, ix: usize) {
+        let st = &mut self.periods[ix];
+        let p = st.period as u64;
+        st.matching = 0;
+        let Some((motif, reverse)) = st
+            .unit
+            .and_then(|u| self.table.get(&(p as usize, u)).copied())
+        else {
+            return;
+        };
+        let start = st.start;
+        if let Some(r) = st
+            .pending
+            .as_mut()
+            .filter(|r| r.motif == motif && r.reverse == reverse && start <= r.end + p)
+        {
+            r.end = self.pos;
+            return;
+        }
+        let run = RepeatRun {
+            contig: self.contig.clone().unwrap(),
+            start,
+            end: self.pos,
+            motif,
+            reverse,
+        };
+        self.flush_pending(ix);
+        self.periods[ix].pending = Some(run)
+    }
+
+    fn end_contig(&mut self) {
+        for ix in 0..self.periods.len() {
+            if self.periods[ix].matching > 0 {
+                self.end_run(ix)
+            }
+            self.flush_pending(ix)
+        }
+    }
+
+    fn add_base(&mut self, c: u8) {
+        let x = code(c);
+        let n = self.hist.len();
+        for ix in 0..self.periods.len() {
+            let p = self.periods[ix].period;
+            let prev = if self.pos >= p as u64 {
+                self.hist[((self.pos - p as u64) % n as u64) as usize]
+            } else {
+                None
+            };
+            if x.is_some() && x == prev {
+                if self.periods[ix].matching == 0 {
+                    let unit = self.unit(p);
+                    let st = &mut self.periods[ix];
+                    st.start = self.pos - p as u64;
+                    st.unit = unit
+                }
+                self.periods[ix].matching += 1
+            } else if self.periods[ix].matching > 0 {
+                self.end_run(ix)
+            }
+        }
+        self.hist[(self.pos % n as u64) as usize] = x;
+        self.pos += 1
+    }
+
+    pub fn finish(mut self) -> RepeatRes {
+        if self.contig.is_some() {
+            self.end_contig()
+        }
+        let mut motifs: Vec<_> = self
+            .motifs
+            .iter()
+            .map(|m| RepeatTotal {
+                motif: m.to_owned(),
+                runs: 0,
+                bases: 0,
+            })
+            .collect();
+        for r in self.runs.iter() {
+            motifs[r.motif].runs += 1;
+            motifs[r.motif].bases += r.end - r.start
+        }
+        RepeatRes {
+            min_length: self.min_length,
+            motifs,
+            runs: self.runs,
+        }
+    }
+}
+
+impl StreamTap for RepeatScan {
+    fn update(&mut self, buf: &[u8]) {
+        for c in buf.iter().copied() {
+            if self.in_header {
+                if c == b'\n' {
+                    self.in_header = false;
+                    self.at_line_start = true;
+                    let name = String::from_utf8_lossy(&self.name).into_owned();
+                    self.contig = Some(Arc::from(name.as_str()));
+                    self.name.clear();
+                    self.pos = 0;
+                    self.hist.iter_mut().for_each(|x| *x = None)
+                } else if !self.name_done {
+                    if c.is_ascii_whitespace() {
+                        self.name_done = true
+                    } else {
+                        self.name.push(c)
+                    }
+                }
+            } else if self.at_line_start && c == b'>' {
+                if self.contig.is_some() {
+                    self.end_contig()
+                }
+                self.in_header = true;
+                self.name_done = false
+            } else if c == b'\n' {
+                self.at_line_start = true
+            } else {
+                self.at_line_start = false;
+                if self.contig.is_some() && !c.is_ascii_whitespace() {
+                    self.add_base(c)
+                }
+            }
+        }
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_repeat_scan() {
+        let motifs: Vec<String> = TELOMERE_MOTIFS.iter().map(|s| s.to_string()).collect();
+        let mut scan = RepeatScan::new(&motifs, 24);
+        let mut input = b">chr1\nACGTCCCTAACCCTAACCCTAA\nCCCTAACCCTAAGT".to_vec();
+        input.extend_from_slice(b"TTAGGGTTAGGGTTAGGGTTCGGGTTAGGGTTAGGGTTAGGGTTAGGG\n");
+        scan.update(&input);
+        let res = scan.finish();
+        let runs = res.runs();
+        assert_eq!(runs.len(), 2);
+        assert_eq!((runs[0].start(), runs[0].end()), (4, 34));
+        assert!(runs[0].reverse());
+        assert_eq!(
+            (runs[1].start(), runs[1].end(), runs[1].motif()),
+            (36, 84, 0)
+        );
+        assert_eq!(res.motif(runs[1].motif()), "TTAGGG");
+        assert!(!runs[1].reverse());
+    }
+}
@@ -0,0 +1,130 @@
+//! Passes over the input after the main analysis, shared between the options that need them
+//!
+//! The options that classify kmers by their genome wide counts (the repeat partitions, contig
+//! groups and --kmer-gc) share one pass to count all canonical kmers and a second pass in which
+//! each sequence is given to every enabled option, rather than each option reading the input
+//! twice.  The target outputs that need the results of the main pass (the mappability track and
+//! the matched control regions) likewise share a single pass.  The kmer counts cover the whole
+//! genome, but the options only see the sequence used in the main pass (see [`rescan_selected`]),
+//! so that their results match the distributions they are reported with.
+use anyhow::Context;
+
+use crate::{
+    cli::Config,
+    fasta::{tar::open_fasta, FastaReader, GapPolicy, Seq},
+    kmers::{KmerBuilder, KmerCounts},
+};
+
+/// Read the input (which must be a file), calling f for each sequence.  Records are split at gaps
+/// of at least 1000 bases
+pub fn rescan<F>(cfg: &Config, mut f: F) -> anyhow::Result<()>
+where
+    F: FnMut(&Seq) -> anyhow::Result<()>,
+{
+    let rdr = open_fasta(cfg.input())?;
+    let mut rdr = FastaReader::new(rdr, GapPolicy::MinGap(1000));
+    while let Some(s) = rdr
+        .next_seq()
+        .with_context(|| "Error reading input sequence")?
+    {
+        f(&s)?
+    }
+    Ok(())
+}
+
+/// As [`rescan`], but only calling f for the sequence that is analyzed in the main pass: contigs
+/// excluded by the length filters are skipped, and with --region only the parts of each sequence
+/// lying in the selected spans are used
+pub fn rescan_selected<F>(cfg: &Config, mut f: F) -> anyhow::Result<()>
+where
+    F: FnMut(&Seq) -> anyhow::Result<()>,
+{
+    rescan(cfg, |s| {
+        if cfg
+            .contig_filter()
+            .is_some_and(|cf| cf.is_excluded(s.contig()))
+        {
+            return Ok(());
+        }
+        match cfg.spans() {
+            Some(sp) => {
+                for x in sp.get(s.contig()).unwrap_or(&[]) {
+                    if let Some(t) = s.subseq(x.start(), x.end()) {
+                        f(&t)?
+                    }
+                }
+                Ok(())
+            }
+            None => f(s),
+        }
+    })
+}
+
+/// Count all canonical kmers in the input using bits per kmer (see [`KmerCounts::with_bits`])
+pub fn count_kmers(cfg: &Config, bits: u32) -> anyhow::Result<KmerCounts> {
+    let mut counts = KmerCounts::with_bits(bits);
+    let mut builder = KmerBuilder::new();
+    info!("Reading input for kmer counts");
+    rescan(cfg, |s| {
+        builder.clear();
+        for b in s.iter() {
+            builder.add_base(*b, None);
+            if let Some(km) = builder.kmers() {
+                counts.add(km)
+            }
+        }
+        Ok(())
+    })?;
+    Ok(counts)
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    #[allow(unused_imports)]
+    use crate::cli::config_from_args;
+
+    #[test]
+    fn test_rescan_selected() {
+        let path =
+            std::env::temp_dir().join(format!("analyze_ref_gc_rescan_{}.fa", std::process::id()));
+        let input = format!(
+            ">chr1\n{}\n>chr2\n{}\n>chr3\n{}\n",
+            "ACGT".repeat(50),
+            "ACGT".repeat(100),
+            "ACGT".repeat(10)
+        );
+        std::fs::write(&path, input).unwrap();
+        let cfg = config_from_args([
+            "analyze_ref_gc",
+            "--region",
+            "chr1:11-20",
+            "--region",
+            "chr2",
+            "--region",
+            "chr3",
+            "--min-contig-len",
+            "100",
+            path.to_str().unwrap(),
+        ])
+        .unwrap();
+        let mut v = Vec::new();
+        rescan_selected(&cfg, |s| {
+            v.push((s.contig().to_owned(), s.start(), s.end()));
+            Ok(())
+        })
+        .unwrap();
+        let mut all = 0;
+        rescan(&cfg, |s| {
+            all += s.len();
+            Ok(())
+        })
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            v,
+            [("chr1".to_owned(), 10, 20), ("chr2".to_owned(), 0, 400)]
+        );
+        assert_eq!(all, 640);
+    }
+}
@@ -0,0 +1,218 @@
+//! Stratification of GC distributions and kmer uniqueness into repeat and non-repeat partitions
+//! using the repeat annotation from a RepeatMasker output (.out) file
+//!
+//! Windows are assigned to the repeat partition if their midpoint lies in an annotated repeat.
+//! Kmer uniqueness requires two passes over the input (as for the bands subcommand): the first
+//! counts all canonical kmers in the genome and the second counts, for each partition, the kmers
+//! (assigned using their first base) that occur only once.  The passes are shared with the other
+//! kmer uniqueness options (see [`crate::rescan`]).
+use std::{io::BufRead, ops::Range, path::Path};
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+
+use crate::{
+    fasta::Seq,
+    kmers::{KmerBuilder, KmerCounts, KMER_LENGTH},
+};
+
+/// Merged repeat intervals for each contig
+pub struct RepeatMask {
+    contigs: FxHashMap<String, Vec<Range<u64>>>,
+    masked_bases: u64,
+}
+
+impl RepeatMask {
+    /// Sorted, non overlapping repeat intervals for contig
+    pub fn get(&self, ctg: &str) -> Option<&[Range<u64>]> {
+        self.contigs.get(ctg).map(|v| v.as_slice())
+    }
+
+    pub fn masked_bases(&self) -> u64 {
+        self.masked_bases
+    }
+}
+
+/// Read a RepeatMasker .out file.  The header lines are skipped, and the query sequence, begin and
+/// end (1 offset, inclusive) columns are used from the remaining lines
+pub fn read_rmsk<P: AsRef<Path>>(path: P) -> anyhow::Result<RepeatMask> {
+    let path = path.as_ref();
+    let rdr = CompressIo::new()
+        .path(path)
+        .bufreader()
+        .with_context(|| format!("Could not open RepeatMasker file {}", path.display()))?;
+
+    let mut contigs: FxHashMap<String, Vec<Range<u64>>> = FxHashMap::default();
+    let mut n = 0;
+    for (i, l) in rdr.lines().enumerate() {
+        let l = l.with_context(|| format!("Error reading from {}", path.display()))?;
+        let v: Vec<_> = l.split_ascii_whitespace().collect();
+        // Header and blank lines do not start with a numeric score
+        if v.len() < 7 || v[0].parse::<f64>().is_err() {
+            continue;
+        }
+        let err = || format!("Error at line {} of {}", i + 1, path.display());
+        let start = v[5].parse::<u64>().with_context(err)?;
+        let end = v[6].parse::<u64>().with_context(err)?;
+        if start == 0 || end < start {
+            return Err(anyhow!("Illegal repeat coordinates")).with_context(err);
+        }
+        contigs
+            .entry(v[4].to_owned())
+            .or_default()
+            .push(start - 1..end);
+        n += 1
+    }
+
+    let mut masked_bases = 0;
+    for v in contigs.values_mut() {
+        v.sort_unstable_by_key(|r| r.start);
+        let mut merged: Vec<Range<u64>> = Vec::with_capacity(v.len());
+        for r in v.drain(..) {
+            match merged.last_mut() {
+                Some(m) if r.start <= m.end => m.end = m.end.max(r.end),
+                _ => merged.push(r),
+            }
+        }
+        masked_bases += merged.iter().map(|r| r.end - r.start).sum::<u64>();
+        *v = merged
+    }
+    info!(
+        "Read {n} repeats for {} contigs covering {masked_bases} bases",
+        contigs.len()
+    );
+    Ok(RepeatMask {
+        contigs,
+        masked_bases,
+    })
+}
+
+/// Lookup of repeat status for positions that are non decreasing for each of a set of cursors
+/// (one per read length)
+pub struct MaskLookup<'a> {
+    v: &'a [Range<u64>],
+    cursor: Vec<usize>,
+}
+
+impl<'a> MaskLookup<'a> {
+    pub fn new(mask: &'a RepeatMask, ctg: &str, start: u64, n: usize) -> Self {
+        let v = mask.get(ctg).unwrap_or(&[]);
+        let i = v.partition_point(|r| r.end <= start);
+        Self {
+            v,
+            cursor: vec![i; n],
+        }
+    }
+
+    pub fn is_masked(&mut self, ix: usize, pos: u64) -> bool {
+        let j = &mut self.cursor[ix];
+        while *j < self.v.len() && self.v[*j].end <= pos {
+            *j += 1
+        }
+        self.v.get(*j).map(|r| r.start <= pos) == Some(true)
+    }
+}
+
+#[derive(Default, Debug, Copy, Clone, Serialize)]
+pub struct KmerUniqueness {
+    kmers: u64,
+    unique_kmers: u64,
+}
+
+impl KmerUniqueness {
+    pub fn kmers(&self) -> u64 {
+        self.kmers
+    }
+
+    pub fn unique_kmers(&self) -> u64 {
+        self.unique_kmers
+    }
+}
+
+#[derive(Default, Debug, Copy, Clone, Serialize)]
+pub struct PartitionKmers {
+    non_repeat: KmerUniqueness,
+    repeat: KmerUniqueness,
+}
+
+impl PartitionKmers {
+    pub fn get(&self, repeat: bool) -> &KmerUniqueness {
+        if repeat {
+            &self.repeat
+        } else {
+            &self.non_repeat
+        }
+    }
+
+    /// Add the kmers of s, classified using the genome wide counts, to the partition of their
+    /// first base
+    pub fn add_seq(&mut self, mask: &RepeatMask, s: &Seq, counts: &KmerCounts) {
+        let mut lookup = MaskLookup::new(mask, s.contig(), s.start(), 1);
+        let mut builder = KmerBuilder::new();
+        let k = KMER_LENGTH as u64;
+        for (pos, b) in (s.start()..).zip(s.iter()) {
+            builder.add_base(*b, None);
+            if let Some(km) = builder.kmers() {
+                let p = if lookup.is_masked(0, pos + 1 - k) {
+                    &mut self.repeat
+                } else {
+                    &mut self.non_repeat
+                };
+                p.kmers += 1;
+                if counts.is_unique(km) {
+                    p.unique_kmers += 1
+                }
+            }
+        }
+    }
+}
+
+/// Summary of the repeat annotation and the kmer uniqueness for each partition
+#[derive(Debug, Clone, Serialize)]
+pub struct RepeatMaskRes {
+    masked_bases: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kmers: Option<PartitionKmers>,
+}
+
+impl RepeatMaskRes {
+    pub fn new(mask: &RepeatMask) -> Self {
+        Self {
+            masked_bases: mask.masked_bases(),
+            kmers: None,
+        }
+    }
+
+    /// Kmer uniqueness for the partitions if calculated
+    pub fn kmers(&self) -> Option<&PartitionKmers> {
+        self.kmers.as_ref()
+    }
+
+    pub fn set_kmers(&mut self, kmers: PartitionKmers) {
+        self.kmers = Some(kmers)
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_mask_lookup() {
+        let mut contigs = FxHashMap::default();
+        contigs.insert("chr1".to_owned(), vec![10..20, 30..40]);
+        let mask = RepeatMask {
+            contigs,
+            masked_bases: 20,
+        };
+        let mut lk = MaskLookup::new(&mask, "chr1", 15, 2);
+        assert!(lk.is_masked(0, 15));
+        assert!(!lk.is_masked(0, 25));
+        assert!(lk.is_masked(1, 19));
+        assert!(lk.is_masked(0, 30));
+        assert!(!lk.is_masked(0, 40));
+        assert!(!MaskLookup::new(&mask, "chr2", 0, 1).is_masked(0, 15));
+    }
+}
@@ -0,0 +1,94 @@
+//! Expected number of distinct unique kmers observed for each target as a function of depth
+//!
+//! A kmer at a given position and strand is contained in a read if the read is from that strand
+//! and starts in one of the L - k + 1 positions that put the kmer inside the read (for read
+//! length L and kmer length k).  With reads placed at random at mean depth d, split evenly between
+//! the strands, the number of reads containing the kmer is Poisson with mean
+//! λ = d (L - k + 1) / 2L, so a kmer is seen with probability 1 - exp(-λ).  The expected number of
+//! distinct informative kmers seen for a target is this times the number of kmers from the target
+//! that map uniquely, as given by the kmcv file.  GC bias is not modelled.
+use std::io::Write;
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+
+use crate::{cli::SaturationConfig, simulate::read_kmcv_targets};
+
+/// Proportion of kmers in the curves used to report the depth at saturation
+const SATURATION: f64 = 0.95;
+
+/// Mean number of reads from one strand containing a given kmer at mean depth d
+fn kmer_lambda(depth: f64, read_length: u32, kmer_length: u8) -> f64 {
+    let l = read_length as f64;
+    depth * (l - kmer_length as f64 + 1.0) / (2.0 * l)
+}
+
+/// Proportion of kmers seen at least once at mean depth d
+fn prop_seen(depth: f64, read_length: u32, kmer_length: u8) -> f64 {
+    -(-kmer_lambda(depth, read_length, kmer_length)).exp_m1()
+}
+
+pub fn saturation(cfg: &SaturationConfig) -> anyhow::Result<()> {
+    info!("Reading kmer file {}", cfg.kmcv().display());
+    let kt = read_kmcv_targets(cfg.kmcv())?;
+    let (contigs, k) = (kt.contigs(), kt.kmer_length());
+    let rl = cfg.read_length();
+    if rl < k as u32 {
+        return Err(anyhow!(
+            "Read length {rl} is less than the kmer length ({k}) of the kmer file"
+        ));
+    }
+    let props: Vec<_> = cfg.depths().iter().map(|d| prop_seen(*d, rl, k)).collect();
+
+    let name = format!("{}_saturation.txt", cfg.prefix());
+    let mut wrt = CompressIo::new()
+        .path(&name)
+        .bufwriter()
+        .with_context(|| format!("Could not open output file {name}"))?;
+    write!(wrt, "target_id\tcontig\tstart\tend\tunique_kmers")?;
+    for d in cfg.depths() {
+        write!(wrt, "\tdepth_{d}")?
+    }
+    writeln!(wrt)?;
+    let mut total = 0;
+    for (i, t) in kt.targets().iter().enumerate() {
+        let u = kt.unique_kmers(i);
+        total += u;
+        write!(
+            wrt,
+            "{}\t{}\t{}\t{}\t{u}",
+            i + 1,
+            contigs[t.contig_id() as usize],
+            t.start(),
+            t.end()
+        )?;
+        for p in props.iter() {
+            write!(wrt, "\t{:.2}", u as f64 * p)?
+        }
+        writeln!(wrt)?
+    }
+    wrt.flush()
+        .with_context(|| "Error flushing data to saturation output file")?;
+    // Depth d where 1 - exp(-λ(d)) = SATURATION
+    let d_sat = -(1.0 - SATURATION).ln() / kmer_lambda(1.0, rl, k);
+    info!(
+        "{total} unique kmers from {} targets: {:.0}% are expected to be seen at depth {d_sat:.1}",
+        kt.targets().len(),
+        100.0 * SATURATION
+    );
+    Ok(())
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_saturation() {
+        // 100 bp reads and 21 bp kmers: each kmer is in 80 read positions per strand
+        assert!((kmer_lambda(10.0, 100, 21) - 4.0).abs() < 1.0e-12);
+        assert!((prop_seen(10.0, 100, 21) - (1.0 - (-4.0f64).exp())).abs() < 1.0e-12);
+        assert_eq!(prop_seen(0.0, 100, 21), 0.0);
+        assert!(prop_seen(1000.0, 100, 21) > 1.0 - 1.0e-12);
+    }
+}
@@ -0,0 +1,136 @@
+//! Detection of duplicated sequence between targets using the kmer table
+//!
+//! The table records up to [`MAX_HITS`] distinct hits for each kmer, so for each kmer found in
+//! more than one target every pair of those targets shares the kmer.  Pairs of targets sharing at
+//! least the given fraction of the kmers of the smaller target (possible duplicated scaffolds or
+//! alt contigs) are reported.  Highly redundant kmers (with more than [`MAX_HITS`] hits) do not
+//! record the targets, so are not counted.
+use std::{io::Write, path::Path};
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+use rustc_hash::FxHashMap;
+
+use crate::{
+    kmers::{KmerVec, KmerWork, MAX_HITS},
+    regions::{Region, Regions},
+};
+
+/// Distinct kmers per target (indexed by target idx - 1) and shared kmers for each pair of targets
+#[derive(Default)]
+struct SharedCounts {
+    kmers: Vec<u64>,
+    pairs: FxHashMap<(u32, u32), u64>,
+}
+
+impl SharedCounts {
+    fn new(n_targets: usize) -> Self {
+        Self {
+            kmers: vec![0; n_targets],
+            pairs: FxHashMap::default(),
+        }
+    }
+
+    /// Add the targets hit by a kmer.  Hits are stored as target idx + 1, with 1 for off target
+    fn add(&mut self, v: &KmerVec) {
+        if v[0] & 0x80000000 != 0 {
+            return;
+        }
+        let mut t = [0u32; MAX_HITS];
+        let mut n = 0;
+        for x in v.iter().take_while(|x| **x != 0).filter(|x| **x > 1) {
+            t[n] = *x - 2;
+            self.kmers[t[n] as usize] += 1;
+            n += 1
+        }
+        t[..n].sort_unstable();
+        for i in 0..n {
+            for j in i + 1..n {
+                *self.pairs.entry((t[i], t[j])).or_insert(0) += 1
+            }
+        }
+    }
+}
+
+/// Write pairs of targets sharing at least min_frac of the kmers of the smaller target, returning
+/// the number of pairs written
+pub fn output_shared_kmers<P: AsRef<Path>>(
+    path: P,
+    reg: &Regions,
+    k_work: &KmerWork,
+    min_frac: f64,
+) -> anyhow::Result<usize> {
+    info!("Counting kmers shared between targets");
+    let mut sc = SharedCounts::new(reg.n_regions());
+    for (_, v) in k_work.kmers() {
+        sc.add(v)
+    }
+
+    let mut targets: Vec<Option<(&str, &Region)>> = vec![None; reg.n_regions()];
+    for (ctg, ctg_regs) in reg.iter() {
+        for r in ctg_regs.regions() {
+            targets[u32::from(r.idx()) as usize - 1] = Some((ctg, r))
+        }
+    }
+    let mut pairs: Vec<_> = sc
+        .pairs
+        .iter()
+        .map(|((a, b), n)| {
+            let (ka, kb) = (sc.kmers[*a as usize], sc.kmers[*b as usize]);
+            (*a, *b, *n, *n as f64 / ka as f64, *n as f64 / kb as f64)
+        })
+        .filter(|(_, _, _, fa, fb)| fa.max(*fb) >= min_frac)
+        .collect();
+    pairs.sort_unstable_by_key(|(a, b, _, _, _)| (*a, *b));
+
+    let mut wrt = CompressIo::new()
+        .path(path)
+        .bufwriter()
+        .with_context(|| "Could not open shared kmer file for output")?;
+    writeln!(
+        wrt,
+        "target_id_1\tlocation_1\ttarget_id_2\tlocation_2\tshared_kmers\tpct_1\tpct_2"
+    )?;
+    let loc = |i: u32| {
+        let (ctg, r) = targets[i as usize].expect("Missing target");
+        format!("{ctg}:{}-{}", r.start() + 1, r.end())
+    };
+    for (a, b, n, fa, fb) in pairs.iter() {
+        writeln!(
+            wrt,
+            "{}\t{}\t{}\t{}\t{n}\t{:.2}\t{:.2}",
+            a + 1,
+            loc(*a),
+            b + 1,
+            loc(*b),
+            100.0 * fa,
+            100.0 * fb
+        )?
+    }
+    wrt.flush()
+        .with_context(|| "Error flushing data to shared kmer file")?;
+    info!(
+        "{} pairs of targets sharing at least {:.1}% of kmers",
+        pairs.len(),
+        100.0 * min_frac
+    );
+    Ok(pairs.len())
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_shared_counts() {
+        let mut sc = SharedCounts::new(3);
+        // Targets 1 and 3 (stored as 2 and 4), with an off target hit
+        sc.add(&[4, 1, 2, 0, 0, 0, 0, 0]);
+        sc.add(&[2, 4, 0, 0, 0, 0, 0, 0]);
+        sc.add(&[3, 0, 0, 0, 0, 0, 0, 0]);
+        sc.add(&[0x80000000, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(sc.kmers, vec![2, 1, 2]);
+        assert_eq!(sc.pairs.len(), 1);
+        assert_eq!(sc.pairs.get(&(0, 2)), Some(&2));
+    }
+}
@@ -0,0 +1,143 @@
+//! Shuffling of sequences for the null (random expectation) GC distributions
+//!
+//! Only the non-gap bases are shuffled, so the gap structure of the sequence is unchanged.
+//! Dinucleotide preserving shuffles use the Altschul-Erickson algorithm (as described by Kandel
+//! et al.), choosing a random Eulerian path through the dinucleotide multigraph.
+use std::hash::{Hash, Hasher};
+
+use clap::{builder::PossibleValue, ValueEnum};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use rustc_hash::FxHasher;
+
+use crate::fasta::{Base, Seq};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShuffleMode {
+    Mono,
+    Di,
+}
+
+impl ValueEnum for ShuffleMode {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Mono, Self::Di]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(self.name()))
+    }
+}
+
+impl ShuffleMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Mono => "mono",
+            Self::Di => "di",
+        }
+    }
+}
+
+/// Dinucleotide preserving shuffle of v (all elements must be non-gap bases)
+fn shuffle_di<R: Rng>(v: &mut [Base], rng: &mut R) {
+    let n = v.len();
+    if n < 3 {
+        return;
+    }
+    // Out edges for each base
+    let mut edges: [Vec<Base>; 4] = Default::default();
+    for w in v.windows(2) {
+        edges[w[0] as usize].push(w[1])
+    }
+    let last = v[n - 1] as usize;
+
+    // Choose a last out edge for each vertex (other than the final base) such that the last
+    // edges form a tree leading to the final base
+    let mut last_edge = [0; 4];
+    loop {
+        for (i, e) in edges.iter().enumerate() {
+            if i != last && !e.is_empty() {
+                last_edge[i] = rng.gen_range(0..e.len())
+            }
+        }
+        let connected = (0..4)
+            .filter(|i| *i != last && !edges[*i].is_empty())
+            .all(|mut i| {
+                for _ in 0..4 {
+                    if i == last {
+                        return true;
+                    }
+                    i = edges[i][last_edge[i]] as usize
+                }
+                i == last
+            });
+        if connected {
+            break;
+        }
+    }
+
+    // Shuffle the remaining edges, with the last edge placed at the end
+    for (i, e) in edges.iter_mut().enumerate() {
+        if !e.is_empty() {
+            if i != last {
+                let k = e.len() - 1;
+                e.swap(last_edge[i], k);
+                e[..k].shuffle(rng)
+            } else {
+                e.shuffle(rng)
+            }
+            e.reverse()
+        }
+    }
+
+    // Walk the graph from the first base
+    let mut b = v[0];
+    for x in v[1..].iter_mut() {
+        b = edges[b as usize].pop().unwrap();
+        *x = b
+    }
+}
+
+/// Copy of s with the non-gap bases shuffled.  The random number generator is seeded from the
+/// seed, contig and start position so that results do not depend on the order in which
+/// sequences are processed
+pub fn shuffle_seq(s: &Seq, mode: ShuffleMode, seed: u64) -> Seq {
+    let mut h = FxHasher::default();
+    (seed, s.contig(), s.start()).hash(&mut h);
+    let mut rng = StdRng::seed_from_u64(h.finish());
+
+    let mut t = s.clone();
+    let mut v: Vec<Base> = s.iter().copied().filter(|b| !b.is_gap()).collect();
+    match mode {
+        ShuffleMode::Mono => v.shuffle(&mut rng),
+        ShuffleMode::Di => shuffle_di(&mut v, &mut rng),
+    }
+    let mut it = v.into_iter();
+    for b in t.bases_mut().iter_mut().filter(|b| !b.is_gap()) {
+        *b = it.next().unwrap()
+    }
+    t
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_shuffle_di() {
+        const BASES: [Base; 4] = [Base::A, Base::C, Base::T, Base::G];
+        let mut rng = StdRng::seed_from_u64(42);
+        let count = |v: &[Base]| {
+            let mut c = [[0; 4]; 4];
+            for w in v.windows(2) {
+                c[w[0] as usize][w[1] as usize] += 1
+            }
+            c
+        };
+        let v: Vec<Base> = (0..1000).map(|_| BASES[rng.gen_range(0..4)]).collect();
+        let mut t = v.clone();
+        shuffle_di(&mut t, &mut rng);
+        assert_ne!(v, t);
+        assert_eq!(count(&v), count(&t));
+        assert_eq!(v[0], t[0]);
+        assert_eq!(v[999], t[999]);
+    }
+}
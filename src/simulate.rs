@@ -0,0 +1,254 @@
+//! Prediction of per target coverage and dropout probability under a GC bias model
+//!
+//! The expected coverage of a target is taken as proportional to the product of the GC bias
+//! at the GC content of the target and the mappability of the target (the proportion of kmers
+//! from the target that map uniquely), scaled so that the length weighted mean coverage over all
+//! targets is equal to the requested depth.  Coverage is modelled as Poisson, and the dropout
+//! probability is the probability that coverage falls below the minimum depth.
+use std::{collections::HashMap, io::BufRead, io::Write, path::Path};
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+
+use crate::{
+    cli::SimulateConfig,
+    dist_table::read_dist_table,
+    kmcv::{open_kmcv, KmcvTarget, KmerType},
+};
+
+/// Target information from a kmcv file with the number of uniquely mapping kmers per target
+pub(crate) struct KmcvTargets {
+    kmer_length: u8,
+    contigs: Vec<Box<str>>,
+    targets: Vec<KmcvTarget>,
+    unique_kmers: Vec<u64>,
+}
+
+impl KmcvTargets {
+    pub(crate) fn contigs(&self) -> &[Box<str>] {
+        &self.contigs
+    }
+
+    pub(crate) fn targets(&self) -> &[KmcvTarget] {
+        &self.targets
+    }
+
+    pub(crate) fn kmer_length(&self) -> u8 {
+        self.kmer_length
+    }
+
+    /// Number of kmers from target i (indexed from 0) that map uniquely (counting both strands)
+    pub(crate) fn unique_kmers(&self, i: usize) -> u64 {
+        self.unique_kmers[i]
+    }
+
+    /// Proportion of kmers from target i (indexed from 0) that map uniquely
+    pub(crate) fn mappability(&self, i: usize) -> f64 {
+        let k = self.kmer_length as u64;
+        let l = self.targets[i].len();
+        // Kmers are recorded for both strands
+        if l >= k {
+            ((self.unique_kmers[i] as f64) / ((2 * (l + 1 - k)) as f64)).min(1.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+struct TargetPred {
+    gc: f64,
+    mappability: f64,
+    bias: f64,
+}
+
+/// Read target id and gc columns from a target statistics file
+fn read_target_gc<P: AsRef<Path>>(path: P) -> anyhow::Result<HashMap<u32, f64>> {
+    let path = path.as_ref();
+    let rdr = CompressIo::new()
+        .path(path)
+        .bufreader()
+        .with_context(|| format!("Could not open target file {}", path.display()))?;
+
+    let mut lines = rdr.lines();
+    let hdr = lines
+        .next()
+        .ok_or_else(|| anyhow!("Empty target file {}", path.display()))??;
+    let col = |name: &str| {
+        hdr.split('\t')
+            .position(|s| s == name)
+            .ok_or_else(|| anyhow!("Missing column {name} in target file {}", path.display()))
+    };
+    let (id_col, gc_col) = (col("target_id")?, col("gc")?);
+
+    let mut m = HashMap::new();
+    for (i, l) in lines.enumerate() {
+        let l = l.with_context(|| format!("Error reading line {} from target file", i + 2))?;
+        let v: Vec<_> = l.split('\t').collect();
+        let get = |j: usize| {
+            v.get(j)
+                .ok_or_else(|| anyhow!("Short line {} in target file", i + 2))
+        };
+        let id = get(id_col)?
+            .parse::<u32>()
+            .with_context(|| format!("Bad target id at line {}", i + 2))?;
+        let gc = get(gc_col)?
+            .parse::<f64>()
+            .with_context(|| format!("Bad gc value at line {}", i + 2))?;
+        m.insert(id, gc);
+    }
+    Ok(m)
+}
+
+/// Count kmers mapping uniquely to each target (indexed by target id - 1)
+pub(crate) fn read_kmcv_targets<P: AsRef<Path>>(path: P) -> anyhow::Result<KmcvTargets> {
+    let mut rdr = open_kmcv(path)?;
+    let mut unique_kmers = vec![0; rdr.targets().len()];
+    while let Some((_, ktype, hits)) = rdr.next_kmer()? {
+        if ktype == KmerType::UniqueOnTarget {
+            unique_kmers[hits[0] as usize - 1] += 1
+        }
+    }
+    Ok(KmcvTargets {
+        kmer_length: rdr.kmer_length(),
+        contigs: rdr.contigs().to_vec(),
+        targets: rdr.targets().to_vec(),
+        unique_kmers,
+    })
+}
+
+/// Bias for a given gc value, taken from the closest bin
+pub(crate) fn lookup_bias(gc: &[f64], bias: &[f64], x: f64) -> f64 {
+    if !x.is_finite() || gc.is_empty() {
+        return 0.0;
+    }
+    let i = gc.partition_point(|g| *g < x);
+    let i = if i == gc.len() || (i > 0 && x - gc[i - 1] < gc[i] - x) {
+        i - 1
+    } else {
+        i
+    };
+    let b = bias[i];
+    if b.is_finite() {
+        b.max(0.0)
+    } else {
+        0.0
+    }
+}
+
+/// P(X < k) where X ~ Poisson(lambda)
+fn poisson_lower_tail(lambda: f64, k: u32) -> f64 {
+    if lambda <= 0.0 {
+        return if k > 0 { 1.0 } else { 0.0 };
+    }
+    let ln_l = lambda.ln();
+    (0..k)
+        .map(|i| (-lambda + (i as f64) * ln_l - libm::lgamma(i as f64 + 1.0)).exp())
+        .sum::<f64>()
+        .min(1.0)
+}
+
+pub fn simulate(cfg: &SimulateConfig) -> anyhow::Result<()> {
+    let bias_tab = read_dist_table(cfg.bias())?;
+    let (col_name, bias) = match cfg.column() {
+        Some(c) => {
+            let c = if c.starts_with("bias_") {
+                c.to_owned()
+            } else {
+                format!("bias_{c}")
+            };
+            let v = bias_tab
+                .column(&c)
+                .ok_or_else(|| anyhow!("Column {c} not found in bias file"))?;
+            (c, v)
+        }
+        None => bias_tab
+            .columns()
+            .find(|(s, _)| s.starts_with("bias_"))
+            .map(|(s, v)| (s.to_owned(), v))
+            .ok_or_else(|| anyhow!("No bias columns found in bias file"))?,
+    };
+    info!("Using bias curve {col_name}");
+
+    let target_gc = read_target_gc(cfg.targets())?;
+    info!("Reading kmer file {}", cfg.kmcv().display());
+    let kt = read_kmcv_targets(cfg.kmcv())?;
+    let (contigs, targets) = (kt.contigs(), kt.targets());
+
+    let mut preds = Vec::with_capacity(targets.len());
+    for i in 0..targets.len() {
+        let gc = *target_gc
+            .get(&(i as u32 + 1))
+            .ok_or_else(|| anyhow!("Target {} not found in target file", i + 1))?;
+        preds.push(TargetPred {
+            gc,
+            mappability: kt.mappability(i),
+            bias: lookup_bias(bias_tab.gc(), bias, gc),
+        })
+    }
+
+    let (sum_l, sum_lr) = targets
+        .iter()
+        .zip(preds.iter())
+        .fold((0.0, 0.0), |(a, b), (t, p)| {
+            let l = t.len() as f64;
+            (a + l, b + l * p.bias * p.mappability)
+        });
+    if sum_lr <= 0.0 {
+        return Err(anyhow!(
+            "No targets with non-zero predicted coverage (check bias and kmer files)"
+        ));
+    }
+    let scale = cfg.depth() * sum_l / sum_lr;
+
+    let name = format!("{}_sim.txt", cfg.prefix());
+    let mut wrt = CompressIo::new()
+        .path(&name)
+        .bufwriter()
+        .with_context(|| format!("Could not open output file {name}"))?;
+
+    writeln!(
+        wrt,
+        "target_id\tcontig\tstart\tend\tgc\tmappability\tbias\texpected_coverage\tdropout_prob"
+    )?;
+    let mut exp_dropouts = 0.0;
+    for (i, (t, p)) in targets.iter().zip(preds.iter()).enumerate() {
+        let lambda = scale * p.bias * p.mappability;
+        let dropout = poisson_lower_tail(lambda, cfg.min_depth());
+        exp_dropouts += dropout;
+        writeln!(
+            wrt,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            i + 1,
+            contigs[t.contig_id() as usize],
+            t.start(),
+            t.end(),
+            p.gc,
+            p.mappability,
+            p.bias,
+            lambda,
+            dropout
+        )?
+    }
+    wrt.flush()
+        .with_context(|| "Error flushing data to simulation output file")?;
+    info!(
+        "Expected number of dropouts (coverage < {}): {:.1} of {} targets",
+        cfg.min_depth(),
+        exp_dropouts,
+        targets.len()
+    );
+    Ok(())
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_poisson_lower_tail() {
+        assert_eq!(poisson_lower_tail(5.0, 0), 0.0);
+        assert!((poisson_lower_tail(2.0, 1) - (-2.0f64).exp()).abs() < 1.0e-12);
+        assert!((poisson_lower_tail(2.0, 3) - 5.0 * (-2.0f64).exp()).abs() < 1.0e-12);
+        assert!(poisson_lower_tail(2000.0, 10) < 1.0e-100);
+    }
+}
@@ -0,0 +1,144 @@
+//! Short human readable summary of a run printed to stderr at the end of the analysis, so that
+//! it is not necessary to open the JSON output to check that the run looks sane
+use std::sync::Mutex;
+
+use crate::{
+    cli::Config, dropout_risk::RiskSummary, fasta::Base, output::mean_gc, process::GcRes,
+    warnings::warnings,
+};
+
+static OUTPUTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Record the name of an output file for the summary
+pub fn add_output_file(name: &str) {
+    OUTPUTS
+        .lock()
+        .expect("Outputs lock poisoned")
+        .push(name.to_owned())
+}
+
+/// Output files recorded so far
+pub fn output_files() -> Vec<String> {
+    OUTPUTS.lock().expect("Outputs lock poisoned").clone()
+}
+
+/// Target coverage and kmer uniqueness on targets
+#[derive(Default, Debug, Clone)]
+pub struct TargetSummary {
+    targets: usize,
+    covered: usize,
+    on_target_kmers: u64,
+    unique_on_target_kmers: u64,
+    risk: Option<RiskSummary>,
+}
+
+impl TargetSummary {
+    pub fn new(
+        targets: usize,
+        covered: usize,
+        on_target_kmers: u64,
+        unique_on_target_kmers: u64,
+    ) -> Self {
+        Self {
+            targets,
+            covered,
+            on_target_kmers,
+            unique_on_target_kmers,
+            risk: None,
+        }
+    }
+
+    pub fn with_risk(mut self, risk: RiskSummary) -> Self {
+        self.risk = Some(risk);
+        self
+    }
+
+    pub fn targets(&self) -> usize {
+        self.targets
+    }
+
+    /// Number of targets with at least one window
+    pub fn covered(&self) -> usize {
+        self.covered
+    }
+
+    pub fn on_target_kmers(&self) -> u64 {
+        self.on_target_kmers
+    }
+
+    pub fn unique_on_target_kmers(&self) -> u64 {
+        self.unique_on_target_kmers
+    }
+
+    /// Summary of the per target dropout risk scores
+    pub fn risk(&self) -> Option<&RiskSummary> {
+        self.risk.as_ref()
+    }
+}
+
+fn pct(a: u64, b: u64) -> f64 {
+    100.0 * a as f64 / b.max(1) as f64
+}
+
+/// Print summary of the run to stderr
+pub fn print_summary(cfg: &Config, res: &GcRes) {
+    let input = cfg
+        .input()
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|| "<stdin>".to_owned());
+    eprintln!("== {} summary ==", env!("CARGO_PKG_NAME"));
+    eprintln!("Input:               {input}");
+    if let Some(c) = res.composition() {
+        let gaps = c.base(Base::N) + c.base(Base::Other);
+        eprintln!(
+            "Sequence:            {} bp in {} records, N fraction {:.4}",
+            c.length(),
+            c.records(),
+            gaps as f64 / c.length().max(1) as f64
+        )
+    }
+    for rl in cfg.read_lengths() {
+        let (n, gc) = mean_gc(res, *rl);
+        eprintln!("Read length {rl:<7} {n:.0} windows, mean GC {gc:.4}")
+    }
+    if let Some(t) = res.target_summary() {
+        eprintln!(
+            "Targets:             {} of {} covered, {:.2}% of on target kmers unique",
+            t.covered(),
+            t.targets(),
+            pct(t.unique_on_target_kmers(), t.on_target_kmers())
+        );
+        if let Some(r) = t.risk() {
+            eprintln!(
+                "Dropout risk:        {} targets with score >= {}",
+                r.high_risk_targets(),
+                r.threshold()
+            )
+        }
+    }
+    if let Some(k) = res.repeatmasker().and_then(|r| r.kmers()) {
+        for (desc, repeat) in [("non-repeat", false), ("repeat", true)] {
+            let u = k.get(repeat);
+            eprintln!(
+                "Unique kmers:        {:.2}% in {desc} sequence",
+                pct(u.unique_kmers(), u.kmers())
+            )
+        }
+    }
+    if let Some(t) = res.kmer_gc() {
+        for (ix, rl) in cfg.read_lengths().iter().enumerate() {
+            let (u, n) = t
+                .counts(ix)
+                .iter()
+                .fold((0, 0), |(u, n), c| (u + c[0], n + c.iter().sum::<u64>()));
+            eprintln!(
+                "Unique kmers:        {:.2}% in windows for read length {rl}",
+                pct(u, n)
+            )
+        }
+    }
+    eprintln!("Warnings:            {}", warnings().len());
+    for name in output_files() {
+        eprintln!("Output:              {name}")
+    }
+}
@@ -0,0 +1,238 @@
+//! Generation of random genomes for benchmarking, testing and validation of the GC model
+//!
+//! Contigs are made up of isochores (segments with exponentially distributed lengths and with GC
+//! content drawn from a normal distribution) within which bases are drawn independently.  Runs of
+//! Ns and copies of a set of repeat elements (on either strand and with a given divergence) are
+//! inserted at random positions.
+use std::io::{self, Write};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const LINE_LENGTH: usize = 60;
+
+/// Parameters for the generated genome.  Rates are per Mb
+#[derive(Debug, Clone)]
+pub struct GenomeParams {
+    pub size: u64,
+    pub contig_length: u64,
+    pub gc: f64,
+    pub gc_sd: f64,
+    pub isochore_length: f64,
+    pub gap_rate: f64,
+    pub gap_length: f64,
+    pub repeat_families: usize,
+    pub repeat_length: usize,
+    pub repeat_rate: f64,
+    pub repeat_divergence: f64,
+    pub seed: u64,
+}
+
+impl Default for GenomeParams {
+    fn default() -> Self {
+        Self {
+            size: 100_000_000,
+            contig_length: 50_000_000,
+            gc: 0.41,
+            gc_sd: 0.05,
+            isochore_length: 300_000.0,
+            gap_rate: 0.5,
+            gap_length: 5_000.0,
+            repeat_families: 10,
+            repeat_length: 300,
+            repeat_rate: 50.0,
+            repeat_divergence: 0.1,
+            seed: 0,
+        }
+    }
+}
+
+/// Writes bases as FASTA lines
+struct LineWriter<'a, W: Write> {
+    w: &'a mut W,
+    line: Vec<u8>,
+}
+
+impl<'a, W: Write> LineWriter<'a, W> {
+    fn new(w: &'a mut W) -> Self {
+        Self {
+            w,
+            line: Vec::with_capacity(LINE_LENGTH + 1),
+        }
+    }
+
+    fn push(&mut self, b: u8) -> io::Result<()> {
+        self.line.push(b);
+        if self.line.len() == LINE_LENGTH {
+            self.flush()?
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.line.is_empty() {
+            self.line.push(b'\n');
+            self.w.write_all(&self.line)?;
+            self.line.clear()
+        }
+        Ok(())
+    }
+}
+
+fn random_base(rng: &mut StdRng, gc: f64) -> u8 {
+    match (rng.gen_bool(gc), rng.gen_bool(0.5)) {
+        (true, true) => b'C',
+        (true, false) => b'G',
+        (false, true) => b'A',
+        (false, false) => b'T',
+    }
+}
+
+fn complement(b: u8) -> u8 {
+    match b {
+        b'A' => b'T',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'T' => b'A',
+        _ => b,
+    }
+}
+
+/// Exponentially distributed value with the given mean
+fn exponential(rng: &mut StdRng, mean: f64) -> f64 {
+    -mean * (1.0 - rng.gen::<f64>()).ln()
+}
+
+/// Normally distributed value (Box-Muller)
+fn normal(rng: &mut StdRng, mean: f64, sd: f64) -> f64 {
+    let (u1, u2) = (1.0 - rng.gen::<f64>(), rng.gen::<f64>());
+    mean + sd * (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+}
+
+/// Distance to the next event for a rate per Mb
+fn next_event(rng: &mut StdRng, rate: f64) -> u64 {
+    if rate > 0.0 {
+        exponential(rng, 1.0e6 / rate).ceil() as u64
+    } else {
+        u64::MAX
+    }
+}
+
+/// Write a random genome in FASTA format.  If bed is set, the isochores (with score = GC x 1000),
+/// gaps and repeat copies (named repeatN, with strand) are written to it in BED format
+pub fn write_genome<W: Write>(
+    w: &mut W,
+    mut bed: Option<&mut dyn Write>,
+    par: &GenomeParams,
+) -> io::Result<()> {
+    let mut rng = StdRng::seed_from_u64(par.seed);
+    let repeats: Vec<Vec<u8>> = (0..par.repeat_families)
+        .map(|_| {
+            (0..par.repeat_length)
+                .map(|_| random_base(&mut rng, par.gc))
+                .collect()
+        })
+        .collect();
+
+    let mut remaining = par.size;
+    let mut ctg = 0;
+    while remaining > 0 {
+        let len = remaining.min(par.contig_length.max(1));
+        remaining -= len;
+        ctg += 1;
+        let name = format!("synth{ctg}");
+        writeln!(w, ">{name}")?;
+        let mut lw = LineWriter::new(w);
+        let (mut pos, mut iso_end, mut gc) = (0, 0, par.gc);
+        let mut next_gap = next_event(&mut rng, par.gap_rate);
+        let mut next_repeat = if repeats.is_empty() {
+            u64::MAX
+        } else {
+            next_event(&mut rng, par.repeat_rate)
+        };
+        while pos < len {
+            if pos >= iso_end {
+                let l = (exponential(&mut rng, par.isochore_length).ceil() as u64).max(1);
+                iso_end = (pos + l).min(len);
+                gc = normal(&mut rng, par.gc, par.gc_sd).clamp(0.05, 0.95);
+                if let Some(b) = bed.as_mut() {
+                    let score = (gc * 1000.0).round();
+                    writeln!(b, "{name}\t{pos}\t{iso_end}\tisochore\t{score}\t.")?
+                }
+            }
+            if pos >= next_gap {
+                let l = (exponential(&mut rng, par.gap_length).ceil() as u64).clamp(1, len - pos);
+                for _ in 0..l {
+                    lw.push(b'N')?
+                }
+                if let Some(b) = bed.as_mut() {
+                    writeln!(b, "{name}\t{pos}\t{}\tgap\t0\t.", pos + l)?
+                }
+                pos += l;
+                next_gap = pos.saturating_add(next_event(&mut rng, par.gap_rate));
+            } else if pos >= next_repeat {
+                let ix = rng.gen_range(0..repeats.len());
+                let rev = rng.gen_bool(0.5);
+                let l = (par.repeat_length as u64).min(len - pos) as usize;
+                for i in 0..l {
+                    let b = if rev {
+                        complement(repeats[ix][par.repeat_length - 1 - i])
+                    } else {
+                        repeats[ix][i]
+                    };
+                    let b = if rng.gen_bool(par.repeat_divergence) {
+                        random_base(&mut rng, gc)
+                    } else {
+                        b
+                    };
+                    lw.push(b)?
+                }
+                if let Some(bd) = bed.as_mut() {
+                    let strand = if rev { '-' } else { '+' };
+                    writeln!(
+                        bd,
+                        "{name}\t{pos}\t{}\trepeat{}\t0\t{strand}",
+                        pos + l as u64,
+                        ix + 1
+                    )?
+                }
+                pos += l as u64;
+                next_repeat = pos.saturating_add(next_event(&mut rng, par.repeat_rate));
+            } else {
+                lw.push(random_base(&mut rng, gc))?;
+                pos += 1
+            }
+        }
+        lw.flush()?
+    }
+    Ok(())
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_write_genome() {
+        let par = GenomeParams {
+            size: 25_000,
+            contig_length: 10_000,
+            gap_rate: 200.0,
+            repeat_rate: 1000.0,
+            ..Default::default()
+        };
+        let (mut fa, mut bed) = (Vec::new(), Vec::new());
+        write_genome(&mut fa, Some(&mut bed), &par).unwrap();
+        let fa = String::from_utf8(fa).unwrap();
+        let hdr: Vec<_> = fa.lines().filter(|l| l.starts_with('>')).collect();
+        assert_eq!(hdr, [">synth1", ">synth2", ">synth3"]);
+        let n_bases: usize = fa
+            .lines()
+            .filter(|l| !l.starts_with('>'))
+            .map(|l| l.len())
+            .sum();
+        assert_eq!(n_bases, 25_000);
+        let bed = String::from_utf8(bed).unwrap();
+        assert!(bed.lines().any(|l| l.contains("\tgap\t")));
+        assert!(bed.lines().any(|l| l.contains("\trepeat")));
+    }
+}
@@ -0,0 +1,51 @@
+//! Pass through reader allowing the raw input to be inspected as it is read.  This is used for
+//! calculations (such as checksums) that need to see the whole input in order, independently of
+//! how the sequence is split up and distributed to the process threads
+use std::io::{self, BufRead, Read};
+
+/// Receives the raw input bytes, in order, as they are consumed
+pub trait StreamTap {
+    fn update(&mut self, buf: &[u8]);
+}
+
+pub struct TapReader<'a, R: BufRead> {
+    inner: R,
+    taps: Vec<&'a mut dyn StreamTap>,
+}
+
+impl<'a, R: BufRead> TapReader<'a, R> {
+    pub fn new(inner: R, taps: Vec<&'a mut dyn StreamTap>) -> Self {
+        Self { inner, taps }
+    }
+}
+
+impl<R: BufRead> Read for TapReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let b = self.fill_buf()?;
+            let n = b.len().min(buf.len());
+            buf[..n].copy_from_slice(&b[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for TapReader<'_, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if !self.taps.is_empty() {
+            // The buffer is already filled, so this does not read from the input
+            if let Ok(b) = self.inner.fill_buf() {
+                for t in self.taps.iter_mut() {
+                    t.update(&b[..amt])
+                }
+            }
+        }
+        self.inner.consume(amt)
+    }
+}
@@ -0,0 +1,90 @@
+//! Joint distribution of target length and GC content (--target-gc-length)
+//!
+//! Targets are counted in a 2D histogram with fixed bins, so that the output from different panel
+//! designs can be compared directly: the GC content (of the ACGT bases, as in the target
+//! statistics file) in bins of 5%, and the length on a 1-2-5 scale from 50 bp to 10 kb.  Targets
+//! with no ACGT bases are counted separately.
+use std::io::Write;
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+use serde::Serialize;
+
+use crate::{fasta::Base, regions::Regions, target_stats::TargetStats};
+
+/// Number of GC bins
+const GC_BINS: usize = 20;
+
+/// Lower limits of the length bins (the last bin is open)
+const LENGTH_BINS: [u64; 9] = [0, 50, 100, 200, 500, 1000, 2000, 5000, 10000];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TargetGcLength {
+    gc_bin_width: f64,
+    length_bins: &'static [u64],
+    /// Number of targets for each length bin (rows) and GC bin (columns)
+    counts: Vec<[u64; GC_BINS]>,
+    /// Targets with no ACGT bases
+    no_acgt: u64,
+}
+
+impl TargetGcLength {
+    pub fn new(reg: &Regions, stats: &TargetStats) -> Self {
+        let mut counts = vec![[0; GC_BINS]; LENGTH_BINS.len()];
+        let mut no_acgt = 0;
+        for (_, ctg_regs) in reg.iter() {
+            for r in ctg_regs.regions() {
+                let cts = stats.counts(r.idx());
+                let acgt: u64 = cts.iter().sum();
+                if acgt == 0 {
+                    no_acgt += 1;
+                    continue;
+                }
+                let gc = cts[Base::C as usize] + cts[Base::G as usize];
+                let i = LENGTH_BINS.partition_point(|l| *l <= r.end() - r.start()) - 1;
+                let j = ((gc * GC_BINS as u64) / acgt).min(GC_BINS as u64 - 1) as usize;
+                counts[i][j] += 1
+            }
+        }
+        Self {
+            gc_bin_width: 1.0 / GC_BINS as f64,
+            length_bins: &LENGTH_BINS,
+            counts,
+            no_acgt,
+        }
+    }
+
+    pub fn counts(&self) -> &[[u64; GC_BINS]] {
+        &self.counts
+    }
+
+    pub fn no_acgt(&self) -> u64 {
+        self.no_acgt
+    }
+
+    /// Write the histogram as a TSV matrix with a row for each length bin and a column for each GC
+    /// bin (labelled by the lower limit of the bin)
+    pub fn write(&self, path: &str) -> anyhow::Result<()> {
+        let mut wrt = CompressIo::new()
+            .path(path)
+            .bufwriter()
+            .with_context(|| "Could not open target GC length file for output")?;
+        write!(wrt, "length")?;
+        for j in 0..GC_BINS {
+            write!(wrt, "\tgc_{:.2}", j as f64 * self.gc_bin_width)?
+        }
+        writeln!(wrt)?;
+        for (i, row) in self.counts.iter().enumerate() {
+            match LENGTH_BINS.get(i + 1) {
+                Some(l) => write!(wrt, "{}-{}", LENGTH_BINS[i], l - 1)?,
+                None => write!(wrt, "{}+", LENGTH_BINS[i])?,
+            }
+            for x in row {
+                write!(wrt, "\t{x}")?
+            }
+            writeln!(wrt)?
+        }
+        wrt.flush()
+            .with_context(|| "Error flushing data to target GC length file")
+    }
+}
@@ -0,0 +1,125 @@
+//! Per target statistics collected while reading the reference
+use std::{io::Write, num::NonZeroU32, path::Path};
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+
+use crate::{dropout_risk::TargetRisk, fasta::Base, regions::Regions};
+
+/// Base counts (A, C, T, G) for each target, indexed by target idx - 1
+pub struct TargetStats {
+    counts: Vec<[u64; 4]>,
+}
+
+impl TargetStats {
+    pub fn new(n_targets: usize) -> Self {
+        Self {
+            counts: vec![[0; 4]; n_targets],
+        }
+    }
+
+    #[inline]
+    pub fn add_base(&mut self, idx: NonZeroU32, b: Base) {
+        if !b.is_gap() {
+            self.counts[u32::from(idx) as usize - 1][b as usize] += 1
+        }
+    }
+
+    /// Base counts (A, C, T, G) for target idx
+    pub fn counts(&self, idx: NonZeroU32) -> &[u64; 4] {
+        &self.counts[u32::from(idx) as usize - 1]
+    }
+
+    /// Number of targets with at least one ACGT base
+    pub fn covered(&self) -> usize {
+        self.counts
+            .iter()
+            .filter(|c| c.iter().any(|x| *x > 0))
+            .count()
+    }
+}
+
+/// Write per target TSV file with target id, coordinates, length, number of ACGT bases, GC
+/// content and the dropout risk components and score (risk is indexed by target idx - 1)
+pub fn output_target_stats<P: AsRef<Path>>(
+    path: P,
+    reg: &Regions,
+    stats: &TargetStats,
+    risk: &[TargetRisk],
+) -> anyhow::Result<()> {
+    let mut wrt = CompressIo::new()
+        .path(path)
+        .bufwriter()
+        .with_context(|| "Could not open target statistics file for output")?;
+
+    writeln!(
+        wrt,
+        "target_id\tcontig\tstart\tend\tlength\tacgt\tgc\tnon_unique_kmers\tn_content\trisk"
+    )?;
+    for (ctg, ctg_regs) in reg.iter() {
+        for r in ctg_regs.regions() {
+            let cts = stats.counts(r.idx());
+            let acgt: u64 = cts.iter().sum();
+            let gc = cts[Base::C as usize] + cts[Base::G as usize];
+            let gc = if acgt > 0 {
+                (gc as f64) / (acgt as f64)
+            } else {
+                f64::NAN
+            };
+            let rk = &risk[u32::from(r.idx()) as usize - 1];
+            writeln!(
+                wrt,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{:.4}\t{:.4}\t{:.4}",
+                r.idx(),
+                ctg,
+                r.start(),
+                r.end(),
+                r.end() - r.start(),
+                acgt,
+                gc,
+                rk.redundancy(),
+                rk.n_content(),
+                rk.score()
+            )?
+        }
+    }
+    wrt.flush()
+        .with_context(|| "Error flushing data to target statistics file")
+}
+
+/// Write TSV file mapping merged target ids to the original (pre-merge) regions from the BED file
+pub fn output_target_map<P: AsRef<Path>>(path: P, reg: &Regions) -> anyhow::Result<()> {
+    let mut wrt = CompressIo::new()
+        .path(path)
+        .bufwriter()
+        .with_context(|| "Could not open target map file for output")?;
+
+    let mut targets = vec![None; reg.n_regions()];
+    for (ctg, ctg_regs) in reg.iter() {
+        for r in ctg_regs.regions() {
+            targets[u32::from(r.idx()) as usize - 1] = Some((ctg, r))
+        }
+    }
+
+    writeln!(
+        wrt,
+        "target_id\tcontig\tstart\tend\tbed_line\tname\tbed_start\tbed_end"
+    )?;
+    for (idx, o) in reg.merge_map() {
+        let (ctg, r) = targets[u32::from(idx) as usize - 1].expect("Missing target");
+        writeln!(
+            wrt,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            idx,
+            ctg,
+            r.start(),
+            r.end(),
+            o.line(),
+            o.name().unwrap_or("."),
+            o.start(),
+            o.end()
+        )?
+    }
+    wrt.flush()
+        .with_context(|| "Error flushing data to target map file")
+}
@@ -0,0 +1,50 @@
+//! Export of targets from a kmcv file with a high proportion of uniquely mapping kmers as BED
+//!
+//! The kmcv file does not record kmer positions within targets, so whole targets are written.
+//! The score column is the proportion of unique kmers scaled to 0-1000
+use std::io::Write;
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+
+use crate::{cli::UniqueBedConfig, simulate::read_kmcv_targets};
+
+pub fn unique_bed(cfg: &UniqueBedConfig) -> anyhow::Result<()> {
+    info!("Reading kmer file {}", cfg.kmcv().display());
+    let kt = read_kmcv_targets(cfg.kmcv())?;
+    let contigs = kt.contigs();
+
+    let name = format!("{}_unique.bed", cfg.prefix());
+    let mut wrt = CompressIo::new()
+        .path(&name)
+        .bufwriter()
+        .with_context(|| format!("Could not open output file {name}"))?;
+
+    let (mut n, mut bases) = (0, 0);
+    for (i, t) in kt.targets().iter().enumerate() {
+        let u = kt.mappability(i);
+        if u < cfg.min_unique() || t.is_empty() {
+            continue;
+        }
+        writeln!(
+            wrt,
+            "{}\t{}\t{}\ttarget_{}\t{}\t{}",
+            contigs[t.contig_id() as usize],
+            t.start(),
+            t.end(),
+            i + 1,
+            (u * 1000.0).round() as u32,
+            t.strand().bed_str()
+        )?;
+        n += 1;
+        bases += t.len()
+    }
+    wrt.flush()
+        .with_context(|| "Error flushing data to BED output file")?;
+    info!(
+        "{n} of {} targets ({bases} bases) with at least {:.1}% unique kmers written to {name}",
+        kt.targets().len(),
+        100.0 * cfg.min_unique()
+    );
+    Ok(())
+}
@@ -0,0 +1,38 @@
+//! Collection of non fatal anomalies found during the analysis, so that they are reported in the
+//! JSON output as well as being logged
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Warning {
+    kind: &'static str,
+    message: String,
+}
+
+impl Warning {
+    pub fn kind(&self) -> &'static str {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+static WARNINGS: Mutex<Vec<Warning>> = Mutex::new(Vec::new());
+
+/// Log a warning and record it for the output.  Repeats of a warning (for example from a later
+/// pass over the input) are ignored
+pub fn add_warning(kind: &'static str, message: String) {
+    let mut v = WARNINGS.lock().expect("Warnings lock poisoned");
+    if !v.iter().any(|w| w.kind == kind && w.message == message) {
+        warn!("{message}");
+        v.push(Warning { kind, message })
+    }
+}
+
+/// Warnings recorded so far
+pub fn warnings() -> Vec<Warning> {
+    WARNINGS.lock().expect("Warnings lock poisoned").clone()
+}
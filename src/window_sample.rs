@@ -0,0 +1,166 @@
+//! Random sample of evaluated windows for validation of the window counts against independent
+//! implementations
+//!
+//! Each window is given a pseudo random key from a hash of its position, read length and the
+//! seed, and the windows with the smallest keys are kept.  This gives a uniform sample without
+//! replacement that does not depend on the number of threads or on how the input was split
+//! between them, so the samples from the process threads can simply be merged.
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    hash::{Hash, Hasher},
+    io::Write,
+    path::Path,
+};
+
+use anyhow::Context;
+use compress_io::compress::CompressIo;
+use rustc_hash::FxHasher;
+
+/// A sampled window
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampledWindow {
+    key: u64,
+    contig: String,
+    pos: u64,
+    read_length: u32,
+    at: u32,
+    gc: u32,
+}
+
+impl Ord for SampledWindow {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+impl PartialOrd for SampledWindow {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Finalizer from splitmix64, to spread the bits of the hash
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+fn window_key(seed: u64, contig: &str, pos: u64, read_length: u32) -> u64 {
+    let mut h = FxHasher::default();
+    (seed, contig, pos, read_length).hash(&mut h);
+    mix(h.finish())
+}
+
+/// Sample of up to n windows (the n with the smallest keys)
+#[derive(Debug, Clone)]
+pub struct WindowSample {
+    n: usize,
+    seed: u64,
+    heap: BinaryHeap<SampledWindow>,
+}
+
+impl WindowSample {
+    pub fn new(n: usize, seed: u64) -> Self {
+        Self {
+            n,
+            seed,
+            heap: BinaryHeap::with_capacity(n + 1),
+        }
+    }
+
+    fn push(&mut self, w: SampledWindow) {
+        if self.heap.len() < self.n {
+            self.heap.push(w)
+        } else if self.heap.peek().is_some_and(|x| w.key < x.key) {
+            self.heap.pop();
+            self.heap.push(w)
+        }
+    }
+
+    /// Add a window starting at pos (zero offset) with at and gc bases
+    pub fn add(&mut self, contig: &str, pos: u64, read_length: u32, at: u32, gc: u32) {
+        let key = window_key(self.seed, contig, pos, read_length);
+        if self.heap.len() < self.n || self.heap.peek().is_some_and(|x| key < x.key) {
+            self.push(SampledWindow {
+                key,
+                contig: contig.to_owned(),
+                pos,
+                read_length,
+                at,
+                gc,
+            })
+        }
+    }
+
+    /// Merge with the sample from another part of the input
+    pub fn merge(&mut self, other: Self) {
+        for w in other.heap {
+            self.push(w)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    /// Sampled windows sorted by contig, position and read length
+    pub fn windows(&self) -> Vec<&SampledWindow> {
+        let mut v: Vec<_> = self.heap.iter().collect();
+        v.sort_unstable_by(|a, b| {
+            (&a.contig, a.pos, a.read_length).cmp(&(&b.contig, b.pos, b.read_length))
+        });
+        v
+    }
+}
+
+/// Write sampled windows as TSV, with the window start as a 1 offset position
+pub fn output_window_sample<P: AsRef<Path>>(path: P, sample: &WindowSample) -> anyhow::Result<()> {
+    let mut wrt = CompressIo::new()
+        .path(path)
+        .bufwriter()
+        .with_context(|| "Could not open window sample file for output")?;
+    writeln!(wrt, "contig\tpos\tread_length\tat\tgc")?;
+    for w in sample.windows() {
+        writeln!(
+            wrt,
+            "{}\t{}\t{}\t{}\t{}",
+            w.contig,
+            w.pos + 1,
+            w.read_length,
+            w.at,
+            w.gc
+        )?
+    }
+    wrt.flush()
+        .with_context(|| "Error flushing data to window sample file")
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_window_sample() {
+        let mut all = WindowSample::new(10, 42);
+        let mut parts = [WindowSample::new(10, 42), WindowSample::new(10, 42)];
+        for pos in 0..1000 {
+            all.add("chr1", pos, 100, 60, 40);
+            parts[(pos % 7 == 0) as usize].add("chr1", pos, 100, 60, 40)
+        }
+        let [mut a, b] = parts;
+        a.merge(b);
+        assert_eq!(a.len(), 10);
+        let p: Vec<_> = all.windows().iter().map(|w| w.pos).collect();
+        assert_eq!(a.windows().iter().map(|w| w.pos).collect::<Vec<_>>(), p);
+        assert!(p.windows(2).all(|x| x[0] < x[1]));
+        let mut c = WindowSample::new(10, 43);
+        (0..1000).for_each(|pos| c.add("chr1", pos, 100, 60, 40));
+        assert_ne!(c.windows().iter().map(|w| w.pos).collect::<Vec<_>>(), p);
+    }
+}
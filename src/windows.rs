@@ -0,0 +1,162 @@
+//! Base counts in fixed size, non overlapping windows along each contig
+use std::{io::BufRead, sync::Arc};
+
+use anyhow::Context;
+
+use crate::fasta::{Base, FastaReader, GapPolicy, Seq};
+
+#[derive(Debug, Clone)]
+pub struct Window {
+    contig: Arc<str>,
+    start: u64,
+    end: u64,
+    counts: [u32; 4],
+}
+
+impl Window {
+    fn new(contig: Arc<str>, start: u64, end: u64) -> Self {
+        Self {
+            contig,
+            start,
+            end,
+            counts: [0; 4],
+        }
+    }
+
+    pub fn contig(&self) -> &str {
+        &self.contig
+    }
+
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+
+    /// Counts of A, C, T and G (in the order of [`Base`])
+    pub fn counts(&self) -> &[u32; 4] {
+        &self.counts
+    }
+
+    pub fn count(&self, b: Base) -> u32 {
+        self.counts.get(b as usize).copied().unwrap_or(0)
+    }
+
+    /// Number of non gap bases
+    pub fn bases(&self) -> u32 {
+        self.counts.iter().sum()
+    }
+
+    /// Proportion of the window covered by non gap bases
+    pub fn coverage(&self) -> f64 {
+        self.bases() as f64 / (self.end - self.start) as f64
+    }
+
+    /// GC content of the non gap bases, or None if there are none
+    pub fn gc(&self) -> Option<f64> {
+        let n = self.bases();
+        (n > 0).then(|| (self.count(Base::C) + self.count(Base::G)) as f64 / n as f64)
+    }
+}
+
+/// Accumulates base counts in windows of size bases from successive sequences of the input, so
+/// that the windows can be found from a pass over the input shared with other consumers
+pub struct WindowScan {
+    size: u64,
+    curr: Option<Window>,
+    last: u64,
+}
+
+impl WindowScan {
+    pub fn new(size: u64) -> Self {
+        assert!(size > 0);
+        Self {
+            size,
+            curr: None,
+            last: 0,
+        }
+    }
+
+    /// Add sequence s (which must follow the previous sequence in input order), calling f for
+    /// each completed window
+    pub fn add_seq<F>(&mut self, s: &Seq, mut f: F) -> anyhow::Result<()>
+    where
+        F: FnMut(&Window) -> anyhow::Result<()>,
+    {
+        if self.curr.as_ref().map(|w| w.contig() != s.contig()) == Some(true) {
+            let mut w = self.curr.take().unwrap();
+            w.end = self.last;
+            f(&w)?
+        }
+        for (pos, b) in (s.start()..).zip(s.iter()) {
+            if b.is_gap() {
+                continue;
+            }
+            if self.curr.as_ref().map(|w| pos >= w.end) != Some(false) {
+                if let Some(w) = self.curr.take() {
+                    f(&w)?
+                }
+                let start = pos - pos % self.size;
+                let contig = Arc::from(s.contig());
+                self.curr = Some(Window::new(contig, start, start + self.size))
+            }
+            self.curr.as_mut().unwrap().counts[*b as usize] += 1;
+            self.last = pos + 1
+        }
+        Ok(())
+    }
+
+    /// Call f for the last window (if any)
+    pub fn finish<F>(self, mut f: F) -> anyhow::Result<()>
+    where
+        F: FnMut(&Window) -> anyhow::Result<()>,
+    {
+        if let Some(mut w) = self.curr {
+            w.end = self.last;
+            f(&w)?
+        }
+        Ok(())
+    }
+}
+
+/// Read FASTA input and call f for each window of size bases containing at least one non gap
+/// base, in input order.  The last window of a contig ends at the last non gap base
+pub fn read_windows<R, F>(r: R, size: u64, mut f: F) -> anyhow::Result<()>
+where
+    R: BufRead,
+    F: FnMut(&Window) -> anyhow::Result<()>,
+{
+    let mut scan = WindowScan::new(size);
+    // Split records at gaps so that long gaps do not have to be held in memory
+    let policy = GapPolicy::MinGap(size.min(u32::MAX as u64) as u32);
+    let mut rdr = FastaReader::new(r, policy);
+    while let Some(s) = rdr
+        .next_seq()
+        .with_context(|| "Error reading input sequence")?
+    {
+        scan.add_seq(&s, &mut f)?
+    }
+    scan.finish(f)
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_read_windows() {
+        let input = b">chr1\nACGTACGTNNNNNNNNNNNNGGGGCC\nAT\n>chr2\nNNNNAAAA\n";
+        let mut v = Vec::new();
+        read_windows(&input[..], 10, |w| {
+            v.push((w.contig().to_owned(), w.start(), w.end(), w.bases(), w.gc()));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(v.len(), 3);
+        assert_eq!(v[0], ("chr1".to_owned(), 0, 10, 8, Some(0.5)));
+        assert_eq!(v[1], ("chr1".to_owned(), 20, 28, 8, Some(0.75)));
+        assert_eq!(v[2], ("chr2".to_owned(), 0, 8, 4, Some(0.0)));
+    }
+}
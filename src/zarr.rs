@@ -0,0 +1,233 @@
+//! Output of windowed tracks as a Zarr (version 2) store (--zarr)
+//!
+//! The store is a directory with a group for each contig, holding one 1 dimensional float32 array
+//! per track.  Element i of an array is the value for the window starting at i x the window size
+//! (given in the array attributes), with NaN for windows that are not reported.  Arrays are
+//! written in chunks of up to [`ZARR_CHUNK`] values as they are generated, each chunk gzip
+//! compressed (using the gzip codec, so the store can be read with zarr-python or any other Zarr
+//! reader).  Compression is done by the external gzip utility found by compress_io at its default
+//! level, so no level is given in the codec metadata.  Arrays shorter than a chunk are written as
+//! a single chunk of their length.  Contig names containing ``/`` are changed to use ``_`` for
+//! the group name, with the original name kept in the group attributes.
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use compress_io::{compress::CompressIo, compress_type::CompressType};
+use serde::Serialize;
+
+/// Number of values in each chunk of an array
+pub const ZARR_CHUNK: usize = 1 << 20;
+
+#[derive(Serialize)]
+struct GroupMeta {
+    zarr_format: u32,
+}
+
+#[derive(Serialize)]
+struct Compressor {
+    id: &'static str,
+}
+
+#[derive(Serialize)]
+struct ArrayMeta {
+    zarr_format: u32,
+    shape: [usize; 1],
+    chunks: [usize; 1],
+    dtype: &'static str,
+    compressor: Compressor,
+    fill_value: &'static str,
+    order: &'static str,
+    filters: Option<()>,
+}
+
+#[derive(Serialize)]
+struct ContigAttrs<'a> {
+    contig: &'a str,
+}
+
+#[derive(Serialize)]
+struct ArrayAttrs<'a> {
+    window: u64,
+    description: &'a str,
+}
+
+#[derive(Serialize)]
+struct StoreAttrs<'a> {
+    program: &'static str,
+    version: &'static str,
+    /// Contig names in input order
+    contigs: &'a [String],
+}
+
+fn write_json<T: Serialize>(path: &Path, x: &T) -> anyhow::Result<()> {
+    let f = File::create(path).with_context(|| format!("Could not create {}", path.display()))?;
+    serde_json::to_writer(f, x).with_context(|| format!("Error writing {}", path.display()))
+}
+
+/// Root of a Zarr store
+pub struct ZarrStore {
+    root: PathBuf,
+    contigs: Vec<String>,
+}
+
+impl ZarrStore {
+    /// Create a store at path.  Any existing store at the same path is first removed
+    pub fn create<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let root = path.as_ref().to_owned();
+        if root.join(".zgroup").exists() {
+            fs::remove_dir_all(&root)
+                .with_context(|| format!("Could not remove existing store {}", root.display()))?
+        }
+        fs::create_dir_all(&root)
+            .with_context(|| format!("Could not create Zarr store {}", root.display()))?;
+        write_json(&root.join(".zgroup"), &GroupMeta { zarr_format: 2 })?;
+        Ok(Self {
+            root,
+            contigs: Vec::new(),
+        })
+    }
+
+    /// Add a group for a contig, returning its path
+    pub fn add_contig(&mut self, contig: &str) -> anyhow::Result<PathBuf> {
+        let dir = self.root.join(contig.replace('/', "_"));
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Could not create Zarr group {}", dir.display()))?;
+        write_json(&dir.join(".zgroup"), &GroupMeta { zarr_format: 2 })?;
+        write_json(&dir.join(".zattrs"), &ContigAttrs { contig })?;
+        self.contigs.push(contig.to_owned());
+        Ok(dir)
+    }
+
+    /// Write the store attributes
+    pub fn finish(self) -> anyhow::Result<()> {
+        write_json(
+            &self.root.join(".zattrs"),
+            &StoreAttrs {
+                program: env!("CARGO_PKG_NAME"),
+                version: env!("CARGO_PKG_VERSION"),
+                contigs: &self.contigs,
+            },
+        )
+    }
+}
+
+/// float32 array of window values, written in chunks as it is filled
+pub struct ZarrArray {
+    dir: PathBuf,
+    buf: Vec<f32>,
+    /// Number of chunks written
+    chunks: usize,
+}
+
+impl ZarrArray {
+    /// Create array name in group (from [`ZarrStore::add_contig`]) for windows of size window
+    pub fn new(group: &Path, name: &str, window: u64, description: &str) -> anyhow::Result<Self> {
+        let dir = group.join(name);
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Could not create Zarr array {}", dir.display()))?;
+        write_json(
+            &dir.join(".zattrs"),
+            &ArrayAttrs {
+                window,
+                description,
+            },
+        )?;
+        Ok(Self {
+            dir,
+            buf: Vec::with_capacity(ZARR_CHUNK),
+            chunks: 0,
+        })
+    }
+
+    /// Number of values set (including NaN fill)
+    pub fn len(&self) -> usize {
+        self.chunks * ZARR_CHUNK + self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Set the value for window ix, which must be after the last window set.  Skipped windows
+    /// are NaN
+    pub fn set(&mut self, ix: usize, x: f32) -> anyhow::Result<()> {
+        assert!(ix >= self.len(), "Zarr array values set out of order");
+        while self.len() < ix {
+            self.push(f32::NAN)?
+        }
+        self.push(x)
+    }
+
+    fn push(&mut self, x: f32) -> anyhow::Result<()> {
+        self.buf.push(x);
+        if self.buf.len() == ZARR_CHUNK {
+            self.write_chunk()?
+        }
+        Ok(())
+    }
+
+    fn write_chunk(&mut self) -> anyhow::Result<()> {
+        let path = self.dir.join(self.chunks.to_string());
+        let mut wrt = CompressIo::new()
+            .path(&path)
+            .ctype(CompressType::Gzip)
+            .fix_path()
+            .bufwriter()
+            .with_context(|| format!("Could not open Zarr chunk {}", path.display()))?;
+        for x in self.buf.iter() {
+            wrt.write_all(&x.to_le_bytes())?
+        }
+        wrt.flush()
+            .with_context(|| format!("Error writing Zarr chunk {}", path.display()))?;
+        self.buf.clear();
+        self.chunks += 1;
+        Ok(())
+    }
+
+    /// Write the last chunk (padded with NaN if other chunks have been written) and the array
+    /// metadata
+    pub fn finish(mut self) -> anyhow::Result<()> {
+        let len = self.len();
+        let chunk = if self.chunks == 0 {
+            len.max(1)
+        } else {
+            ZARR_CHUNK
+        };
+        if !self.buf.is_empty() {
+            self.buf.resize(chunk, f32::NAN);
+            self.write_chunk()?
+        }
+        write_json(&self.dir.join(".zarray"), &array_meta(len, chunk))
+    }
+}
+
+fn array_meta(len: usize, chunk: usize) -> ArrayMeta {
+    ArrayMeta {
+        zarr_format: 2,
+        shape: [len],
+        chunks: [chunk],
+        dtype: "<f4",
+        compressor: Compressor { id: "gzip" },
+        fill_value: "NaN",
+        order: "C",
+        filters: None,
+    }
+}
+
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_zarr_meta() {
+        let s = serde_json::to_string(&array_meta(10, 10)).unwrap();
+        assert_eq!(
+            s,
+            r#"{"zarr_format":2,"shape":[10],"chunks":[10],"dtype":"<f4","compressor":{"id":"gzip"},"fill_value":"NaN","order":"C","filters":null}"#
+        );
+    }
+}